@@ -0,0 +1,692 @@
+//! The live boot sequence (what `init_tiny.template` does, step by step).
+
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::cmdline::{BreakPoint, Cmdline, PersistSource};
+use crate::config::Config;
+use crate::error::{BootError, Context, Stage};
+use crate::log::{Log, LOG_PATH};
+use crate::modules::Modules;
+use crate::sys::{self, MS_BIND, MS_NODEV, MS_NOSUID, MS_RDONLY, MS_REMOUNT};
+use crate::sysfs::{DeviceScanner, Sysfs};
+use crate::{net, probe};
+
+/// Diagnostics dump for `levitate.diag`.
+pub const DIAG_PATH: &str = "/run/initramfs/levitate-diag.txt";
+
+/// Persistence filesystem label (matches `src/artifact/persist.rs`).
+const PERSIST_LABEL: &str = "LEVITATE_PERSIST";
+
+/// Persistence image on writable boot media, used if there is no partition.
+const PERSIST_IMAGE: &str = "/mnt/LEVITATE_PERSIST.img";
+
+/// RAM left over for the running system with `toram`, in KiB.
+const TORAM_RESERVE_KIB: u64 = 1 << 20;
+
+/// How often the boot device scan runs while waiting for `rootwait=`.
+const SCAN_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Run a busybox applet, returning whether it succeeded.
+pub fn busybox(args: &[&str]) -> bool {
+    Command::new("/bin/busybox")
+        .args(args)
+        .env("PATH", "/bin")
+        .status()
+        .is_ok_and(|s| s.success())
+}
+
+/// State carried through the boot.
+pub struct Boot {
+    pub cmdline: Cmdline,
+    pub config: Config,
+    pub log: Log,
+    pub sysfs: Sysfs,
+    pub modules: Option<Modules>,
+    /// Where the live media came from (device or URL), for messages.
+    boot_source: String,
+    /// Disk of the block device the live media was found on (`None` for
+    /// network boots).
+    boot_disk: Option<String>,
+    /// Device mounted on /overlay as the persistent upper layer.
+    persist_dev: Option<PathBuf>,
+}
+
+impl Boot {
+    pub fn new(cmdline: Cmdline, config: Config) -> Self {
+        let log = Log {
+            debug: cmdline.debug,
+            file: cmdline.diag.then(|| PathBuf::from(LOG_PATH)),
+        };
+        Self {
+            cmdline,
+            config,
+            log,
+            sysfs: Sysfs::new("/"),
+            modules: Modules::find(Path::new("/")),
+            boot_source: String::new(),
+            boot_disk: None,
+            persist_dev: None,
+        }
+    }
+
+    fn root_label(&self) -> &str {
+        self.cmdline
+            .root_label
+            .as_deref()
+            .unwrap_or(&self.config.iso_label)
+    }
+
+    /// Interactive shell; boot continues when it exits.
+    pub fn shell(&self) {
+        let _ = Command::new("/bin/busybox")
+            .arg("sh")
+            .env("PATH", "/bin")
+            .status();
+    }
+
+    /// `rd.break=<point>`
+    fn break_point(&self, point: BreakPoint) {
+        if self.cmdline.breaks.contains(&point) {
+            self.log.msg(&format!(
+                "rd.break={}: dropping to shell. Type 'exit' to continue boot.",
+                point
+            ));
+            self.shell();
+        }
+    }
+
+    /// With `levitate.diag`, append the state of the initramfs to `DIAG_PATH`.
+    pub fn write_diag(&self, reason: &str) {
+        if !self.cmdline.diag {
+            return;
+        }
+        let mut report = format!("=== {} ===\n", reason);
+        for (title, file) in [("cmdline", "/proc/cmdline"), ("lsmod", "/proc/modules")] {
+            report.push_str(&format!("--- {}\n", title));
+            report.push_str(&fs::read_to_string(file).unwrap_or_default());
+        }
+        report.push_str("--- blkid\n");
+        for (dev, fs) in self.sysfs.probe_all() {
+            report.push_str(&format!(
+                "{}: TYPE=\"{}\" LABEL=\"{}\" UUID=\"{}\"\n",
+                dev.display(),
+                fs.fstype,
+                fs.label,
+                fs.uuid.unwrap_or_default()
+            ));
+        }
+        report.push_str("--- mounts\n");
+        report.push_str(&fs::read_to_string("/proc/mounts").unwrap_or_default());
+        report.push_str("--- dmesg\n");
+        if let Ok(output) = Command::new("/bin/busybox").arg("dmesg").output() {
+            report.push_str(&String::from_utf8_lossy(&output.stdout));
+        }
+        report.push('\n');
+
+        let written = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(DIAG_PATH)
+            .and_then(|mut f| f.write_all(report.as_bytes()));
+        if written.is_ok() {
+            self.log
+                .msg(&format!("Diagnostics written to {}", DIAG_PATH));
+        }
+    }
+
+    /// Everything from the mounted pseudo filesystems to switch_root.
+    pub fn run(&mut self) -> Result<(), BootError> {
+        for warning in &self.cmdline.warnings {
+            self.log.msg(warning);
+        }
+        self.log
+            .debug(&format!("ROOT_LABEL: {}", self.root_label()));
+
+        self.log.debug("Loading kernel modules...");
+        match &mut self.modules {
+            Some(modules) => {
+                modules.load_set("boot", &self.log);
+                modules.load_storage_drivers(&self.sysfs, &self.log);
+            }
+            None => self.log.debug("  No kernel modules found"),
+        }
+
+        match self.cmdline.fetch.clone() {
+            Some(url) => {
+                net::fetch_live_media(self, &url)?;
+                self.boot_source = url;
+            }
+            None => self.wait_for_boot_device()?,
+        }
+
+        if self.cmdline.emergency {
+            self.log
+                .msg("Emergency shell requested via kernel cmdline.");
+            self.log.msg("Boot media mounted at /mnt");
+            self.log.msg("Type 'exit' to continue boot.");
+            self.shell();
+        }
+
+        // Network boot already runs from RAM
+        if self.cmdline.toram && self.cmdline.fetch.is_none() {
+            self.copy_to_ram()?;
+        }
+
+        self.break_point(BreakPoint::PreMount);
+        for dir in ["/rootfs", "/live-overlay", "/overlay", "/newroot"] {
+            fs::create_dir_all(dir).stage(Stage::Setup, || format!("failed to create {}", dir))?;
+        }
+        self.mount_rootfs()?;
+
+        // Live-specific configs (autologin, serial console, empty root
+        // password) that are not in the EROFS base system
+        let live_overlay = format!("/mnt{}", self.config.live_overlay_path);
+        let has_live_overlay = Path::new(&live_overlay).is_dir();
+        if has_live_overlay {
+            sys::mount(&live_overlay, "/live-overlay", None, MS_BIND, None)
+                .stage(Stage::Overlay, || "failed to bind the live overlay".into())?;
+        } else {
+            self.log
+                .debug("No live overlay found on boot media (installed system behavior)");
+        }
+
+        self.break_point(BreakPoint::PreOverlay);
+        self.mount_overlay(has_live_overlay)?;
+
+        self.break_point(BreakPoint::PrePivot);
+        self.switch_root()
+    }
+
+    /// Wait up to `rootwait=` for a block device labeled like the live media
+    /// that contains the EROFS, and mount it on /mnt.
+    fn wait_for_boot_device(&mut self) -> Result<(), BootError> {
+        let label = self.root_label().to_string();
+        let rootwait = self.cmdline.rootwait;
+        self.log.debug(&format!(
+            "Searching for boot device with label '{}' (rootwait={}s)...",
+            label,
+            rootwait.as_secs()
+        ));
+        let mut scanner = DeviceScanner::default();
+        let start = Instant::now();
+        let mut next_progress = Duration::from_secs(5);
+        loop {
+            if let Some(modules) = &mut self.modules {
+                modules.load_storage_drivers(&self.sysfs, &self.log);
+            }
+            for (dev, fs) in scanner.scan(&self.sysfs, &label) {
+                self.log
+                    .debug(&format!("Candidate: {} ({})", dev.display(), fs.fstype));
+                if sys::mount(&dev, "/mnt", Some(fs.fstype), MS_RDONLY, None).is_err() {
+                    self.log.debug("  Mount failed");
+                    continue;
+                }
+                if Path::new(&format!("/mnt{}", self.config.rootfs_path)).is_file() {
+                    self.log
+                        .debug(&format!("Found boot device: {}", dev.display()));
+                    self.boot_source = dev.display().to_string();
+                    self.boot_disk = self.disk_of(&dev);
+                    return Ok(());
+                }
+                let _ = sys::umount("/mnt");
+            }
+
+            let elapsed = start.elapsed();
+            if elapsed >= rootwait {
+                break;
+            }
+            if elapsed >= next_progress {
+                self.log.msg(&format!(
+                    "Still waiting for boot device ({}s of {}s)...",
+                    elapsed.as_secs(),
+                    rootwait.as_secs()
+                ));
+                next_progress += Duration::from_secs(5);
+            }
+            thread::sleep(SCAN_INTERVAL);
+        }
+
+        let mut err = BootError::new(Stage::BootDevice, "could not find the live media")
+            .detail(format!(
+                "Expected a filesystem labeled '{}' containing {}",
+                label, self.config.rootfs_path
+            ))
+            .detail(format!(
+                "Block devices after {}s (raise with rootwait=<seconds>):",
+                rootwait.as_secs()
+            ));
+        let devices = self.sysfs.probe_all();
+        if devices.is_empty() {
+            err = err.detail("  (none found)");
+        }
+        for (dev, fs) in devices {
+            err = err.detail(format!("  {}: {} '{}'", dev.display(), fs.fstype, fs.label));
+        }
+        Err(err)
+    }
+
+    /// `toram`: copy the live media into a tmpfs and release the boot
+    /// device. Falls back to booting from the device if RAM is short.
+    fn copy_to_ram(&mut self) -> Result<(), BootError> {
+        let rootfs = self.config.rootfs_path.clone();
+        let files = [rootfs.clone(), format!("{}.verity", rootfs)];
+        let overlay = format!("/mnt{}", self.config.live_overlay_path);
+        let need_kib = files
+            .iter()
+            .map(|f| tree_size(Path::new(&format!("/mnt{}", f))))
+            .sum::<u64>()
+            .saturating_add(tree_size(Path::new(&overlay)))
+            / 1024;
+        let avail_kib = fs::read_to_string("/proc/meminfo")
+            .ok()
+            .and_then(|m| mem_available_kib(&m));
+
+        if avail_kib.is_none_or(|avail| avail < need_kib + TORAM_RESERVE_KIB) {
+            self.log.msg(&format!(
+                "toram: not enough RAM (need {} MiB + {} MiB, available {} MiB)",
+                need_kib / 1024,
+                TORAM_RESERVE_KIB / 1024,
+                avail_kib.unwrap_or(0) / 1024
+            ));
+            self.log
+                .msg(&format!("toram: booting from {} instead", self.boot_source));
+            self.write_diag("toram: not enough RAM");
+            return Ok(());
+        }
+
+        self.log.msg(&format!(
+            "toram: copying live media to RAM ({} MiB)...",
+            need_kib / 1024
+        ));
+        let _ = fs::create_dir_all("/toram");
+        let size = format!("size={}k", need_kib + 65536);
+        if sys::mount("tmpfs", "/toram", Some("tmpfs"), 0, Some(&size)).is_err() {
+            self.log.msg(&format!(
+                "toram: failed to create tmpfs, booting from {} instead",
+                self.boot_source
+            ));
+            self.write_diag("toram: tmpfs failed");
+            return Ok(());
+        }
+        for file in &files {
+            let src = PathBuf::from(format!("/mnt{}", file));
+            if !src.is_file() {
+                continue;
+            }
+            let dest = PathBuf::from(format!("/toram{}", file));
+            copy_with_progress(&src, &dest)
+                .stage(Stage::ToRam, || format!("failed to copy {}", file))?;
+        }
+        if Path::new(&overlay).is_dir() {
+            let dest = format!("/toram{}", self.config.live_overlay_path);
+            let _ = fs::create_dir_all(&dest);
+            if !busybox(&["cp", "-a", &format!("{}/.", overlay), &format!("{}/", dest)]) {
+                return Err(BootError::new(Stage::ToRam, "failed to copy live overlay"));
+            }
+        }
+
+        // From here on /mnt is the RAM copy; the rest of boot is unchanged
+        sys::umount("/mnt").stage(Stage::ToRam, || {
+            format!("failed to unmount {}", self.boot_source)
+        })?;
+        sys::mount_move("/toram", "/mnt")
+            .stage(Stage::ToRam, || "failed to move RAM copy to /mnt".into())?;
+        self.log
+            .msg(&format!("toram: done, {} can be removed", self.boot_source));
+        Ok(())
+    }
+
+    /// Mount the EROFS on /rootfs, through dm-verity with `levitate.verity=`.
+    fn mount_rootfs(&mut self) -> Result<(), BootError> {
+        let image = PathBuf::from(format!("/mnt{}", self.config.rootfs_path));
+
+        let Some(root_hash) = self.cmdline.verity.clone() else {
+            self.log.debug("Mounting EROFS...");
+            let source = match sys::losetup(&image, false) {
+                Ok(loop_dev) => loop_dev,
+                Err(e) => {
+                    // Fall back to a file-backed mount (kernel might handle it)
+                    self.log
+                        .debug(&format!("losetup failed ({}), trying direct mount", e));
+                    image
+                }
+            };
+            return sys::mount(&source, "/rootfs", Some("erofs"), MS_RDONLY, None)
+                .stage(Stage::Rootfs, || {
+                    format!("failed to mount EROFS from {}", source.display())
+                })
+                .map_err(|e| e.detail("Is CONFIG_EROFS_FS=y in the kernel?"));
+        };
+
+        // dm-verity: every EROFS block is checked against the hash tree, whose
+        // root hash comes from the (signed) UKI cmdline. No fallback to an
+        // unverified mount - a mismatch means the EROFS image is corrupted or
+        // was modified. Only the EROFS is covered: the live overlay on the
+        // same medium is bind-mounted above it unverified.
+        self.log.debug("Verifying EROFS with dm-verity...");
+        let hash_tree = PathBuf::from(format!("{}.verity", image.display()));
+        if !hash_tree.is_file() {
+            return Err(BootError::new(
+                Stage::Verity,
+                format!(
+                    "dm-verity hash tree {}.verity missing from boot media",
+                    self.config.rootfs_path
+                ),
+            ));
+        }
+        if let Some(modules) = &self.modules {
+            modules.load_set("verity", &self.log);
+        }
+        let _ = fs::create_dir_all("/dev/mapper");
+        let _ = fs::create_dir_all("/run/cryptsetup");
+        let data_dev = sys::losetup(&image, false)
+            .stage(Stage::Verity, || "losetup failed for EROFS".into())?;
+        let hash_dev = sys::losetup(&hash_tree, false).stage(Stage::Verity, || {
+            "losetup failed for verity hash tree".into()
+        })?;
+        // veritysetup is appended to this initramfs with its libraries in /usr/lib64
+        let opened = Command::new("/usr/lib64/ld-linux-x86-64.so.2")
+            .args([
+                "--library-path",
+                "/usr/lib64",
+                "/usr/sbin/veritysetup",
+                "open",
+            ])
+            .arg(&data_dev)
+            .arg("live-root")
+            .arg(&hash_dev)
+            .arg(&root_hash)
+            .status()
+            .is_ok_and(|s| s.success());
+        if !opened {
+            return Err(BootError::new(
+                Stage::Verity,
+                "dm-verity root hash mismatch - the EROFS image is corrupted or has been modified",
+            ));
+        }
+        sys::mount(
+            "/dev/mapper/live-root",
+            "/rootfs",
+            Some("erofs"),
+            MS_RDONLY,
+            None,
+        )
+        .stage(Stage::Verity, || {
+            "failed to mount verified EROFS from /dev/mapper/live-root".into()
+        })
+    }
+
+    /// Overlay: EROFS lower, live overlay in the middle, tmpfs or the
+    /// persistence filesystem as upper layer, mounted on /newroot.
+    fn mount_overlay(&mut self, has_live_overlay: bool) -> Result<(), BootError> {
+        self.log.debug("Creating overlay filesystem...");
+        if !self.cmdline.nopersist {
+            self.mount_persistence();
+        }
+        if self.persist_dev.is_none() {
+            sys::mount("tmpfs", "/overlay", Some("tmpfs"), 0, Some("size=50%"))
+                .stage(Stage::Overlay, || {
+                    "failed to mount tmpfs upper layer".into()
+                })?;
+        }
+        for dir in ["/overlay/upper", "/overlay/work"] {
+            fs::create_dir_all(dir)
+                .stage(Stage::Overlay, || format!("failed to create {}", dir))?;
+        }
+
+        // lowerdir=<higher>:<lower>, rightmost is lowest
+        let lower = if has_live_overlay {
+            "/live-overlay:/rootfs"
+        } else {
+            "/rootfs"
+        };
+        let options = format!(
+            "lowerdir={},upperdir=/overlay/upper,workdir=/overlay/work",
+            lower
+        );
+        sys::mount("overlay", "/newroot", Some("overlay"), 0, Some(&options))
+            .stage(Stage::Overlay, || "failed to create overlay".into())
+            .map_err(|e| e.detail("Is CONFIG_OVERLAY_FS=y in the kernel?"))?;
+        if has_live_overlay {
+            // Marker so systemd units can detect live boot
+            let _ = File::create("/newroot/live-boot-marker");
+        }
+        Ok(())
+    }
+
+    /// Persistence: an ext4 filesystem labeled `LEVITATE_PERSIST` (partition,
+    /// or `/LEVITATE_PERSIST.img` on writable boot media) holds the overlay
+    /// upper layer so changes survive reboots. Failures are not fatal.
+    fn mount_persistence(&mut self) {
+        if let Some(modules) = &self.modules {
+            modules.load_set("persist", &self.log);
+        }
+        let mut dev = self.find_persistence();
+        if dev.is_none()
+            && self.cmdline.persist.is_none()
+            && Path::new(PERSIST_IMAGE).is_file()
+            && sys::mount("none", "/mnt", None, MS_REMOUNT, None).is_ok()
+        {
+            dev = sys::losetup(Path::new(PERSIST_IMAGE), true).ok();
+        }
+        let Some(dev) = dev else {
+            if let Some(source) = &self.cmdline.persist {
+                self.log.msg(&format!(
+                    "persistence: {} not found, changes will not be saved",
+                    source
+                ));
+                self.write_diag(&format!("persistence: {} not found", source));
+            }
+            return;
+        };
+        if let Err(e) = sys::mount(&dev, "/overlay", Some("ext4"), libc::MS_NOATIME, None) {
+            self.log.msg(&format!(
+                "persistence: failed to mount {} ({}), changes will not be saved",
+                dev.display(),
+                e
+            ));
+            self.write_diag(&format!("persistence: mount {} failed", dev.display()));
+            return;
+        }
+        self.log
+            .msg(&format!("persistence: saving changes to {}", dev.display()));
+        self.persist_dev = Some(dev);
+    }
+
+    /// With `levitate.verity=`, a partition found by label alone must be on
+    /// the boot disk: the upper layer sits above the verified EROFS, so any
+    /// other attached stick labeled `LEVITATE_PERSIST` could replace /etc.
+    /// An explicit `persist=` is trusted.
+    fn find_persistence(&self) -> Option<PathBuf> {
+        let matches = |fs: &probe::Filesystem| match &self.cmdline.persist {
+            Some(PersistSource::Label(label)) => fs.label == *label,
+            Some(PersistSource::Uuid(uuid)) => fs
+                .uuid
+                .as_deref()
+                .is_some_and(|u| u.eq_ignore_ascii_case(uuid)),
+            Some(PersistSource::Device(_)) => false,
+            None => fs.label == PERSIST_LABEL,
+        };
+        if let Some(PersistSource::Device(dev)) = &self.cmdline.persist {
+            let dev = PathBuf::from(dev);
+            return dev.exists().then_some(dev);
+        }
+        let boot_disk_only = self.cmdline.verity.is_some() && self.cmdline.persist.is_none();
+        self.sysfs
+            .probe_all()
+            .into_iter()
+            .filter(|(_, fs)| matches(fs))
+            .find(|(dev, _)| {
+                if !boot_disk_only {
+                    return true;
+                }
+                let on_boot_disk = self.boot_disk.is_some()
+                    && self.disk_of(dev).as_deref() == self.boot_disk.as_deref();
+                if !on_boot_disk {
+                    self.log.msg(&format!(
+                        "persistence: ignoring {} - not on the boot disk of a verity boot (use persist=)",
+                        dev.display()
+                    ));
+                }
+                on_boot_disk
+            })
+            .map(|(dev, _)| dev)
+    }
+
+    /// Disk holding the block device `dev` (`/dev/sdb3` -> `sdb`).
+    fn disk_of(&self, dev: &Path) -> Option<String> {
+        let name = dev.file_name()?.to_str()?;
+        self.sysfs.disk_of(name)
+    }
+
+    /// Move the pseudo filesystems, the boot media and persistence into the
+    /// new root and hand over to systemd.
+    fn switch_root(&mut self) -> Result<(), BootError> {
+        self.log.debug("Preparing switch_root...");
+        // systemd keeps an already mounted /run, including /run/initramfs
+        for dir in ["/dev", "/proc", "/sys", "/run"] {
+            sys::mount_move(dir, format!("/newroot{}", dir))
+                .stage(Stage::SwitchRoot, || format!("failed to move {}", dir))?;
+        }
+        // Keep the live media at /media/cdrom so recstrap can access the EROFS
+        // (for network boot and toram, this is the RAM copy)
+        let _ = fs::create_dir_all("/newroot/media/cdrom");
+        sys::mount_move("/mnt", "/newroot/media/cdrom")
+            .stage(Stage::SwitchRoot, || "failed to move the boot media".into())?;
+        // Keep the persistence filesystem visible so it is synced and
+        // unmounted cleanly at shutdown
+        if self.persist_dev.is_some() {
+            let _ = fs::create_dir_all("/newroot/media/persist");
+            sys::mount_move("/overlay", "/newroot/media/persist")
+                .stage(Stage::SwitchRoot, || "failed to move persistence".into())?;
+        }
+
+        if fs::symlink_metadata("/newroot/sbin/init").is_err() {
+            return Err(BootError::new(
+                Stage::SwitchRoot,
+                "/newroot/sbin/init not found",
+            ));
+        }
+        self.log.debug("Switching root to live system...");
+        let err = sys::switch_root(Path::new("/newroot"), "/sbin/init");
+        Err(BootError {
+            source: Some(err),
+            ..BootError::new(Stage::SwitchRoot, "switch_root failed")
+        })
+    }
+}
+
+/// Mount /proc, /sys, /dev and /run (before anything can be logged to /run).
+pub fn mount_pseudo_filesystems() -> Result<(), BootError> {
+    let mounts: [(&str, &str, &str, libc::c_ulong, Option<&str>); 4] = [
+        ("proc", "/proc", "proc", 0, None),
+        ("sysfs", "/sys", "sysfs", 0, None),
+        ("devtmpfs", "/dev", "devtmpfs", 0, None),
+        // /run is handed over to systemd at switch_root, so diagnostics survive
+        (
+            "tmpfs",
+            "/run",
+            "tmpfs",
+            MS_NOSUID | MS_NODEV,
+            Some("mode=0755"),
+        ),
+    ];
+    for (source, target, fstype, flags, data) in mounts {
+        let _ = fs::create_dir_all(target);
+        sys::mount(source, target, Some(fstype), flags, data)
+            .stage(Stage::Setup, || format!("failed to mount {}", target))?;
+    }
+    fs::create_dir_all("/run/initramfs")
+        .stage(Stage::Setup, || "failed to create /run/initramfs".into())
+}
+
+/// `MemAvailable` from /proc/meminfo, in KiB.
+fn mem_available_kib(meminfo: &str) -> Option<u64> {
+    meminfo
+        .lines()
+        .find_map(|line| line.strip_prefix("MemAvailable:"))
+        .and_then(|rest| rest.split_whitespace().next())
+        .and_then(|kib| kib.parse().ok())
+}
+
+/// Total size of the regular files at or below `path`, in bytes.
+fn tree_size(path: &Path) -> u64 {
+    let Ok(meta) = fs::symlink_metadata(path) else {
+        return 0;
+    };
+    if !meta.is_dir() {
+        return if meta.is_file() { meta.len() } else { 0 };
+    }
+    fs::read_dir(path)
+        .map(|entries| entries.flatten().map(|e| tree_size(&e.path())).sum())
+        .unwrap_or(0)
+}
+
+/// Copy `src` to `dest`, printing progress every 64 MiB.
+fn copy_with_progress(src: &Path, dest: &Path) -> io::Result<()> {
+    const REPORT_EVERY: u64 = 64 << 20;
+    if let Some(parent) = dest.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let total = fs::metadata(src)?.len().max(1);
+    let name = src.file_name().unwrap_or_default().to_string_lossy();
+    let mut input = File::open(src)?;
+    let mut output = File::create(dest)?;
+    let mut buf = vec![0u8; 1 << 20];
+    let mut done = 0u64;
+    let mut reported = 0u64;
+    loop {
+        let n = input.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        output.write_all(&buf[..n])?;
+        done += n as u64;
+        if done - reported >= REPORT_EVERY || done == total {
+            reported = done;
+            print!(
+                "\r  {}: {} / {} MiB ({}%)",
+                name,
+                done >> 20,
+                total >> 20,
+                done * 100 / total
+            );
+            let _ = io::stdout().flush();
+        }
+    }
+    println!();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mem_available() {
+        let meminfo =
+            "MemTotal:        8000000 kB\nMemFree:         1000 kB\nMemAvailable:    6000000 kB\n";
+        assert_eq!(mem_available_kib(meminfo), Some(6_000_000));
+        assert_eq!(mem_available_kib("MemTotal: 1 kB\n"), None);
+    }
+
+    #[test]
+    fn test_copy_with_progress_and_tree_size() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let src = temp.path().join("media/live/filesystem.erofs");
+        fs::create_dir_all(src.parent().unwrap()).unwrap();
+        let data: Vec<u8> = (0..3_000_000u32).map(|i| i as u8).collect();
+        fs::write(&src, &data).unwrap();
+        fs::write(temp.path().join("media/live/small"), b"abc").unwrap();
+        assert_eq!(tree_size(&temp.path().join("media")), 3_000_003);
+
+        let dest = temp.path().join("toram/live/filesystem.erofs");
+        copy_with_progress(&src, &dest).unwrap();
+        assert_eq!(fs::read(&dest).unwrap(), data);
+    }
+}
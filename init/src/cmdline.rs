@@ -0,0 +1,235 @@
+//! Kernel cmdline parameters understood by the live init.
+
+use std::collections::BTreeSet;
+use std::fmt;
+use std::time::Duration;
+
+/// Default for `rootwait=`, in seconds.
+pub const DEFAULT_ROOTWAIT: u64 = 30;
+
+/// `rd.break=<point>`: where to stop with an interactive shell.
+// Named after the rd.break values (pre-mount, ...)
+#[allow(clippy::enum_variant_names)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum BreakPoint {
+    /// Boot media found, before the EROFS is mounted.
+    PreMount,
+    /// EROFS mounted, before the overlay is created.
+    PreOverlay,
+    /// Before switch_root (a bare `rd.break`).
+    PrePivot,
+}
+
+impl BreakPoint {
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "pre-mount" => Some(Self::PreMount),
+            "pre-overlay" => Some(Self::PreOverlay),
+            "pre-pivot" => Some(Self::PrePivot),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for BreakPoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::PreMount => "pre-mount",
+            Self::PreOverlay => "pre-overlay",
+            Self::PrePivot => "pre-pivot",
+        })
+    }
+}
+
+/// `persist=`: which filesystem holds the persistent overlay upper layer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PersistSource {
+    Label(String),
+    Uuid(String),
+    Device(String),
+}
+
+impl fmt::Display for PersistSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Label(label) => write!(f, "LABEL={}", label),
+            Self::Uuid(uuid) => write!(f, "UUID={}", uuid),
+            Self::Device(dev) => f.write_str(dev),
+        }
+    }
+}
+
+/// Parsed kernel cmdline. Unknown parameters are ignored (they belong to the
+/// kernel or systemd); known ones with an invalid value end up in `warnings`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cmdline {
+    /// `root=LABEL=<label>`
+    pub root_label: Option<String>,
+    /// `emergency`: shell once the boot media is mounted
+    pub emergency: bool,
+    /// `rootwait=<seconds>`
+    pub rootwait: Duration,
+    /// `toram`
+    pub toram: bool,
+    /// `persist=LABEL=<x>|UUID=<x>|/dev/<x>`
+    pub persist: Option<PersistSource>,
+    /// `nopersist`
+    pub nopersist: bool,
+    /// `levitate.verity=<root hash>`
+    pub verity: Option<String>,
+    /// `levitate.fetch=<url>`, without a trailing slash
+    pub fetch: Option<String>,
+    /// `levitate.overlay_sha256=<hex>`: checksum of the fetched overlay tarball
+    pub overlay_sha256: Option<String>,
+    /// `rd.break[=<point>,...]`
+    pub breaks: BTreeSet<BreakPoint>,
+    /// `rd.shell=0` turns this off: reboot instead of an emergency shell
+    pub rd_shell: bool,
+    /// `debug` (or `levitate.diag`)
+    pub debug: bool,
+    /// `levitate.diag`
+    pub diag: bool,
+    pub warnings: Vec<String>,
+}
+
+impl Default for Cmdline {
+    fn default() -> Self {
+        Self {
+            root_label: None,
+            emergency: false,
+            rootwait: Duration::from_secs(DEFAULT_ROOTWAIT),
+            toram: false,
+            persist: None,
+            nopersist: false,
+            verity: None,
+            fetch: None,
+            overlay_sha256: None,
+            breaks: BTreeSet::new(),
+            rd_shell: true,
+            debug: false,
+            diag: false,
+            warnings: Vec::new(),
+        }
+    }
+}
+
+impl Cmdline {
+    pub fn parse(cmdline: &str) -> Self {
+        let mut parsed = Self::default();
+        for param in cmdline.split_whitespace() {
+            let (key, value) = match param.split_once('=') {
+                Some((key, value)) => (key, Some(value)),
+                None => (param, None),
+            };
+            match (key, value) {
+                ("root", Some(root)) => match root.strip_prefix("LABEL=") {
+                    Some(label) => parsed.root_label = Some(label.to_string()),
+                    None => parsed.warn(param, "only root=LABEL=<label> is supported"),
+                },
+                ("emergency", None) => parsed.emergency = true,
+                ("rootwait", Some(secs)) => match secs.parse() {
+                    Ok(secs) => parsed.rootwait = Duration::from_secs(secs),
+                    Err(_) => parsed.warn(param, "expected a number of seconds"),
+                },
+                ("toram", None) => parsed.toram = true,
+                ("persist", Some(source)) => {
+                    parsed.persist = if let Some(label) = source.strip_prefix("LABEL=") {
+                        Some(PersistSource::Label(label.to_string()))
+                    } else if let Some(uuid) = source.strip_prefix("UUID=") {
+                        Some(PersistSource::Uuid(uuid.to_string()))
+                    } else if source.starts_with("/dev/") {
+                        Some(PersistSource::Device(source.to_string()))
+                    } else {
+                        parsed.warn(param, "expected LABEL=<x>, UUID=<x> or /dev/<x>");
+                        None
+                    }
+                }
+                ("nopersist", None) => parsed.nopersist = true,
+                ("levitate.verity", Some(hash)) => parsed.verity = Some(hash.to_string()),
+                ("levitate.fetch", Some(url)) => {
+                    parsed.fetch = Some(url.trim_end_matches('/').to_string())
+                }
+                ("levitate.overlay_sha256", Some(hash)) => {
+                    parsed.overlay_sha256 = Some(hash.to_ascii_lowercase())
+                }
+                ("levitate.diag", None) => {
+                    parsed.diag = true;
+                    parsed.debug = true;
+                }
+                ("rd.break", None) => {
+                    parsed.breaks.insert(BreakPoint::PrePivot);
+                }
+                ("rd.break", Some(points)) => {
+                    for point in points.split(',') {
+                        match BreakPoint::parse(point) {
+                            Some(point) => {
+                                parsed.breaks.insert(point);
+                            }
+                            None => parsed.warn(
+                                param,
+                                "break points are pre-mount, pre-overlay and pre-pivot",
+                            ),
+                        }
+                    }
+                }
+                ("rd.shell", None | Some("1")) => parsed.rd_shell = true,
+                ("rd.shell", Some("0")) => parsed.rd_shell = false,
+                ("debug", None) => parsed.debug = true,
+                _ => {}
+            }
+        }
+        parsed
+    }
+
+    fn warn(&mut self, param: &str, expected: &str) {
+        self.warnings
+            .push(format!("ignoring '{}': {}", param, expected));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_live_cmdline() {
+        let cmdline = Cmdline::parse(
+            "console=ttyS0 root=LABEL=LEVITATEOS rootwait=60 toram \
+             levitate.verity=abc123 levitate.fetch=http://10.0.2.2:8000/ \
+             levitate.overlay_sha256=ABC456 persist=UUID=1234 rd.break=pre-mount,pre-pivot rd.shell=0 quiet",
+        );
+        assert_eq!(cmdline.root_label.as_deref(), Some("LEVITATEOS"));
+        assert_eq!(cmdline.rootwait, Duration::from_secs(60));
+        assert!(cmdline.toram);
+        assert_eq!(cmdline.verity.as_deref(), Some("abc123"));
+        assert_eq!(cmdline.fetch.as_deref(), Some("http://10.0.2.2:8000"));
+        assert_eq!(cmdline.overlay_sha256.as_deref(), Some("abc456"));
+        assert_eq!(cmdline.persist, Some(PersistSource::Uuid("1234".into())));
+        assert_eq!(
+            cmdline.breaks.iter().copied().collect::<Vec<_>>(),
+            [BreakPoint::PreMount, BreakPoint::PrePivot]
+        );
+        assert!(!cmdline.rd_shell);
+        assert!(!cmdline.debug);
+        assert!(cmdline.warnings.is_empty());
+    }
+
+    #[test]
+    fn test_parse_invalid_values() {
+        let cmdline = Cmdline::parse("rootwait=soon rd.break=later persist=sdb1 root=/dev/sr0");
+        assert_eq!(cmdline.rootwait, Duration::from_secs(DEFAULT_ROOTWAIT));
+        assert!(cmdline.breaks.is_empty());
+        assert_eq!(cmdline.persist, None);
+        assert_eq!(cmdline.root_label, None);
+        assert_eq!(cmdline.warnings.len(), 4);
+        assert!(cmdline.warnings[0].starts_with("ignoring 'rootwait=soon'"));
+    }
+
+    #[test]
+    fn test_parse_diag_and_bare_break() {
+        let cmdline = Cmdline::parse("levitate.diag rd.break");
+        assert!(cmdline.diag && cmdline.debug);
+        assert!(cmdline.breaks.contains(&BreakPoint::PrePivot));
+        assert!(cmdline.rd_shell);
+    }
+}
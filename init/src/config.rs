@@ -0,0 +1,84 @@
+//! Build-time settings, written by leviso to `/etc/levitate/init.conf`.
+//!
+//! These are the values the shell template gets substituted in
+//! (`{{ISO_LABEL}}`, `{{ROOTFS_PATH}}`, `{{LIVE_OVERLAY_PATH}}`), kept out of
+//! the binary so it does not need rebuilding when they change.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Location of the config in the initramfs.
+pub const CONFIG_PATH: &str = "/etc/levitate/init.conf";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Config {
+    /// Label of the live media, unless overridden by `root=LABEL=`.
+    pub iso_label: String,
+    /// EROFS image on the live media, e.g. `/live/filesystem.erofs`.
+    pub rootfs_path: String,
+    /// Live overlay directory on the live media.
+    pub live_overlay_path: String,
+}
+
+impl Config {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        Self::parse(&fs::read_to_string(path)?)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Parse `KEY=value` lines; `#` starts a comment.
+    pub fn parse(content: &str) -> Result<Self, String> {
+        let mut iso_label = None;
+        let mut rootfs_path = None;
+        let mut live_overlay_path = None;
+        for line in content.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| format!("invalid line '{}'", line))?;
+            let slot = match key {
+                "ISO_LABEL" => &mut iso_label,
+                "ROOTFS_PATH" => &mut rootfs_path,
+                "LIVE_OVERLAY_PATH" => &mut live_overlay_path,
+                _ => return Err(format!("unknown key '{}'", key)),
+            };
+            *slot = Some(value.to_string());
+        }
+        let require = |value: Option<String>, key: &str| {
+            value
+                .filter(|v| !v.is_empty())
+                .ok_or_else(|| format!("{} is not set", key))
+        };
+        Ok(Self {
+            iso_label: require(iso_label, "ISO_LABEL")?,
+            rootfs_path: require(rootfs_path, "ROOTFS_PATH")?,
+            live_overlay_path: require(live_overlay_path, "LIVE_OVERLAY_PATH")?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_config() {
+        let config = Config::parse(
+            "# written by leviso\n\
+             ISO_LABEL=LEVITATEOS\n\
+             ROOTFS_PATH=/live/filesystem.erofs\n\
+             LIVE_OVERLAY_PATH=/live/overlay\n",
+        )
+        .unwrap();
+        assert_eq!(config.iso_label, "LEVITATEOS");
+        assert_eq!(config.rootfs_path, "/live/filesystem.erofs");
+
+        assert!(Config::parse("ISO_LABEL=X\nROOTFS_PATH=/a\n")
+            .unwrap_err()
+            .contains("LIVE_OVERLAY_PATH"));
+        assert!(Config::parse("BOGUS=1").is_err());
+    }
+}
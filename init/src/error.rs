@@ -0,0 +1,101 @@
+//! Boot errors: what failed, in which stage, and what to try.
+
+use std::fmt;
+use std::io;
+
+/// Boot stage an error happened in (printed with every error).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    Setup,
+    Network,
+    BootDevice,
+    ToRam,
+    Verity,
+    Rootfs,
+    Overlay,
+    SwitchRoot,
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Setup => "setup",
+            Self::Network => "network",
+            Self::BootDevice => "boot-device",
+            Self::ToRam => "toram",
+            Self::Verity => "verity",
+            Self::Rootfs => "rootfs",
+            Self::Overlay => "overlay",
+            Self::SwitchRoot => "switch-root",
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct BootError {
+    pub stage: Stage,
+    pub message: String,
+    /// Underlying OS error, if any.
+    pub source: Option<io::Error>,
+    /// Extra lines shown below the error (hints, what was found).
+    pub details: Vec<String>,
+}
+
+impl BootError {
+    pub fn new(stage: Stage, message: impl Into<String>) -> Self {
+        Self {
+            stage,
+            message: message.into(),
+            source: None,
+            details: Vec::new(),
+        }
+    }
+
+    pub fn detail(mut self, line: impl Into<String>) -> Self {
+        self.details.push(line.into());
+        self
+    }
+}
+
+impl fmt::Display for BootError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}] {}", self.stage, self.message)?;
+        if let Some(source) = &self.source {
+            write!(f, ": {}", source)?;
+        }
+        Ok(())
+    }
+}
+
+/// Attach a stage and message to `io::Result`s.
+pub trait Context<T> {
+    fn stage(self, stage: Stage, message: impl FnOnce() -> String) -> Result<T, BootError>;
+}
+
+impl<T> Context<T> for io::Result<T> {
+    fn stage(self, stage: Stage, message: impl FnOnce() -> String) -> Result<T, BootError> {
+        self.map_err(|e| BootError {
+            source: Some(e),
+            ..BootError::new(stage, message())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_display() {
+        let err: Result<(), _> = Err(io::Error::from_raw_os_error(libc::ENOENT));
+        let err = err
+            .stage(Stage::Rootfs, || "failed to mount EROFS".into())
+            .unwrap_err()
+            .detail("Is CONFIG_EROFS_FS=y in the kernel?");
+        assert_eq!(
+            err.to_string(),
+            "[rootfs] failed to mount EROFS: No such file or directory (os error 2)"
+        );
+        assert_eq!(err.details.len(), 1);
+    }
+}
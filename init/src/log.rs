@@ -0,0 +1,36 @@
+//! Console output, mirrored to `/run/initramfs/init.log` with `levitate.diag`.
+
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+
+/// Log kept across switch_root (systemd takes over the mounted /run).
+pub const LOG_PATH: &str = "/run/initramfs/init.log";
+
+#[derive(Debug, Clone, Default)]
+pub struct Log {
+    pub debug: bool,
+    /// Mirror everything to this file (`levitate.diag`).
+    pub file: Option<PathBuf>,
+}
+
+impl Log {
+    pub fn msg(&self, text: &str) {
+        self.emit(&format!("initramfs: {}", text));
+    }
+
+    pub fn debug(&self, text: &str) {
+        if self.debug {
+            self.emit(&format!("DEBUG: {}", text));
+        }
+    }
+
+    fn emit(&self, line: &str) {
+        println!("{}", line);
+        if let Some(path) = &self.file {
+            if let Ok(mut file) = OpenOptions::new().create(true).append(true).open(path) {
+                let _ = writeln!(file, "{}", line);
+            }
+        }
+    }
+}
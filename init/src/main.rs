@@ -0,0 +1,93 @@
+//! `/init` for the LevitateOS live initramfs.
+//!
+//! A static replacement for the busybox script `profile/init_tiny.template`
+//! with the same boot flow and cmdline:
+//!
+//! 1. Mount /proc, /sys, /dev, /run
+//! 2. Load the boot modules and storage drivers (build-time load lists)
+//! 3. Wait (`rootwait=`) for the block device labeled like the ISO that holds
+//!    the EROFS, or download the live media (`levitate.fetch=`)
+//! 4. Optionally copy it to RAM (`toram`)
+//! 5. Mount the EROFS, through dm-verity with `levitate.verity=`
+//! 6. Overlay: EROFS + live overlay + tmpfs or `LEVITATE_PERSIST`
+//! 7. switch_root to systemd
+//!
+//! busybox stays in the initramfs for the emergency shell, DHCP and
+//! downloads. Errors name the boot stage that failed.
+
+mod boot;
+mod cmdline;
+mod config;
+mod error;
+mod log;
+mod modules;
+mod net;
+mod probe;
+mod sys;
+mod sysfs;
+
+use std::fs;
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+
+use boot::Boot;
+use cmdline::Cmdline;
+use config::{Config, CONFIG_PATH};
+use error::BootError;
+
+fn main() {
+    if let Err(err) = boot::mount_pseudo_filesystems() {
+        // Without /proc and /dev there is nothing sensible left to try
+        eprintln!("initramfs: ERROR {}", err);
+        emergency(None, err);
+    }
+
+    let cmdline = Cmdline::parse(&fs::read_to_string("/proc/cmdline").unwrap_or_default());
+    let config = match Config::load(Path::new(CONFIG_PATH)) {
+        Ok(config) => config,
+        Err(e) => {
+            let err = BootError::new(
+                error::Stage::Setup,
+                format!("invalid {}: {}", CONFIG_PATH, e),
+            );
+            eprintln!("initramfs: ERROR {}", err);
+            emergency(None, err);
+        }
+    };
+
+    let mut boot = Boot::new(cmdline, config);
+    boot.log.debug("LevitateOS initramfs starting...");
+    // run() only returns on failure: success ends in exec of systemd
+    let err = match boot.run() {
+        Ok(()) => unreachable!("switch_root returned"),
+        Err(err) => err,
+    };
+    emergency(Some(&boot), err)
+}
+
+/// Report `err`, then drop to a shell (or reboot with `rd.shell=0`). PID 1
+/// must never exit, so this reboots once the shell exits.
+fn emergency(boot: Option<&Boot>, err: BootError) -> ! {
+    if let Some(boot) = boot {
+        boot.log.msg(&format!("ERROR {}", err));
+        for line in &err.details {
+            boot.log.msg(&format!("  {}", line));
+        }
+        boot.write_diag(&format!("FAILURE: {}", err));
+        if !boot.cmdline.rd_shell {
+            boot.log.msg("rd.shell=0: rebooting in 30 seconds");
+            thread::sleep(Duration::from_secs(30));
+            sys::reboot();
+        }
+        boot.log
+            .msg("Dropping to emergency shell. Type 'exit' to reboot.");
+        boot.shell();
+    } else {
+        eprintln!("initramfs: dropping to emergency shell. Type 'exit' to reboot.");
+        let _ = std::process::Command::new("/bin/busybox")
+            .arg("sh")
+            .status();
+    }
+    sys::reboot()
+}
@@ -0,0 +1,214 @@
+//! Kernel module loading from the build-time load lists.
+//!
+//! leviso resolves dependencies from `modules.dep` and ships decompressed
+//! modules with one load list per feature in `/lib/modules/<kver>/live/`
+//! (see `src/artifact/modules.rs`): `<set>.order` with module paths in load
+//! order, and `modalias` with `<alias pattern> <module path>...` lines for
+//! storage controllers.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::log::Log;
+use crate::sys;
+use crate::sysfs::Sysfs;
+
+#[derive(Debug)]
+pub struct Modules {
+    /// `/lib/modules/<kver>`
+    dir: PathBuf,
+    /// Storage driver lines from `live/modalias`.
+    modalias: Vec<(String, Vec<String>)>,
+    /// Device modaliases at the last `load_storage_drivers`.
+    matched: Vec<String>,
+}
+
+impl Modules {
+    /// Modules of the (only) kernel version under `root/lib/modules`.
+    pub fn find(root: &Path) -> Option<Self> {
+        let base = root.join("lib/modules");
+        let kver = fs::read_dir(&base).ok()?.flatten().next()?.file_name();
+        let dir = base.join(kver);
+        let modalias = fs::read_to_string(dir.join("live/modalias"))
+            .map(|content| parse_modalias(&content))
+            .unwrap_or_default();
+        Some(Self {
+            dir,
+            modalias,
+            matched: Vec::new(),
+        })
+    }
+
+    /// Load every module of a set: boot, verity, net or persist.
+    pub fn load_set(&self, set: &str, log: &Log) {
+        let Ok(order) = fs::read_to_string(self.dir.join(format!("live/{}.order", set))) else {
+            return;
+        };
+        for path in order.lines().filter(|l| !l.is_empty()) {
+            self.load(path, log);
+        }
+    }
+
+    /// Load the storage drivers whose alias patterns match a device modalias.
+    /// Cheap to call repeatedly: nothing happens unless devices changed
+    /// (loading a controller driver makes new devices appear).
+    pub fn load_storage_drivers(&mut self, sysfs: &Sysfs, log: &Log) {
+        let devices = sysfs.modaliases();
+        if devices == self.matched {
+            return;
+        }
+        for paths in matching_drivers(&self.modalias, &devices) {
+            for path in paths {
+                self.load(path, log);
+            }
+        }
+        self.matched = devices;
+    }
+
+    fn load(&self, path: &str, log: &Log) {
+        match sys::insmod(&self.dir.join(path)) {
+            Ok(()) => log.debug(&format!(
+                "  Loaded {}",
+                path.rsplit('/').next().unwrap_or(path)
+            )),
+            Err(e) => log.debug(&format!("  {}: {}", path, e)),
+        }
+    }
+}
+
+fn parse_modalias(content: &str) -> Vec<(String, Vec<String>)> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let pattern = fields.next()?.to_string();
+            Some((pattern, fields.map(str::to_string).collect()))
+        })
+        .collect()
+}
+
+/// Load orders of the drivers matching any of `devices`.
+fn matching_drivers<'a>(
+    modalias: &'a [(String, Vec<String>)],
+    devices: &[String],
+) -> Vec<&'a [String]> {
+    modalias
+        .iter()
+        .filter(|(pattern, _)| devices.iter().any(|dev| glob_match(pattern, dev)))
+        .map(|(_, paths)| paths.as_slice())
+        .collect()
+}
+
+/// fnmatch(3)-style matching as used by `modules.alias`: `*`, `?` and
+/// `[...]` classes.
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern = pattern.as_bytes();
+    let text = text.as_bytes();
+    let (mut p, mut t) = (0, 0);
+    // Position after the last `*` and the text position it matched up to
+    let mut backtrack = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some(b'*') => {
+                p += 1;
+                backtrack = Some((p, t));
+                continue;
+            }
+            Some(b'?') => {
+                p += 1;
+                t += 1;
+                continue;
+            }
+            Some(b'[') => {
+                if let Some((matched, next)) = match_class(&pattern[p..], text[t]) {
+                    if matched {
+                        p += next;
+                        t += 1;
+                        continue;
+                    }
+                } else if text[t] == b'[' {
+                    p += 1;
+                    t += 1;
+                    continue;
+                }
+            }
+            Some(&c) if c == text[t] => {
+                p += 1;
+                t += 1;
+                continue;
+            }
+            _ => {}
+        }
+        match backtrack {
+            Some((bp, bt)) => {
+                p = bp;
+                t = bt + 1;
+                backtrack = Some((bp, bt + 1));
+            }
+            None => return false,
+        }
+    }
+    pattern[p..].iter().all(|&c| c == b'*')
+}
+
+/// Match `c` against the class at the start of `pattern` (`[...]`). Returns
+/// whether it matched and the class length, or `None` if unterminated.
+fn match_class(pattern: &[u8], c: u8) -> Option<(bool, usize)> {
+    let mut i = 1;
+    let negate = matches!(pattern.get(i), Some(b'!' | b'^'));
+    if negate {
+        i += 1;
+    }
+    let mut matched = false;
+    let mut first = true;
+    while let Some(&start) = pattern.get(i) {
+        if start == b']' && !first {
+            return Some((matched != negate, i + 1));
+        }
+        first = false;
+        if pattern.get(i + 1) == Some(&b'-') && pattern.get(i + 2).is_some_and(|&e| e != b']') {
+            matched |= (start..=pattern[i + 2]).contains(&c);
+            i += 3;
+        } else {
+            matched |= start == c;
+            i += 1;
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glob_match() {
+        let ahci = "pci:v00008086d00002922sv00001AF4sd00001100bc01sc06i01";
+        assert!(glob_match("pci:v*d*sv*sd*bc01sc06i01*", ahci));
+        assert!(glob_match("pci:v00008086d00002922sv*sd*bc*sc*i*", ahci));
+        assert!(!glob_match("pci:v*d*sv*sd*bc01sc08i02*", ahci));
+        assert!(glob_match("scsi:t-0x0[05]*", "scsi:t-0x05"));
+        assert!(!glob_match("scsi:t-0x0[!05]*", "scsi:t-0x05"));
+        assert!(glob_match("usb:v0BC2p?00?d*", "usb:v0BC2p2003d0100"));
+        assert!(glob_match("*", ""));
+        assert!(!glob_match(
+            "virtio:d00000002v*",
+            "virtio:d00000001v00001AF4"
+        ));
+    }
+
+    #[test]
+    fn test_matching_drivers() {
+        let modalias = parse_modalias(
+            "pci:v*d*sv*sd*bc01sc06i01* kernel/drivers/ata/libata.ko kernel/drivers/ata/ahci.ko\n\
+             virtio:d00000002v* kernel/drivers/block/virtio_blk.ko\n",
+        );
+        let devices = vec![
+            "pci:v00008086d00002922sv00001AF4sd00001100bc01sc06i01".to_string(),
+            "virtio:d00000001v00001AF4".to_string(),
+        ];
+        let drivers = matching_drivers(&modalias, &devices);
+        assert_eq!(drivers.len(), 1);
+        assert_eq!(drivers[0][1], "kernel/drivers/ata/ahci.ko");
+    }
+}
@@ -0,0 +1,163 @@
+//! Network boot (`levitate.fetch=<url>`): DHCP on the first interface that
+//! gets a lease, then download the live media into a tmpfs on `/mnt` laid
+//! out like the ISO. DHCP, HTTP and tar stay busybox applets.
+//!
+//! The EROFS is checked by dm-verity; the overlay tarball, which is layered
+//! above it, must match `levitate.overlay_sha256=` before it is unpacked. A
+//! verity boot without that checksum gets no overlay rather than an
+//! unchecked one.
+
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::process::Command;
+use std::thread;
+use std::time::Duration;
+
+use crate::boot::{busybox, Boot};
+use crate::error::{BootError, Context, Stage};
+use crate::sys;
+
+const UDHCPC_SCRIPT: &str = "/usr/share/udhcpc/default.script";
+
+/// udhcpc only reports the lease; this script applies it.
+const UDHCPC_SCRIPT_CONTENT: &str = r#"#!/bin/busybox sh
+case "$1" in
+    bound|renew)
+        busybox ifconfig "$interface" "$ip" netmask "${subnet:-255.255.255.0}" up
+        for gw in $router; do
+            busybox route add default gw "$gw" dev "$interface"
+            break
+        done
+        ;;
+esac
+"#;
+
+pub fn fetch_live_media(boot: &mut Boot, url: &str) -> Result<(), BootError> {
+    boot.log.msg(&format!("Network boot from {}", url));
+    if let Some(modules) = &boot.modules {
+        modules.load_set("net", &boot.log);
+    }
+
+    let script = Path::new(UDHCPC_SCRIPT);
+    fs::create_dir_all(script.parent().unwrap())
+        .and_then(|()| fs::write(script, UDHCPC_SCRIPT_CONTENT))
+        .and_then(|()| fs::set_permissions(script, fs::Permissions::from_mode(0o755)))
+        .stage(Stage::Network, || {
+            "failed to write the udhcpc script".into()
+        })?;
+
+    let iface = dhcp(boot).ok_or_else(|| {
+        BootError::new(Stage::Network, "no DHCP lease on any interface")
+            .detail(format!("Interfaces: {:?}", boot.sysfs.net_interfaces()))
+    })?;
+    boot.log.msg(&format!("Network up on {}", iface));
+
+    // The live media lives in RAM, so this tmpfs must hold the whole EROFS
+    sys::mount("tmpfs", "/mnt", Some("tmpfs"), 0, Some("size=90%"))
+        .stage(Stage::Network, || {
+            "failed to create RAM disk for live media".into()
+        })?;
+    let rootfs = boot.config.rootfs_path.clone();
+    fetch(boot, url, &rootfs)?;
+    if boot.cmdline.verity.is_some() {
+        fetch(boot, url, &format!("{}.verity", rootfs))?;
+    }
+
+    let overlay = boot.config.live_overlay_path.clone();
+    let tarball = format!("{}.tar.gz", overlay);
+    let expected = boot.cmdline.overlay_sha256.clone();
+    if expected.is_none() && boot.cmdline.verity.is_some() {
+        return Err(BootError::new(
+            Stage::Network,
+            "levitate.verity= is set but levitate.overlay_sha256= is not",
+        )
+        .detail("The live overlay would be layered unchecked above the verified EROFS."));
+    }
+    if fetch(boot, url, &tarball).is_ok() {
+        let local = format!("/mnt{}", tarball);
+        if let Some(expected) = &expected {
+            let actual = sha256sum(&local).unwrap_or_default();
+            if actual != *expected {
+                let _ = fs::remove_file(&local);
+                return Err(BootError::new(
+                    Stage::Network,
+                    format!("checksum mismatch for {}{}", url, tarball),
+                )
+                .detail(format!("expected {}, got {}", expected, actual)));
+            }
+        }
+        let dest = format!("/mnt{}", overlay);
+        fs::create_dir_all(&dest).stage(Stage::Network, || format!("failed to create {}", dest))?;
+        if !busybox(&["tar", "-xzf", &local, "-C", &dest]) {
+            return Err(BootError::new(
+                Stage::Network,
+                "failed to unpack live overlay",
+            ));
+        }
+    } else if expected.is_some() {
+        return Err(BootError::new(
+            Stage::Network,
+            format!("failed to download {}{}", url, tarball),
+        ));
+    } else {
+        boot.log
+            .msg(&format!("No live overlay at {}{}", url, tarball));
+    }
+    let _ = fs::remove_file(format!("/mnt{}", tarball));
+    Ok(())
+}
+
+/// SHA-256 of a file, from `busybox sha256sum`.
+fn sha256sum(path: &str) -> Option<String> {
+    let output = Command::new("/bin/busybox")
+        .args(["sha256sum", path])
+        .output()
+        .ok()?;
+    let stdout = String::from_utf8(output.stdout).ok()?;
+    stdout.split_whitespace().next().map(str::to_string)
+}
+
+/// Bring up the first interface that gets a lease (10 rounds over all of
+/// them, NIC drivers may still be probing).
+fn dhcp(boot: &Boot) -> Option<String> {
+    for attempt in 1..=10 {
+        for iface in boot.sysfs.net_interfaces() {
+            busybox(&["ifconfig", &iface, "up"]);
+            if busybox(&[
+                "udhcpc",
+                "-i",
+                &iface,
+                "-n",
+                "-q",
+                "-t",
+                "3",
+                "-s",
+                UDHCPC_SCRIPT,
+            ]) {
+                return Some(iface);
+            }
+        }
+        boot.log
+            .debug(&format!("No DHCP lease yet (attempt {})", attempt));
+        thread::sleep(Duration::from_secs(1));
+    }
+    None
+}
+
+/// Download `<url><path>` to `/mnt<path>`.
+fn fetch(boot: &Boot, url: &str, path: &str) -> Result<(), BootError> {
+    let dest = format!("/mnt{}", path);
+    if let Some(parent) = Path::new(&dest).parent() {
+        let _ = fs::create_dir_all(parent);
+    }
+    boot.log.msg(&format!("  GET {}{}", url, path));
+    if busybox(&["wget", "-q", "-O", &dest, &format!("{}{}", url, path)]) {
+        Ok(())
+    } else {
+        Err(BootError::new(
+            Stage::Network,
+            format!("failed to download {}{}", url, path),
+        ))
+    }
+}
@@ -0,0 +1,165 @@
+//! Filesystem type, label and UUID from the superblock, for the filesystems
+//! the live media and persistence can be on (replaces `busybox blkid`).
+
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+/// Bytes needed to probe every supported filesystem (ISO 9660's primary
+/// volume descriptor is at 32 KiB).
+const PROBE_SIZE: usize = 0x8800;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Filesystem {
+    /// Type to pass to mount(2).
+    pub fstype: &'static str,
+    pub label: String,
+    pub uuid: Option<String>,
+}
+
+/// Probe the device (or image file) at `path`. `Ok(None)` means readable but
+/// not a filesystem we know; an error usually means no media yet.
+pub fn probe_device(path: &Path) -> io::Result<Option<Filesystem>> {
+    let mut buf = Vec::with_capacity(PROBE_SIZE);
+    File::open(path)?
+        .take(PROBE_SIZE as u64)
+        .read_to_end(&mut buf)?;
+    Ok(probe(&buf))
+}
+
+pub fn probe(buf: &[u8]) -> Option<Filesystem> {
+    iso9660(buf).or_else(|| ext4(buf)).or_else(|| vfat(buf))
+}
+
+fn iso9660(buf: &[u8]) -> Option<Filesystem> {
+    let pvd = buf.get(0x8000..0x8800)?;
+    if pvd[0] != 1 || &pvd[1..6] != b"CD001" {
+        return None;
+    }
+    Some(Filesystem {
+        fstype: "iso9660",
+        label: text(&pvd[40..72]),
+        uuid: None,
+    })
+}
+
+fn ext4(buf: &[u8]) -> Option<Filesystem> {
+    let sb = buf.get(0x400..0x800)?;
+    if sb[0x38..0x3A] != [0x53, 0xEF] {
+        return None;
+    }
+    Some(Filesystem {
+        fstype: "ext4",
+        label: text(&sb[0x78..0x88]),
+        uuid: Some(uuid(&sb[0x68..0x78])),
+    })
+}
+
+fn vfat(buf: &[u8]) -> Option<Filesystem> {
+    let boot = buf.get(0..0x200)?;
+    if boot[0x1FE..0x200] != [0x55, 0xAA] {
+        return None;
+    }
+    // FAT32 and FAT12/16 keep the extended BPB at different offsets
+    let (serial, label) = if &boot[0x52..0x57] == b"FAT32" {
+        (&boot[0x43..0x47], &boot[0x47..0x52])
+    } else if &boot[0x36..0x39] == b"FAT" {
+        (&boot[0x27..0x2B], &boot[0x2B..0x36])
+    } else {
+        return None;
+    };
+    let label = text(label);
+    Some(Filesystem {
+        fstype: "vfat",
+        label: if label == "NO NAME" {
+            String::new()
+        } else {
+            label
+        },
+        uuid: Some(format!(
+            "{:02X}{:02X}-{:02X}{:02X}",
+            serial[3], serial[2], serial[1], serial[0]
+        )),
+    })
+}
+
+/// Label bytes without NUL/space padding.
+fn text(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes)
+        .trim_end_matches(['\0', ' '])
+        .to_string()
+}
+
+fn uuid(bytes: &[u8]) -> String {
+    let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    )
+}
+
+/// Build images for tests elsewhere in the crate.
+#[cfg(test)]
+pub mod fake {
+    use super::PROBE_SIZE;
+
+    pub fn iso9660(label: &str) -> Vec<u8> {
+        let mut buf = vec![0u8; PROBE_SIZE];
+        buf[0x8000] = 1;
+        buf[0x8001..0x8006].copy_from_slice(b"CD001");
+        buf[0x8028..0x8048].fill(b' ');
+        buf[0x8028..0x8028 + label.len()].copy_from_slice(label.as_bytes());
+        buf
+    }
+
+    pub fn ext4(label: &str, uuid: [u8; 16]) -> Vec<u8> {
+        let mut buf = vec![0u8; PROBE_SIZE];
+        buf[0x438..0x43A].copy_from_slice(&[0x53, 0xEF]);
+        buf[0x468..0x478].copy_from_slice(&uuid);
+        buf[0x478..0x478 + label.len()].copy_from_slice(label.as_bytes());
+        buf
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_probe_iso9660_and_ext4() {
+        let iso = probe(&fake::iso9660("LEVITATEOS")).unwrap();
+        assert_eq!(iso.fstype, "iso9660");
+        assert_eq!(iso.label, "LEVITATEOS");
+
+        let mut uuid = [0u8; 16];
+        uuid[0] = 0xde;
+        uuid[15] = 0x01;
+        let ext4 = probe(&fake::ext4("LEVITATE_PERSIST", uuid)).unwrap();
+        assert_eq!(ext4.fstype, "ext4");
+        assert_eq!(ext4.label, "LEVITATE_PERSIST");
+        assert_eq!(
+            ext4.uuid.as_deref(),
+            Some("de000000-0000-0000-0000-000000000001")
+        );
+
+        assert_eq!(probe(&[0u8; PROBE_SIZE]), None);
+        assert_eq!(probe(&[0u8; 16]), None);
+    }
+
+    #[test]
+    fn test_probe_vfat() {
+        let mut boot = vec![0u8; 0x200];
+        boot[0x1FE..0x200].copy_from_slice(&[0x55, 0xAA]);
+        boot[0x52..0x5A].copy_from_slice(b"FAT32   ");
+        boot[0x43..0x47].copy_from_slice(&[0x78, 0x56, 0x34, 0x12]);
+        boot[0x47..0x52].copy_from_slice(b"LEVITATEOS ");
+        let fat = probe(&boot).unwrap();
+        assert_eq!(fat.fstype, "vfat");
+        assert_eq!(fat.label, "LEVITATEOS");
+        assert_eq!(fat.uuid.as_deref(), Some("1234-5678"));
+    }
+}
@@ -0,0 +1,181 @@
+//! Thin wrappers around the syscalls busybox used to provide: mount, loop
+//! devices, module loading, switch_root.
+
+use std::ffi::CString;
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{MetadataExt, OpenOptionsExt};
+use std::os::unix::io::AsRawFd;
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+pub use libc::{MS_BIND, MS_MOVE, MS_NODEV, MS_NOSUID, MS_RDONLY, MS_REMOUNT};
+
+const LOOP_SET_FD: libc::c_ulong = 0x4C00;
+const LOOP_CTL_GET_FREE: libc::c_ulong = 0x4C82;
+
+fn cstr(path: &Path) -> io::Result<CString> {
+    CString::new(path.as_os_str().as_bytes())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+}
+
+fn check(ret: libc::c_int) -> io::Result<()> {
+    if ret < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+/// mount(2). `source` may be a device, a path or a name like `tmpfs`.
+pub fn mount(
+    source: impl AsRef<Path>,
+    target: impl AsRef<Path>,
+    fstype: Option<&str>,
+    flags: libc::c_ulong,
+    data: Option<&str>,
+) -> io::Result<()> {
+    let source = cstr(source.as_ref())?;
+    let target = cstr(target.as_ref())?;
+    let fstype = fstype.map(|t| CString::new(t).unwrap());
+    let data = data.map(|d| CString::new(d).unwrap());
+    // SAFETY: all pointers are valid NUL-terminated strings or null
+    check(unsafe {
+        libc::mount(
+            source.as_ptr(),
+            target.as_ptr(),
+            fstype.as_ref().map_or(std::ptr::null(), |t| t.as_ptr()),
+            flags,
+            data.as_ref()
+                .map_or(std::ptr::null(), |d| d.as_ptr().cast()),
+        )
+    })
+}
+
+/// `mount --move <from> <to>`
+pub fn mount_move(from: impl AsRef<Path>, to: impl AsRef<Path>) -> io::Result<()> {
+    mount(from, to, None, MS_MOVE, None)
+}
+
+pub fn umount(target: impl AsRef<Path>) -> io::Result<()> {
+    let target = cstr(target.as_ref())?;
+    // SAFETY: valid NUL-terminated path
+    check(unsafe { libc::umount(target.as_ptr()) })
+}
+
+/// Attach `file` to a free loop device and return the device path. The loop
+/// device is read-only unless `writable`.
+pub fn losetup(file: &Path, writable: bool) -> io::Result<PathBuf> {
+    let backing = OpenOptions::new().read(true).write(writable).open(file)?;
+    let control = File::open("/dev/loop-control")?;
+    // SAFETY: LOOP_CTL_GET_FREE takes no argument
+    let number = unsafe { libc::ioctl(control.as_raw_fd(), LOOP_CTL_GET_FREE as _) };
+    if number < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let device = PathBuf::from(format!("/dev/loop{}", number));
+    if !device.exists() {
+        mknod_block(&device, 7, number as u32)?;
+    }
+    let dev = OpenOptions::new()
+        .read(true)
+        .write(writable)
+        .open(&device)?;
+    // SAFETY: LOOP_SET_FD takes the backing file descriptor
+    check(unsafe { libc::ioctl(dev.as_raw_fd(), LOOP_SET_FD as _, backing.as_raw_fd()) })?;
+    Ok(device)
+}
+
+pub fn mknod_block(path: &Path, major: u32, minor: u32) -> io::Result<()> {
+    let path = cstr(path)?;
+    // SAFETY: valid NUL-terminated path
+    check(unsafe {
+        libc::mknod(
+            path.as_ptr(),
+            libc::S_IFBLK | 0o600,
+            libc::makedev(major, minor),
+        )
+    })
+}
+
+/// Load an (uncompressed) kernel module. Already loaded counts as success.
+pub fn insmod(path: &Path) -> io::Result<()> {
+    let module = OpenOptions::new()
+        .read(true)
+        .custom_flags(libc::O_CLOEXEC)
+        .open(path)?;
+    let params = CString::default();
+    // SAFETY: finit_module(fd, params, flags) with a valid fd and empty params
+    let ret = unsafe {
+        libc::syscall(
+            libc::SYS_finit_module,
+            module.as_raw_fd(),
+            params.as_ptr(),
+            0,
+        )
+    };
+    match ret {
+        0 => Ok(()),
+        _ => match io::Error::last_os_error() {
+            e if e.raw_os_error() == Some(libc::EEXIST) => Ok(()),
+            e => Err(e),
+        },
+    }
+}
+
+/// Free the initramfs (everything on the root filesystem, not crossing into
+/// other mounts), make `new_root` the root and exec `init` as PID 1. Only
+/// returns on failure.
+pub fn switch_root(new_root: &Path, init: &str) -> io::Error {
+    let result = (|| {
+        let root_dev = fs::symlink_metadata("/")?.dev();
+        delete_tree(Path::new("/"), root_dev);
+        std::env::set_current_dir(new_root)?;
+        mount(".", "/", None, MS_MOVE, None)?;
+        let dot = CString::new(".").unwrap();
+        // SAFETY: valid NUL-terminated path
+        check(unsafe { libc::chroot(dot.as_ptr()) })?;
+        std::env::set_current_dir("/")
+    })();
+    match result {
+        Ok(()) => Command::new(init).exec(),
+        Err(e) => e,
+    }
+}
+
+/// Remove everything below `dir` that lives on device `dev`.
+fn delete_tree(dir: &Path, dev: u64) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let Ok(meta) = fs::symlink_metadata(&path) else {
+            continue;
+        };
+        if meta.dev() != dev {
+            continue;
+        }
+        if meta.is_dir() {
+            delete_tree(&path, dev);
+            let _ = fs::remove_dir(&path);
+        } else {
+            let _ = fs::remove_file(&path);
+        }
+    }
+}
+
+/// Sync and reboot immediately (`rd.shell=0`, or after the emergency shell).
+pub fn reboot() -> ! {
+    // SAFETY: plain syscalls without pointers
+    unsafe {
+        libc::sync();
+        libc::reboot(libc::RB_AUTOBOOT);
+    }
+    // reboot(2) only returns on failure; PID 1 must not exit
+    loop {
+        std::thread::sleep(std::time::Duration::from_secs(60));
+    }
+}
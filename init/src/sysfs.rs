@@ -0,0 +1,221 @@
+//! Block devices, device modaliases and network interfaces from sysfs.
+//!
+//! Everything is relative to a root directory (`/` at boot) so the discovery
+//! logic can be tested against a fake sysfs and fake device images.
+
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::probe::{self, Filesystem};
+
+/// Block devices that never hold the live media.
+const IGNORED_BLOCK_PREFIXES: &[&str] = &["loop", "ram", "dm-", "zram", "md"];
+
+#[derive(Debug, Clone)]
+pub struct Sysfs {
+    root: PathBuf,
+}
+
+impl Sysfs {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    /// `/dev/<name>` under the root.
+    pub fn dev_path(&self, name: &str) -> PathBuf {
+        self.root.join("dev").join(name)
+    }
+
+    /// Disks and partitions (including NVMe, MMC, USB and CD drives), sorted.
+    pub fn block_devices(&self) -> Vec<String> {
+        entries(&self.root.join("sys/class/block"))
+            .into_iter()
+            .filter(|name| !IGNORED_BLOCK_PREFIXES.iter().any(|p| name.starts_with(p)))
+            .collect()
+    }
+
+    /// Modaliases of all devices on all buses, sorted and deduplicated.
+    pub fn modaliases(&self) -> Vec<String> {
+        let bus = self.root.join("sys/bus");
+        let mut aliases = BTreeSet::new();
+        for bus_name in entries(&bus) {
+            let devices = bus.join(&bus_name).join("devices");
+            for device in entries(&devices) {
+                if let Ok(alias) = fs::read_to_string(devices.join(device).join("modalias")) {
+                    let alias = alias.trim();
+                    if !alias.is_empty() {
+                        aliases.insert(alias.to_string());
+                    }
+                }
+            }
+        }
+        aliases.into_iter().collect()
+    }
+
+    /// Network interfaces except loopback.
+    pub fn net_interfaces(&self) -> Vec<String> {
+        entries(&self.root.join("sys/class/net"))
+            .into_iter()
+            .filter(|name| name != "lo")
+            .collect()
+    }
+
+    /// Disk a block device belongs to: the parent of a partition
+    /// (`/sys/class/block/sdb3` links to `.../block/sdb/sdb3`), or the
+    /// device itself.
+    pub fn disk_of(&self, name: &str) -> Option<String> {
+        let class = self.root.join("sys/class/block").join(name);
+        if !class.join("partition").exists() {
+            return class.exists().then(|| name.to_string());
+        }
+        let device = fs::canonicalize(&class).ok()?;
+        Some(device.parent()?.file_name()?.to_string_lossy().to_string())
+    }
+
+    /// Probe every block device, for diagnostics and persistence lookup.
+    pub fn probe_all(&self) -> Vec<(PathBuf, Filesystem)> {
+        self.block_devices()
+            .into_iter()
+            .map(|name| self.dev_path(&name))
+            .filter_map(|dev| match probe::probe_device(&dev) {
+                Ok(Some(fs)) => Some((dev, fs)),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+/// Sorted names in `dir` (empty if it does not exist).
+fn entries(dir: &Path) -> Vec<String> {
+    let mut names: Vec<String> = fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .flatten()
+                .map(|e| e.file_name().to_string_lossy().to_string())
+                .collect()
+        })
+        .unwrap_or_default();
+    names.sort();
+    names
+}
+
+/// Finds block devices labeled like the live media as they appear.
+///
+/// A device only counts as seen once it could be probed, so a CD drive or
+/// card reader whose media is not ready yet is checked again next scan.
+#[derive(Debug, Default)]
+pub struct DeviceScanner {
+    seen: BTreeSet<String>,
+}
+
+impl DeviceScanner {
+    /// New devices since the last scan whose label is `label`.
+    pub fn scan(&mut self, sysfs: &Sysfs, label: &str) -> Vec<(PathBuf, Filesystem)> {
+        let mut found = Vec::new();
+        for name in sysfs.block_devices() {
+            if self.seen.contains(&name) {
+                continue;
+            }
+            let dev = sysfs.dev_path(&name);
+            let fs = match probe::probe_device(&dev) {
+                Ok(fs) => fs,
+                Err(_) => continue,
+            };
+            self.seen.insert(name);
+            if let Some(fs) = fs.filter(|fs| fs.label == label) {
+                found.push((dev, fs));
+            }
+        }
+        found
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::probe::fake;
+
+    /// Fake root with `/sys/class/block/<name>` and `/dev/<name>` holding
+    /// `image` (or nothing, like a drive without media).
+    fn add_block_device(root: &Path, name: &str, image: Option<&[u8]>) {
+        fs::create_dir_all(root.join("sys/class/block").join(name)).unwrap();
+        fs::create_dir_all(root.join("dev")).unwrap();
+        if let Some(image) = image {
+            fs::write(root.join("dev").join(name), image).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_scanner_finds_media_once_ready() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let root = temp.path();
+        let sysfs = Sysfs::new(root);
+        add_block_device(root, "loop0", Some(&fake::iso9660("LEVITATEOS")));
+        add_block_device(root, "sda", Some(&fake::ext4("DATA", [0; 16])));
+        add_block_device(root, "sr0", None);
+
+        let mut scanner = DeviceScanner::default();
+        assert!(scanner.scan(&sysfs, "LEVITATEOS").is_empty());
+
+        // Media inserted: sr0 was not marked seen, so it is probed again
+        fs::write(root.join("dev/sr0"), fake::iso9660("LEVITATEOS")).unwrap();
+        let found = scanner.scan(&sysfs, "LEVITATEOS");
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].0, root.join("dev/sr0"));
+        assert_eq!(found[0].1.fstype, "iso9660");
+        assert!(scanner.scan(&sysfs, "LEVITATEOS").is_empty());
+    }
+
+    #[test]
+    fn test_disk_of_partition() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let root = temp.path();
+        let devices = root.join("sys/devices/usb1/block");
+        fs::create_dir_all(devices.join("sdb/sdb3")).unwrap();
+        fs::write(devices.join("sdb/sdb3/partition"), "3\n").unwrap();
+        fs::create_dir_all(root.join("sys/class/block")).unwrap();
+        for (name, target) in [("sdb", "sdb"), ("sdb3", "sdb/sdb3")] {
+            std::os::unix::fs::symlink(
+                devices.join(target),
+                root.join("sys/class/block").join(name),
+            )
+            .unwrap();
+        }
+
+        let sysfs = Sysfs::new(root);
+        assert_eq!(sysfs.disk_of("sdb3").as_deref(), Some("sdb"));
+        assert_eq!(sysfs.disk_of("sdb").as_deref(), Some("sdb"));
+        assert_eq!(sysfs.disk_of("sdc1"), None);
+    }
+
+    #[test]
+    fn test_modaliases_and_interfaces() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let root = temp.path();
+        for (bus, dev, alias) in [
+            (
+                "pci",
+                "0000:00:1f.2",
+                "pci:v00008086d00002922sv00001AF4sd00001100bc01sc06i01\n",
+            ),
+            (
+                "pci",
+                "0000:00:1f.3",
+                "pci:v00008086d00002922sv00001AF4sd00001100bc01sc06i01\n",
+            ),
+            ("virtio", "virtio0", "virtio:d00000001v00001AF4\n"),
+        ] {
+            let dir = root.join("sys/bus").join(bus).join("devices").join(dev);
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.join("modalias"), alias).unwrap();
+        }
+        for iface in ["lo", "eth0"] {
+            fs::create_dir_all(root.join("sys/class/net").join(iface)).unwrap();
+        }
+
+        let sysfs = Sysfs::new(root);
+        assert_eq!(sysfs.modaliases().len(), 2);
+        assert_eq!(sysfs.net_interfaces(), ["eth0"]);
+    }
+}
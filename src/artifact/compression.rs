@@ -0,0 +1,323 @@
+//! Initramfs compression: gzip, zstd, xz or lz4, selected per artifact.
+//!
+//! recinit and the appended segments always write gzip. For another format
+//! the finished image (every segment) is decompressed into one cpio stream
+//! and recompressed; the kernel unpacks concatenated cpio archives inside a
+//! single compressed stream. This happens before the early microcode cpio is
+//! prepended, which must stay uncompressed.
+//!
+//! ```text
+//! LEVISO_LIVE_INITRAMFS_COMPRESSION=zstd      # live initramfs
+//! LEVISO_INSTALL_INITRAMFS_COMPRESSION=zstd   # install initramfs
+//! ```
+//!
+//! The file names keep their suffix; the kernel detects the format by magic.
+//! Each format must be enabled in the kernel config (`CONFIG_RD_ZSTD` etc.).
+
+use anyhow::{bail, Context, Result};
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use super::cpio;
+use distro_builder::process::shell_in;
+
+/// Compression of the live initramfs.
+pub const LIVE_COMPRESSION_ENV: &str = "LEVISO_LIVE_INITRAMFS_COMPRESSION";
+
+/// Compression of the install initramfs.
+pub const INSTALL_COMPRESSION_ENV: &str = "LEVISO_INSTALL_INITRAMFS_COMPRESSION";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    Gzip,
+    Zstd,
+    Xz,
+    Lz4,
+}
+
+impl Compression {
+    pub const ALL: [Compression; 4] = [Self::Gzip, Self::Zstd, Self::Xz, Self::Lz4];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Gzip => "gzip",
+            Self::Zstd => "zstd",
+            Self::Xz => "xz",
+            Self::Lz4 => "lz4",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|c| c.name() == name)
+    }
+
+    /// Compression selected by `var`; gzip if unset.
+    pub fn from_env(var: &str) -> Result<Self> {
+        match env::var(var).as_deref() {
+            Ok(name) if !name.is_empty() => Self::parse(name)
+                .with_context(|| format!("{} must be gzip, zstd, xz or lz4, got '{}'", var, name)),
+            _ => Ok(Self::Gzip),
+        }
+    }
+
+    /// Kernel option needed to unpack this format.
+    pub fn kconfig_symbol(self) -> &'static str {
+        match self {
+            Self::Gzip => "CONFIG_RD_GZIP",
+            Self::Zstd => "CONFIG_RD_ZSTD",
+            Self::Xz => "CONFIG_RD_XZ",
+            Self::Lz4 => "CONFIG_RD_LZ4",
+        }
+    }
+
+    /// Compressor writing stdin to stdout in a format the kernel accepts
+    /// (xz: CRC32 check and a small dictionary; lz4: legacy frames).
+    pub fn compress_command(self) -> &'static str {
+        match self {
+            Self::Gzip => "gzip -9 -n",
+            Self::Zstd => "zstd -19 -q",
+            Self::Xz => "xz --check=crc32 --lzma2=dict=1MiB -9",
+            Self::Lz4 => "lz4 -l -9 -q",
+        }
+    }
+
+    /// Decompressor writing stdin to stdout.
+    pub fn decompress_command(self) -> &'static str {
+        match self {
+            Self::Gzip => "gzip -dc",
+            Self::Zstd => "zstd -dc -q",
+            Self::Xz => "xz -dc",
+            Self::Lz4 => "lz4 -dc -q",
+        }
+    }
+
+    /// Format of compressed data, by magic.
+    pub fn detect(data: &[u8]) -> Option<Self> {
+        const MAGICS: &[(&[u8], Compression)] = &[
+            (b"\x1f\x8b", Compression::Gzip),
+            (b"\x28\xb5\x2f\xfd", Compression::Zstd),
+            (b"\xfd7zXZ\x00", Compression::Xz),
+            (b"\x02\x21\x4c\x18", Compression::Lz4),
+        ];
+        MAGICS
+            .iter()
+            .find(|(magic, _)| data.starts_with(magic))
+            .map(|&(_, c)| c)
+    }
+}
+
+impl fmt::Display for Compression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Kernel config the initramfs is built for: the custom kernel build's
+/// `.config`, else the checked-in `kconfig`.
+pub fn kernel_config_path(base_dir: &Path) -> Option<PathBuf> {
+    let output_dir = distro_builder::artifact_store::central_output_dir_for_distro(base_dir);
+    [
+        output_dir.join("kernel-build/.config"),
+        base_dir.join("kconfig"),
+    ]
+    .into_iter()
+    .find(|p| p.exists())
+}
+
+/// Whether `config` (kernel `.config` syntax) enables `compression`.
+pub fn kernel_supports(config: &str, compression: Compression) -> bool {
+    let enabled = format!("{}=y", compression.kconfig_symbol());
+    config.lines().any(|line| line.trim() == enabled)
+}
+
+/// Fail if the kernel config cannot unpack `compression`.
+pub fn check_kernel_support(base_dir: &Path, compression: Compression) -> Result<()> {
+    if compression == Compression::Gzip {
+        return Ok(());
+    }
+    let Some(path) = kernel_config_path(base_dir) else {
+        bail!(
+            "{} initramfs requested, but no kernel config found to check {}",
+            compression,
+            compression.kconfig_symbol()
+        );
+    };
+    let config = fs::read_to_string(&path)?;
+    if !kernel_supports(&config, compression) {
+        bail!(
+            "{} initramfs requested, but {} is not enabled in {}.\n\
+             The kernel could not unpack it.",
+            compression,
+            compression.kconfig_symbol(),
+            path.display()
+        );
+    }
+    Ok(())
+}
+
+/// Recompress an initramfs `image` (without early cpio) in place.
+pub fn recompress(image: &Path, compression: Compression) -> Result<()> {
+    let mut magic = [0u8; 6];
+    fs::File::open(image)?
+        .read_exact(&mut magic)
+        .with_context(|| format!("{} is truncated", image.display()))?;
+    let Some(current) = Compression::detect(&magic) else {
+        bail!("{}: unknown initramfs compression", image.display());
+    };
+    if current == compression {
+        return Ok(());
+    }
+
+    let tmp = image.with_extension("recompress.tmp");
+    shell_in(
+        &format!(
+            "{} < '{}' | {} > '{}'",
+            current.decompress_command(),
+            image.display(),
+            compression.compress_command(),
+            tmp.display()
+        ),
+        image.parent().unwrap_or(Path::new(".")),
+    )
+    .with_context(|| {
+        format!(
+            "Failed to recompress {} with {}",
+            image.display(),
+            compression
+        )
+    })?;
+    fs::rename(&tmp, image)?;
+    println!(
+        "  Recompressed {} with {} ({} KiB)",
+        image.file_name().unwrap_or_default().to_string_lossy(),
+        compression,
+        fs::metadata(image)?.len() / 1024
+    );
+    Ok(())
+}
+
+/// Whether `image` is plain gzip without an early cpio, the form recinit
+/// writes (and fsdbg's `CpioReader` reads from offset 0).
+pub fn is_plain_gzip(image: &Path) -> Result<bool> {
+    let mut head = [0u8; 6];
+    fs::File::open(image)?
+        .read_exact(&mut head)
+        .with_context(|| format!("{} is truncated", image.display()))?;
+    Ok(Compression::detect(&head) == Some(Compression::Gzip))
+}
+
+/// Write the cpio archives of a finished `image` to `dest` as recinit would:
+/// without the early cpio and gzip-compressed, whatever the image uses.
+pub fn write_gzip_payload(image: &Path, dest: &Path) -> Result<()> {
+    let data = fs::read(image).with_context(|| format!("Failed to read {}", image.display()))?;
+    let (_, payload) = cpio::split_archive(&data);
+    let Some(compression) = Compression::detect(payload) else {
+        bail!("{}: unknown initramfs compression", image.display());
+    };
+    let raw = dest.with_extension("payload.tmp");
+    fs::write(&raw, payload)?;
+    let result = shell_in(
+        &format!(
+            "{} < '{}' | gzip -c > '{}'",
+            compression.decompress_command(),
+            raw.display(),
+            dest.display()
+        ),
+        dest.parent().unwrap_or(Path::new(".")),
+    )
+    .with_context(|| format!("Failed to decompress {}", image.display()));
+    let _ = fs::remove_file(&raw);
+    result
+}
+
+/// Decompress the payload of `image` (everything after the early cpio) in
+/// `work_dir`; returns the early cpio and the decompressed cpio stream.
+pub fn decompress_initramfs(image: &Path, work_dir: &Path) -> Result<(Vec<u8>, Vec<u8>)> {
+    let data = fs::read(image).with_context(|| format!("Failed to read {}", image.display()))?;
+    let (early, payload) = cpio::split_archive(&data);
+    if payload.is_empty() {
+        return Ok((early.to_vec(), Vec::new()));
+    }
+    let Some(compression) = Compression::detect(payload) else {
+        bail!("{}: unknown initramfs compression", image.display());
+    };
+
+    let _ = fs::remove_dir_all(work_dir);
+    fs::create_dir_all(work_dir)?;
+    fs::write(work_dir.join("payload"), payload)?;
+    let result = shell_in(
+        &format!("{} < payload > stream", compression.decompress_command()),
+        work_dir,
+    )
+    .with_context(|| format!("Failed to decompress {}", image.display()));
+    let stream = fs::read(work_dir.join("stream"));
+    let _ = fs::remove_dir_all(work_dir);
+    result?;
+    Ok((early.to_vec(), stream?))
+}
+
+/// Extract an initramfs (early cpio, then a compressed stream of one or more
+/// cpio archives) into `dest`, later archives overwriting earlier files like
+/// the kernel does. Device nodes need root and are skipped.
+pub fn extract_initramfs(image: &Path, dest: &Path) -> Result<()> {
+    let (early, stream) = decompress_initramfs(image, &dest.with_extension("decompress"))?;
+    let mut archives = vec![early.as_slice()];
+    archives.extend(cpio::split_archives(&stream)?);
+
+    let parts = dest.with_extension("parts");
+    let _ = fs::remove_dir_all(&parts);
+    fs::create_dir_all(&parts)?;
+    fs::create_dir_all(dest)?;
+    for (i, archive) in archives.iter().enumerate().filter(|(_, a)| !a.is_empty()) {
+        let file = parts.join(format!("{}.cpio", i));
+        fs::write(&file, archive)?;
+        // cpio fails on device nodes as non-root; the other files are extracted
+        let _ = shell_in(
+            &format!(
+                "cpio -idu --quiet --no-absolute-filenames < '{}'",
+                file.display()
+            ),
+            dest,
+        );
+    }
+    let _ = fs::remove_dir_all(&parts);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_detect() {
+        for c in Compression::ALL {
+            assert_eq!(Compression::parse(c.name()), Some(c));
+        }
+        assert_eq!(Compression::parse("bzip2"), None);
+        assert_eq!(
+            Compression::detect(b"\x28\xb5\x2f\xfd\x04\x58"),
+            Some(Compression::Zstd)
+        );
+        assert_eq!(
+            Compression::detect(b"\x02\x21\x4c\x18\x00"),
+            Some(Compression::Lz4)
+        );
+        assert_eq!(Compression::detect(b"070701"), None);
+    }
+
+    #[test]
+    fn test_kernel_supports() {
+        let config = "# === INITRAMFS COMPRESSION ===\n\
+                      CONFIG_RD_GZIP=y\n\
+                      CONFIG_RD_XZ=y\n\
+                      # CONFIG_RD_ZSTD is not set\n\
+                      CONFIG_RD_LZ4=m\n";
+        assert!(kernel_supports(config, Compression::Gzip));
+        assert!(kernel_supports(config, Compression::Xz));
+        assert!(!kernel_supports(config, Compression::Zstd));
+        assert!(!kernel_supports(config, Compression::Lz4));
+    }
+}
@@ -0,0 +1,213 @@
+//! newc cpio archives, read the way the kernel unpacks an initramfs.
+//!
+//! An image is an uncompressed early cpio (microcode) followed by one
+//! compressed stream of concatenated newc archives; entries of later
+//! archives replace earlier ones. This is the only newc reader in leviso:
+//! `microcode` and `compression` split images with it, `leviso diff` reads
+//! their entries.
+
+use anyhow::{bail, Context, Result};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use super::compression;
+
+/// Length of a newc header.
+const HEADER_LEN: usize = 110;
+
+/// Name of the entry ending an archive.
+const TRAILER: &[u8] = b"TRAILER!!!";
+
+/// An initramfs entry as the kernel leaves it after unpacking.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CpioEntry {
+    /// File type and permission bits (`st_mode`)
+    pub mode: u32,
+    /// File contents, or the target of a symlink
+    pub data: Vec<u8>,
+}
+
+impl CpioEntry {
+    pub fn is_file(&self) -> bool {
+        self.mode & 0o170000 == 0o100000
+    }
+
+    pub fn is_symlink(&self) -> bool {
+        self.mode & 0o170000 == 0o120000
+    }
+}
+
+/// One entry of an archive, borrowed from it.
+struct RawEntry<'a> {
+    name: &'a [u8],
+    mode: u32,
+    data: &'a [u8],
+}
+
+/// Parse the archive at the start of `data`: its entries (without the
+/// trailer) and its length, including the zero padding after the trailer.
+fn parse_archive(data: &[u8]) -> Result<(Vec<RawEntry<'_>>, usize)> {
+    let align = |n: usize| n.next_multiple_of(4);
+    let mut entries = Vec::new();
+    let mut offset = 0;
+    loop {
+        let header = &data[offset..];
+        if header.len() < HEADER_LEN
+            || !(header.starts_with(b"070701") || header.starts_with(b"070702"))
+        {
+            bail!("not a newc cpio archive");
+        }
+        // Fields after the magic: ino, mode, uid, gid, nlink, mtime, filesize,
+        // devmajor, devminor, rdevmajor, rdevminor, namesize, check
+        let field = |i: usize| -> Result<usize> {
+            let hex = std::str::from_utf8(&header[6 + i * 8..14 + i * 8])?;
+            Ok(usize::from_str_radix(hex, 16)?)
+        };
+        let (mode, file_size, name_size) = (field(1)? as u32, field(6)?, field(11)?);
+        let name_end = HEADER_LEN + name_size;
+        let data_start = align(name_end);
+        let data_end = data_start + file_size;
+        if name_size == 0 || header.len() < data_end {
+            bail!("truncated cpio entry");
+        }
+        let name = &header[HEADER_LEN..name_end - 1];
+        offset += align(data_end).min(header.len());
+
+        if name == TRAILER {
+            while data.get(offset) == Some(&0) {
+                offset += 1;
+            }
+            return Ok((entries, offset));
+        }
+        entries.push(RawEntry {
+            name,
+            mode,
+            data: &header[data_start..data_end],
+        });
+    }
+}
+
+/// Split off the uncompressed archive at the start of `data` (empty if there
+/// is none) from the rest, like the kernel does: newc entries up to
+/// `TRAILER!!!`, then zero padding.
+pub fn split_archive(data: &[u8]) -> (&[u8], &[u8]) {
+    let len = parse_archive(data).map_or(0, |(_, len)| len);
+    data.split_at(len)
+}
+
+/// The concatenated archives of a decompressed cpio stream, in order (zero
+/// padding between them allowed).
+pub fn split_archives(mut data: &[u8]) -> Result<Vec<&[u8]>> {
+    let mut archives = Vec::new();
+    loop {
+        data = &data[data.iter().position(|&b| b != 0).unwrap_or(data.len())..];
+        if data.is_empty() {
+            return Ok(archives);
+        }
+        let (_, len) = parse_archive(data)?;
+        let (archive, rest) = data.split_at(len);
+        archives.push(archive);
+        data = rest;
+    }
+}
+
+/// Every entry of an initramfs by path (without `./`): the early cpio, then
+/// each archive of the compressed stream, later entries replacing earlier.
+/// The stream is decompressed in `work_dir`.
+pub fn read_initramfs(image: &Path, work_dir: &Path) -> Result<BTreeMap<String, CpioEntry>> {
+    let (early, stream) = compression::decompress_initramfs(image, work_dir)?;
+    let mut entries = BTreeMap::new();
+    read_cpio_archives(&early, &mut entries)?;
+    read_cpio_archives(&stream, &mut entries)
+        .with_context(|| format!("{}: corrupt cpio stream", image.display()))?;
+    Ok(entries)
+}
+
+/// Add the entries of concatenated archives to `entries`, later ones
+/// replacing earlier.
+fn read_cpio_archives(data: &[u8], entries: &mut BTreeMap<String, CpioEntry>) -> Result<()> {
+    for archive in split_archives(data)? {
+        for entry in parse_archive(archive)?.0 {
+            let name = String::from_utf8_lossy(entry.name);
+            let name = name.trim_start_matches("./");
+            if matches!(name, "." | "") {
+                continue;
+            }
+            entries.insert(
+                name.to_string(),
+                CpioEntry {
+                    mode: entry.mode,
+                    data: entry.data.to_vec(),
+                },
+            );
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// newc entry (other header fields zero).
+    fn newc(name: &str, mode: u32, data: &[u8]) -> Vec<u8> {
+        let mut entry = format!(
+            "070701{:08X}{:08X}{}{:08X}{}{:08X}00000000",
+            0,
+            mode,
+            "0".repeat(32),
+            data.len(),
+            "0".repeat(32),
+            name.len() + 1
+        )
+        .into_bytes();
+        entry.extend(name.as_bytes());
+        entry.push(0);
+        entry.resize(entry.len().next_multiple_of(4), 0);
+        entry.extend(data);
+        entry.resize(entry.len().next_multiple_of(4), 0);
+        entry
+    }
+
+    #[test]
+    fn test_read_cpio_archives() {
+        let mut stream = Vec::new();
+        stream.extend(newc(".", 0o40755, b""));
+        stream.extend(newc("init", 0o100755, b"#!/bin/busybox sh\n"));
+        stream.extend(newc("bin/sh", 0o120777, b"busybox"));
+        stream.extend(newc("TRAILER!!!", 0, b""));
+        stream.resize(512, 0);
+        // Appended segment replacing /init
+        stream.extend(newc("./init", 0o100755, b"\x7fELF"));
+        stream.extend(newc("TRAILER!!!", 0, b""));
+
+        let mut entries = BTreeMap::new();
+        read_cpio_archives(&stream, &mut entries).unwrap();
+        assert_eq!(entries.keys().collect::<Vec<_>>(), ["bin/sh", "init"]);
+        assert_eq!(entries["init"].data, b"\x7fELF");
+        assert!(entries["init"].is_file());
+        assert!(entries["bin/sh"].is_symlink());
+        assert!(read_cpio_archives(b"\x1f\x8b", &mut entries).is_err());
+
+        let archives = split_archives(&stream).unwrap();
+        assert_eq!(archives.len(), 2);
+        assert_eq!(archives[0].len(), 512);
+    }
+
+    #[test]
+    fn test_split_archive() {
+        let mut image = newc("kernel/x86/microcode/AuthenticAMD.bin", 0o100644, b"AMD");
+        image.extend(newc("TRAILER!!!", 0, b""));
+        image.resize(512, 0);
+        let early_len = image.len();
+        image.extend(b"\x1f\x8bmain");
+
+        let (early, rest) = split_archive(&image);
+        assert_eq!(early.len(), early_len);
+        assert_eq!(rest, b"\x1f\x8bmain");
+        assert_eq!(
+            split_archive(b"\x1f\x8bmain"),
+            (&b""[..], &b"\x1f\x8bmain"[..])
+        );
+    }
+}
@@ -11,7 +11,7 @@
 //!
 //! ```text
 //! 1. GRUB loads kernel + live initramfs
-//! 2. Kernel extracts initramfs to rootfs, runs /init (busybox script)
+//! 2. Kernel extracts initramfs to rootfs, runs /init (static Rust init)
 //! 3. /init mounts ISO, EROFS rootfs, creates overlay
 //! 4. switch_root to overlay, systemd takes over
 //! ```
@@ -29,7 +29,7 @@ use anyhow::{bail, Context, Result};
 use leviso_cheat_guard::cheat_bail;
 use std::env;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 use fsdbg::checklist::ChecklistType;
 use fsdbg::cpio::CpioReader;
@@ -41,6 +41,8 @@ use distro_spec::levitate::{
 };
 use recinit::{download_busybox, InstallConfig, ModulePreset, TinyConfig};
 
+use super::compression::{self, Compression, INSTALL_COMPRESSION_ENV, LIVE_COMPRESSION_ENV};
+
 /// Get busybox download URL from environment or use default.
 fn busybox_url() -> String {
     env::var(BUSYBOX_URL_ENV).unwrap_or_else(|_| BUSYBOX_URL.to_string())
@@ -49,13 +51,27 @@ fn busybox_url() -> String {
 /// Build the tiny initramfs for live ISO boot.
 ///
 /// This creates a small (~5MB) busybox-based initramfs that:
-/// 1. Loads kernel modules for CDROM/storage access
-/// 2. Finds and mounts the EROFS root filesystem
+/// 1. Loads kernel modules for CDROM/storage access (storage controllers by
+///    modalias)
+/// 2. Waits for the boot device by label (`rootwait=`) and mounts the EROFS
 /// 3. Creates an overlay for writable storage
 /// 4. switch_root to the live system
+///
+/// `veritysetup` is appended afterwards (see `verity::append_verity_tools`)
+/// so the init script can verify the EROFS, followed by the kernel modules
+/// (recinit itself copies none) with load lists resolved from `modules.dep` (`modules::append_live_modules`):
+/// boot, dm-verity, NIC drivers for `levitate.fetch=`, ext4 for persistence
+/// and storage controllers matched by modalias. Last, the static Rust `/init`
+/// replaces the rendered busybox script unless `LEVISO_LIVE_INIT=shell`
+/// (`live_init::append_live_init`). Once the image is verified it is
+/// recompressed if `LEVISO_LIVE_INITRAMFS_COMPRESSION` asks for another format
+/// (`compression`) and early microcode is prepended
+/// (`microcode::prepend_early_microcode`).
 pub fn build_tiny_initramfs(base_dir: &Path) -> Result<()> {
     let output_dir = distro_builder::artifact_store::central_output_dir_for_distro(base_dir);
     let downloads_dir = base_dir.join("downloads");
+    let compression = Compression::from_env(LIVE_COMPRESSION_ENV)?;
+    compression::check_kernel_support(base_dir, compression)?;
 
     // Find kernel modules directory
     let modules_dir = find_kernel_modules_dir(base_dir)?;
@@ -74,7 +90,7 @@ pub fn build_tiny_initramfs(base_dir: &Path) -> Result<()> {
 
     // Build using recinit
     let config = TinyConfig {
-        modules_dir: modules_path,
+        modules_dir: modules_path.clone(),
         busybox_path,
         template_path: base_dir.join("profile/init_tiny.template"),
         output: output_dir.join(INITRAMFS_LIVE_OUTPUT),
@@ -82,22 +98,52 @@ pub fn build_tiny_initramfs(base_dir: &Path) -> Result<()> {
         rootfs_path: ROOTFS_ISO_PATH.to_string(),
         live_overlay_image_path: Some(LIVE_OVERLAY_ISO_PATH.to_string()),
         live_overlay_path: Some(LIVE_OVERLAY_ISO_PATH.to_string()),
+        // Required by recinit; init_tiny.template discovers devices through
+        // /sys/class/block and blkid labels instead of this fixed list
         boot_devices: BOOT_DEVICE_PROBE_ORDER
             .iter()
             .map(|s| s.to_string())
             .collect(),
-        module_preset: ModulePreset::Live,
+        // Modules come only from append_live_modules; the template loads
+        // the live/*.order lists and never looks at recinit's copies
+        module_preset: ModulePreset::Custom(Vec::new()),
         gzip_level: CPIO_GZIP_LEVEL,
-        check_builtin: true,
+        check_builtin: false,
         extra_template_vars: Vec::new(),
     };
 
     recinit::build_tiny_initramfs(&config, true)?;
 
-    // Verify the built initramfs
     let output_path = output_dir.join(INITRAMFS_LIVE_OUTPUT);
+    super::verity::append_verity_tools(
+        &base_dir.join("downloads/rootfs"),
+        &output_path,
+        &output_dir.join("initramfs-verity.work"),
+    )?;
+    super::modules::append_live_modules(
+        &modules_path,
+        &output_path,
+        &output_dir.join("initramfs-modules.work"),
+    )?;
+    if super::live_init::rust_init_enabled()? {
+        super::live_init::append_live_init(
+            base_dir,
+            &output_path,
+            &output_dir.join("initramfs-init.work"),
+        )?;
+    }
+
+    // Verify the built initramfs
     verify_live_initramfs(&output_path)?;
 
+    compression::recompress(&output_path, compression)?;
+    // Early microcode must be the first (uncompressed) cpio
+    super::microcode::prepend_early_microcode(
+        &downloads_dir.join("rootfs"),
+        &output_path,
+        &output_dir.join("initramfs-microcode.work"),
+    )?;
+
     Ok(())
 }
 
@@ -109,11 +155,16 @@ pub fn build_tiny_initramfs(base_dir: &Path) -> Result<()> {
 /// 3. Mounts the root filesystem from disk
 /// 4. Hands off to systemd
 ///
+/// Compression (`LEVISO_INSTALL_INITRAMFS_COMPRESSION`) and early microcode
+/// are handled like for the live initramfs.
+///
 /// By pre-building this during ISO creation, we save time during installation.
 /// The initramfs is generic (all drivers) so it works on any hardware.
 pub fn build_install_initramfs(base_dir: &Path) -> Result<()> {
     let output_dir = distro_builder::artifact_store::central_output_dir_for_distro(base_dir);
     let downloads_rootfs = base_dir.join("downloads/rootfs");
+    let compression = Compression::from_env(INSTALL_COMPRESSION_ENV)?;
+    compression::check_kernel_support(base_dir, compression)?;
 
     // Use downloads/rootfs for install initramfs - it has the full systemd units
     // including initrd.target which rootfs-staging lacks (stripped for live use)
@@ -154,6 +205,13 @@ pub fn build_install_initramfs(base_dir: &Path) -> Result<()> {
     let output_path = output_dir.join(INITRAMFS_INSTALLED_OUTPUT);
     verify_install_initramfs(&output_path)?;
 
+    compression::recompress(&output_path, compression)?;
+    super::microcode::prepend_early_microcode(
+        &base_dir.join("downloads/rootfs"),
+        &output_path,
+        &output_dir.join("install-initramfs-microcode.work"),
+    )?;
+
     Ok(())
 }
 
@@ -206,6 +264,20 @@ fn find_kernel_modules_dir(base_dir: &Path) -> Result<std::path::PathBuf> {
 }
 
 /// Find kernel version from modules directory.
+/// `lib/modules/<kver>` the live initramfs takes its modules from, picked like
+/// `build_tiny_initramfs` does (for the rebuild key); `None` if there is none.
+pub fn live_modules_path(base_dir: &Path) -> Option<PathBuf> {
+    let output_dir = distro_builder::artifact_store::central_output_dir_for_distro(base_dir);
+    let modules_dir = [
+        output_dir.join("staging/usr/lib/modules"),
+        base_dir.join("downloads/rootfs/usr/lib/modules"),
+    ]
+    .into_iter()
+    .find(|p| p.exists())?;
+    let kernel_version = find_kernel_version(&modules_dir).ok()?;
+    Some(modules_dir.join(kernel_version))
+}
+
 fn find_kernel_version(modules_dir: &Path) -> Result<String> {
     fs::read_dir(modules_dir)?
         .filter_map(|e| e.ok())
@@ -230,25 +302,40 @@ pub fn verify_install_initramfs(path: &Path) -> Result<()> {
 }
 
 /// Internal: Verify initramfs using fsdbg.
+///
+/// fsdbg's `CpioReader` reads a gzip stream from offset 0, so a finished
+/// image (early microcode cpio in front, possibly zstd/xz/lz4) is verified
+/// through a gzip copy of its payload.
 fn do_verify_initramfs(path: &Path, checklist_type: ChecklistType) -> Result<()> {
     print!("  Verifying {}... ", checklist_type.name());
 
-    let reader = CpioReader::open(path).with_context(|| {
-        format!(
-            "Failed to open initramfs for verification: {}",
-            path.display()
-        )
-    })?;
-
-    let report = match checklist_type {
-        ChecklistType::InstallInitramfs => fsdbg::checklist::install_initramfs::verify(&reader),
-        ChecklistType::LiveInitramfs => fsdbg::checklist::live_initramfs::verify(&reader),
-        // This function is for CPIO initramfs only - other types shouldn't reach here
-        _ => bail!(
-            "do_verify_initramfs() only handles initramfs types, got {:?}",
-            checklist_type
-        ),
+    let payload = path.with_extension("verify.tmp");
+    let readable = if compression::is_plain_gzip(path)? {
+        path
+    } else {
+        compression::write_gzip_payload(path, &payload)?;
+        payload.as_path()
     };
+    let report = CpioReader::open(readable)
+        .with_context(|| {
+            format!(
+                "Failed to open initramfs for verification: {}",
+                path.display()
+            )
+        })
+        .and_then(|reader| match checklist_type {
+            ChecklistType::InstallInitramfs => {
+                Ok(fsdbg::checklist::install_initramfs::verify(&reader))
+            }
+            ChecklistType::LiveInitramfs => Ok(fsdbg::checklist::live_initramfs::verify(&reader)),
+            // This function is for CPIO initramfs only - other types shouldn't reach here
+            _ => bail!(
+                "do_verify_initramfs() only handles initramfs types, got {:?}",
+                checklist_type
+            ),
+        });
+    let _ = fs::remove_file(&payload);
+    let report = report?;
 
     let passed = report.passed();
     let total = report.total();
@@ -4,6 +4,10 @@
 //! - UKIs (~50MB each) - kernel + initramfs + cmdline in signed PE binary
 //! - EROFS image (~350MB) - complete base system
 //! - Live overlay - live-specific configs (autologin, serial console, empty root password)
+//! - dm-verity hash tree for the EROFS, root hash in the live UKI cmdlines
+//! - Secure Boot enrollment files, when signing keys are configured
+//! - Detached OpenPGP/minisign signatures for the ISO and checksum, when configured
+//! - `release.json` next to the ISO, and `/usr/lib/levitate/build-info.json` in the live overlay
 //!
 //! Delegates to the standalone `reciso` crate for core ISO building.
 
@@ -13,6 +17,12 @@ use std::env;
 use std::fs;
 use std::path::{Path, PathBuf};
 
+use super::release::{ReleaseManifest, UkiInfo, BUILD_INFO_PATH, RELEASE_JSON};
+use super::secureboot::{self, SecureBootKeys, ISO_KEYS_DIR, SECUREBOOT_OUTPUT_DIR};
+use super::signing;
+use super::uki::{installed_cmdline, live_uki_entries};
+use super::verity;
+use crate::build::sources::SOURCES_TXT;
 use crate::component::custom::create_live_overlay_at;
 use distro_spec::levitate::{
     INITRAMFS_INSTALLED_ISO_PATH,
@@ -29,15 +39,18 @@ use distro_spec::levitate::{
     OS_NAME,
     OS_VERSION,
     // Rootfs (EROFS)
+    ROOTFS_ISO_PATH,
     ROOTFS_NAME,
     // UKI entries
-    UKI_ENTRIES,
     UKI_INSTALLED_ENTRIES,
     // Installed UKIs
     UKI_INSTALLED_ISO_DIR,
 };
 use reciso::{IsoConfig, UkiSource};
 
+/// Scratch directory for the installed UKIs, removed after ISO creation.
+const INSTALLED_UKI_DIR: &str = "installed-ukis";
+
 /// Get ISO volume label from environment or use default.
 /// Used for boot device detection (root=LABEL=X).
 fn iso_label() -> String {
@@ -84,6 +97,40 @@ impl IsoPaths {
 /// - Live ISO has autologin and empty root password (via overlay)
 /// - Installed systems (via recstrap) have proper security (EROFS only)
 pub fn create_iso(base_dir: &Path) -> Result<()> {
+    build_iso_installed_ukis(base_dir)?;
+    assemble_iso(base_dir)
+}
+
+/// Build the installed UKIs that ship in the ISO's `boot/uki/` directory.
+///
+/// Only needs the kernel and the install initramfs, so `build_full` runs it
+/// in parallel with the rootfs build.
+pub fn build_iso_installed_ukis(base_dir: &Path) -> Result<()> {
+    let paths = IsoPaths::new(base_dir);
+    let kernel_path = find_kernel(&paths)?;
+
+    if !paths.initramfs_installed.exists() {
+        bail!(
+            "Install initramfs not found at {}.\n\
+             Run 'leviso build' to build it first.",
+            paths.initramfs_installed.display()
+        );
+    }
+
+    let installed_uki_dir = paths.output_dir.join(INSTALLED_UKI_DIR);
+    fs::create_dir_all(&installed_uki_dir)?;
+    crate::artifact::uki::build_installed_ukis(
+        &kernel_path,
+        &paths.initramfs_installed,
+        &installed_uki_dir,
+    )?;
+    Ok(())
+}
+
+/// Assemble the ISO from already-built artifacts (see `create_iso`).
+///
+/// Expects the installed UKIs from `build_iso_installed_ukis`.
+pub fn assemble_iso(base_dir: &Path) -> Result<()> {
     let paths = IsoPaths::new(base_dir);
 
     println!("=== Building LevitateOS ISO (Atomic) ===\n");
@@ -96,15 +143,16 @@ pub fn create_iso(base_dir: &Path) -> Result<()> {
     // This is ONLY applied during live boot, NOT extracted to installed systems
     create_live_overlay_at(&paths.output_dir, base_dir)?;
 
-    // Stage 3: Build installed UKIs (for users to copy during installation)
-    // These need to be created before the ISO since they go into boot/uki/
-    let installed_uki_dir = paths.output_dir.join("installed-ukis");
-    fs::create_dir_all(&installed_uki_dir)?;
-    crate::artifact::uki::build_installed_ukis(
-        &kernel_path,
-        &paths.initramfs_installed,
-        &installed_uki_dir,
-    )?;
+    // Stage 3: Installed UKIs (for users to copy during installation) were
+    // built by build_iso_installed_ukis; they go into boot/uki/
+    let installed_uki_dir = paths.output_dir.join(INSTALLED_UKI_DIR);
+    if !installed_uki_dir.exists() {
+        bail!(
+            "Installed UKIs not found at {}.\n\
+             Build them with build_iso_installed_ukis() before assembling the ISO.",
+            installed_uki_dir.display()
+        );
+    }
 
     // Stage 4: Build reciso config
     let label = iso_label();
@@ -118,11 +166,34 @@ pub fn create_iso(base_dir: &Path) -> Result<()> {
     .with_os_release(OS_NAME, OS_ID, OS_VERSION)
     .with_overlay(paths.output_dir.join("live-overlay"));
 
+    // dm-verity: the hash tree ships next to the EROFS, and every live UKI
+    // carries the root hash so init_tiny can refuse a tampered rootfs
+    let verity = verity::ensure_verity(&paths.rootfs)?;
+    config.extra_files.push((
+        verity.hash_tree.clone(),
+        format!(
+            "{}{}",
+            ROOTFS_ISO_PATH.trim_start_matches('/'),
+            verity::HASH_TREE_SUFFIX
+        ),
+    ));
+
     // Add LevitateOS-specific UKI entries
-    for entry in UKI_ENTRIES {
+    let mut uki_infos = Vec::new();
+    for entry in live_uki_entries() {
+        let extra_cmdline = if entry.extra_cmdline.is_empty() {
+            verity.cmdline_arg()
+        } else {
+            format!("{} {}", entry.extra_cmdline, verity.cmdline_arg())
+        };
+        uki_infos.push(UkiInfo {
+            filename: entry.filename.to_string(),
+            cmdline: extra_cmdline.clone(),
+            installed: false,
+        });
         config.ukis.push(UkiSource::Build {
             name: entry.name.to_string(),
-            extra_cmdline: entry.extra_cmdline.to_string(),
+            extra_cmdline,
             filename: entry.filename.to_string(),
         });
     }
@@ -133,6 +204,14 @@ pub fn create_iso(base_dir: &Path) -> Result<()> {
         INITRAMFS_INSTALLED_ISO_PATH.to_string(),
     ));
 
+    // Add corresponding-source manifest (written offer for GPL packages)
+    let sources_txt = paths.output_dir.join(SOURCES_TXT);
+    if sources_txt.exists() {
+        config
+            .extra_files
+            .push((sources_txt, SOURCES_TXT.to_string()));
+    }
+
     // Add installed UKIs as extra files
     fs::create_dir_all(
         paths
@@ -146,9 +225,45 @@ pub fn create_iso(base_dir: &Path) -> Result<()> {
             config
                 .extra_files
                 .push((src, format!("{}/{}", UKI_INSTALLED_ISO_DIR, entry.filename)));
+            uki_infos.push(UkiInfo {
+                filename: format!("{}/{}", UKI_INSTALLED_ISO_DIR, entry.filename),
+                cmdline: installed_cmdline(entry.extra_cmdline),
+                installed: true,
+            });
         }
     }
 
+    // Release manifest: the in-image copy (live overlay) cannot know the ISO hash
+    let mut release = ReleaseManifest::collect(base_dir, &paths.output_dir, uki_infos)?;
+    for artifact in [
+        &paths.rootfs,
+        &paths.initramfs_live,
+        &paths.initramfs_installed,
+    ] {
+        release.add_artifact(artifact)?;
+    }
+    release.write(&paths.output_dir.join("live-overlay").join(BUILD_INFO_PATH))?;
+
+    // Secure Boot: reciso signs the live UKIs and systemd-boot with the db key
+    // (installed UKIs were signed by build_uki); enrollment files go in the ISO
+    if let Some(keys) = SecureBootKeys::from_env()? {
+        println!(
+            "Secure Boot signing enabled (db: {})",
+            keys.cert("db").display()
+        );
+        config = config.with_secure_boot(keys.key("db"), keys.cert("db"));
+
+        let auth_dir = paths.output_dir.join(SECUREBOOT_OUTPUT_DIR);
+        for auth in secureboot::write_auth_files(&keys, &auth_dir)? {
+            let name = auth.file_name().unwrap_or_default().to_string_lossy();
+            let iso_path = format!("{}/{}", ISO_KEYS_DIR, name);
+            config.extra_files.push((auth, iso_path));
+        }
+        config
+            .extra_files
+            .push((keys.cert("db"), format!("{}/db.crt", ISO_KEYS_DIR)));
+    }
+
     // Stage 5: Create the ISO using reciso (to temp file for atomicity)
     println!("Creating ISO via reciso...");
     reciso::create_iso(&config)?;
@@ -179,6 +294,16 @@ pub fn create_iso(base_dir: &Path) -> Result<()> {
     // Stage 8: Verify ISO contents - MUST pass before declaring success
     verify_iso(&paths.iso_output)?;
 
+    // Stage 9: release.json next to the ISO, now including the ISO itself
+    release.add_artifact(&paths.iso_output)?;
+    release.write(&paths.output_dir.join(RELEASE_JSON))?;
+    if let Some(build_id) = &release.build_id {
+        println!("  Release manifest: build {}", build_id);
+    }
+
+    // Stage 10: Detached signatures for the ISO and checksum (if configured)
+    signing::sign_iso(&paths.iso_output)?;
+
     print_iso_summary(&paths.iso_output);
     Ok(())
 }
@@ -0,0 +1,243 @@
+//! Static Rust `/init` for the live initramfs.
+//!
+//! `init/` is a standalone crate (std + libc only) with the same boot flow
+//! and cmdline as `profile/init_tiny.template`, but typed cmdline parsing,
+//! errors that name the failing boot stage, and unit tests against a fake
+//! sysfs. It is built as a static binary (musl if the target is installed,
+//! otherwise static glibc) and appended as `/init` in a later cpio segment,
+//! which the kernel unpacks over recinit's rendered script.
+//!
+//! The values recinit substitutes into the template go to
+//! `/etc/levitate/init.conf` instead, so the binary does not depend on them.
+//!
+//! `LEVISO_LIVE_INIT=shell` keeps the busybox script. That script is a
+//! fallback frozen at its current behaviour: new boot features go into
+//! `init/` only, and the script only gets security fixes.
+
+use anyhow::{bail, Context, Result};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use distro_builder::process::Cmd;
+use distro_spec::levitate::{ISO_LABEL, LIVE_OVERLAY_ISO_PATH, ROOTFS_ISO_PATH};
+use leviso_elf::make_executable;
+
+use super::verity;
+
+/// Selects the live `/init`: `rust` (default) or `shell`.
+pub const LIVE_INIT_ENV: &str = "LEVISO_LIVE_INIT";
+
+/// Crate directory, relative to the leviso checkout.
+pub const LIVE_INIT_CRATE: &str = "init";
+
+/// Config path inside the initramfs (matches `init/src/config.rs`).
+const CONFIG_PATH: &str = "etc/levitate/init.conf";
+
+const MUSL_TARGET: &str = "x86_64-unknown-linux-musl";
+const GNU_TARGET: &str = "x86_64-unknown-linux-gnu";
+
+/// Where distributions install glibc's static archive (`glibc-static`).
+const GLIBC_STATIC_PATHS: &[&str] = &[
+    "/usr/lib64/libc.a",
+    "/usr/lib/x86_64-linux-gnu/libc.a",
+    "/usr/lib/libc.a",
+];
+
+/// Whether the live initramfs gets the Rust init.
+pub fn rust_init_enabled() -> Result<bool> {
+    match env::var(LIVE_INIT_ENV).as_deref() {
+        Ok("rust") => Ok(true),
+        Ok("shell") => Ok(false),
+        Ok(other) if !other.is_empty() => {
+            bail!("{} must be rust or shell, got '{}'", LIVE_INIT_ENV, other)
+        }
+        _ => Ok(true),
+    }
+}
+
+/// How the init can be linked statically on this host: the musl target, or
+/// static glibc for the gnu target. `None` if neither is installed.
+pub fn static_toolchain() -> Option<&'static str> {
+    if target_installed(MUSL_TARGET) {
+        Some(MUSL_TARGET)
+    } else if GLIBC_STATIC_PATHS.iter().any(|p| Path::new(p).exists()) {
+        Some("glibc-static")
+    } else {
+        None
+    }
+}
+
+/// Build the init as a static binary and return its path.
+///
+/// Built `--locked` with build paths remapped, so the binary is reproducible
+/// across checkouts.
+pub fn build_live_init(base_dir: &Path) -> Result<PathBuf> {
+    let output_dir = distro_builder::artifact_store::central_output_dir_for_distro(base_dir);
+    let target_dir = output_dir.join("live-init-target");
+    let manifest = base_dir.join(LIVE_INIT_CRATE).join("Cargo.toml");
+
+    // musl binaries are static by default; glibc needs crt-static
+    let target = if target_installed(MUSL_TARGET) {
+        MUSL_TARGET
+    } else {
+        GNU_TARGET
+    };
+    let mut rustflags = remap_path_flags(base_dir, &target_dir);
+    if target == GNU_TARGET {
+        rustflags.push("-Ctarget-feature=+crt-static".to_string());
+    }
+    let rustflags: Vec<String> = rustflags.iter().map(|f| format!("{:?}", f)).collect();
+
+    println!("  Building static /init ({})...", target);
+    Cmd::new("cargo")
+        .args(["build", "--release", "--locked", "--manifest-path"])
+        .arg_path(&manifest)
+        .arg("--target-dir")
+        .arg_path(&target_dir)
+        .arg("--config")
+        .arg(format!(
+            "target.{}.rustflags=[{}]",
+            target,
+            rustflags.join(", ")
+        ))
+        .args(["--target", target])
+        .error_msg("Failed to build the live init (init/)")
+        .run()?;
+
+    let binary = target_dir.join(target).join("release/levitate-init");
+    let elf = fs::read(&binary).with_context(|| format!("{} not built", binary.display()))?;
+    if !is_static_elf(&elf)? {
+        bail!(
+            "{} is dynamically linked; the initramfs has no libc for it.\n\
+             Install the {} target (rustup target add {}) or static glibc (glibc-static).",
+            binary.display(),
+            MUSL_TARGET,
+            MUSL_TARGET
+        );
+    }
+    Ok(binary)
+}
+
+/// `--remap-path-prefix` flags for the paths that end up in the binary's panic
+/// messages and debug info: the cargo registry, the checkout and the target
+/// directory. Rebuilders in other directories then produce the same bytes.
+fn remap_path_flags(base_dir: &Path, target_dir: &Path) -> Vec<String> {
+    let cargo_home = env::var_os("CARGO_HOME")
+        .map(PathBuf::from)
+        .or_else(|| dirs::home_dir().map(|home| home.join(".cargo")));
+    let absolute = |p: &Path| fs::canonicalize(p).unwrap_or_else(|_| p.to_path_buf());
+
+    // The last matching remap wins, so more specific paths (a target dir
+    // inside the checkout) come later
+    let mut remaps = vec![(absolute(base_dir), "/leviso")];
+    if let Some(cargo_home) = cargo_home {
+        remaps.push((absolute(&cargo_home), "/cargo"));
+    }
+    remaps.push((absolute(target_dir), "/target"));
+    remaps
+        .into_iter()
+        .map(|(from, to)| format!("--remap-path-prefix={}={}", from.display(), to))
+        .collect()
+}
+
+/// Append the Rust init and its config to a built live initramfs.
+pub fn append_live_init(base_dir: &Path, initramfs: &Path, work_dir: &Path) -> Result<()> {
+    let binary = build_live_init(base_dir)?;
+
+    let _ = fs::remove_dir_all(work_dir);
+    fs::create_dir_all(work_dir.join(CONFIG_PATH).parent().unwrap())?;
+    let init = work_dir.join("init");
+    fs::copy(&binary, &init)?;
+    make_executable(&init)?;
+    fs::write(work_dir.join(CONFIG_PATH), init_conf())?;
+    let size = fs::metadata(&init)?.len();
+
+    verity::append_cpio_segment(work_dir, initramfs)
+        .context("Failed to append the Rust init to live initramfs")?;
+    println!(
+        "  Appended static /init ({} KiB) to live initramfs",
+        size / 1024
+    );
+    Ok(())
+}
+
+/// `/etc/levitate/init.conf`: the template variables of `init_tiny.template`.
+fn init_conf() -> String {
+    format!(
+        "# Written by leviso, read by /init (init/src/config.rs)\n\
+         ISO_LABEL={}\n\
+         ROOTFS_PATH={}\n\
+         LIVE_OVERLAY_PATH={}\n",
+        ISO_LABEL, ROOTFS_ISO_PATH, LIVE_OVERLAY_ISO_PATH
+    )
+}
+
+fn target_installed(target: &str) -> bool {
+    Cmd::new("rustc")
+        .args(["--print", "sysroot"])
+        .allow_fail()
+        .run()
+        .ok()
+        .filter(|r| r.success())
+        .is_some_and(|r| {
+            Path::new(r.stdout.trim())
+                .join("lib/rustlib")
+                .join(target)
+                .exists()
+        })
+}
+
+/// Whether an ELF64 executable has no program interpreter (`PT_INTERP`).
+fn is_static_elf(elf: &[u8]) -> Result<bool> {
+    const PT_INTERP: u32 = 3;
+    if elf.len() < 64 || &elf[..4] != b"\x7fELF" || elf[4] != 2 {
+        bail!("not an ELF64 binary");
+    }
+    let u16_at = |off: usize| u16::from_le_bytes([elf[off], elf[off + 1]]) as usize;
+    let phoff = u64::from_le_bytes(elf[0x20..0x28].try_into()?) as usize;
+    let (phentsize, phnum) = (u16_at(0x36), u16_at(0x38));
+    for i in 0..phnum {
+        let off = phoff + i * phentsize;
+        let header = elf
+            .get(off..off + 4)
+            .context("truncated ELF program headers")?;
+        if u32::from_le_bytes(header.try_into()?) == PT_INTERP {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Minimal ELF64 header with program header types `types`.
+    fn elf_with_phdrs(types: &[u32]) -> Vec<u8> {
+        let mut elf = vec![0u8; 64 + 56 * types.len()];
+        elf[..4].copy_from_slice(b"\x7fELF");
+        elf[4] = 2;
+        elf[0x20..0x28].copy_from_slice(&64u64.to_le_bytes());
+        elf[0x36..0x38].copy_from_slice(&56u16.to_le_bytes());
+        elf[0x38..0x3A].copy_from_slice(&(types.len() as u16).to_le_bytes());
+        for (i, t) in types.iter().enumerate() {
+            elf[64 + 56 * i..68 + 56 * i].copy_from_slice(&t.to_le_bytes());
+        }
+        elf
+    }
+
+    #[test]
+    fn test_is_static_elf() {
+        assert!(is_static_elf(&elf_with_phdrs(&[6, 1, 1, 2])).unwrap());
+        assert!(!is_static_elf(&elf_with_phdrs(&[6, 3, 1])).unwrap());
+        assert!(is_static_elf(b"#!/bin/busybox sh\n").is_err());
+    }
+
+    #[test]
+    fn test_init_conf() {
+        let conf = init_conf();
+        assert!(conf.contains(&format!("ISO_LABEL={}\n", ISO_LABEL)));
+        assert!(conf.contains(&format!("ROOTFS_PATH={}\n", ROOTFS_ISO_PATH)));
+    }
+}
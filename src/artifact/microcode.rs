@@ -0,0 +1,216 @@
+//! Early microcode for the live and install initramfs.
+//!
+//! The kernel only applies CPU microcode early (before most errata matter)
+//! if it finds it in an uncompressed cpio at the very start of the initrd:
+//!
+//! ```text
+//! initramfs:
+//! ├── early cpio (uncompressed)
+//! │   └── kernel/x86/microcode/{GenuineIntel,AuthenticAMD}.bin
+//! └── main cpio (gzip), plus appended segments
+//! ```
+//!
+//! The blobs are concatenated from the Rocky rootfs the same way
+//! `copy_all_firmware` stages them: AMD from linux-firmware's `amd-ucode/`,
+//! Intel from linux-firmware's `intel-ucode/` overridden by `microcode_ctl`.
+//! UKIs, netboot and GRUB all use the prepended image, so no separate
+//! `.ucode` UKI section is needed.
+
+use anyhow::{bail, Context, Result};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::build::reproducible;
+use distro_builder::process::{shell_in, Cmd};
+
+/// Where the kernel's early loader looks for microcode in the cpio.
+pub const EARLY_MICROCODE_DIR: &str = "kernel/x86/microcode";
+
+const AMD_UCODE_DIR: &str = "usr/lib/firmware/amd-ucode";
+
+/// Intel microcode sources; a file in a later directory replaces the
+/// same-named file from an earlier one.
+const INTEL_UCODE_DIRS: &[&str] = &[
+    "usr/lib/firmware/intel-ucode",
+    "usr/share/microcode_ctl/ucode_with_caveats/intel/intel-ucode",
+];
+
+/// Microcode files in `rootfs`, in the order they are concatenated.
+#[derive(Debug, Default)]
+pub struct MicrocodeFiles {
+    pub intel: Vec<PathBuf>,
+    pub amd: Vec<PathBuf>,
+}
+
+impl MicrocodeFiles {
+    pub fn find(rootfs: &Path) -> Self {
+        let mut intel = BTreeMap::new();
+        for dir in INTEL_UCODE_DIRS {
+            for path in files_in(&rootfs.join(dir)) {
+                // family-model-stepping names, possibly compressed
+                let name = path.file_name().unwrap().to_string_lossy().to_string();
+                intel.insert(name.trim_end_matches(".xz").to_string(), path);
+            }
+        }
+        let amd = files_in(&rootfs.join(AMD_UCODE_DIR))
+            .into_iter()
+            .filter(|path| {
+                let name = path.file_name().unwrap().to_string_lossy();
+                name.ends_with(".bin") || name.ends_with(".bin.xz")
+            })
+            .collect();
+        Self {
+            intel: intel.into_values().collect(),
+            amd,
+        }
+    }
+
+    /// All files, for rebuild detection.
+    pub fn all(&self) -> Vec<PathBuf> {
+        self.intel.iter().chain(&self.amd).cloned().collect()
+    }
+}
+
+/// Sorted regular files in `dir` (empty if it does not exist).
+fn files_in(dir: &Path) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .filter_map(|e| e.ok())
+                .map(|e| e.path())
+                .filter(|p| p.is_file())
+                .collect()
+        })
+        .unwrap_or_default();
+    files.sort();
+    files
+}
+
+/// Prepend an early microcode cpio built from `rootfs` to `initramfs`.
+pub fn prepend_early_microcode(rootfs: &Path, initramfs: &Path, work_dir: &Path) -> Result<()> {
+    let files = MicrocodeFiles::find(rootfs);
+    if files.intel.is_empty() || files.amd.is_empty() {
+        bail!(
+            "Microcode missing in {} (Intel: {} files, AMD: {} files).\n\
+             Early microcode is required for CPU errata fixes; install microcode_ctl and linux-firmware.",
+            rootfs.display(),
+            files.intel.len(),
+            files.amd.len()
+        );
+    }
+
+    let _ = fs::remove_dir_all(work_dir);
+    let root = work_dir.join("root");
+    let ucode_dir = root.join(EARLY_MICROCODE_DIR);
+    fs::create_dir_all(&ucode_dir)?;
+    let scratch = work_dir.join("blob");
+    for (name, blobs) in [
+        ("GenuineIntel.bin", &files.intel),
+        ("AuthenticAMD.bin", &files.amd),
+    ] {
+        let mut bundle = Vec::new();
+        for blob in blobs {
+            bundle.extend(read_blob(blob, &scratch)?);
+        }
+        fs::write(ucode_dir.join(name), bundle)?;
+    }
+    let intel_size = fs::metadata(ucode_dir.join("GenuineIntel.bin"))?.len();
+    let amd_size = fs::metadata(ucode_dir.join("AuthenticAMD.bin"))?.len();
+
+    if let Some(epoch) = reproducible::source_date_epoch()? {
+        reproducible::clamp_mtimes(&root, epoch)?;
+    }
+    let early = work_dir.join("early.cpio");
+    shell_in(
+        &format!(
+            "find . | LC_ALL=C sort | cpio -o -H newc --quiet --reproducible > '{}'",
+            early.display()
+        ),
+        &root,
+    )?;
+    prepend(&fs::read(&early)?, initramfs)
+        .with_context(|| format!("Failed to prepend microcode to {}", initramfs.display()))?;
+    let _ = fs::remove_dir_all(work_dir);
+
+    println!(
+        "  Prepended early microcode (Intel {} KiB, AMD {} KiB)",
+        intel_size / 1024,
+        amd_size / 1024
+    );
+    Ok(())
+}
+
+/// Contents of a microcode file, xz-decompressed if needed (via `scratch`).
+fn read_blob(path: &Path, scratch: &Path) -> Result<Vec<u8>> {
+    if path.extension().is_none_or(|e| e != "xz") {
+        return fs::read(path).with_context(|| format!("Failed to read {}", path.display()));
+    }
+    let compressed = scratch.with_extension("xz");
+    fs::copy(path, &compressed)?;
+    Cmd::new("xz")
+        .args(["-d", "-f"])
+        .arg_path(&compressed)
+        .error_msg(&format!("Failed to decompress {}", path.display()))
+        .run()?;
+    let data = fs::read(scratch)?;
+    fs::remove_file(scratch)?;
+    Ok(data)
+}
+
+/// Write `early` in front of the existing contents of `image`.
+fn prepend(early: &[u8], image: &Path) -> Result<()> {
+    let mut data = early.to_vec();
+    data.extend(fs::read(image)?);
+    let tmp = image.with_extension("ucode.tmp");
+    fs::write(&tmp, data)?;
+    fs::rename(&tmp, image)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_microcode() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let rootfs = temp.path();
+        for (dir, name) in [
+            (INTEL_UCODE_DIRS[0], "06-55-04"),
+            (INTEL_UCODE_DIRS[0], "06-8f-08"),
+            (INTEL_UCODE_DIRS[1], "06-55-04"),
+            (AMD_UCODE_DIR, "microcode_amd_fam19h.bin"),
+            (AMD_UCODE_DIR, "microcode_amd_fam19h.bin.asc"),
+            (AMD_UCODE_DIR, "README"),
+        ] {
+            fs::create_dir_all(rootfs.join(dir)).unwrap();
+            fs::write(rootfs.join(dir).join(name), name).unwrap();
+        }
+
+        let files = MicrocodeFiles::find(rootfs);
+        assert_eq!(
+            files.intel,
+            [
+                rootfs.join(INTEL_UCODE_DIRS[1]).join("06-55-04"),
+                rootfs.join(INTEL_UCODE_DIRS[0]).join("06-8f-08"),
+            ]
+        );
+        assert_eq!(
+            files.amd,
+            [rootfs.join(AMD_UCODE_DIR).join("microcode_amd_fam19h.bin")]
+        );
+        assert!(MicrocodeFiles::find(&rootfs.join("missing"))
+            .all()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_prepend() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let image = temp.path().join("initramfs.img");
+        fs::write(&image, b"\x1f\x8bmain").unwrap();
+        prepend(b"070701early", &image).unwrap();
+        assert_eq!(fs::read(&image).unwrap(), b"070701early\x1f\x8bmain");
+    }
+}
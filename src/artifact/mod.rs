@@ -2,20 +2,44 @@
 //!
 //! This module contains all artifact creation logic:
 //! - `initramfs` - Tiny initramfs builder (~5MB)
+//! - `compression` - Selectable initramfs compression (gzip, zstd, xz, lz4)
+//! - `cpio` - The newc reader: splitting images, initramfs contents
+//! - `live_init` - Static Rust `/init` for the live initramfs
+//! - `microcode` - Early microcode cpio prepended to both initramfs images
+//! - `modules` - Kernel modules and load lists for the live initramfs
 //! - `rootfs` - EROFS system image builder (~350MB)
 //! - `uki` - Unified Kernel Image builder
+//! - `secureboot` - Secure Boot signing and key enrollment
+//! - `verity` - dm-verity hash tree for the live EROFS
 //! - `iso` - Bootable ISO creation
+//! - `netboot` - PXE/HTTP network boot bundle
+//! - `persist` - Persistent live sessions (LEVITATE_PERSIST partition)
+//! - `signing` - Detached signatures for the ISO and checksum
+//! - `release` - release.json / build-info.json manifest
+//! - `remaster` - Repack a released ISO with a new overlay or cmdline
 //! - `qcow2` - Bootable VM disk image
 
+pub mod compression;
+pub mod cpio;
 pub mod initramfs;
 pub mod iso;
+pub mod live_init;
+pub mod microcode;
+pub mod modules;
+pub mod netboot;
+pub mod persist;
 pub mod qcow2;
+pub mod release;
+pub mod remaster;
 pub mod rootfs;
+pub mod secureboot;
+pub mod signing;
 pub mod uki;
+pub mod verity;
 
 pub use initramfs::{
     build_install_initramfs, build_tiny_initramfs, verify_install_initramfs, verify_live_initramfs,
 };
-pub use iso::{create_iso, verify_iso};
+pub use iso::{assemble_iso, build_iso_installed_ukis, create_iso, verify_iso};
 pub use qcow2::{build_qcow2, verify_qcow2};
-pub use rootfs::build_rootfs;
+pub use rootfs::{build_rootfs, build_rootfs_resume};
@@ -0,0 +1,357 @@
+//! Kernel modules for the live initramfs.
+//!
+//! busybox has no modprobe database, so module dependencies are resolved at
+//! build time from `modules.dep` instead of by hand in `init_tiny.template`.
+//! `append_live_modules` appends one cpio segment with the modules already
+//! decompressed and a load list per feature, dependencies first:
+//!
+//! ```text
+//! /lib/modules/<kver>/
+//! ├── kernel/...             # .ko files (no .xz/.gz/.zst)
+//! └── live/
+//!     ├── boot.order         # BOOT_MODULES, loaded unconditionally
+//!     ├── verity.order       # loaded with levitate.verity=
+//!     ├── net.order          # loaded with levitate.fetch=
+//!     ├── persist.order      # loaded unless nopersist
+//!     └── modalias           # "<alias pattern> <module path>..." per line
+//! ```
+//!
+//! `modalias` covers the storage controller drivers in `STORAGE_DRIVER_DIRS`:
+//! init matches `/sys/bus/*/devices/*/modalias` against the patterns and
+//! loads only the drivers (and their dependencies) for hardware that is
+//! present. Supporting a new controller means adding its directory, not
+//! working out an insmod order.
+
+use anyhow::{Context, Result};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::Path;
+
+use distro_builder::process::Cmd;
+use distro_spec::levitate::BOOT_MODULES;
+
+use super::{netboot, persist, verity};
+
+/// Directory with the load lists, relative to `/lib/modules/<kver>`
+/// (matches `init_tiny.template`).
+pub const LIVE_MODULES_DIR: &str = "live";
+
+/// Load lists written to `LIVE_MODULES_DIR` as `<name>.order`, and the
+/// modules they start from.
+const LIVE_MODULE_SETS: &[(&str, &[&str])] = &[
+    ("boot", BOOT_MODULES),
+    ("verity", verity::VERITY_MODULES),
+    ("net", netboot::NETBOOT_MODULES),
+    ("persist", persist::PERSIST_MODULES),
+];
+
+/// Storage controller drivers loaded by modalias. Only modules directly in
+/// these directories are considered, so RAID/FC HBA drivers in subdirectories
+/// of `scsi` stay in the EROFS.
+const STORAGE_DRIVER_DIRS: &[&str] = &[
+    "kernel/drivers/ata",
+    "kernel/drivers/block",
+    "kernel/drivers/mmc/host",
+    "kernel/drivers/nvme/host",
+    "kernel/drivers/scsi",
+    "kernel/drivers/usb/host",
+    "kernel/drivers/usb/storage",
+];
+
+/// Alias patterns that match a device modalias (as opposed to `fs-*`,
+/// `devname:*`, `block-major-*` and friends).
+const HARDWARE_ALIAS_PREFIXES: &[&str] = &[
+    "acpi",
+    "of:",
+    "pci:",
+    "platform:",
+    "scsi:",
+    "usb:",
+    "virtio:",
+];
+
+/// Parsed `modules.dep`: module name -> (path, dependency paths). Paths are
+/// relative to `/lib/modules/<kver>`.
+#[derive(Debug, Default)]
+struct ModuleDeps {
+    modules: BTreeMap<String, (String, Vec<String>)>,
+}
+
+impl ModuleDeps {
+    fn parse(content: &str) -> Self {
+        let modules = content
+            .lines()
+            .filter_map(|line| line.split_once(':'))
+            .map(|(path, deps)| {
+                let path = path.trim().to_string();
+                let deps = deps.split_whitespace().map(str::to_string).collect();
+                (module_name(&path), (path, deps))
+            })
+            .collect();
+        Self { modules }
+    }
+
+    /// Paths of `roots` and everything they depend on, dependencies first,
+    /// plus the roots that are not loadable modules (built in or missing).
+    fn load_order(&self, roots: &[&str]) -> (Vec<String>, Vec<String>) {
+        let mut order = Vec::new();
+        let mut seen = BTreeSet::new();
+        let mut missing = Vec::new();
+        for root in roots {
+            let name = module_name(root);
+            if self.modules.contains_key(&name) {
+                self.visit(&name, &mut order, &mut seen);
+            } else {
+                missing.push(name);
+            }
+        }
+        (order, missing)
+    }
+
+    fn visit(&self, name: &str, order: &mut Vec<String>, seen: &mut BTreeSet<String>) {
+        if !seen.insert(name.to_string()) {
+            return;
+        }
+        let Some((path, deps)) = self.modules.get(name) else {
+            return;
+        };
+        for dep in deps {
+            self.visit(&module_name(dep), order, seen);
+        }
+        order.push(path.clone());
+    }
+
+    /// Names of the modules directly inside one of `dirs`.
+    fn in_dirs(&self, dirs: &[&str]) -> BTreeSet<String> {
+        self.modules
+            .iter()
+            .filter(|(_, (path, _))| {
+                dirs.iter().any(|dir| {
+                    path.strip_prefix(dir)
+                        .and_then(|rest| rest.strip_prefix('/'))
+                        .is_some_and(|file| !file.contains('/'))
+                })
+            })
+            .map(|(name, _)| name.clone())
+            .collect()
+    }
+}
+
+/// Module name as the kernel sees it: `kernel/drivers/md/dm-mod.ko.xz` -> `dm_mod`.
+pub(crate) fn module_name(path: &str) -> String {
+    let file = path.rsplit('/').next().unwrap_or(path);
+    let stem = file.split(".ko").next().unwrap_or(file);
+    stem.replace('-', "_")
+}
+
+/// `path` without its compression suffix.
+fn decompressed_path(path: &str) -> &str {
+    [".xz", ".gz", ".zst"]
+        .iter()
+        .find_map(|ext| path.strip_suffix(ext))
+        .unwrap_or(path)
+}
+
+/// Hardware aliases (`modules.alias` format) of the modules in `wanted`, as
+/// (pattern, module name) pairs.
+fn hardware_aliases(content: &str, wanted: &BTreeSet<String>) -> Vec<(String, String)> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            match (fields.next(), fields.next(), fields.next()) {
+                (Some("alias"), Some(pattern), Some(name)) => {
+                    Some((pattern, name.replace('-', "_")))
+                }
+                _ => None,
+            }
+        })
+        .filter(|(pattern, name)| {
+            wanted.contains(name)
+                && HARDWARE_ALIAS_PREFIXES
+                    .iter()
+                    .any(|p| pattern.starts_with(p))
+        })
+        .map(|(pattern, name)| (pattern.to_string(), name))
+        .collect()
+}
+
+/// The `modalias` load list: one line per alias pattern with the module's
+/// load order.
+fn modalias_list(deps: &ModuleDeps, aliases: &[(String, String)]) -> Vec<(String, Vec<String>)> {
+    aliases
+        .iter()
+        .map(|(pattern, name)| (pattern.clone(), deps.load_order(&[name.as_str()]).0))
+        .collect()
+}
+
+/// Append the live initramfs modules and their load lists as one cpio segment.
+pub fn append_live_modules(modules_path: &Path, initramfs: &Path, work_dir: &Path) -> Result<()> {
+    let read = |name: &str| fs::read_to_string(modules_path.join(name));
+    let deps = ModuleDeps::parse(&read("modules.dep").with_context(|| {
+        format!(
+            "Failed to read {}/modules.dep (run depmod)",
+            modules_path.display()
+        )
+    })?);
+    let builtin: BTreeSet<String> = read("modules.builtin")
+        .unwrap_or_default()
+        .lines()
+        .map(module_name)
+        .collect();
+
+    let _ = fs::remove_dir_all(work_dir);
+    let kver = modules_path
+        .file_name()
+        .context("Invalid kernel modules path")?;
+    let root = work_dir.join("lib/modules").join(kver);
+    let lists = root.join(LIVE_MODULES_DIR);
+    fs::create_dir_all(&lists)?;
+
+    let mut shipped = BTreeSet::new();
+    for (set, roots) in LIVE_MODULE_SETS {
+        let (order, missing) = deps.load_order(roots);
+        for name in missing.iter().filter(|name| !builtin.contains(*name)) {
+            println!(
+                "  Warning: {} module '{}' is neither built in nor in modules.dep",
+                set, name
+            );
+        }
+        write_list(
+            &lists.join(format!("{}.order", set)),
+            order.iter().map(|p| decompressed_path(p)),
+        )?;
+        shipped.extend(order);
+    }
+
+    let storage = deps.in_dirs(STORAGE_DRIVER_DIRS);
+    let aliases = hardware_aliases(&read("modules.alias").unwrap_or_default(), &storage);
+    let modalias = modalias_list(&deps, &aliases);
+    write_list(
+        &lists.join("modalias"),
+        modalias.iter().map(|(pattern, order)| {
+            let paths: Vec<&str> = order.iter().map(|p| decompressed_path(p)).collect();
+            format!("{} {}", pattern, paths.join(" "))
+        }),
+    )?;
+    shipped.extend(modalias.into_iter().flat_map(|(_, order)| order));
+
+    for path in &shipped {
+        install_module(modules_path, path, &root)?;
+    }
+    verity::append_cpio_segment(work_dir, initramfs)
+        .context("Failed to append kernel modules to live initramfs")?;
+
+    println!(
+        "  Appended {} kernel modules ({} storage modalias patterns) to live initramfs",
+        shipped.len(),
+        aliases.len()
+    );
+    Ok(())
+}
+
+fn write_list<I, S>(path: &Path, lines: I) -> Result<()>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    let mut content = String::new();
+    for line in lines {
+        content.push_str(line.as_ref());
+        content.push('\n');
+    }
+    fs::write(path, content).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Copy module `path` into `root`, decompressed so init can insmod it as is.
+fn install_module(modules_path: &Path, path: &str, root: &Path) -> Result<()> {
+    let dest = root.join(path);
+    fs::create_dir_all(dest.parent().unwrap())?;
+    fs::copy(modules_path.join(path), &dest)
+        .with_context(|| format!("Kernel module {} listed in modules.dep is missing", path))?;
+
+    let decompress = match dest.extension().and_then(|e| e.to_str()) {
+        Some("xz") => Cmd::new("xz").arg("-d"),
+        Some("gz") => Cmd::new("gzip").arg("-d"),
+        Some("zst") => Cmd::new("zstd").args(["-d", "-q", "--rm"]),
+        _ => return Ok(()),
+    };
+    decompress
+        .arg_path(&dest)
+        .error_msg(&format!("Failed to decompress {}", path))
+        .run()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MODULES_DEP: &str = "\
+kernel/drivers/md/dm-verity.ko.xz: kernel/drivers/md/dm-bufio.ko.xz kernel/drivers/md/dm-mod.ko.xz
+kernel/drivers/md/dm-bufio.ko.xz:
+kernel/drivers/md/dm-mod.ko.xz:
+kernel/drivers/ata/ahci.ko.xz: kernel/drivers/ata/libahci.ko.xz kernel/drivers/ata/libata.ko.xz
+kernel/drivers/ata/libahci.ko.xz: kernel/drivers/ata/libata.ko.xz
+kernel/drivers/ata/libata.ko.xz:
+kernel/drivers/scsi/qla2xxx/qla2xxx.ko.xz:
+";
+
+    #[test]
+    fn test_module_name() {
+        assert_eq!(module_name("kernel/drivers/md/dm-mod.ko.xz"), "dm_mod");
+        assert_eq!(module_name("kernel/fs/ext4/ext4.ko"), "ext4");
+        assert_eq!(module_name("dm-verity"), "dm_verity");
+        assert_eq!(
+            decompressed_path("kernel/fs/ext4/ext4.ko.zst"),
+            "kernel/fs/ext4/ext4.ko"
+        );
+    }
+
+    #[test]
+    fn test_load_order_dependencies_first() {
+        let deps = ModuleDeps::parse(MODULES_DEP);
+        let (order, missing) = deps.load_order(&["dm-verity", "dm_mod", "isofs"]);
+        assert_eq!(
+            order,
+            [
+                "kernel/drivers/md/dm-bufio.ko.xz",
+                "kernel/drivers/md/dm-mod.ko.xz",
+                "kernel/drivers/md/dm-verity.ko.xz",
+            ]
+        );
+        assert_eq!(missing, ["isofs"]);
+
+        let (order, _) = deps.load_order(&["ahci"]);
+        assert_eq!(
+            order,
+            [
+                "kernel/drivers/ata/libata.ko.xz",
+                "kernel/drivers/ata/libahci.ko.xz",
+                "kernel/drivers/ata/ahci.ko.xz",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_storage_modalias() {
+        let deps = ModuleDeps::parse(MODULES_DEP);
+        let storage = deps.in_dirs(&["kernel/drivers/ata", "kernel/drivers/scsi"]);
+        assert!(storage.contains("ahci"));
+        assert!(!storage.contains("qla2xxx"));
+
+        let aliases = hardware_aliases(
+            "alias pci:v*d*sv*sd*bc01sc06i01* ahci\n\
+             alias devname:mapper/control dm_mod\n\
+             alias fs-ext4 ext4\n",
+            &storage,
+        );
+        assert_eq!(
+            aliases,
+            [("pci:v*d*sv*sd*bc01sc06i01*".to_string(), "ahci".to_string())]
+        );
+        let list = modalias_list(&deps, &aliases);
+        assert_eq!(list[0].1.len(), 3);
+        assert_eq!(list[0].1[2], "kernel/drivers/ata/ahci.ko.xz");
+    }
+}
@@ -0,0 +1,222 @@
+//! Network boot bundle (PXE / HTTP).
+//!
+//! `leviso build netboot` lays out everything a lab network needs to boot the
+//! live system without an ISO:
+//!
+//! ```text
+//! output/netboot/
+//! ├── vmlinuz                        # kernel
+//! ├── initramfs.img                  # live initramfs (with NIC drivers)
+//! ├── live/filesystem.erofs          # same paths as on the ISO
+//! ├── live/filesystem.erofs.verity
+//! ├── <LIVE_OVERLAY_ISO_PATH>.tar.gz  # live overlay, unpacked by init
+//! ├── boot.ipxe                      # sample iPXE script
+//! └── dnsmasq.conf                   # sample DHCP + TFTP config
+//! ```
+//!
+//! iPXE loads the kernel and initramfs over HTTP with `levitate.fetch=<url>`
+//! on the cmdline. `init_tiny.template` then brings up the network with
+//! busybox `udhcpc`, downloads the EROFS (and verity tree) into RAM and
+//! continues exactly like an ISO boot.
+//!
+//! Plain HTTP has no integrity of its own: the EROFS is checked by dm-verity,
+//! and the overlay tarball (layered above it) against
+//! `levitate.overlay_sha256=` on the cmdline. Init refuses a verity boot
+//! whose overlay has no checksum.
+//!
+//! The NIC drivers the kernel ships as modules are part of the live initramfs
+//! (`net.order`, see `modules::append_live_modules`).
+
+use anyhow::{bail, Context, Result};
+use std::fs;
+use std::path::Path;
+
+use distro_builder::process::Cmd;
+use distro_spec::levitate::{
+    INITRAMFS_LIVE_OUTPUT, LIVE_OVERLAY_ISO_PATH, ROOTFS_ISO_PATH, ROOTFS_NAME, SELINUX_DISABLE,
+    SERIAL_CONSOLE, VGA_CONSOLE,
+};
+use sha2::Sha256;
+
+use super::verity;
+use crate::component::custom::create_live_overlay_at;
+
+/// Output subdirectory holding the bundle.
+pub const NETBOOT_DIR: &str = "netboot";
+
+/// Kernel cmdline parameter with the base URL of the bundle.
+pub const FETCH_PARAM: &str = "levitate.fetch";
+
+/// Kernel cmdline parameter with the SHA-256 of the live overlay tarball.
+pub const OVERLAY_SHA256_PARAM: &str = "levitate.overlay_sha256";
+
+/// Default base URL: the host as seen from QEMU user networking.
+pub const DEFAULT_URL: &str = "http://10.0.2.2:8000/";
+
+/// NIC drivers loaded for network boot (dependencies are resolved from
+/// `modules.dep`).
+pub(super) const NETBOOT_MODULES: &[&str] = &["virtio_net", "e1000", "e1000e", "r8169"];
+
+/// iPXE binaries shipped by the host's ipxe package, for chainloading from
+/// plain PXE firmware.
+const IPXE_EFI_CANDIDATES: &[&str] = &[
+    "/usr/share/ipxe/ipxe-x86_64.efi",
+    "/usr/lib/ipxe/ipxe.efi",
+    "/usr/share/ipxe/ipxe.efi",
+];
+
+/// Build the netboot bundle in `output/netboot`, served from `url`.
+pub fn build_netboot(base_dir: &Path, url: &str) -> Result<()> {
+    let output_dir = distro_builder::artifact_store::central_output_dir_for_distro(base_dir);
+    let kernel = output_dir.join("staging/boot/vmlinuz");
+    let initramfs = output_dir.join(INITRAMFS_LIVE_OUTPUT);
+    let rootfs = output_dir.join(ROOTFS_NAME);
+    for (path, hint) in [
+        (&kernel, "cargo xtask kernels build leviso"),
+        (&rootfs, "leviso build rootfs"),
+        (&initramfs, "leviso build initramfs"),
+    ] {
+        if !path.exists() {
+            bail!("{} not found.\nRun '{}' first.", path.display(), hint);
+        }
+    }
+
+    println!("=== Building Netboot Bundle ===\n");
+    let dir = output_dir.join(NETBOOT_DIR);
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir)?;
+
+    copy_file(&kernel, &dir.join("vmlinuz"))?;
+    copy_file(&initramfs, &dir.join("initramfs.img"))?;
+
+    let verity = verity::ensure_verity(&rootfs)?;
+    let rootfs_dest = dir.join(ROOTFS_ISO_PATH.trim_start_matches('/'));
+    fs::create_dir_all(rootfs_dest.parent().unwrap())?;
+    copy_file(&rootfs, &rootfs_dest)?;
+    copy_file(
+        &verity.hash_tree,
+        &super::signing::with_suffix(&rootfs_dest, verity::HASH_TREE_SUFFIX),
+    )?;
+
+    // The live overlay ships as a tarball; init unpacks it next to the EROFS
+    create_live_overlay_at(&output_dir, base_dir)?;
+    let tarball = dir.join(format!(
+        "{}.tar.gz",
+        LIVE_OVERLAY_ISO_PATH.trim_start_matches('/')
+    ));
+    Cmd::new("tar")
+        .args(["--owner=0", "--group=0", "--numeric-owner", "--sort=name"])
+        .arg("-czf")
+        .arg_path(&tarball)
+        .arg("-C")
+        .arg_path(&output_dir.join("live-overlay"))
+        .arg(".")
+        .error_msg("Failed to pack live overlay")
+        .run()?;
+
+    let overlay_sha256 = super::signing::hash_file::<Sha256>(&tarball)?;
+    let cmdline = netboot_cmdline(url, &verity.cmdline_arg(), &overlay_sha256);
+    fs::write(dir.join("boot.ipxe"), ipxe_script(url, &cmdline))?;
+    fs::write(dir.join("dnsmasq.conf"), dnsmasq_conf(&dir))?;
+    if let Some(ipxe) = IPXE_EFI_CANDIDATES
+        .iter()
+        .map(Path::new)
+        .find(|p| p.exists())
+    {
+        fs::copy(ipxe, dir.join("ipxe.efi"))?;
+    }
+
+    println!("\nNetboot bundle: {}", dir.display());
+    println!("  cmdline: {}", cmdline);
+    println!("\nTest with QEMU user networking (built-in TFTP + iPXE ROM):");
+    println!("  python3 -m http.server 8000 -d {}", dir.display());
+    println!(
+        "  qemu-system-x86_64 -m 4G -enable-kvm -boot n \\\n    \
+         -netdev user,id=net0,tftp={},bootfile=boot.ipxe \\\n    \
+         -device virtio-net-pci,netdev=net0",
+        dir.display()
+    );
+    Ok(())
+}
+
+/// Kernel cmdline for a network boot from `url`.
+fn netboot_cmdline(url: &str, verity_arg: &str, overlay_sha256: &str) -> String {
+    format!(
+        "{} {} {} {}={} {} {}={}",
+        SERIAL_CONSOLE,
+        VGA_CONSOLE,
+        SELINUX_DISABLE,
+        FETCH_PARAM,
+        base_url(url),
+        verity_arg,
+        OVERLAY_SHA256_PARAM,
+        overlay_sha256
+    )
+}
+
+/// `url` without a trailing slash, so `<base>/vmlinuz` is well-formed.
+fn base_url(url: &str) -> &str {
+    url.trim_end_matches('/')
+}
+
+fn ipxe_script(url: &str, cmdline: &str) -> String {
+    format!(
+        "#!ipxe\n\
+         # LevitateOS network boot - kernel and initramfs over HTTP, the live\n\
+         # media is downloaded by the initramfs ({}=...)\n\
+         dhcp\n\
+         set base {}\n\
+         kernel ${{base}}/vmlinuz {}\n\
+         initrd ${{base}}/initramfs.img\n\
+         boot\n",
+        FETCH_PARAM,
+        base_url(url),
+        cmdline
+    )
+}
+
+fn dnsmasq_conf(tftp_root: &Path) -> String {
+    format!(
+        "# Sample dnsmasq config: DHCP + TFTP for LevitateOS network boot.\n\
+         # Adjust interface and range, serve the bundle over HTTP, then:\n\
+         #   dnsmasq --conf-file=dnsmasq.conf --no-daemon\n\
+         interface=eth1\n\
+         bind-interfaces\n\
+         dhcp-range=192.168.100.100,192.168.100.200,12h\n\
+         enable-tftp\n\
+         tftp-root={}\n\
+         # UEFI PXE firmware chainloads iPXE, which then runs boot.ipxe\n\
+         dhcp-match=set:ipxe,175\n\
+         dhcp-boot=tag:!ipxe,ipxe.efi\n\
+         dhcp-boot=tag:ipxe,boot.ipxe\n",
+        tftp_root.display()
+    )
+}
+
+/// Copy an output into the bundle. Never hard-linked: a later in-place
+/// rewrite of the output must not change the tree being served (and break its
+/// verity hash). `fs::copy` uses `copy_file_range`, which reflinks where the
+/// filesystem supports it.
+fn copy_file(src: &Path, dst: &Path) -> Result<()> {
+    fs::copy(src, dst)
+        .with_context(|| format!("Failed to copy {} to {}", src.display(), dst.display()))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ipxe_script() {
+        let cmdline = netboot_cmdline("http://10.0.2.2:8000/", "levitate.verity=abc", "def");
+        assert!(cmdline.contains("levitate.fetch=http://10.0.2.2:8000 "));
+        assert!(cmdline.ends_with("levitate.verity=abc levitate.overlay_sha256=def"));
+
+        let script = ipxe_script("http://10.0.2.2:8000/", &cmdline);
+        assert!(script.starts_with("#!ipxe\n"));
+        assert!(script.contains("set base http://10.0.2.2:8000\n"));
+        assert!(script.contains(&format!("kernel ${{base}}/vmlinuz {}\n", cmdline)));
+        assert!(script.contains("initrd ${base}/initramfs.img\n"));
+    }
+}
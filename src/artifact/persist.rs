@@ -0,0 +1,134 @@
+//! Persistent live sessions.
+//!
+//! `init_tiny.template` uses an ext4 filesystem labeled `LEVITATE_PERSIST`
+//! as the overlay upper layer instead of tmpfs, so changes (WiFi credentials,
+//! notes, installed tools) survive reboots:
+//!
+//! | Source | How it is found |
+//! |--------|-----------------|
+//! | Partition labeled `LEVITATE_PERSIST` | `findfs LABEL=...` (default) |
+//! | `persist=LABEL=x`, `UUID=x` or `/dev/x` | cmdline override |
+//! | `/LEVITATE_PERSIST.img` on writable boot media | loop-mounted ext4 image |
+//!
+//! `nopersist` (the "No Persistence" boot entry) ignores all of them. When
+//! the EROFS is protected by dm-verity (`levitate.verity=`), a partition found
+//! by label must be on the boot disk (like the `--persist-size` image's);
+//! otherwise any attached stick could override the verified rootfs. An
+//! explicit `persist=` is trusted.
+//!
+//! `leviso build iso --persist-size N` writes a hybrid image next to the ISO
+//! with an N MiB ext4 partition appended, ready to `dd` to a USB stick. The
+//! ISO itself is left untouched (signatures and reproducibility still apply).
+
+use anyhow::{bail, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use distro_builder::process::{self, Cmd};
+
+use crate::build::reproducible;
+
+/// Filesystem label of the persistence partition (matches `init_tiny.template`).
+pub const PERSIST_LABEL: &str = "LEVITATE_PERSIST";
+
+/// Smallest useful persistence partition, in MiB.
+pub const MIN_PERSIST_SIZE_MIB: u32 = 64;
+
+/// Kernel modules needed to mount the persistence filesystem (dependencies are
+/// resolved from `modules.dep`).
+pub(super) const PERSIST_MODULES: &[&str] = &["ext4"];
+
+/// Partition number of the appended ext4 partition (reciso uses 1 and 2).
+const PERSIST_PARTITION: &str = "3";
+
+/// Hybrid image written next to `iso` (`x.iso` -> `x-persist.img`).
+pub fn persist_image_path(iso: &Path) -> PathBuf {
+    let stem = iso
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    iso.with_file_name(format!("{}-persist.img", stem))
+}
+
+/// Write `persist_image_path(iso)`: the ISO plus a `size_mib` ext4 partition
+/// labeled `PERSIST_LABEL`.
+pub fn build_persist_image(iso: &Path, size_mib: u32) -> Result<PathBuf> {
+    if size_mib < MIN_PERSIST_SIZE_MIB {
+        bail!(
+            "--persist-size must be at least {} MiB, got {}",
+            MIN_PERSIST_SIZE_MIB,
+            size_mib
+        );
+    }
+    for (tool, package) in [("xorriso", "xorriso"), ("mkfs.ext4", "e2fsprogs")] {
+        if !process::exists(tool) {
+            bail!("{} not found. Install the {} package.", tool, package);
+        }
+    }
+    if !iso.exists() {
+        bail!("ISO not found: {}", iso.display());
+    }
+
+    println!(
+        "Creating hybrid image with {} MiB persistence partition...",
+        size_mib
+    );
+    let output = persist_image_path(iso);
+    let partition = super::signing::with_suffix(&output, ".part");
+    let _ = fs::remove_file(&output);
+    let _ = fs::remove_file(&partition);
+
+    fs::File::create(&partition)?.set_len(u64::from(size_mib) << 20)?;
+    let mut mkfs = Cmd::new("mkfs.ext4").args(["-q", "-F", "-L", PERSIST_LABEL]);
+    // UUID and directory hash seed are random by default
+    if let Some(epoch) = reproducible::source_date_epoch()? {
+        let uuid = reproducible::stable_uuid("persist", epoch);
+        let hash_seed = reproducible::stable_uuid("persist-hash", epoch);
+        mkfs = mkfs
+            .args(["-U", uuid.as_str()])
+            .arg("-E")
+            .arg(format!("hash_seed={}", hash_seed));
+    }
+    mkfs.arg_path(&partition)
+        .error_msg("Failed to create persistence filesystem")
+        .run()?;
+
+    // Replay the ISO's boot setup (El Torito, EFI partition) into the new
+    // image and add the ext4 partition after it
+    let result = Cmd::new("xorriso")
+        .arg("-indev")
+        .arg_path(iso)
+        .arg("-outdev")
+        .arg_path(&output)
+        .args(["-boot_image", "any", "replay"])
+        .args(["-append_partition", PERSIST_PARTITION, "0x83"])
+        .arg_path(&partition)
+        .error_msg("Failed to append persistence partition")
+        .run();
+    let _ = fs::remove_file(&partition);
+    result?;
+
+    println!("  Hybrid image: {}", output.display());
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_persist_image_path() {
+        assert_eq!(
+            persist_image_path(Path::new("/out/levitateos-x86_64.iso")),
+            PathBuf::from("/out/levitateos-x86_64-persist.img")
+        );
+    }
+
+    #[test]
+    fn test_persist_size_minimum() {
+        let err = build_persist_image(Path::new("/nonexistent.iso"), 1)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("at least"));
+    }
+}
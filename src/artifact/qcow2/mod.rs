@@ -43,6 +43,8 @@ use distro_spec::shared::QCOW2_IMAGE_FILENAME;
 use std::fs;
 use std::path::Path;
 
+use crate::common::OutputLock;
+
 // TEAM_151: Re-organized qcow2 module into dedicated submodules for better maintainability
 
 /// Build a qcow2 VM disk image without requiring root.
@@ -51,6 +53,8 @@ use std::path::Path;
 /// * `base_dir` - The leviso base directory (contains output/, downloads/)
 /// * `disk_size_gb` - Disk size in GB (sparse allocation)
 pub fn build_qcow2(base_dir: &Path, disk_size_gb: u32) -> Result<()> {
+    // Reuses the lock if called from `leviso build qcow2`
+    let _lock = OutputLock::acquire(base_dir, "build_qcow2")?;
     println!("=== Building qcow2 VM Image (sudo-free) ===\n");
 
     // Step 1: Verify host tools
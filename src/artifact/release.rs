@@ -0,0 +1,307 @@
+//! Release manifest - what a given ISO contains, in one JSON file.
+//!
+//! `assemble_iso` writes `release.json` next to the ISO:
+//!
+//! ```json
+//! {
+//!   "name": "LevitateOS",
+//!   "version": "1.0",
+//!   "build_id": "20261018.120000-g1a2b3c4",
+//!   "git_commit": "1a2b3c4...",
+//!   "kernel_version": "6.12.0-levitate",
+//!   "package_count": 412,
+//!   "artifacts": {
+//!     "filesystem.erofs": { "size": 734003200, "sha256": "...", "sha512": "..." },
+//!     ...
+//!   },
+//!   "ukis": [{ "filename": "levitateos-live.efi", "cmdline": "..." }, ...]
+//! }
+//! ```
+//!
+//! The same manifest goes into the live overlay as
+//! `/usr/lib/levitate/build-info.json`, minus the ISO entry (an image cannot
+//! contain its own hash). The rootfs build writes one at the same path into
+//! the EROFS for installed systems (which don't get the live overlay). That
+//! copy holds only what the rootfs inputs decide (version, kernel, package
+//! count): `build_id`, `git_commit` and `source_date_epoch` are `null`, since
+//! an EROFS is reused across builds (and restored from the artifact store)
+//! and would otherwise name a build other than the ISO's. The website,
+//! installer and bug reports read these instead of guessing from file names.
+//!
+//! `package_count` is `null` when the rootfs source manifest is missing.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256, Sha512};
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use distro_builder::process::Cmd;
+use distro_spec::levitate::{OS_NAME, OS_VERSION};
+
+use crate::build::reproducible;
+use crate::build::sources::{SourceManifest, SOURCES_JSON};
+
+/// Manifest filename next to the ISO.
+pub const RELEASE_JSON: &str = "release.json";
+
+/// Manifest path inside the image (relative to `/`).
+pub const BUILD_INFO_PATH: &str = "usr/lib/levitate/build-info.json";
+
+/// Overrides the generated build ID (e.g. a CI pipeline number).
+pub const BUILD_ID_ENV: &str = "BUILD_ID";
+
+/// Size and hashes of one artifact.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ArtifactInfo {
+    pub size: u64,
+    pub sha256: String,
+    pub sha512: String,
+}
+
+impl ArtifactInfo {
+    /// Hash `path` with SHA-256 and SHA-512 in a single read.
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let mut file =
+            fs::File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+        let (mut sha256, mut sha512) = (Sha256::new(), Sha512::new());
+        let mut size = 0;
+        let mut buf = vec![0u8; 1 << 20];
+        loop {
+            let n = file.read(&mut buf)?;
+            if n == 0 {
+                break;
+            }
+            sha256.update(&buf[..n]);
+            sha512.update(&buf[..n]);
+            size += n as u64;
+        }
+        Ok(Self {
+            size,
+            sha256: hex(&sha256.finalize()),
+            sha512: hex(&sha512.finalize()),
+        })
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// A UKI shipped on the ISO and its kernel cmdline.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UkiInfo {
+    pub filename: String,
+    /// For live UKIs, the arguments leviso adds to reciso's base cmdline
+    /// (root=LABEL=..., consoles); installed UKIs list the full cmdline.
+    pub cmdline: String,
+    pub installed: bool,
+}
+
+/// Machine-readable description of one build.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReleaseManifest {
+    pub name: String,
+    pub version: String,
+    /// `None` in the EROFS copy, like `git_commit` and `source_date_epoch`.
+    pub build_id: Option<String>,
+    pub git_commit: Option<String>,
+    pub source_date_epoch: Option<u64>,
+    pub kernel_version: Option<String>,
+    /// Binary packages in the rootfs; `None` without its source manifest.
+    pub package_count: Option<usize>,
+    /// Keyed by output filename.
+    pub artifacts: BTreeMap<String, ArtifactInfo>,
+    pub ukis: Vec<UkiInfo>,
+}
+
+impl ReleaseManifest {
+    /// Collect build metadata for `base_dir` (artifacts are added separately).
+    pub fn collect(base_dir: &Path, output_dir: &Path, ukis: Vec<UkiInfo>) -> Result<Self> {
+        let git_commit = git_commit(base_dir);
+        let epoch = reproducible::source_date_epoch()?;
+        let build_id = match env::var(BUILD_ID_ENV) {
+            Ok(id) if !id.trim().is_empty() => id.trim().to_string(),
+            _ => {
+                let secs = epoch.unwrap_or_else(|| {
+                    SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .map(|d| d.as_secs())
+                        .unwrap_or(0)
+                });
+                default_build_id(secs, git_commit.as_deref())
+            }
+        };
+
+        // Binary packages registered during the rootfs build
+        let sources = output_dir.join(SOURCES_JSON);
+        let package_count = if sources.exists() {
+            let manifest = SourceManifest::load(&sources)?;
+            Some(
+                manifest
+                    .packages
+                    .iter()
+                    .map(|p| p.binary_packages.len())
+                    .sum::<usize>()
+                    + manifest.unresolved.len(),
+            )
+        } else {
+            None
+        };
+
+        Ok(Self {
+            name: OS_NAME.to_string(),
+            version: OS_VERSION.to_string(),
+            build_id: Some(build_id),
+            git_commit,
+            source_date_epoch: epoch,
+            kernel_version: kernel_version(output_dir),
+            package_count,
+            artifacts: BTreeMap::new(),
+            ukis,
+        })
+    }
+
+    /// The manifest without the fields naming one build, for the EROFS copy.
+    pub fn without_build_identity(self) -> Self {
+        Self {
+            build_id: None,
+            git_commit: None,
+            source_date_epoch: None,
+            ..self
+        }
+    }
+
+    /// Record the size and hashes of an output file.
+    pub fn add_artifact(&mut self, path: &Path) -> Result<()> {
+        let name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .with_context(|| format!("No file name in {}", path.display()))?;
+        self.artifacts.insert(name, ArtifactInfo::from_file(path)?);
+        Ok(())
+    }
+
+    /// Write the manifest as pretty JSON, creating parent directories.
+    pub fn write(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(self)? + "\n")
+            .with_context(|| format!("Failed to write {}", path.display()))
+    }
+}
+
+fn git_commit(base_dir: &Path) -> Option<String> {
+    let result = Cmd::new("git")
+        .arg("-C")
+        .arg_path(base_dir)
+        .args(["rev-parse", "HEAD"])
+        .allow_fail()
+        .run()
+        .ok()?;
+    let commit = result.stdout.trim();
+    (result.success() && !commit.is_empty()).then(|| commit.to_string())
+}
+
+/// Version directory under the installed kernel modules.
+fn kernel_version(output_dir: &Path) -> Option<String> {
+    fs::read_dir(output_dir.join("staging/usr/lib/modules"))
+        .ok()?
+        .filter_map(|e| e.ok())
+        .find(|e| e.path().is_dir())
+        .map(|e| e.file_name().to_string_lossy().to_string())
+}
+
+/// `YYYYMMDD.HHMMSS` (UTC) plus `-g<short commit>` when known.
+fn default_build_id(secs: u64, git_commit: Option<&str>) -> String {
+    let days = (secs / 86_400) as i64;
+    let rem = secs % 86_400;
+    let (year, month, day) = civil_from_days(days);
+    let mut id = format!(
+        "{:04}{:02}{:02}.{:02}{:02}{:02}",
+        year,
+        month,
+        day,
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    );
+    if let Some(commit) = git_commit {
+        id.push_str(&format!("-g{}", &commit[..commit.len().min(7)]));
+    }
+    id
+}
+
+/// Days since 1970-01-01 to a (year, month, day) civil date.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_default_build_id() {
+        assert_eq!(default_build_id(0, None), "19700101.000000");
+        assert_eq!(
+            default_build_id(1_700_000_000, Some("1a2b3c4d5e6f")),
+            "20231114.221320-g1a2b3c4"
+        );
+        // Leap day
+        assert_eq!(default_build_id(951_782_400, None), "20000229.000000");
+    }
+
+    #[test]
+    fn test_artifact_info_and_roundtrip() {
+        let temp = TempDir::new().unwrap();
+        let erofs = temp.path().join("filesystem.erofs");
+        fs::write(&erofs, "abc").unwrap();
+
+        let mut manifest = ReleaseManifest {
+            name: OS_NAME.to_string(),
+            version: OS_VERSION.to_string(),
+            build_id: Some("test".to_string()),
+            git_commit: None,
+            source_date_epoch: Some(1),
+            kernel_version: None,
+            package_count: None,
+            artifacts: BTreeMap::new(),
+            ukis: Vec::new(),
+        };
+        manifest.add_artifact(&erofs).unwrap();
+        let info = &manifest.artifacts["filesystem.erofs"];
+        assert_eq!(info.size, 3);
+        assert_eq!(
+            info.sha256,
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(info.sha512.len(), 128);
+
+        let path = temp.path().join("usr/lib/levitate/build-info.json");
+        manifest.write(&path).unwrap();
+        let loaded: ReleaseManifest =
+            serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(loaded, manifest);
+
+        let rootfs_copy = manifest.without_build_identity();
+        assert_eq!(rootfs_copy.build_id, None);
+        assert_eq!(rootfs_copy.source_date_epoch, None);
+        assert_eq!(rootfs_copy.version, OS_VERSION);
+    }
+}
@@ -0,0 +1,438 @@
+//! Remaster an existing LevitateOS ISO without a rebuild.
+//!
+//! Field engineers need to add a support SSH key or a driver to a released
+//! ISO in seconds, without the Rocky download or a rootfs build. Remastering:
+//!
+//! 1. Extracts the ISO with `xorriso`
+//! 2. Pulls kernel and initramfs out of the default live UKI, and the boot
+//!    menu name and cmdline of every UKI in `EFI/Linux/` (`objcopy`)
+//! 3. Merges (or replaces) the live overlay with the given directory
+//! 4. Repacks through `reciso` with the original EROFS, the same live UKIs
+//!    (so their verity root hash) and every other file of the ISO that reciso
+//!    doesn't write itself (what `assemble_iso` added, and the additions of
+//!    earlier remasters), optionally appending arguments to every live UKI
+//!    cmdline
+//!
+//! The UKIs are taken from the ISO, not from this checkout, so remastering
+//! an older release keeps its boot entries. The EROFS is reused byte for
+//! byte, so the verity root hash stays valid.
+//!
+//! UKIs and systemd-boot are re-signed when `SECUREBOOT_KEY_DIR` is set. An
+//! ISO that ships Secure Boot enrollment keys is refused without it, since
+//! its unsigned remaster would not boot with Secure Boot enabled.
+
+use anyhow::{bail, Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use distro_builder::process::{self, Cmd};
+use distro_spec::levitate::{
+    EFIBOOT_FILENAME, ISO_CHECKSUM_SUFFIX, LIVE_OVERLAY_ISO_PATH, OS_ID, OS_NAME, OS_VERSION,
+    ROOTFS_ISO_PATH,
+};
+use leviso_elf::copy_dir_recursive;
+use reciso::{IsoConfig, UkiSource};
+
+use super::secureboot::{SecureBootKeys, ISO_KEYS_DIR, KEY_DIR_ENV};
+use super::signing::with_suffix;
+use super::uki::{live_base_cmdline, live_uki_entries};
+
+/// ISO directory holding the live UKIs.
+const LIVE_UKI_DIR: &str = "EFI/Linux";
+
+/// ISO paths reciso writes on every repack (a directory covers everything
+/// under it). Everything else on the source ISO is carried over.
+const RECISO_PATHS: &[&str] = &[LIVE_UKI_DIR, "EFI/BOOT", "loader"];
+
+/// File names reciso writes wherever they are (El Torito image and catalog).
+const RECISO_FILE_NAMES: &[&str] = &[EFIBOOT_FILENAME, "boot.catalog"];
+
+/// A live UKI found on the ISO.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct IsoUki {
+    /// Boot menu name (`PRETTY_NAME` of its `.osrel`).
+    name: String,
+    /// Cmdline without the base reciso adds again.
+    extra_cmdline: String,
+    filename: String,
+}
+
+/// What to change in the ISO.
+#[derive(Debug, Clone, Default)]
+pub struct RemasterOptions {
+    /// Directory merged over the live overlay.
+    pub overlay: Option<PathBuf>,
+    /// Replace the live overlay instead of merging into it.
+    pub replace_overlay: bool,
+    /// Extra files as (source, path on the ISO).
+    pub add: Vec<(PathBuf, String)>,
+    /// Arguments appended to every live UKI cmdline.
+    pub cmdline: Option<String>,
+}
+
+/// Parse a `--add SRC:DEST` argument.
+pub fn parse_add(spec: &str) -> Result<(PathBuf, String)> {
+    let Some((src, dest)) = spec.split_once(':') else {
+        bail!("--add expects SRC:DEST (path on the ISO), got '{}'", spec);
+    };
+    let dest = dest.trim_start_matches('/');
+    if src.is_empty() || dest.is_empty() || dest.split('/').any(|c| c == "..") {
+        bail!("--add expects SRC:DEST (path on the ISO), got '{}'", spec);
+    }
+    Ok((PathBuf::from(src), dest.to_string()))
+}
+
+/// Remaster `input` into `output`.
+pub fn remaster_iso(input: &Path, output: &Path, options: &RemasterOptions) -> Result<()> {
+    for (tool, package) in [("xorriso", "xorriso"), ("objcopy", "binutils")] {
+        if !process::exists(tool) {
+            bail!("{} not found. Install the {} package.", tool, package);
+        }
+    }
+    if !input.exists() {
+        bail!("ISO not found: {}", input.display());
+    }
+    if input == output {
+        bail!("Refusing to remaster an ISO onto itself - pass a different -o path");
+    }
+    for (src, _) in &options.add {
+        if !src.exists() {
+            bail!("--add source not found: {}", src.display());
+        }
+    }
+
+    let work = with_suffix(output, ".remaster");
+    let _ = fs::remove_dir_all(&work);
+    let extracted = work.join("iso");
+    fs::create_dir_all(&extracted)?;
+
+    println!("=== Remastering {} ===\n", input.display());
+    let label = volume_id(input)?;
+    println!("  Volume label: {}", label);
+    Cmd::new("xorriso")
+        .args(["-osirrox", "on", "-indev"])
+        .arg_path(input)
+        .args(["-extract", "/"])
+        .arg_path(&extracted)
+        .error_msg(&format!("Failed to extract {}", input.display()))
+        .run()?;
+    // xorriso extracts read-only; the tree is rewritten below
+    Cmd::new("chmod")
+        .args(["-R", "u+w"])
+        .arg_path(&extracted)
+        .error_msg("Failed to make extracted ISO writable")
+        .run()?;
+
+    let keys = SecureBootKeys::from_env()?;
+    if keys.is_none() && !list_files(&extracted, ISO_KEYS_DIR)?.is_empty() {
+        bail!(
+            "{} ships Secure Boot keys ({}/), but {} is not set.\n\
+             The remastered UKIs would be unsigned and fail to boot with Secure Boot\n\
+             enabled. Set {} to the key directory the ISO was signed with.",
+            input.display(),
+            ISO_KEYS_DIR,
+            KEY_DIR_ENV,
+            KEY_DIR_ENV
+        );
+    }
+
+    // Live UKIs as the ISO has them; kernel and initramfs from the default one
+    let ukis = read_live_ukis(&extracted.join(LIVE_UKI_DIR), &label, &work)?;
+    let kernel = work.join("vmlinuz");
+    let initramfs = work.join("initramfs.img");
+    let default_uki = extracted.join(LIVE_UKI_DIR).join(&ukis[0].filename);
+    dump_section(&default_uki, ".linux", &kernel)?;
+    dump_section(&default_uki, ".initrd", &initramfs)?;
+    println!(
+        "  {} live UKIs, kernel and initramfs from {}",
+        ukis.len(),
+        ukis[0].filename
+    );
+
+    // Live overlay
+    let rootfs = extracted.join(ROOTFS_ISO_PATH.trim_start_matches('/'));
+    if !rootfs.is_file() {
+        bail!("{} not found on the ISO", ROOTFS_ISO_PATH);
+    }
+    let overlay = extracted.join(LIVE_OVERLAY_ISO_PATH.trim_start_matches('/'));
+    if options.replace_overlay {
+        let _ = fs::remove_dir_all(&overlay);
+    }
+    fs::create_dir_all(&overlay)?;
+    if let Some(src) = &options.overlay {
+        if !src.is_dir() {
+            bail!("Overlay directory not found: {}", src.display());
+        }
+        copy_dir_recursive(src, &overlay)?;
+        println!("  Merged {} into the live overlay", src.display());
+    }
+
+    let temp_iso = with_suffix(output, ".tmp");
+    let mut config = IsoConfig::new(&kernel, &initramfs, &rootfs, &label, temp_iso.clone())
+        .with_os_release(OS_NAME, OS_ID, OS_VERSION)
+        .with_overlay(overlay.clone());
+
+    for uki in ukis {
+        let mut args: Vec<String> = Vec::new();
+        if !uki.extra_cmdline.is_empty() {
+            args.push(uki.extra_cmdline);
+        }
+        if let Some(extra) = &options.cmdline {
+            args.push(extra.trim().to_string());
+        }
+        config.ukis.push(UkiSource::Build {
+            name: uki.name,
+            extra_cmdline: args.join(" "),
+            filename: uki.filename,
+        });
+    }
+
+    // Carry over everything reciso doesn't write itself: what assemble_iso
+    // added, and files added by earlier remasters
+    for iso_path in carried_files(&extracted)? {
+        if options.add.iter().any(|(_, dest)| *dest == iso_path) {
+            continue;
+        }
+        config
+            .extra_files
+            .push((extracted.join(&iso_path), iso_path));
+    }
+    config.extra_files.extend(options.add.iter().cloned());
+
+    if let Some(keys) = keys {
+        config = config.with_secure_boot(keys.key("db"), keys.cert("db"));
+    }
+
+    println!("Repacking via reciso...");
+    reciso::create_iso(&config)?;
+    fs::rename(&temp_iso, output)?;
+    let checksum_ext = ISO_CHECKSUM_SUFFIX.trim_start_matches('.');
+    let temp_checksum = temp_iso.with_extension(checksum_ext);
+    if temp_checksum.exists() {
+        fs::rename(&temp_checksum, output.with_extension(checksum_ext))?;
+    }
+    let _ = fs::remove_dir_all(&work);
+
+    super::verify_iso(output)?;
+    println!("\nRemastered ISO: {}", output.display());
+    Ok(())
+}
+
+/// Read the ISO volume ID, so the initramfs still finds the boot device.
+fn volume_id(iso: &Path) -> Result<String> {
+    let result = Cmd::new("xorriso")
+        .arg("-indev")
+        .arg_path(iso)
+        .arg("-pvd_info")
+        .error_msg(&format!("Failed to read {}", iso.display()))
+        .run()?;
+    parse_volume_id(&result.stdout).with_context(|| format!("No volume ID in {}", iso.display()))
+}
+
+fn parse_volume_id(pvd_info: &str) -> Option<String> {
+    pvd_info.lines().find_map(|line| {
+        let (key, value) = line.split_once(':')?;
+        (key.trim() == "Volume Id" && !value.trim().is_empty()).then(|| value.trim().to_string())
+    })
+}
+
+/// Extract a PE section of a UKI to a file.
+fn dump_section(uki: &Path, section: &str, output: &Path) -> Result<()> {
+    Cmd::new("objcopy")
+        .arg("--dump-section")
+        .arg(format!("{}={}", section, output.display()))
+        .arg_path(uki)
+        .arg("/dev/null")
+        .error_msg(&format!(
+            "Failed to extract {} from {}",
+            section,
+            uki.display()
+        ))
+        .run()?;
+    Ok(())
+}
+
+/// Every `*.efi` in `dir` with its boot menu name and cmdline, in boot menu
+/// order: the entries this checkout knows first (the default entry leads),
+/// then the rest by filename.
+fn read_live_ukis(dir: &Path, label: &str, work: &Path) -> Result<Vec<IsoUki>> {
+    let mut filenames: Vec<String> = fs::read_dir(dir)
+        .with_context(|| format!("No {}/ on the ISO - not a LevitateOS ISO?", LIVE_UKI_DIR))?
+        .filter_map(|e| e.ok())
+        .map(|e| e.file_name().to_string_lossy().into_owned())
+        .filter(|name| name.ends_with(".efi"))
+        .collect();
+    let known: Vec<&str> = live_uki_entries().iter().map(|e| e.filename).collect();
+    filenames.sort_by_key(|name| {
+        (
+            known
+                .iter()
+                .position(|k| *k == name.as_str())
+                .unwrap_or(known.len()),
+            name.clone(),
+        )
+    });
+    if filenames.is_empty() {
+        bail!("No live UKI in {}/ - not a LevitateOS ISO?", LIVE_UKI_DIR);
+    }
+
+    let base = live_base_cmdline(label);
+    let mut ukis = Vec::new();
+    for filename in filenames {
+        let uki = dir.join(&filename);
+        let cmdline = work.join(format!("{}.cmdline", filename));
+        let osrel = work.join(format!("{}.osrel", filename));
+        dump_section(&uki, ".cmdline", &cmdline)?;
+        dump_section(&uki, ".osrel", &osrel)?;
+        let name = os_release_name(&fs::read_to_string(&osrel)?)
+            .unwrap_or_else(|| filename.trim_end_matches(".efi").to_string());
+        ukis.push(IsoUki {
+            name,
+            extra_cmdline: strip_base_args(&fs::read_to_string(&cmdline)?, &base),
+            filename,
+        });
+    }
+    Ok(ukis)
+}
+
+/// `cmdline` without the arguments of `base` (each removed once), in order.
+fn strip_base_args(cmdline: &str, base: &str) -> String {
+    let mut base: Vec<&str> = base.split_whitespace().collect();
+    cmdline
+        .trim_end_matches('\0')
+        .split_whitespace()
+        .filter(|arg| match base.iter().position(|b| b == arg) {
+            Some(i) => {
+                base.remove(i);
+                false
+            }
+            None => true,
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// `PRETTY_NAME` (or `NAME`) from os-release contents.
+fn os_release_name(os_release: &str) -> Option<String> {
+    let value = |key: &str| {
+        os_release.lines().find_map(|line| {
+            let value = line.trim().strip_prefix(key)?.strip_prefix('=')?;
+            let value = value.trim_matches('"').trim_matches('\'');
+            (!value.is_empty()).then(|| value.to_string())
+        })
+    };
+    value("PRETTY_NAME").or_else(|| value("NAME"))
+}
+
+/// Files on the extracted ISO that reciso won't regenerate, as ISO paths.
+fn carried_files(extracted: &Path) -> Result<Vec<String>> {
+    let regenerated = [ROOTFS_ISO_PATH, LIVE_OVERLAY_ISO_PATH]
+        .map(|p| p.trim_start_matches('/'))
+        .into_iter()
+        .chain(RECISO_PATHS.iter().copied())
+        .collect::<Vec<_>>();
+    Ok(list_files(extracted, "")?
+        .into_iter()
+        .filter(|file| {
+            let path = Path::new(file);
+            !regenerated.iter().any(|r| path.starts_with(r))
+                && !path
+                    .file_name()
+                    .is_some_and(|n| RECISO_FILE_NAMES.iter().any(|r| n == *r))
+        })
+        .collect())
+}
+
+/// Files under `dir` on the extracted ISO, as ISO paths.
+fn list_files(root: &Path, dir: &str) -> Result<Vec<String>> {
+    let mut files = Vec::new();
+    let base = root.join(dir);
+    if !base.is_dir() {
+        return Ok(files);
+    }
+    for entry in walkdir::WalkDir::new(&base).sort_by_file_name() {
+        let entry = entry?;
+        if entry.file_type().is_file() {
+            let relative = entry.path().strip_prefix(root)?;
+            files.push(relative.to_string_lossy().to_string());
+        }
+    }
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_add() {
+        let (src, dest) = parse_add("keys/support.pub:/extras/support.pub").unwrap();
+        assert_eq!(src, PathBuf::from("keys/support.pub"));
+        assert_eq!(dest, "extras/support.pub");
+        assert!(parse_add("no-destination").is_err());
+        assert!(parse_add("a:../../etc/passwd").is_err());
+    }
+
+    #[test]
+    fn test_uki_cmdline_and_name() {
+        let base = live_base_cmdline("LEVITATEOS");
+        let cmdline = format!("{} toram levitate.verity=abc123 sshd.debug\n\0", base);
+        assert_eq!(
+            strip_base_args(&cmdline, &base),
+            "toram levitate.verity=abc123 sshd.debug"
+        );
+        assert_eq!(strip_base_args(&format!("{}\0", base), &base), "");
+
+        let osrel =
+            "NAME=\"LevitateOS\"\nID=levitateos\nPRETTY_NAME=\"LevitateOS (Copy to RAM)\"\n";
+        assert_eq!(
+            os_release_name(osrel).as_deref(),
+            Some("LevitateOS (Copy to RAM)")
+        );
+        assert_eq!(
+            os_release_name("NAME=LevitateOS\n").as_deref(),
+            Some("LevitateOS")
+        );
+        assert_eq!(os_release_name("ID=levitateos\n"), None);
+    }
+
+    #[test]
+    fn test_remastered_iso_keeps_earlier_additions() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let iso = temp.path();
+        for file in [
+            "EFI/Linux/levitateos-live.efi",
+            "EFI/BOOT/BOOTX64.EFI",
+            "loader/loader.conf",
+            "boot/efiboot.img",
+            ROOTFS_ISO_PATH.trim_start_matches('/'),
+            &format!("{}/etc/motd", LIVE_OVERLAY_ISO_PATH.trim_start_matches('/')),
+            "live/filesystem.erofs.verity",
+            "boot/uki/levitateos.efi",
+            // Added by an earlier `remaster --add`
+            "extras/support.pub",
+            "drivers/r8125.ko",
+        ] {
+            let path = iso.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "x").unwrap();
+        }
+
+        assert_eq!(
+            carried_files(iso).unwrap(),
+            [
+                "boot/uki/levitateos.efi",
+                "drivers/r8125.ko",
+                "extras/support.pub",
+                "live/filesystem.erofs.verity",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_volume_id() {
+        let pvd = "Volume Id    : LEVITATEOS\nApp Id       : reciso\n";
+        assert_eq!(parse_volume_id(pvd).as_deref(), Some("LEVITATEOS"));
+        assert_eq!(parse_volume_id("Volume Id    : \n"), None);
+    }
+}
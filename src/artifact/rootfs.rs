@@ -45,13 +45,20 @@ use anyhow::{bail, Context, Result};
 use std::fs;
 use std::path::Path;
 
-use crate::build::BuildContext;
+use super::release::{ReleaseManifest, BUILD_INFO_PATH};
+use crate::build::checkpoint::{self, Checkpoints};
+use crate::build::{inputs, reproducible, BuildContext};
+use crate::rebuild;
 use distro_builder::build_erofs_default;
+use distro_builder::process::Cmd;
 use distro_spec::levitate::ROOTFS_NAME;
 use distro_spec::shared::{
     AUTH_BIN, BIN_UTILS, ESSENTIAL_UNITS, ETC_FILES, FHS_DIRS, NM_BIN, NM_UNITS, SSH_BIN, WPA_UNITS,
 };
 
+/// Compression for reproducible EROFS images, matching `build_erofs_default`.
+const EROFS_COMPRESSION: &str = "-zzstd";
+
 /// Build the complete rootfs (EROFS) system image.
 ///
 /// This creates a filesystem.erofs in output/ containing the complete
@@ -64,7 +71,24 @@ use distro_spec::shared::{
 /// - Build into `.work` files (rootfs-staging.work, filesystem.erofs.work)
 /// - Only swap to final locations after successful completion
 /// - If cancelled mid-build, existing rootfs-staging/ and filesystem.erofs are preserved
+///
+/// # Checkpoints
+///
+/// A checkpoint is written after every `build_system()` phase (see
+/// `build::checkpoint`). If a phase fails, `rootfs-staging.work` is kept so
+/// the build can continue with `build_rootfs_resume()`.
 pub fn build_rootfs(base_dir: &Path) -> Result<()> {
+    build_rootfs_inner(base_dir, false)
+}
+
+/// Continue an interrupted rootfs build after its last completed phase.
+///
+/// Refuses to resume if the rootfs inputs changed since the checkpoint.
+pub fn build_rootfs_resume(base_dir: &Path) -> Result<()> {
+    build_rootfs_inner(base_dir, true)
+}
+
+fn build_rootfs_inner(base_dir: &Path, resume: bool) -> Result<()> {
     println!("=== Building EROFS System Image ===\n");
 
     check_host_tools()?;
@@ -76,16 +100,44 @@ pub fn build_rootfs(base_dir: &Path) -> Result<()> {
     let final_staging = output_dir.join("rootfs-staging");
     let final_output = output_dir.join(ROOTFS_NAME);
 
-    // 1. Clean WORK directories only (preserve final)
-    // Use let _ = to ignore errors (may not exist)
-    let _ = fs::remove_dir_all(&work_staging);
-    let _ = fs::remove_file(&work_output);
-    fs::create_dir_all(&work_staging)?;
+    // Checkpoints are keyed by the input hash; without one we can't resume
+    let input_hash = rebuild::rootfs_artifact(base_dir)?
+        .current_hash()
+        .unwrap_or_default();
+
+    // 1. Clean WORK directories only (preserve final), unless resuming
+    let checkpoints = if resume {
+        let checkpoints = Checkpoints::resume(&output_dir, &input_hash)?;
+        if !work_staging.exists() {
+            bail!(
+                "Checkpoint found but {} is missing - cannot resume.\n\
+                 Run 'leviso build rootfs' for a fresh build.",
+                work_staging.display()
+            );
+        }
+        let _ = fs::remove_file(&work_output);
+        checkpoints
+    } else {
+        // Use let _ = to ignore errors (may not exist)
+        let _ = fs::remove_dir_all(&work_staging);
+        let _ = fs::remove_file(&work_output);
+        fs::create_dir_all(&work_staging)?;
+        Checkpoints::fresh(&output_dir, &input_hash)?
+    };
 
     // 2. Build into work directory (may fail - final is preserved)
+    // Record every repo file the build reads, for rebuild detection.
+    inputs::start_recording();
     let build_result = (|| -> Result<()> {
         let ctx = BuildContext::new(base_dir, &work_staging)?;
-        crate::component::build_system(&ctx)?;
+        let checkpoints = (!input_hash.is_empty()).then_some(&checkpoints);
+        crate::component::build_system(&ctx, checkpoints)?;
+
+        // Build info for installed systems, which don't get the live overlay.
+        // The EROFS outlives this build, so it doesn't name one.
+        ReleaseManifest::collect(base_dir, &output_dir, Vec::new())?
+            .without_build_identity()
+            .write(&work_staging.join(BUILD_INFO_PATH))?;
 
         // Verify staging directory before creating EROFS
         verify_staging(&work_staging)?;
@@ -94,13 +146,28 @@ pub fn build_rootfs(base_dir: &Path) -> Result<()> {
         create_erofs_internal(&work_staging, &work_output)?;
         Ok(())
     })();
+    let recorded_inputs = inputs::finish_recording();
 
-    // 3. On failure, clean up work files and propagate error
+    // 3. On failure, keep the work staging if a phase checkpoint exists,
+    // otherwise clean up work files. Propagate the error either way.
     if let Err(e) = build_result {
-        let _ = fs::remove_dir_all(&work_staging);
         let _ = fs::remove_file(&work_output);
+        match checkpoint::load(&output_dir).ok().flatten() {
+            Some(point) => {
+                println!(
+                    "\n[CHECKPOINT] Phases up to {} ({}) completed - work directory kept.\n\
+                     If nothing in the inputs needs to change (disk full, missing host tool),\n\
+                     continue with: leviso build rootfs --resume",
+                    point.completed_phase, point.phase_name
+                );
+            }
+            None => {
+                let _ = fs::remove_dir_all(&work_staging);
+            }
+        }
         return Err(e);
     }
+    checkpoint::clear(&output_dir)?;
 
     // 4. Atomic swap (only reached if build succeeded)
     // Order matters: remove old, then rename new
@@ -112,6 +179,10 @@ pub fn build_rootfs(base_dir: &Path) -> Result<()> {
         .context("Failed to move rootfs-staging.work to rootfs-staging")?;
     fs::rename(&work_output, &final_output)
         .context("Failed to move filesystem.erofs.work to filesystem.erofs")?;
+    inputs::write_input_list(
+        &output_dir.join(inputs::ROOTFS_INPUTS_LIST),
+        &recorded_inputs,
+    )?;
 
     println!("\n=== EROFS Build Complete ===");
     println!("  Output: {}", final_output.display());
@@ -126,7 +197,11 @@ pub fn build_rootfs(base_dir: &Path) -> Result<()> {
 fn check_host_tools() -> Result<()> {
     use distro_builder::process;
 
-    let tools = [("mkfs.erofs", "erofs-utils"), ("readelf", "binutils")];
+    let tools = [
+        ("mkfs.erofs", "erofs-utils"),
+        ("readelf", "binutils"),
+        ("rpm", "rpm"),
+    ];
 
     for (tool, package) in tools {
         if !process::exists(tool) {
@@ -152,11 +227,34 @@ fn check_host_tools() -> Result<()> {
 /// Uses the shared implementation from `distro_builder::artifact::rootfs`.
 /// This ensures both LevitateOS and AcornOS use the same EROFS building code.
 ///
+/// In reproducible builds (`SOURCE_DATE_EPOCH` set) mkfs.erofs is run here
+/// instead, with staging mtimes clamped and a fixed timestamp, owner and UUID
+/// (see `build::reproducible`).
+///
 /// NOTE: This does NOT delete the output file first - mkfs.erofs creates a fresh file.
 /// Caller is responsible for cleanup.
 fn create_erofs_internal(staging: &Path, output: &Path) -> Result<()> {
-    // Use the shared distro-builder implementation
-    build_erofs_default(staging, output)
+    let Some(epoch) = reproducible::source_date_epoch()? else {
+        // Use the shared distro-builder implementation
+        return build_erofs_default(staging, output);
+    };
+
+    println!(
+        "  Creating reproducible EROFS (SOURCE_DATE_EPOCH={})...",
+        epoch
+    );
+    reproducible::clamp_mtimes(staging, epoch)?;
+    let uuid = reproducible::stable_uuid("erofs", epoch);
+    Cmd::new("mkfs.erofs")
+        .arg(EROFS_COMPRESSION)
+        .arg(format!("-T{}", epoch))
+        .arg("--all-root")
+        .args(["-U", uuid.as_str()])
+        .arg_path(output)
+        .arg_path(staging)
+        .error_msg("mkfs.erofs failed")
+        .run()?;
+    Ok(())
 }
 
 /// Verify the staging directory contains required files before creating EROFS.
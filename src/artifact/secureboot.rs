@@ -0,0 +1,314 @@
+//! Secure Boot signing with local keys.
+//!
+//! When `SECUREBOOT_KEY_DIR` is set, every UKI (live, emergency, debug,
+//! installed) and the ISO's systemd-boot binary are signed with the db key,
+//! and the ISO ships `.auth` enrollment files for PK, KEK and db.
+//!
+//! The key directory holds one keypair per Secure Boot variable plus the
+//! owner GUID used in the signature lists:
+//!
+//! ```text
+//! $SECUREBOOT_KEY_DIR/
+//! ├── PK.key  PK.crt     # Platform Key - signs KEK updates
+//! ├── KEK.key KEK.crt    # Key Exchange Key - signs db updates
+//! ├── db.key  db.crt     # Signature database - signs UKIs and systemd-boot
+//! └── GUID               # Owner GUID for the EFI signature lists
+//! ```
+//!
+//! `leviso secureboot-keys <dir>` generates a test set with openssl.
+//! `leviso test --secureboot` enrolls the certs into a copy of the OVMF
+//! secure-boot vars and fails unless the kernel reports Secure Boot enabled.
+//!
+//! Host tools: `sbsigntools` (sbsign, sbverify), `efitools`
+//! (cert-to-efi-sig-list, sign-efi-sig-list) and, for testing, `virt-fw-vars`
+//! from python3-virt-firmware.
+
+use anyhow::{bail, Context, Result};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use distro_builder::process::{self, Cmd};
+
+/// Environment variable pointing at the key directory.
+pub const KEY_DIR_ENV: &str = "SECUREBOOT_KEY_DIR";
+
+/// Secure Boot variables, in enrollment order (each signed by the previous).
+pub const VARIABLES: [&str; 3] = ["PK", "KEK", "db"];
+
+/// ISO directory holding the enrollment files.
+pub const ISO_KEYS_DIR: &str = "EFI/levitate/keys";
+
+/// Output subdirectory for `.auth` files and enrolled OVMF vars.
+pub const SECUREBOOT_OUTPUT_DIR: &str = "secureboot";
+
+/// Secure-boot-capable OVMF builds: (code, vars template).
+const OVMF_SECBOOT_CANDIDATES: &[(&str, &str)] = &[
+    (
+        "/usr/share/edk2/ovmf/OVMF_CODE.secboot.fd",
+        "/usr/share/edk2/ovmf/OVMF_VARS.fd",
+    ),
+    (
+        "/usr/share/OVMF/OVMF_CODE_4M.secboot.fd",
+        "/usr/share/OVMF/OVMF_VARS_4M.fd",
+    ),
+    (
+        "/usr/share/OVMF/OVMF_CODE.secboot.fd",
+        "/usr/share/OVMF/OVMF_VARS.fd",
+    ),
+    (
+        "/usr/share/edk2/x64/OVMF_CODE.secboot.4m.fd",
+        "/usr/share/edk2/x64/OVMF_VARS.4m.fd",
+    ),
+];
+
+/// A Secure Boot key directory.
+#[derive(Debug, Clone)]
+pub struct SecureBootKeys {
+    dir: PathBuf,
+    guid: String,
+}
+
+impl SecureBootKeys {
+    /// Keys from `SECUREBOOT_KEY_DIR`, or `None` if signing is not configured.
+    pub fn from_env() -> Result<Option<Self>> {
+        match env::var(KEY_DIR_ENV) {
+            Ok(dir) if !dir.trim().is_empty() => Self::open(Path::new(dir.trim())).map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    /// Open a key directory, failing if any key, cert or the GUID is missing.
+    pub fn open(dir: &Path) -> Result<Self> {
+        let missing: Vec<String> = VARIABLES
+            .iter()
+            .flat_map(|var| [format!("{}.key", var), format!("{}.crt", var)])
+            .chain(["GUID".to_string()])
+            .filter(|name| !dir.join(name).exists())
+            .collect();
+        if !missing.is_empty() {
+            bail!(
+                "Secure Boot key directory {} is incomplete (missing: {}).\n\
+                 Generate test keys with: leviso secureboot-keys {}",
+                dir.display(),
+                missing.join(", "),
+                dir.display()
+            );
+        }
+        let guid = fs::read_to_string(dir.join("GUID"))?.trim().to_string();
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            guid,
+        })
+    }
+
+    pub fn key(&self, var: &str) -> PathBuf {
+        self.dir.join(format!("{}.key", var))
+    }
+
+    pub fn cert(&self, var: &str) -> PathBuf {
+        self.dir.join(format!("{}.crt", var))
+    }
+
+    pub fn guid(&self) -> &str {
+        &self.guid
+    }
+}
+
+/// Generate a self-signed PK/KEK/db test key set in `dir`.
+///
+/// Refuses to overwrite existing keys.
+pub fn generate_keys(dir: &Path) -> Result<SecureBootKeys> {
+    require_tools(&[("openssl", "openssl")])?;
+    if dir.join("PK.key").exists() {
+        bail!(
+            "{} already contains Secure Boot keys - refusing to overwrite",
+            dir.display()
+        );
+    }
+    fs::create_dir_all(dir)?;
+
+    for var in VARIABLES {
+        println!("  Generating {} keypair...", var);
+        let subject = format!("/CN=LevitateOS Test {}/", var);
+        Cmd::new("openssl")
+            .args([
+                "req", "-new", "-x509", "-newkey", "rsa:2048", "-nodes", "-sha256",
+            ])
+            .args(["-days", "3650"])
+            .args(["-subj", subject.as_str()])
+            .arg("-keyout")
+            .arg_path(&dir.join(format!("{}.key", var)))
+            .arg("-out")
+            .arg_path(&dir.join(format!("{}.crt", var)))
+            .error_msg(&format!("Failed to generate {} keypair", var))
+            .run()?;
+    }
+
+    let guid = fs::read_to_string("/proc/sys/kernel/random/uuid")
+        .context("Failed to generate owner GUID")?;
+    fs::write(dir.join("GUID"), guid.trim().to_string() + "\n")?;
+
+    println!("  Keys written to {}", dir.display());
+    SecureBootKeys::open(dir)
+}
+
+/// Sign an EFI binary in place with the db key, and verify the signature.
+pub fn sign_efi(keys: &SecureBootKeys, efi: &Path) -> Result<()> {
+    require_tools(&[("sbsign", "sbsigntools"), ("sbverify", "sbsigntools")])?;
+
+    let signed = efi.with_extension("efi.signed");
+    Cmd::new("sbsign")
+        .arg("--key")
+        .arg_path(&keys.key("db"))
+        .arg("--cert")
+        .arg_path(&keys.cert("db"))
+        .arg("--output")
+        .arg_path(&signed)
+        .arg_path(efi)
+        .error_msg(&format!("sbsign failed for {}", efi.display()))
+        .run()?;
+
+    let verified = Cmd::new("sbverify")
+        .arg("--cert")
+        .arg_path(&keys.cert("db"))
+        .arg_path(&signed)
+        .allow_fail()
+        .run()?;
+    if !verified.success() {
+        let _ = fs::remove_file(&signed);
+        bail!(
+            "Signature on {} does not verify against {}",
+            efi.display(),
+            keys.cert("db").display()
+        );
+    }
+
+    fs::rename(&signed, efi)
+        .with_context(|| format!("Failed to replace {} with signed binary", efi.display()))?;
+    println!("  Signed {}", efi.display());
+    Ok(())
+}
+
+/// Write `PK.auth`, `KEK.auth` and `db.auth` into `out_dir`.
+///
+/// PK is self-signed, KEK is signed by PK and db by KEK, so they can be
+/// enrolled in that order from firmware setup mode.
+pub fn write_auth_files(keys: &SecureBootKeys, out_dir: &Path) -> Result<Vec<PathBuf>> {
+    require_tools(&[
+        ("cert-to-efi-sig-list", "efitools"),
+        ("sign-efi-sig-list", "efitools"),
+    ])?;
+    fs::create_dir_all(out_dir)?;
+
+    let mut outputs = Vec::new();
+    for (i, var) in VARIABLES.iter().enumerate() {
+        let signer = signer_for(i);
+        let esl = out_dir.join(format!("{}.esl", var));
+        let auth = out_dir.join(format!("{}.auth", var));
+
+        Cmd::new("cert-to-efi-sig-list")
+            .args(["-g", keys.guid()])
+            .arg_path(&keys.cert(var))
+            .arg_path(&esl)
+            .error_msg(&format!("Failed to create {} signature list", var))
+            .run()?;
+        Cmd::new("sign-efi-sig-list")
+            .args(["-g", keys.guid()])
+            .arg("-k")
+            .arg_path(&keys.key(signer))
+            .arg("-c")
+            .arg_path(&keys.cert(signer))
+            .arg(var)
+            .arg_path(&esl)
+            .arg_path(&auth)
+            .error_msg(&format!("Failed to sign {} signature list", var))
+            .run()?;
+
+        let _ = fs::remove_file(&esl);
+        outputs.push(auth);
+    }
+    Ok(outputs)
+}
+
+/// Variable whose key signs the enrollment of `VARIABLES[index]`.
+fn signer_for(index: usize) -> &'static str {
+    VARIABLES[index.saturating_sub(1)]
+}
+
+/// Find a secure-boot OVMF build: (code, vars template).
+pub fn find_ovmf_secboot() -> Result<(PathBuf, PathBuf)> {
+    OVMF_SECBOOT_CANDIDATES
+        .iter()
+        .map(|(code, vars)| (PathBuf::from(code), PathBuf::from(vars)))
+        .find(|(code, vars)| code.exists() && vars.exists())
+        .context(
+            "Secure Boot OVMF firmware not found (OVMF_CODE*.secboot.fd).\n\
+             Install OVMF:\n\
+             - Fedora/RHEL: sudo dnf install edk2-ovmf\n\
+             - Debian/Ubuntu: sudo apt install ovmf",
+        )
+}
+
+/// Create an OVMF vars file with the PK/KEK/db certs enrolled and Secure
+/// Boot enabled.
+pub fn enroll_ovmf_vars(keys: &SecureBootKeys, template: &Path, output: &Path) -> Result<()> {
+    require_tools(&[("virt-fw-vars", "python3-virt-firmware")])?;
+    if let Some(parent) = output.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    Cmd::new("virt-fw-vars")
+        .arg("--input")
+        .arg_path(template)
+        .arg("--output")
+        .arg_path(output)
+        .args(["--set-pk", keys.guid()])
+        .arg_path(&keys.cert("PK"))
+        .args(["--add-kek", keys.guid()])
+        .arg_path(&keys.cert("KEK"))
+        .args(["--add-db", keys.guid()])
+        .arg_path(&keys.cert("db"))
+        .arg("--secure-boot")
+        .error_msg("Failed to enroll Secure Boot keys into OVMF vars")
+        .run()?;
+    Ok(())
+}
+
+fn require_tools(tools: &[(&str, &str)]) -> Result<()> {
+    for (tool, package) in tools {
+        if !process::exists(tool) {
+            bail!(
+                "{} not found (needed for Secure Boot). Install the {} package.",
+                tool,
+                package
+            );
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_open_reports_missing_files() {
+        let temp = TempDir::new().unwrap();
+        fs::write(temp.path().join("PK.key"), "").unwrap();
+        fs::write(temp.path().join("PK.crt"), "").unwrap();
+
+        let err = SecureBootKeys::open(temp.path()).unwrap_err().to_string();
+        assert!(err.contains("KEK.key, KEK.crt, db.key, db.crt, GUID"));
+        assert!(!err.contains("PK.key"));
+    }
+
+    #[test]
+    fn test_enrollment_chain() {
+        // PK self-signs, KEK is signed by PK, db by KEK
+        assert_eq!(signer_for(0), "PK");
+        assert_eq!(signer_for(1), "PK");
+        assert_eq!(signer_for(2), "KEK");
+    }
+}
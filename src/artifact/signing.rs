@@ -0,0 +1,439 @@
+//! Detached signatures for the ISO and its checksum.
+//!
+//! When a signing key is configured, `assemble_iso` signs both the ISO and
+//! its checksum file after they are written:
+//!
+//! | Config | Signature files |
+//! |--------|-----------------|
+//! | `ISO_SIGNING_GPG_KEY=<key id>` | `<file>.asc` (armored OpenPGP, `gpg --detach-sign`) |
+//! | `ISO_SIGNING_MINISIGN_KEY=<secret key>` | `<file>.minisig` (minisign/ed25519) |
+//!
+//! `leviso verify-iso <path>` checks the checksum and every signature found
+//! next to the ISO without network access. OpenPGP signatures must be a
+//! `VALIDSIG` from the expected key (`--gpg-fingerprint` or
+//! `ISO_SIGNING_GPG_FINGERPRINT`), not just from any key in the local
+//! keyring; minisign signatures need the public key (`--key` or
+//! `ISO_SIGNING_MINISIGN_PUBKEY`).
+
+use anyhow::{bail, Context, Result};
+use sha2::{Digest, Sha256, Sha512};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use distro_builder::process::{self, Cmd};
+use distro_spec::levitate::ISO_CHECKSUM_SUFFIX;
+
+/// OpenPGP key ID or fingerprint to sign with.
+pub const GPG_KEY_ENV: &str = "ISO_SIGNING_GPG_KEY";
+
+/// Path to a minisign secret key to sign with.
+pub const MINISIGN_KEY_ENV: &str = "ISO_SIGNING_MINISIGN_KEY";
+
+/// Path to the minisign public key used by `verify-iso`.
+pub const MINISIGN_PUBKEY_ENV: &str = "ISO_SIGNING_MINISIGN_PUBKEY";
+
+/// Full fingerprint of the OpenPGP key `verify-iso` accepts signatures from.
+pub const GPG_FINGERPRINT_ENV: &str = "ISO_SIGNING_GPG_FINGERPRINT";
+
+/// Signature file suffixes, one per signer.
+pub const SIGNATURE_SUFFIXES: [&str; 2] = [".asc", ".minisig"];
+
+/// A configured signing key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Signer {
+    Gpg { key_id: String },
+    Minisign { secret_key: PathBuf },
+}
+
+impl Signer {
+    /// Signer from the environment, or `None` if signing is not configured.
+    pub fn from_env() -> Result<Option<Self>> {
+        let gpg = non_empty_env(GPG_KEY_ENV);
+        let minisign = non_empty_env(MINISIGN_KEY_ENV);
+        match (gpg, minisign) {
+            (Some(_), Some(_)) => bail!(
+                "Both {} and {} are set - pick one signing key",
+                GPG_KEY_ENV,
+                MINISIGN_KEY_ENV
+            ),
+            (Some(key_id), None) => Ok(Some(Signer::Gpg { key_id })),
+            (None, Some(path)) => {
+                let secret_key = PathBuf::from(path);
+                if !secret_key.exists() {
+                    bail!(
+                        "{} points to {}, which does not exist",
+                        MINISIGN_KEY_ENV,
+                        secret_key.display()
+                    );
+                }
+                Ok(Some(Signer::Minisign { secret_key }))
+            }
+            (None, None) => Ok(None),
+        }
+    }
+
+    /// Signature file written by this signer for `file`.
+    pub fn signature_path(&self, file: &Path) -> PathBuf {
+        match self {
+            Signer::Gpg { .. } => with_suffix(file, SIGNATURE_SUFFIXES[0]),
+            Signer::Minisign { .. } => with_suffix(file, SIGNATURE_SUFFIXES[1]),
+        }
+    }
+
+    /// Write a detached signature for `file`.
+    pub fn sign(&self, file: &Path) -> Result<PathBuf> {
+        let signature = self.signature_path(file);
+        match self {
+            Signer::Gpg { key_id } => {
+                require_tool("gpg", "gnupg2")?;
+                Cmd::new("gpg")
+                    .args(["--batch", "--yes", "--armor", "--detach-sign"])
+                    .args(["--local-user", key_id.as_str()])
+                    .arg("--output")
+                    .arg_path(&signature)
+                    .arg_path(file)
+                    .error_msg(&format!("gpg failed to sign {}", file.display()))
+                    .run()?;
+            }
+            Signer::Minisign { secret_key } => {
+                require_tool("minisign", "minisign")?;
+                Cmd::new("minisign")
+                    .arg("-S")
+                    .arg("-s")
+                    .arg_path(secret_key)
+                    .arg("-m")
+                    .arg_path(file)
+                    .arg("-x")
+                    .arg_path(&signature)
+                    .error_msg(&format!("minisign failed to sign {}", file.display()))
+                    .run()?;
+            }
+        }
+        println!("  Signed {}", signature.display());
+        Ok(signature)
+    }
+}
+
+fn non_empty_env(name: &str) -> Option<String> {
+    env::var(name)
+        .ok()
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty())
+}
+
+/// `path` with `suffix` appended to the file name (`x.iso` -> `x.iso.asc`).
+pub fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+/// Checksum file written by reciso next to `iso`.
+pub fn checksum_path(iso: &Path) -> PathBuf {
+    iso.with_extension(ISO_CHECKSUM_SUFFIX.trim_start_matches('.'))
+}
+
+/// Remove signatures left over from a previous build of `file`.
+pub fn remove_signatures(file: &Path) -> Result<()> {
+    for suffix in SIGNATURE_SUFFIXES {
+        let signature = with_suffix(file, suffix);
+        if signature.exists() {
+            fs::remove_file(&signature)?;
+        }
+    }
+    Ok(())
+}
+
+/// Sign the ISO and its checksum if a signing key is configured.
+///
+/// Stale signatures are removed either way, so an unsigned rebuild never
+/// ships a signature for the previous ISO.
+pub fn sign_iso(iso: &Path) -> Result<()> {
+    let checksum = checksum_path(iso);
+    remove_signatures(iso)?;
+    remove_signatures(&checksum)?;
+
+    let Some(signer) = Signer::from_env()? else {
+        return Ok(());
+    };
+    println!("Signing ISO...");
+    signer.sign(iso)?;
+    if checksum.exists() {
+        signer.sign(&checksum)?;
+    }
+    Ok(())
+}
+
+/// Check the checksum and every detached signature of `iso`.
+///
+/// Fails if the checksum does not match, if any signature does not verify
+/// (or, for OpenPGP, was not made by `gpg_fingerprint`), or if there is no
+/// signature at all.
+pub fn verify_iso_signatures(
+    iso: &Path,
+    minisign_pubkey: Option<&Path>,
+    gpg_fingerprint: Option<&str>,
+) -> Result<()> {
+    if !iso.exists() {
+        bail!("ISO not found: {}", iso.display());
+    }
+
+    let checksum = checksum_path(iso);
+    if !checksum.exists() {
+        bail!("Checksum file not found: {}", checksum.display());
+    }
+    verify_checksum(iso, &checksum)?;
+    println!("  [OK] checksum {}", checksum.display());
+
+    let pubkey = minisign_pubkey
+        .map(Path::to_path_buf)
+        .or_else(|| non_empty_env(MINISIGN_PUBKEY_ENV).map(PathBuf::from));
+    let fingerprint = gpg_fingerprint
+        .map(str::to_string)
+        .or_else(|| non_empty_env(GPG_FINGERPRINT_ENV))
+        .map(|f| normalize_fingerprint(&f))
+        .transpose()?;
+
+    let mut verified = 0;
+    for file in [iso, checksum.as_path()] {
+        for suffix in SIGNATURE_SUFFIXES {
+            let signature = with_suffix(file, suffix);
+            if !signature.exists() {
+                continue;
+            }
+            verify_signature(file, &signature, pubkey.as_deref(), fingerprint.as_deref())?;
+            println!("  [OK] signature {}", signature.display());
+            verified += 1;
+        }
+    }
+
+    if verified == 0 {
+        bail!(
+            "No signature found for {} (expected {}.asc or {}.minisig)",
+            iso.display(),
+            iso.display(),
+            iso.display()
+        );
+    }
+    Ok(())
+}
+
+/// Compare `iso` against the first hash in `checksum` (sha512sum or sha256sum format).
+fn verify_checksum(iso: &Path, checksum: &Path) -> Result<()> {
+    let contents = fs::read_to_string(checksum)?;
+    let expected = contents
+        .split_whitespace()
+        .next()
+        .map(str::to_ascii_lowercase)
+        .with_context(|| format!("{} is empty", checksum.display()))?;
+
+    let actual = match expected.len() {
+        128 => hash_file::<Sha512>(iso)?,
+        64 => hash_file::<Sha256>(iso)?,
+        _ => bail!(
+            "{} does not contain a SHA-512 or SHA-256 hash",
+            checksum.display()
+        ),
+    };
+    if actual != expected {
+        bail!(
+            "Checksum mismatch for {}:\n  expected {}\n  actual   {}",
+            iso.display(),
+            expected,
+            actual
+        );
+    }
+    Ok(())
+}
+
+/// Hex digest of a file.
+pub(super) fn hash_file<D: Digest + std::io::Write>(path: &Path) -> Result<String> {
+    let mut file =
+        fs::File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let mut hasher = D::new();
+    std::io::copy(&mut file, &mut hasher)?;
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect())
+}
+
+fn verify_signature(
+    file: &Path,
+    signature: &Path,
+    minisign_pubkey: Option<&Path>,
+    gpg_fingerprint: Option<&str>,
+) -> Result<()> {
+    let is_minisign = signature.to_string_lossy().ends_with(SIGNATURE_SUFFIXES[1]);
+    let result = if is_minisign {
+        require_tool("minisign", "minisign")?;
+        let Some(pubkey) = minisign_pubkey else {
+            bail!(
+                "{} needs a minisign public key: pass --key or set {}",
+                signature.display(),
+                MINISIGN_PUBKEY_ENV
+            );
+        };
+        Cmd::new("minisign")
+            .arg("-V")
+            .arg("-p")
+            .arg_path(pubkey)
+            .arg("-m")
+            .arg_path(file)
+            .arg("-x")
+            .arg_path(signature)
+            .allow_fail()
+            .run()?
+    } else {
+        require_tool("gpg", "gnupg2")?;
+        let Some(fingerprint) = gpg_fingerprint else {
+            bail!(
+                "{} needs the fingerprint of the release key: pass --gpg-fingerprint or set {}",
+                signature.display(),
+                GPG_FINGERPRINT_ENV
+            );
+        };
+        let result = Cmd::new("gpg")
+            .args(["--batch", "--status-fd", "1", "--verify"])
+            .arg_path(signature)
+            .arg_path(file)
+            .allow_fail()
+            .run()?;
+        // Any key in the keyring makes gpg succeed; only the release key counts
+        if result.success() && !has_validsig(&result.stdout, fingerprint) {
+            bail!(
+                "{} is not signed by {} (good signature from another key)",
+                signature.display(),
+                fingerprint
+            );
+        }
+        result
+    };
+
+    if !result.success() {
+        bail!(
+            "BAD signature {} for {}",
+            signature.display(),
+            file.display()
+        );
+    }
+    Ok(())
+}
+
+/// Uppercase hex fingerprint without spaces; rejects short key IDs, which
+/// are easy to collide.
+fn normalize_fingerprint(fingerprint: &str) -> Result<String> {
+    let normalized: String = fingerprint
+        .trim_start_matches("0x")
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<String>()
+        .to_ascii_uppercase();
+    if !matches!(normalized.len(), 40 | 64) || !normalized.chars().all(|c| c.is_ascii_hexdigit()) {
+        bail!(
+            "'{}' is not a full OpenPGP fingerprint (40 or 64 hex digits)",
+            fingerprint
+        );
+    }
+    Ok(normalized)
+}
+
+/// Whether gpg's `--status-fd` output has a `VALIDSIG` line for `fingerprint`,
+/// as the signing key or as the primary key of the signing subkey.
+fn has_validsig(status: &str, fingerprint: &str) -> bool {
+    status.lines().any(|line| {
+        let mut fields = line.split_whitespace();
+        if fields.next() != Some("[GNUPG:]") || fields.next() != Some("VALIDSIG") {
+            return false;
+        }
+        let fields: Vec<&str> = fields.collect();
+        // <signing key fpr> ... <primary key fpr> (10th field, if present)
+        let matches = |i: usize| {
+            fields
+                .get(i)
+                .is_some_and(|f| f.eq_ignore_ascii_case(fingerprint))
+        };
+        matches(0) || matches(9)
+    })
+}
+
+fn require_tool(tool: &str, package: &str) -> Result<()> {
+    if !process::exists(tool) {
+        bail!("{} not found. Install the {} package.", tool, package);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serial_test::serial;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_verify_checksum() {
+        let temp = TempDir::new().unwrap();
+        let iso = temp.path().join("levitateos.iso");
+        fs::write(&iso, "iso contents").unwrap();
+        let checksum = temp.path().join("levitateos.sha512");
+
+        let hash = hash_file::<Sha512>(&iso).unwrap();
+        fs::write(&checksum, format!("{}  levitateos.iso\n", hash)).unwrap();
+        verify_checksum(&iso, &checksum).unwrap();
+
+        fs::write(&iso, "tampered").unwrap();
+        let err = verify_checksum(&iso, &checksum).unwrap_err().to_string();
+        assert!(err.contains("Checksum mismatch"));
+    }
+
+    #[test]
+    fn test_validsig_must_name_the_release_key() {
+        const RELEASE: &str = "0123456789ABCDEF0123456789ABCDEF01234567";
+        const SUBKEY: &str = "89ABCDEF0123456789ABCDEF0123456789ABCDEF";
+        let status = |signer: &str, primary: &str| {
+            format!(
+                "[GNUPG:] NEWSIG\n\
+                 [GNUPG:] GOODSIG 0123456789ABCDEF LevitateOS\n\
+                 [GNUPG:] VALIDSIG {} 2026-10-18 1792300000 0 4 0 22 10 00 {}\n",
+                signer, primary
+            )
+        };
+
+        assert!(has_validsig(&status(RELEASE, RELEASE), RELEASE));
+        assert!(has_validsig(&status(SUBKEY, RELEASE), RELEASE));
+        assert!(!has_validsig(&status(SUBKEY, SUBKEY), RELEASE));
+        assert!(!has_validsig(
+            "[GNUPG:] GOODSIG 0123456789ABCDEF LevitateOS",
+            RELEASE
+        ));
+
+        assert_eq!(
+            normalize_fingerprint("0123 4567 89ab cdef 0123  4567 89ab cdef 0123 4567").unwrap(),
+            RELEASE
+        );
+        assert!(normalize_fingerprint("89ABCDEF01234567").is_err());
+    }
+
+    #[test]
+    #[serial]
+    fn test_signer_from_env() {
+        env::remove_var(GPG_KEY_ENV);
+        env::remove_var(MINISIGN_KEY_ENV);
+        assert_eq!(Signer::from_env().unwrap(), None);
+
+        env::set_var(GPG_KEY_ENV, "releases@levitateos.org");
+        let signer = Signer::from_env().unwrap().unwrap();
+        assert_eq!(
+            signer.signature_path(Path::new("/out/levitateos.iso")),
+            PathBuf::from("/out/levitateos.iso.asc")
+        );
+
+        env::set_var(MINISIGN_KEY_ENV, "/nonexistent/minisign.key");
+        assert!(Signer::from_env().is_err());
+
+        env::remove_var(GPG_KEY_ENV);
+        env::remove_var(MINISIGN_KEY_ENV);
+    }
+}
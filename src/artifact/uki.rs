@@ -5,24 +5,69 @@
 //!
 //! This module provides LevitateOS-specific wrappers around recuki, handling:
 //! - OS branding (LevitateOS name/version in boot menu)
-//! - Predefined UKI entries (live, emergency, debug, installed)
+//! - Predefined UKI entries (live, emergency, debug, toram, nopersist, installed)
 //! - Base cmdline construction from distro-spec constants
+//! - Secure Boot signing when `SECUREBOOT_KEY_DIR` is set (see `secureboot`)
 
 use anyhow::Result;
 use std::path::{Path, PathBuf};
 
 use distro_spec::levitate::{
-    EFI_DEBUG, OS_ID, OS_NAME, OS_VERSION, SELINUX_DISABLE, SERIAL_CONSOLE, UKI_INSTALLED_ENTRIES,
-    VGA_CONSOLE,
+    EFI_DEBUG, OS_ID, OS_NAME, OS_VERSION, SELINUX_DISABLE, SERIAL_CONSOLE, UKI_ENTRIES,
+    UKI_INSTALLED_ENTRIES, VGA_CONSOLE,
 };
-
-#[cfg(test)]
-use distro_spec::levitate::UKI_ENTRIES;
 use recuki::UkiConfig;
 
+use super::secureboot::{self, SecureBootKeys};
+
+/// A live UKI on the ISO (boot menu name, cmdline additions, filename).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LiveUki {
+    pub name: &'static str,
+    pub extra_cmdline: &'static str,
+    pub filename: &'static str,
+}
+
+/// Live entries leviso adds after distro-spec's `UKI_ENTRIES`.
+const LEVISO_LIVE_ENTRIES: &[LiveUki] = &[
+    LiveUki {
+        name: "LevitateOS (Copy to RAM)",
+        extra_cmdline: "toram",
+        filename: "levitateos-toram.efi",
+    },
+    LiveUki {
+        name: "LevitateOS (No Persistence)",
+        extra_cmdline: "nopersist",
+        filename: "levitateos-nopersist.efi",
+    },
+];
+
+/// All live UKIs, in boot menu order. The first one is the default entry.
+pub fn live_uki_entries() -> Vec<LiveUki> {
+    UKI_ENTRIES
+        .iter()
+        .map(|entry| LiveUki {
+            name: entry.name,
+            extra_cmdline: entry.extra_cmdline,
+            filename: entry.filename,
+        })
+        .chain(LEVISO_LIVE_ENTRIES.iter().copied())
+        .collect()
+}
+
+/// Cmdline reciso puts in front of the extra cmdline of every live UKI on an
+/// ISO labeled `label`.
+pub fn live_base_cmdline(label: &str) -> String {
+    format!(
+        "root=LABEL={} {} {} {} {}",
+        label, SERIAL_CONSOLE, VGA_CONSOLE, SELINUX_DISABLE, EFI_DEBUG
+    )
+}
+
 /// Build a UKI from kernel + initramfs + cmdline.
 ///
-/// Uses `recuki` library which wraps `ukify` from systemd.
+/// Uses `recuki` library which wraps `ukify` from systemd. If Secure Boot
+/// keys are configured, the UKI is signed with the db key.
 ///
 /// # Arguments
 ///
@@ -36,7 +81,12 @@ pub fn build_uki(kernel: &Path, initramfs: &Path, cmdline: &str, output: &Path)
     let config = UkiConfig::new(kernel, initramfs, cmdline, output)
         .with_os_release(OS_NAME, OS_ID, OS_VERSION);
 
-    recuki::build_uki(&config)
+    recuki::build_uki(&config)?;
+
+    if let Some(keys) = SecureBootKeys::from_env()? {
+        secureboot::sign_efi(&keys, output)?;
+    }
+    Ok(())
 }
 
 /// Build UKIs for installed systems.
@@ -66,24 +116,10 @@ pub fn build_installed_ukis(
 ) -> Result<Vec<PathBuf>> {
     println!("Building UKIs for installed systems...");
 
-    // Base cmdline for installed systems
-    // Uses root=LABEL=root - user must label their root partition accordingly
-    // Can be edited at boot time if needed (systemd-boot allows editing)
-    // efi=debug helps diagnose UKI boot issues by showing EFI stub activity
-    let base_cmdline = format!(
-        "root=LABEL=root rw {} {} {} {}",
-        SERIAL_CONSOLE, VGA_CONSOLE, SELINUX_DISABLE, EFI_DEBUG
-    );
-
     let mut outputs = Vec::new();
 
     for entry in UKI_INSTALLED_ENTRIES {
-        let cmdline = if entry.extra_cmdline.is_empty() {
-            base_cmdline.clone()
-        } else {
-            format!("{} {}", base_cmdline, entry.extra_cmdline)
-        };
-
+        let cmdline = installed_cmdline(entry.extra_cmdline);
         let output = output_dir.join(entry.filename);
         build_uki(kernel, initramfs, &cmdline, &output)?;
         outputs.push(output);
@@ -93,17 +129,30 @@ pub fn build_installed_ukis(
     Ok(outputs)
 }
 
+/// Full cmdline of an installed UKI with the given extra arguments.
+pub fn installed_cmdline(extra_cmdline: &str) -> String {
+    // Base cmdline for installed systems
+    // Uses root=LABEL=root - user must label their root partition accordingly
+    // Can be edited at boot time if needed (systemd-boot allows editing)
+    // efi=debug helps diagnose UKI boot issues by showing EFI stub activity
+    let base_cmdline = format!(
+        "root=LABEL=root rw {} {} {} {}",
+        SERIAL_CONSOLE, VGA_CONSOLE, SELINUX_DISABLE, EFI_DEBUG
+    );
+    if extra_cmdline.is_empty() {
+        base_cmdline
+    } else {
+        format!("{} {}", base_cmdline, extra_cmdline)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     #[test]
     fn test_base_cmdline_format() {
-        let label = "TESTISO";
-        let cmdline = format!(
-            "root=LABEL={} {} {} {} {}",
-            label, SERIAL_CONSOLE, VGA_CONSOLE, SELINUX_DISABLE, EFI_DEBUG
-        );
+        let cmdline = live_base_cmdline("TESTISO");
 
         assert!(cmdline.contains("root=LABEL=TESTISO"));
         assert!(cmdline.contains("console=ttyS0"));
@@ -126,4 +175,14 @@ mod tests {
             .iter()
             .any(|e| e.filename == "levitateos-debug.efi"));
     }
+
+    #[test]
+    fn test_live_uki_entries_include_toram() {
+        let entries = live_uki_entries();
+        assert_eq!(entries[0].filename, UKI_ENTRIES[0].filename);
+        assert_eq!(entries.len(), UKI_ENTRIES.len() + LEVISO_LIVE_ENTRIES.len());
+        assert!(entries
+            .iter()
+            .any(|e| e.extra_cmdline == "toram" && e.filename == "levitateos-toram.efi"));
+    }
 }
@@ -0,0 +1,287 @@
+//! dm-verity protection for the live EROFS image.
+//!
+//! A corrupted or tampered USB stick used to surface only as random EROFS
+//! errors after boot. With verity, every block of `filesystem.erofs` is
+//! checked against a hash tree whose root hash is part of the (signed) live
+//! UKI cmdline, so a bad medium fails at the first read of a bad block.
+//!
+//! ```text
+//! ISO:
+//! ├── live/filesystem.erofs          # unchanged
+//! ├── live/filesystem.erofs.verity   # hash tree (veritysetup format)
+//! └── EFI/Linux/levitateos-*.efi     # cmdline: levitate.verity=<root hash>
+//! ```
+//!
+//! The hash tree is a side file rather than appended to the image, so the
+//! same `filesystem.erofs` stays extractable by recstrap and restorable from
+//! the artifact store. `init_tiny.template` opens the verity device with
+//! `veritysetup` (appended to the live initramfs by `append_verity_tools`)
+//! before mounting the lower layer.
+//!
+//! Only the EROFS is protected. The live overlay (`/live` on the ISO) is
+//! bind-mounted above it without verification, so verity detects a corrupted
+//! or modified rootfs image, not every change to the boot medium. (Network
+//! boots check the overlay tarball against `levitate.overlay_sha256=`.)
+
+use anyhow::{bail, Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::build::reproducible;
+use distro_builder::process::{self, shell_in, Cmd};
+use leviso_elf::{copy_library_to, find_sbin_binary, get_all_dependencies, make_executable};
+use sha2::Sha256;
+
+/// Kernel cmdline parameter carrying the root hash.
+pub const CMDLINE_PARAM: &str = "levitate.verity";
+
+/// Suffix of the hash tree file next to the image.
+pub const HASH_TREE_SUFFIX: &str = ".verity";
+
+/// Suffix of the file caching the root hash next to the image, as
+/// `<root hash> <sha256 of the image>`.
+pub const ROOT_HASH_SUFFIX: &str = ".roothash";
+
+/// Dynamic loader used to run veritysetup in the busybox initramfs.
+const LOADER: &str = "ld-linux-x86-64.so.2";
+
+/// Kernel modules needed for dm-verity (dependencies are resolved from
+/// `modules.dep`, see `modules::append_live_modules`).
+pub(super) const VERITY_MODULES: &[&str] = &["dm-verity"];
+
+/// Hash tree and root hash of an image.
+#[derive(Debug, Clone)]
+pub struct Verity {
+    pub hash_tree: PathBuf,
+    pub root_hash: String,
+}
+
+impl Verity {
+    /// `levitate.verity=<root hash>` for the live cmdline.
+    pub fn cmdline_arg(&self) -> String {
+        format!("{}={}", CMDLINE_PARAM, self.root_hash)
+    }
+}
+
+fn side_file(image: &Path, suffix: &str) -> PathBuf {
+    let mut name = image
+        .file_name()
+        .map(|n| n.to_os_string())
+        .unwrap_or_default();
+    name.push(suffix);
+    image.with_file_name(name)
+}
+
+/// Hash tree file for `image` (`filesystem.erofs.verity`).
+pub fn hash_tree_path(image: &Path) -> PathBuf {
+    side_file(image, HASH_TREE_SUFFIX)
+}
+
+/// Root hash file for `image` (`filesystem.erofs.roothash`).
+pub fn root_hash_path(image: &Path) -> PathBuf {
+    side_file(image, ROOT_HASH_SUFFIX)
+}
+
+/// Generate the hash tree for `image`, reusing it if it was made for exactly
+/// this image.
+///
+/// Reuse goes by the image's sha256 recorded with the root hash, not by
+/// mtime: an image restored from the artifact store or hard-linked from a
+/// blob can be older than a hash tree made for different contents.
+pub fn ensure_verity(image: &Path) -> Result<Verity> {
+    let hash_tree = hash_tree_path(image);
+    let root_hash_file = root_hash_path(image);
+    let image_sha256 = super::signing::hash_file::<Sha256>(image)?;
+
+    if hash_tree.exists() {
+        if let Some(root_hash) = fs::read_to_string(&root_hash_file)
+            .ok()
+            .and_then(|s| cached_root_hash(&s, &image_sha256))
+        {
+            return Ok(Verity {
+                hash_tree,
+                root_hash,
+            });
+        }
+    }
+
+    if !process::exists("veritysetup") {
+        bail!(
+            "veritysetup not found. Install cryptsetup package.\n\
+             On Fedora: sudo dnf install cryptsetup\n\
+             On Ubuntu: sudo apt install cryptsetup-bin"
+        );
+    }
+
+    println!(
+        "  Generating dm-verity hash tree for {}...",
+        image.display()
+    );
+    let tmp = side_file(image, ".verity.tmp");
+    let _ = fs::remove_file(&tmp);
+    let mut cmd = Cmd::new("veritysetup").arg("format");
+    // Salt and UUID are random by default; derive them in reproducible builds
+    if let Some(epoch) = reproducible::source_date_epoch()? {
+        let salt = reproducible::stable_hex("verity-salt", epoch);
+        let uuid = reproducible::stable_uuid("verity", epoch);
+        cmd = cmd
+            .arg(format!("--salt={}", salt))
+            .arg(format!("--uuid={}", uuid));
+    }
+    let result = cmd
+        .arg_path(image)
+        .arg_path(&tmp)
+        .error_msg("veritysetup format failed")
+        .run()?;
+    let root_hash = parse_root_hash(&result.stdout).with_context(|| {
+        format!(
+            "No root hash in veritysetup output:\n{}",
+            result.stdout.trim()
+        )
+    })?;
+
+    fs::rename(&tmp, &hash_tree)?;
+    fs::write(&root_hash_file, format!("{} {}\n", root_hash, image_sha256))?;
+    println!("  Root hash: {}", root_hash);
+
+    Ok(Verity {
+        hash_tree,
+        root_hash,
+    })
+}
+
+/// Extract the root hash from `veritysetup format` output.
+fn parse_root_hash(output: &str) -> Option<String> {
+    output
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("Root hash:"))
+        .and_then(|hash| valid_root_hash(hash.trim()))
+}
+
+/// Root hash from a `.roothash` file, if it was recorded for the image with
+/// `image_sha256`.
+fn cached_root_hash(contents: &str, image_sha256: &str) -> Option<String> {
+    let mut fields = contents.split_whitespace();
+    let root_hash = valid_root_hash(fields.next()?)?;
+    (fields.next()? == image_sha256).then_some(root_hash)
+}
+
+fn valid_root_hash(hash: &str) -> Option<String> {
+    (hash.len() >= 64 && hash.chars().all(|c| c.is_ascii_hexdigit())).then(|| hash.to_string())
+}
+
+/// Append a cpio segment with `veritysetup` and its libraries to a built live
+/// initramfs (the dm-verity modules ship with the other live modules).
+///
+/// The kernel unpacks concatenated (separately compressed) cpio archives in
+/// order, so this adds files without touching recinit's archive. Libraries go
+/// to `/usr/lib64` and the init script runs veritysetup through the dynamic
+/// loader, so nothing depends on the busybox initramfs layout.
+pub fn append_verity_tools(source_rootfs: &Path, initramfs: &Path, work_dir: &Path) -> Result<()> {
+    let _ = fs::remove_dir_all(work_dir);
+    fs::create_dir_all(work_dir)?;
+
+    let veritysetup = find_sbin_binary(source_rootfs, "veritysetup").context(
+        "veritysetup not found in the source rootfs.\n\
+         The live initramfs needs it to open the dm-verity root.",
+    )?;
+    let dest = work_dir.join("usr/sbin/veritysetup");
+    fs::create_dir_all(dest.parent().unwrap())?;
+    fs::copy(&veritysetup, &dest)?;
+    make_executable(&dest)?;
+
+    // The init script calls the loader directly, make sure it is included
+    let mut libs = get_all_dependencies(source_rootfs, &veritysetup, &[])?;
+    if !libs.iter().any(|lib| lib == LOADER) {
+        libs.push(LOADER.to_string());
+    }
+    for lib in &libs {
+        copy_library_to(
+            source_rootfs,
+            lib,
+            work_dir,
+            "usr/lib64",
+            "usr/lib64",
+            &[],
+            &[],
+        )
+        .with_context(|| format!("veritysetup requires missing library '{}'", lib))?;
+    }
+
+    append_cpio_segment(work_dir, initramfs)
+        .context("Failed to append veritysetup to live initramfs")?;
+
+    println!(
+        "  Appended veritysetup ({} libraries) to live initramfs",
+        libs.len()
+    );
+    Ok(())
+}
+
+/// Append `work_dir` to `initramfs` as a separately compressed cpio segment,
+/// then remove `work_dir`.
+///
+/// Entries are in a stable order with clamped mtimes so SOURCE_DATE_EPOCH
+/// builds stay reproducible.
+pub(super) fn append_cpio_segment(work_dir: &Path, initramfs: &Path) -> Result<()> {
+    if let Some(epoch) = reproducible::source_date_epoch()? {
+        reproducible::clamp_mtimes(work_dir, epoch)?;
+    }
+    let cmd = format!(
+        "find . | LC_ALL=C sort | cpio -o -H newc --quiet --reproducible | gzip -9 -n >> '{}'",
+        initramfs.display()
+    );
+    shell_in(&cmd, work_dir)?;
+    let _ = fs::remove_dir_all(work_dir);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_root_hash() {
+        let output = "VERITY header information for filesystem.erofs.verity\n\
+                      UUID:            \t8c1e8b8f-2f4e-4a57-9d1c-2b1f3a9f0c11\n\
+                      Hash type:       \t1\n\
+                      Data blocks:     \t89600\n\
+                      Hash algorithm:  \tsha256\n\
+                      Salt:            \t0f1d\n\
+                      Root hash:      \t4392712ba01368efdf14b05c76f9e4df0d53664630b5d48632ed17a137f39076\n";
+        assert_eq!(
+            parse_root_hash(output).as_deref(),
+            Some("4392712ba01368efdf14b05c76f9e4df0d53664630b5d48632ed17a137f39076")
+        );
+        assert!(parse_root_hash("Root hash: not-a-hash").is_none());
+    }
+
+    #[test]
+    fn test_cached_root_hash_needs_the_same_image() {
+        let root_hash = "4392712ba01368efdf14b05c76f9e4df0d53664630b5d48632ed17a137f39076";
+        let contents = format!("{} {}\n", root_hash, "ab".repeat(32));
+        assert_eq!(
+            cached_root_hash(&contents, &"ab".repeat(32)).as_deref(),
+            Some(root_hash)
+        );
+        assert_eq!(cached_root_hash(&contents, &"cd".repeat(32)), None);
+        // Written before the image hash was recorded
+        assert_eq!(
+            cached_root_hash(&format!("{}\n", root_hash), &"ab".repeat(32)),
+            None
+        );
+    }
+
+    #[test]
+    fn test_side_files() {
+        let image = Path::new("/out/filesystem.erofs");
+        assert_eq!(
+            hash_tree_path(image),
+            PathBuf::from("/out/filesystem.erofs.verity")
+        );
+        assert_eq!(
+            root_hash_path(image),
+            PathBuf::from("/out/filesystem.erofs.roothash")
+        );
+    }
+}
@@ -0,0 +1,56 @@
+//! Reference server for the shared artifact store.
+//!
+//! Serves the content-addressed protocol described in
+//! `leviso::common::remote_store`, storing blobs under `--root`. Point builds
+//! at it with `LEVISO_REMOTE_STORE=http://<host>:<port>`.
+//!
+//! It listens on localhost by default; reach it from other machines through
+//! an SSH tunnel (`ssh -N -L 8750:localhost:8750 <host>`) rather than
+//! exposing it with `--listen`.
+//!
+//! Uploads need the token from `--push-token-file` (clients send it from
+//! `LEVISO_REMOTE_STORE_TOKEN`); without one the server is read-only.
+
+use anyhow::{bail, Context, Result};
+use clap::Parser;
+use std::path::PathBuf;
+
+use leviso::common::remote_store::Server;
+
+#[derive(Parser)]
+#[command(name = "leviso-store-server")]
+#[command(about = "Shared artifact store server for leviso builds")]
+struct Cli {
+    /// Directory holding the stored blobs
+    #[arg(long)]
+    root: PathBuf,
+
+    /// Address to listen on (keep it on localhost and tunnel over SSH)
+    #[arg(long, default_value = "127.0.0.1:8750")]
+    listen: String,
+
+    /// File holding the shared token that authorizes uploads
+    #[arg(long)]
+    push_token_file: Option<PathBuf>,
+}
+
+fn main() -> Result<()> {
+    let cli = Cli::parse();
+    let mut server = Server::bind(&cli.root, &cli.listen)?;
+    if let Some(path) = &cli.push_token_file {
+        let token = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        if token.trim().is_empty() {
+            bail!("Push token file {} is empty", path.display());
+        }
+        server = server.with_push_token(token.trim());
+    } else {
+        println!("No --push-token-file given - serving read-only");
+    }
+    println!(
+        "Serving artifact store {} on http://{}",
+        cli.root.display(),
+        server.local_addr()?
+    );
+    server.run()
+}
@@ -0,0 +1,184 @@
+//! Phase checkpoints for resumable rootfs builds.
+//!
+//! After each `build_system()` phase completes, a checkpoint is written next
+//! to `rootfs-staging.work`. When a later phase fails, the work directory is
+//! kept, and `leviso build rootfs --resume` continues after the last completed
+//! phase instead of starting over from Filesystem.
+//!
+//! The checkpoint stores the rootfs input hash (see `rebuild::rootfs_artifact`)
+//! and refuses to resume when the inputs have changed since. It also carries
+//! the state that otherwise lives only in memory during a build: the packages
+//! registered for license tracking and the input files recorded so far.
+//!
+//! The failed phase itself is re-run on top of its partial output; phase
+//! operations overwrite files and replace symlinks, so this is safe.
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Checkpoint file, stored in the output directory next to the work staging.
+pub const CHECKPOINT_FILE: &str = "rootfs-staging.work.checkpoint";
+
+/// State of an interrupted rootfs build.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Checkpoint {
+    /// Rootfs input hash when the build started.
+    pub input_hash: String,
+    /// Number of the last completed phase (1-based).
+    pub completed_phase: usize,
+    /// Name of the last completed phase (for messages).
+    pub phase_name: String,
+    /// Packages registered with the license tracker so far.
+    pub packages: Vec<String>,
+    /// Build inputs recorded so far.
+    pub inputs: Vec<PathBuf>,
+}
+
+/// Writes checkpoints for one build and, when resuming, carries the
+/// checkpoint to resume from.
+#[derive(Debug)]
+pub struct Checkpoints {
+    path: PathBuf,
+    input_hash: String,
+    resume: Option<Checkpoint>,
+}
+
+impl Checkpoints {
+    /// Start a fresh build (any old checkpoint is discarded).
+    pub fn fresh(output_dir: &Path, input_hash: &str) -> Result<Self> {
+        clear(output_dir)?;
+        Ok(Self {
+            path: output_dir.join(CHECKPOINT_FILE),
+            input_hash: input_hash.to_string(),
+            resume: None,
+        })
+    }
+
+    /// Resume from the checkpoint in `output_dir`.
+    ///
+    /// Fails if there is no checkpoint or the inputs changed since it was
+    /// written.
+    pub fn resume(output_dir: &Path, input_hash: &str) -> Result<Self> {
+        let Some(checkpoint) = load(output_dir)? else {
+            bail!(
+                "No rootfs checkpoint to resume from in {}.\n\
+                 Run 'leviso build rootfs' for a fresh build.",
+                output_dir.display()
+            );
+        };
+        if checkpoint.input_hash != input_hash {
+            bail!(
+                "Rootfs inputs changed since the checkpoint after phase {} ({}).\n\
+                 Refusing to resume - run 'leviso build rootfs' for a fresh build.\n\
+                 See 'leviso show status --explain' for what changed.",
+                checkpoint.completed_phase,
+                checkpoint.phase_name
+            );
+        }
+        Ok(Self {
+            path: output_dir.join(CHECKPOINT_FILE),
+            input_hash: input_hash.to_string(),
+            resume: Some(checkpoint),
+        })
+    }
+
+    /// The checkpoint being resumed from, if any.
+    pub fn resume_point(&self) -> Option<&Checkpoint> {
+        self.resume.as_ref()
+    }
+
+    /// Record that `phase` completed.
+    pub fn save(
+        &self,
+        phase: usize,
+        phase_name: &str,
+        packages: Vec<String>,
+        inputs: Vec<PathBuf>,
+    ) -> Result<()> {
+        let checkpoint = Checkpoint {
+            input_hash: self.input_hash.clone(),
+            completed_phase: phase,
+            phase_name: phase_name.to_string(),
+            packages,
+            inputs,
+        };
+        // Write-then-rename so a crash never leaves a truncated checkpoint
+        let tmp = self.path.with_extension("checkpoint.new");
+        fs::write(&tmp, serde_json::to_string_pretty(&checkpoint)? + "\n")?;
+        fs::rename(&tmp, &self.path)
+            .with_context(|| format!("Failed to write checkpoint {}", self.path.display()))
+    }
+}
+
+/// Load the checkpoint in `output_dir`, if there is one.
+pub fn load(output_dir: &Path) -> Result<Option<Checkpoint>> {
+    let path = output_dir.join(CHECKPOINT_FILE);
+    if !path.exists() {
+        return Ok(None);
+    }
+    let content = fs::read_to_string(&path)?;
+    let checkpoint = serde_json::from_str(&content)
+        .with_context(|| format!("Invalid checkpoint at {}", path.display()))?;
+    Ok(Some(checkpoint))
+}
+
+/// Remove the checkpoint in `output_dir`.
+pub fn clear(output_dir: &Path) -> Result<()> {
+    let path = output_dir.join(CHECKPOINT_FILE);
+    if path.exists() {
+        fs::remove_file(&path)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_save_and_resume() {
+        let temp = TempDir::new().unwrap();
+        let checkpoints = Checkpoints::fresh(temp.path(), "abc").unwrap();
+        checkpoints
+            .save(
+                7,
+                "Packages",
+                vec!["kbd".into()],
+                vec![PathBuf::from("/x/etc/files/passwd")],
+            )
+            .unwrap();
+
+        let resumed = Checkpoints::resume(temp.path(), "abc").unwrap();
+        let point = resumed.resume_point().unwrap();
+        assert_eq!(point.completed_phase, 7);
+        assert_eq!(point.packages, vec!["kbd".to_string()]);
+    }
+
+    #[test]
+    fn test_refuses_resume_when_inputs_changed() {
+        let temp = TempDir::new().unwrap();
+        Checkpoints::fresh(temp.path(), "abc")
+            .unwrap()
+            .save(3, "Systemd", Vec::new(), Vec::new())
+            .unwrap();
+
+        let err = Checkpoints::resume(temp.path(), "def").unwrap_err();
+        assert!(err.to_string().contains("inputs changed"));
+    }
+
+    #[test]
+    fn test_fresh_discards_old_checkpoint() {
+        let temp = TempDir::new().unwrap();
+        Checkpoints::fresh(temp.path(), "abc")
+            .unwrap()
+            .save(3, "Systemd", Vec::new(), Vec::new())
+            .unwrap();
+
+        Checkpoints::fresh(temp.path(), "abc").unwrap();
+        assert!(load(temp.path()).unwrap().is_none());
+        assert!(Checkpoints::resume(temp.path(), "abc").is_err());
+    }
+}
@@ -0,0 +1,345 @@
+//! Build input discovery for rebuild detection.
+//!
+//! Instead of hand-listing every data file the rootfs build reads, the build
+//! records them as they are read (via `read_manifest_file` and the custom ops
+//! that copy repo files, the Rocky RPMs they unpack and the source trees of
+//! the monorepo tools they compile). The recorded list is saved next to the
+//! input hash and hashed on the next `needs_rebuild()` check. Recording is
+//! per thread, so build graph nodes running in parallel don't mix inputs.
+//!
+//! The Rocky source rootfs is covered by a content hash of its package
+//! manifest (every installed NEVRA in the rpmdb), not by a marker file. The
+//! hash is cached next to the rootfs and reused while the rpmdb files keep
+//! their size and mtime, so `rpm -qa` only runs after the rpmdb changed.
+
+use anyhow::{bail, Result};
+use sha2::{Digest, Sha256};
+use std::cell::RefCell;
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use distro_builder::process::Cmd;
+
+/// Recorded input list for the rootfs, stored in the output directory.
+pub const ROOTFS_INPUTS_LIST: &str = ".rootfs-inputs.list";
+
+/// rpmdb locations inside a source rootfs.
+const RPMDB_DIRS: &[&str] = &["var/lib/rpm", "usr/lib/sysimage/rpm"];
+
+thread_local! {
+    /// Files read while recording is active on this thread. `None` when not
+    /// recording. Per thread, because build graph nodes run in parallel and
+    /// only the rootfs node records.
+    static RECORDER: RefCell<Option<BTreeSet<PathBuf>>> = const { RefCell::new(None) };
+}
+
+/// Start recording build inputs on this thread (discards any previous
+/// recording).
+pub fn start_recording() {
+    RECORDER.with_borrow_mut(|recorder| *recorder = Some(BTreeSet::new()));
+}
+
+/// Record a file as an input of the current build. No-op when this thread
+/// is not recording.
+pub fn record_input(path: &Path) {
+    RECORDER.with_borrow_mut(|recorder| {
+        if let Some(inputs) = recorder.as_mut() {
+            inputs.insert(path.to_path_buf());
+        }
+    });
+}
+
+/// Inputs recorded so far, sorted, without stopping the recording.
+pub fn recorded_inputs() -> Vec<PathBuf> {
+    RECORDER.with_borrow(|recorder| {
+        recorder
+            .as_ref()
+            .map(|inputs| inputs.iter().cloned().collect())
+            .unwrap_or_default()
+    })
+}
+
+/// Stop recording and return the recorded inputs, sorted.
+pub fn finish_recording() -> Vec<PathBuf> {
+    RECORDER.with_borrow_mut(|recorder| recorder.take().unwrap_or_default().into_iter().collect())
+}
+
+/// Save a recorded input list (one path per line).
+pub fn write_input_list(path: &Path, inputs: &[PathBuf]) -> Result<()> {
+    let content: String = inputs
+        .iter()
+        .map(|p| format!("{}\n", p.display()))
+        .collect();
+    fs::write(path, content)?;
+    Ok(())
+}
+
+/// Load a recorded input list. Missing or unreadable lists yield no inputs.
+pub fn read_input_list(path: &Path) -> Vec<PathBuf> {
+    fs::read_to_string(path)
+        .map(|content| {
+            content
+                .lines()
+                .filter(|l| !l.trim().is_empty())
+                .map(PathBuf::from)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// All files under `dir` with the given extension, sorted.
+///
+/// Used for build logic (Rust sources) so new modules are picked up without
+/// touching the artifact definitions.
+pub fn source_files(dir: &Path, extension: &str) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = walkdir::WalkDir::new(dir)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+        .map(|e| e.into_path())
+        .filter(|p| p.extension().is_some_and(|ext| ext == extension))
+        .collect();
+    files.sort();
+    files
+}
+
+/// Every file of a source tree (a crate or other project the build compiles
+/// and copies into the image), sorted, skipping build outputs (`target/`,
+/// `node_modules/`) and hidden entries.
+pub fn source_tree_files(dir: &Path) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = walkdir::WalkDir::new(dir)
+        .into_iter()
+        .filter_entry(|e| {
+            let name = e.file_name().to_string_lossy();
+            e.depth() == 0 || !(name.starts_with('.') || name == "target" || name == "node_modules")
+        })
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+        .map(|e| e.into_path())
+        .collect();
+    files.sort();
+    files
+}
+
+/// Record every file of a source tree (see `source_tree_files`).
+pub fn record_source_tree(dir: &Path) {
+    for file in source_tree_files(dir) {
+        record_input(&file);
+    }
+}
+
+/// Content hash of the package manifest of a source rootfs.
+///
+/// Hashes the sorted `NEVRA` list from the rpmdb, so any package added,
+/// removed or updated (including EPEL packages registered with `--justdb`)
+/// changes the hash. Returns `None` if the rootfs has not been extracted yet;
+/// fails if the rpmdb cannot be queried.
+///
+/// The result is cached in `.<rootfs>.rpmdb-hash` next to the rootfs, keyed
+/// by the size and mtime of every rpmdb file.
+pub fn rpm_manifest_hash(rootfs: &Path) -> Result<Option<String>> {
+    if !rootfs.exists() {
+        return Ok(None);
+    }
+
+    let cache = rpmdb_cache_path(rootfs);
+    let stamp = rpmdb_stamp(rootfs);
+    if let Some(stamp) = &stamp {
+        let cached = fs::read_to_string(&cache).unwrap_or_default();
+        if let Some((cached_stamp, hash)) = cached.trim().split_once(' ') {
+            if cached_stamp == stamp {
+                return Ok(Some(hash.to_string()));
+            }
+        }
+    }
+
+    let hash = query_manifest_hash(rootfs)?;
+    if let Some(stamp) = stamp {
+        // Only a speedup; the next call queries rpm again if this fails
+        let _ = fs::write(&cache, format!("{} {}\n", stamp, hash));
+    }
+    Ok(Some(hash))
+}
+
+/// Cache file for the manifest hash of `rootfs`.
+fn rpmdb_cache_path(rootfs: &Path) -> PathBuf {
+    let name = rootfs
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    rootfs.with_file_name(format!(".{}.rpmdb-hash", name))
+}
+
+/// Hash of the path, size and mtime of every rpmdb file in `rootfs`, or
+/// `None` if there are none.
+fn rpmdb_stamp(rootfs: &Path) -> Option<String> {
+    let mut files: Vec<(PathBuf, u64, u128)> = RPMDB_DIRS
+        .iter()
+        .flat_map(|dir| {
+            walkdir::WalkDir::new(rootfs.join(dir))
+                .follow_root_links(false)
+                .into_iter()
+                .filter_map(|e| e.ok())
+        })
+        .filter(|e| e.file_type().is_file())
+        .filter_map(|e| {
+            let meta = e.metadata().ok()?;
+            let mtime = meta
+                .modified()
+                .ok()?
+                .duration_since(std::time::UNIX_EPOCH)
+                .ok()?
+                .as_nanos();
+            Some((e.into_path(), meta.len(), mtime))
+        })
+        .collect();
+    if files.is_empty() {
+        return None;
+    }
+    files.sort();
+
+    let mut hasher = Sha256::new();
+    for (path, size, mtime) in files {
+        hasher.update(format!("{}\0{}\0{}\n", path.display(), size, mtime).as_bytes());
+    }
+    Some(format!("{:x}", hasher.finalize()))
+}
+
+/// Hash of the sorted `NEVRA` list reported by `rpm -qa`.
+fn query_manifest_hash(rootfs: &Path) -> Result<String> {
+    if !distro_builder::process::exists("rpm") {
+        bail!(
+            "rpm not found - needed to fingerprint the packages in {}.\n\
+             Install: sudo dnf install rpm",
+            rootfs.display()
+        );
+    }
+
+    let result = Cmd::new("rpm")
+        .arg("--root")
+        .arg_path(rootfs)
+        .args(["-qa", "--qf", "%{NEVRA}\\n"])
+        .error_msg(&format!(
+            "Failed to query the rpmdb in {}",
+            rootfs.display()
+        ))
+        .run()?;
+
+    let mut packages: Vec<&str> = result.stdout.lines().filter(|l| !l.is_empty()).collect();
+    if packages.is_empty() {
+        bail!("The rpmdb in {} lists no packages", rootfs.display());
+    }
+    packages.sort_unstable();
+
+    let mut hasher = Sha256::new();
+    for package in packages {
+        hasher.update(package.as_bytes());
+        hasher.update(b"\n");
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serial_test::serial;
+    use tempfile::TempDir;
+
+    #[test]
+    #[serial]
+    fn test_recording_only_when_active() {
+        record_input(Path::new("/before/start"));
+        start_recording();
+        record_input(Path::new("/b"));
+        record_input(Path::new("/a"));
+        record_input(Path::new("/b"));
+        let inputs = finish_recording();
+        record_input(Path::new("/after/finish"));
+
+        assert_eq!(inputs, vec![PathBuf::from("/a"), PathBuf::from("/b")]);
+        assert!(finish_recording().is_empty());
+    }
+
+    #[test]
+    fn test_recording_is_per_thread() {
+        start_recording();
+        std::thread::spawn(|| {
+            start_recording();
+            record_input(Path::new("/initramfs/input"));
+            assert_eq!(finish_recording(), vec![PathBuf::from("/initramfs/input")]);
+        })
+        .join()
+        .unwrap();
+        record_input(Path::new("/rootfs/input"));
+        assert_eq!(finish_recording(), vec![PathBuf::from("/rootfs/input")]);
+    }
+
+    #[test]
+    fn test_input_list_roundtrip() {
+        let temp = TempDir::new().unwrap();
+        let list = temp.path().join(ROOTFS_INPUTS_LIST);
+        let inputs = vec![PathBuf::from("/x/etc/files/passwd"), PathBuf::from("/y")];
+
+        write_input_list(&list, &inputs).unwrap();
+        assert_eq!(read_input_list(&list), inputs);
+        assert!(read_input_list(&temp.path().join("missing")).is_empty());
+    }
+
+    #[test]
+    #[serial]
+    fn test_record_source_tree_skips_build_output() {
+        let temp = TempDir::new().unwrap();
+        let tool = temp.path().join("recstrap");
+        for file in [
+            "Cargo.toml",
+            "src/main.rs",
+            "target/release/recstrap",
+            ".git/HEAD",
+        ] {
+            fs::create_dir_all(tool.join(file).parent().unwrap()).unwrap();
+            fs::write(tool.join(file), "").unwrap();
+        }
+
+        start_recording();
+        record_source_tree(&tool);
+        assert_eq!(
+            finish_recording(),
+            vec![tool.join("Cargo.toml"), tool.join("src/main.rs")]
+        );
+    }
+
+    #[test]
+    fn test_rpm_manifest_hash_cached_by_rpmdb_mtime() {
+        let temp = TempDir::new().unwrap();
+        let rootfs = temp.path().join("rootfs");
+        let rpmdb = rootfs.join("var/lib/rpm/rpmdb.sqlite");
+        fs::create_dir_all(rpmdb.parent().unwrap()).unwrap();
+        fs::write(&rpmdb, "db").unwrap();
+
+        // A matching stamp answers without querying rpm
+        let stamp = rpmdb_stamp(&rootfs).unwrap();
+        let cache = temp.path().join(".rootfs.rpmdb-hash");
+        fs::write(&cache, format!("{} cafe\n", stamp)).unwrap();
+        assert_eq!(rpm_manifest_hash(&rootfs).unwrap().as_deref(), Some("cafe"));
+
+        fs::write(&rpmdb, "db with one more package").unwrap();
+        assert_ne!(rpmdb_stamp(&rootfs).unwrap(), stamp);
+        assert!(rpmdb_stamp(temp.path()).is_none());
+    }
+
+    #[test]
+    fn test_source_files_filters_by_extension() {
+        let temp = TempDir::new().unwrap();
+        fs::create_dir_all(temp.path().join("sub")).unwrap();
+        fs::write(temp.path().join("b.rs"), "").unwrap();
+        fs::write(temp.path().join("sub/a.rs"), "").unwrap();
+        fs::write(temp.path().join("notes.md"), "").unwrap();
+
+        let files = source_files(temp.path(), "rs");
+        assert_eq!(
+            files,
+            vec![temp.path().join("b.rs"), temp.path().join("sub/a.rs")]
+        );
+    }
+}
@@ -0,0 +1,419 @@
+//! License policy enforcement.
+//!
+//! Every package registered with the `LicenseTracker` during `build_system()`
+//! is checked against `profile/license-policy.json`. The policy lists SPDX
+//! license identifiers in three buckets:
+//!
+//! - `allowed` - redistributable, nothing to do
+//! - `review_required` - redistributable with conditions, printed as a warning
+//! - `forbidden` - must never ship, fails the build
+//!
+//! The license expression of each package is read from the RPM database of the
+//! source rootfs (`%{LICENSE}` tag) and evaluated per SPDX semantics:
+//! `OR` picks the most permissive branch, `AND` requires every branch.
+//!
+//! A package that was registered but has no license directory in the staging
+//! tree, or has no `LICENSE` tag, also fails the build - we cannot
+//! redistribute what we cannot attribute. The check fails closed: if the
+//! rpmdb can't be queried for a package, the build fails too.
+
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use std::fs;
+use std::path::Path;
+
+use crate::build::context::BuildContext;
+use crate::build::inputs::record_input;
+use distro_builder::process::Cmd;
+use distro_builder::LicenseTracker;
+
+/// Policy file location, relative to the leviso crate root.
+pub const LICENSE_POLICY_PATH: &str = "profile/license-policy.json";
+
+/// License directory inside the staging tree (where `copy_licenses` writes).
+const LICENSES_DIR: &str = "usr/share/licenses";
+
+/// Allowed/forbidden/review-required SPDX license identifiers.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct LicensePolicy {
+    /// Licenses that may be redistributed without further review.
+    #[serde(default)]
+    pub allowed: Vec<String>,
+    /// Licenses that must never be redistributed.
+    #[serde(default)]
+    pub forbidden: Vec<String>,
+    /// Licenses that are redistributable but need a human to look at them.
+    #[serde(default)]
+    pub review_required: Vec<String>,
+}
+
+/// Outcome of evaluating a license expression against the policy.
+///
+/// Ordered from most to least permissive, so `AND` takes the max and `OR`
+/// takes the min.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verdict {
+    Allowed,
+    ReviewRequired,
+    /// Not listed in the policy at all - treated like review-required.
+    Unknown,
+    Forbidden,
+}
+
+impl LicensePolicy {
+    /// Load the policy from a JSON file.
+    pub fn load(path: &Path) -> Result<Self> {
+        record_input(path);
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read license policy at {}", path.display()))?;
+        serde_json::from_str(&content)
+            .with_context(|| format!("Invalid license policy at {}", path.display()))
+    }
+
+    /// Classify a single license identifier (possibly `X WITH Y`).
+    fn classify_id(&self, id: &str) -> Verdict {
+        let matches = |list: &[String], id: &str| list.iter().any(|l| l.eq_ignore_ascii_case(id));
+
+        // Exact match first, so "GPL-2.0-only WITH Linux-syscall-note" can be
+        // listed explicitly. Otherwise fall back to the base license.
+        let candidates = match id.split_once(" WITH ") {
+            Some((base, _)) => vec![id, base.trim()],
+            None => vec![id],
+        };
+
+        for candidate in candidates {
+            if matches(&self.forbidden, candidate) {
+                return Verdict::Forbidden;
+            }
+            if matches(&self.allowed, candidate) {
+                return Verdict::Allowed;
+            }
+            if matches(&self.review_required, candidate) {
+                return Verdict::ReviewRequired;
+            }
+        }
+        Verdict::Unknown
+    }
+
+    /// Evaluate a full SPDX license expression.
+    pub fn evaluate(&self, expression: &str) -> Result<Verdict> {
+        let expression = expression.trim();
+        if expression.is_empty() {
+            return Ok(Verdict::Unknown);
+        }
+
+        // Legacy (pre-SPDX) RPM tags like "Redistributable, no modification
+        // permitted" can be listed verbatim in the policy.
+        let whole = self.classify_id(expression);
+        if whole != Verdict::Unknown {
+            return Ok(whole);
+        }
+
+        let tokens = tokenize(expression);
+        let mut parser = Parser {
+            tokens: &tokens,
+            pos: 0,
+            policy: self,
+        };
+        let verdict = parser.parse_or()?;
+        if parser.pos != tokens.len() {
+            bail!(
+                "Unexpected '{}' in license expression '{}'",
+                tokens[parser.pos],
+                expression
+            );
+        }
+        Ok(verdict)
+    }
+}
+
+/// Split an expression into parens and whitespace-separated words.
+fn tokenize(expression: &str) -> Vec<String> {
+    expression
+        .replace('(', " ( ")
+        .replace(')', " ) ")
+        .split_whitespace()
+        .map(str::to_string)
+        .collect()
+}
+
+fn is_keyword(token: &str, keyword: &str) -> bool {
+    token.eq_ignore_ascii_case(keyword)
+}
+
+/// Recursive-descent evaluator: `or := and (OR and)*`, `and := atom (AND atom)*`,
+/// `atom := '(' or ')' | id [WITH id]`.
+struct Parser<'a> {
+    tokens: &'a [String],
+    pos: usize,
+    policy: &'a LicensePolicy,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.pos).map(String::as_str)
+    }
+
+    fn parse_or(&mut self) -> Result<Verdict> {
+        let mut verdict = self.parse_and()?;
+        while self.peek().is_some_and(|t| is_keyword(t, "OR")) {
+            self.pos += 1;
+            verdict = verdict.min(self.parse_and()?);
+        }
+        Ok(verdict)
+    }
+
+    fn parse_and(&mut self) -> Result<Verdict> {
+        let mut verdict = self.parse_atom()?;
+        while self.peek().is_some_and(|t| is_keyword(t, "AND")) {
+            self.pos += 1;
+            verdict = verdict.max(self.parse_atom()?);
+        }
+        Ok(verdict)
+    }
+
+    fn parse_atom(&mut self) -> Result<Verdict> {
+        if self.peek() == Some("(") {
+            self.pos += 1;
+            let verdict = self.parse_or()?;
+            if self.peek() != Some(")") {
+                bail!("Unbalanced parentheses in license expression");
+            }
+            self.pos += 1;
+            return Ok(verdict);
+        }
+
+        let mut id = self.parse_words()?;
+        if self.peek().is_some_and(|t| is_keyword(t, "WITH")) {
+            self.pos += 1;
+            let exception = self.parse_words()?;
+            id = format!("{} WITH {}", id, exception);
+        }
+        Ok(self.policy.classify_id(&id))
+    }
+
+    /// Consume consecutive non-operator words as one identifier.
+    fn parse_words(&mut self) -> Result<String> {
+        let mut words = Vec::new();
+        while let Some(t) = self.peek() {
+            if t == "(" || t == ")" || ["AND", "OR", "WITH"].iter().any(|k| is_keyword(t, k)) {
+                break;
+            }
+            words.push(t.to_string());
+            self.pos += 1;
+        }
+        if words.is_empty() {
+            bail!("Expected a license identifier");
+        }
+        Ok(words.join(" "))
+    }
+}
+
+/// Query the `%{LICENSE}` tag of an installed package in the source rootfs.
+///
+/// `Ok(None)` if the package has no license tag; an error if rpm fails
+/// (unreadable rpmdb, package not installed).
+fn query_rpm_license(source: &Path, package: &str) -> Result<Option<String>> {
+    let result = Cmd::new("rpm")
+        .arg("--root")
+        .arg_path(source)
+        .args(["-q", "--qf", "%{LICENSE}"])
+        .arg(package)
+        .allow_fail()
+        .run()?;
+
+    if !result.success() {
+        bail!(
+            "Failed to query the license of package '{}' in {}: {}",
+            package,
+            source.display(),
+            result.stderr_trimmed()
+        );
+    }
+    Ok(parse_license_tag(&result.stdout))
+}
+
+/// License from `rpm --qf %{LICENSE}` output; rpm prints `(none)` for an
+/// unset tag.
+fn parse_license_tag(stdout: &str) -> Option<String> {
+    let license = stdout.trim();
+    (!license.is_empty() && license != "(none)").then(|| license.to_string())
+}
+
+/// Check that a package has at least one license file in staging.
+fn has_license_files(staging: &Path, package: &str) -> bool {
+    fs::read_dir(staging.join(LICENSES_DIR).join(package))
+        .map(|mut entries| entries.next().is_some())
+        .unwrap_or(false)
+}
+
+/// Check every registered package against the license policy.
+///
+/// Fails if any package resolves to a forbidden license, has no `LICENSE` tag
+/// or has no license files in the staging tree, and if the rpmdb can't be
+/// queried. Review-required and unknown licenses are reported but do not
+/// fail the build.
+pub fn enforce_license_policy(ctx: &BuildContext, tracker: &LicenseTracker) -> Result<()> {
+    let policy = LicensePolicy::load(&ctx.base_dir.join(LICENSE_POLICY_PATH))?;
+
+    let mut forbidden = Vec::new();
+    let mut untagged = Vec::new();
+    let mut missing = Vec::new();
+    let mut review = Vec::new();
+    let mut checked = 0;
+
+    for package in tracker.registered_packages() {
+        checked += 1;
+
+        let Some(license) = query_rpm_license(&ctx.source, &package)? else {
+            untagged.push(package.clone());
+            if !has_license_files(&ctx.staging, &package) {
+                missing.push(package);
+            }
+            continue;
+        };
+
+        let verdict = policy
+            .evaluate(&license)
+            .with_context(|| format!("Failed to evaluate license of package '{}'", package))?;
+        match verdict {
+            Verdict::Allowed => {}
+            Verdict::ReviewRequired | Verdict::Unknown => {
+                review.push(format!("{} ({})", package, license));
+            }
+            Verdict::Forbidden => forbidden.push(format!("{} ({})", package, license)),
+        }
+
+        if !has_license_files(&ctx.staging, &package) {
+            missing.push(package);
+        }
+    }
+
+    for item in &review {
+        println!("  [REVIEW] {}", item);
+    }
+
+    if forbidden.is_empty() && untagged.is_empty() && missing.is_empty() {
+        println!(
+            "  License policy OK ({} packages, {} need review)",
+            checked,
+            review.len()
+        );
+        return Ok(());
+    }
+
+    for item in &forbidden {
+        println!("  ✗ {} - forbidden license", item);
+    }
+    for item in &untagged {
+        println!("  ✗ {} - no LICENSE tag in rpmdb", item);
+    }
+    for item in &missing {
+        println!("  ✗ {} - no license file in /{}", item, LICENSES_DIR);
+    }
+    bail!(
+        "License policy FAILED: {} forbidden, {} without a license tag, {} without license files.\n\
+         Remove the offending packages or update {}.",
+        forbidden.len(),
+        untagged.len(),
+        missing.len(),
+        LICENSE_POLICY_PATH
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> LicensePolicy {
+        LicensePolicy {
+            allowed: vec!["MIT".into(), "GPL-2.0-only".into(), "Apache-2.0".into()],
+            forbidden: vec![
+                "LicenseRef-Proprietary".into(),
+                "Redistributable, no modification permitted".into(),
+            ],
+            review_required: vec!["LicenseRef-Fedora-Firmware".into()],
+        }
+    }
+
+    #[test]
+    fn test_single_identifiers() {
+        let p = policy();
+        assert_eq!(p.evaluate("MIT").unwrap(), Verdict::Allowed);
+        assert_eq!(p.evaluate("mit").unwrap(), Verdict::Allowed);
+        assert_eq!(
+            p.evaluate("LicenseRef-Fedora-Firmware").unwrap(),
+            Verdict::ReviewRequired
+        );
+        assert_eq!(p.evaluate("WTFPL").unwrap(), Verdict::Unknown);
+        assert_eq!(
+            p.evaluate("LicenseRef-Proprietary").unwrap(),
+            Verdict::Forbidden
+        );
+    }
+
+    #[test]
+    fn test_or_picks_most_permissive() {
+        let p = policy();
+        assert_eq!(
+            p.evaluate("LicenseRef-Proprietary OR MIT").unwrap(),
+            Verdict::Allowed
+        );
+    }
+
+    #[test]
+    fn test_and_requires_all() {
+        let p = policy();
+        assert_eq!(
+            p.evaluate("GPL-2.0-only AND LicenseRef-Proprietary")
+                .unwrap(),
+            Verdict::Forbidden
+        );
+        assert_eq!(
+            p.evaluate("(MIT OR WTFPL) AND (Apache-2.0 AND LicenseRef-Fedora-Firmware)")
+                .unwrap(),
+            Verdict::ReviewRequired
+        );
+    }
+
+    #[test]
+    fn test_with_exception_falls_back_to_base() {
+        let p = policy();
+        assert_eq!(
+            p.evaluate("GPL-2.0-only WITH Linux-syscall-note").unwrap(),
+            Verdict::Allowed
+        );
+    }
+
+    #[test]
+    fn test_legacy_multi_word_tag() {
+        let p = policy();
+        assert_eq!(
+            p.evaluate("GPL-2.0-only AND Redistributable, no modification permitted")
+                .unwrap(),
+            Verdict::Forbidden
+        );
+    }
+
+    #[test]
+    fn test_malformed_expression() {
+        let p = policy();
+        assert!(p.evaluate("(MIT OR Apache-2.0").is_err());
+        assert!(p.evaluate("MIT AND").is_err());
+    }
+
+    #[test]
+    fn test_unset_license_tag() {
+        assert_eq!(parse_license_tag("MIT\n"), Some("MIT".to_string()));
+        assert_eq!(parse_license_tag("(none)"), None);
+        assert_eq!(parse_license_tag("  "), None);
+    }
+
+    #[test]
+    fn test_shipped_policy_parses() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join(LICENSE_POLICY_PATH);
+        let p = LicensePolicy::load(&path).unwrap();
+        assert!(!p.allowed.is_empty());
+        assert!(!p.forbidden.is_empty());
+    }
+}
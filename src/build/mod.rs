@@ -7,16 +7,26 @@
 //! # Remaining modules
 //!
 //! - `context`: BuildContext for paths during build
+//! - `checkpoint`: Phase checkpoints for resumable rootfs builds
 //! - `filesystem`: Filesystem structure creation utilities
+//! - `inputs`: Build input recording for rebuild detection
+//! - `licenses`: License policy enforcement for redistributed packages
+//! - `reproducible`: SOURCE_DATE_EPOCH handling for reproducible builds
+//! - `sources`: Corresponding-source (SRPM) manifest for GPL compliance
 //! - `libdeps`: Library dependency resolution utilities
 //! - `users`: User/group file manipulation utilities
 //!
 //! Note: Kernel building is now handled by `crate::recipe::linux()`.
 
+pub mod checkpoint;
 pub mod context;
 pub mod distro_config;
 pub mod filesystem;
+pub mod inputs;
 pub mod libdeps;
+pub mod licenses;
+pub mod reproducible;
+pub mod sources;
 pub mod users;
 
 // Re-export commonly used items
@@ -0,0 +1,181 @@
+//! Reproducible builds with `SOURCE_DATE_EPOCH`.
+//!
+//! When `SOURCE_DATE_EPOCH` is set, a build is expected to be bit-for-bit
+//! reproducible so independent rebuilders can confirm published ISO hashes.
+//! The variable is read once per process and inherited by every tool leviso
+//! runs, so all artifacts share a single timestamp:
+//!
+//! | Step | How the epoch is applied |
+//! |------|--------------------------|
+//! | rootfs staging | mtimes clamped to the epoch (`clamp_mtimes`) |
+//! | `mkfs.erofs` | `-T <epoch> --all-root -U <stable_uuid>` |
+//! | dm-verity hash tree | `--salt`/`--uuid` derived from the epoch |
+//! | live/install initramfs | recinit and the verity segment clamp mtimes, `cpio --reproducible`, `gzip -n` |
+//! | `ukify` | honours `SOURCE_DATE_EPOCH` for PE timestamps |
+//! | `xorriso` | honours `SOURCE_DATE_EPOCH` for volume dates and UUIDs |
+//!
+//! Pre-generated SSH host keys are random by nature, so they are skipped in
+//! reproducible builds unless `LEVISO_SSH_HOST_KEYS=1`; sshd-keygen@.service
+//! then creates them on first boot.
+//!
+//! `leviso verify-reproducible` builds twice and compares the results.
+
+use anyhow::{bail, Context, Result};
+use sha2::{Digest, Sha256};
+use std::env;
+use std::path::Path;
+
+use distro_builder::process::Cmd;
+
+/// Standard reproducible-builds timestamp variable.
+pub const SOURCE_DATE_EPOCH_ENV: &str = "SOURCE_DATE_EPOCH";
+
+/// Force SSH host key generation on (`1`) or off (`0`).
+pub const SSH_HOST_KEYS_ENV: &str = "LEVISO_SSH_HOST_KEYS";
+
+/// The configured `SOURCE_DATE_EPOCH`, or `None` for a normal build.
+pub fn source_date_epoch() -> Result<Option<u64>> {
+    match env::var(SOURCE_DATE_EPOCH_ENV) {
+        Ok(value) if !value.trim().is_empty() => parse_epoch(&value).map(Some),
+        _ => Ok(None),
+    }
+}
+
+fn parse_epoch(value: &str) -> Result<u64> {
+    value.trim().parse().with_context(|| {
+        format!(
+            "{} must be a UNIX timestamp in seconds, got '{}'",
+            SOURCE_DATE_EPOCH_ENV, value
+        )
+    })
+}
+
+/// Commit time of `HEAD` in `base_dir`, the conventional default epoch.
+pub fn git_commit_epoch(base_dir: &Path) -> Result<u64> {
+    let result = Cmd::new("git")
+        .arg("-C")
+        .arg_path(base_dir)
+        .args(["log", "-1", "--format=%ct"])
+        .error_msg("Failed to read the last commit time (is this a git checkout?)")
+        .run()?;
+    parse_epoch(&result.stdout)
+}
+
+/// Whether to pre-generate SSH host keys in the rootfs.
+///
+/// Defaults to yes, except in reproducible builds.
+pub fn ssh_host_keys_enabled() -> Result<bool> {
+    match env::var(SSH_HOST_KEYS_ENV).as_deref() {
+        Ok("1") => Ok(true),
+        Ok("0") => Ok(false),
+        Ok(other) if !other.is_empty() => {
+            bail!("{} must be 0 or 1, got '{}'", SSH_HOST_KEYS_ENV, other)
+        }
+        _ => Ok(source_date_epoch()?.is_none()),
+    }
+}
+
+/// Set every mtime under `dir` (including `dir`) newer than `epoch` to `epoch`.
+///
+/// Older timestamps (e.g. from upstream RPM payloads) are kept, like
+/// `tar --clamp-mtime`. Symlinks are clamped themselves, not their targets.
+pub fn clamp_mtimes(dir: &Path, epoch: u64) -> Result<()> {
+    let at = format!("@{}", epoch);
+    Cmd::new("find")
+        .arg_path(dir)
+        .args([
+            "-newermt", &at, "-exec", "touch", "-h", "-d", &at, "{}", "+",
+        ])
+        .error_msg(&format!("Failed to clamp mtimes in {}", dir.display()))
+        .run()?;
+    Ok(())
+}
+
+/// 64 hex digits derived from `purpose` and `epoch`, e.g. a dm-verity salt.
+pub fn stable_hex(purpose: &str, epoch: u64) -> String {
+    Sha256::digest(format!("levitateos-{}-{}", purpose, epoch).as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Deterministic UUID for `purpose` (e.g. the EROFS filesystem UUID).
+pub fn stable_uuid(purpose: &str, epoch: u64) -> String {
+    let hex = stable_hex(purpose, epoch);
+    // RFC 4122 version 4 / variant 1 digits, so tools accept it as a normal UUID
+    let variant = (u8::from_str_radix(&hex[16..17], 16).unwrap_or(0) & 0x3) | 0x8;
+    format!(
+        "{}-{}-4{}-{:x}{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[13..16],
+        variant,
+        &hex[17..20],
+        &hex[20..32]
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serial_test::serial;
+    use std::fs;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn test_parse_epoch() {
+        assert_eq!(parse_epoch(" 1700000000\n").unwrap(), 1_700_000_000);
+        assert!(parse_epoch("yesterday").is_err());
+    }
+
+    #[test]
+    fn test_stable_uuid_is_deterministic() {
+        let uuid = stable_uuid("erofs", 1_700_000_000);
+        assert_eq!(uuid, stable_uuid("erofs", 1_700_000_000));
+        assert_ne!(uuid, stable_uuid("erofs", 1_700_000_001));
+        assert_ne!(uuid, stable_uuid("verity", 1_700_000_000));
+        assert_eq!(uuid.len(), 36);
+        assert_eq!(&uuid[14..15], "4");
+        assert!("89ab".contains(&uuid[19..20]));
+        assert_eq!(stable_hex("verity", 1).len(), 64);
+    }
+
+    #[test]
+    #[serial]
+    fn test_ssh_host_keys_default_follows_epoch() {
+        env::remove_var(SSH_HOST_KEYS_ENV);
+        env::remove_var(SOURCE_DATE_EPOCH_ENV);
+        assert!(ssh_host_keys_enabled().unwrap());
+
+        env::set_var(SOURCE_DATE_EPOCH_ENV, "1700000000");
+        assert!(!ssh_host_keys_enabled().unwrap());
+
+        env::set_var(SSH_HOST_KEYS_ENV, "1");
+        assert!(ssh_host_keys_enabled().unwrap());
+
+        env::remove_var(SSH_HOST_KEYS_ENV);
+        env::remove_var(SOURCE_DATE_EPOCH_ENV);
+    }
+
+    #[test]
+    fn test_clamp_mtimes_only_lowers() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let old = temp.path().join("old");
+        let new = temp.path().join("new");
+        fs::write(&old, "").unwrap();
+        fs::write(&new, "").unwrap();
+        let old_time = UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+        fs::File::options()
+            .write(true)
+            .open(&old)
+            .unwrap()
+            .set_modified(old_time)
+            .unwrap();
+
+        clamp_mtimes(temp.path(), 1_700_000_000).unwrap();
+
+        let mtime = |p: &Path| fs::metadata(p).unwrap().modified().unwrap();
+        assert_eq!(mtime(&old), old_time);
+        assert_eq!(mtime(&new), UNIX_EPOCH + Duration::from_secs(1_700_000_000));
+    }
+}
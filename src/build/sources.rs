@@ -0,0 +1,331 @@
+//! Corresponding-source manifest for GPL compliance.
+//!
+//! Every binary RPM that contributed to the image is mapped to its source RPM
+//! via the `SOURCERPM` header tag. The result is written next to the other
+//! build outputs as:
+//!
+//! - `sources.json` - machine-readable manifest (read back by `leviso sources`)
+//! - `SOURCES.txt` - human-readable list, also shipped on the ISO
+//!
+//! Together with the SRPMs collected from a mirror this backs the written
+//! offer for the ISO.
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use distro_builder::process::Cmd;
+use distro_spec::levitate::{OS_NAME, OS_VERSION};
+
+/// JSON manifest filename in the output directory.
+pub const SOURCES_JSON: &str = "sources.json";
+
+/// Text manifest filename in the output directory (and on the ISO).
+pub const SOURCES_TXT: &str = "SOURCES.txt";
+
+/// Artifact store kind of `sources.json`, stored under the rootfs key so a
+/// restored rootfs comes with the manifest it was built with.
+pub const ROOTFS_SOURCES_KIND: &str = "rootfs_sources";
+
+/// A source RPM and the binary packages built from it that we ship.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SourcePackage {
+    /// Source package name (e.g. "systemd").
+    pub name: String,
+    /// Version-release (e.g. "257-9.el10").
+    pub version: String,
+    /// Expected SRPM filename (e.g. "systemd-257-9.el10.src.rpm").
+    pub srpm: String,
+    /// Binary packages in the image that came from this SRPM.
+    pub binary_packages: Vec<String>,
+}
+
+/// Complete source manifest for one build.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SourceManifest {
+    /// Source packages, sorted by name.
+    pub packages: Vec<SourcePackage>,
+    /// Registered packages with no SOURCERPM in the rpmdb.
+    #[serde(default)]
+    pub unresolved: Vec<String>,
+}
+
+impl SourceManifest {
+    /// Build the manifest from `(binary package, SOURCERPM)` pairs.
+    pub fn from_pairs(pairs: &[(String, String)], unresolved: Vec<String>) -> Result<Self> {
+        let mut by_srpm: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for (binary, srpm) in pairs {
+            by_srpm
+                .entry(srpm.clone())
+                .or_default()
+                .push(binary.clone());
+        }
+
+        let mut packages = Vec::new();
+        for (srpm, mut binary_packages) in by_srpm {
+            let (name, version) = parse_srpm_filename(&srpm)?;
+            binary_packages.sort();
+            binary_packages.dedup();
+            packages.push(SourcePackage {
+                name,
+                version,
+                srpm,
+                binary_packages,
+            });
+        }
+        packages.sort_by(|a, b| a.name.cmp(&b.name).then(a.version.cmp(&b.version)));
+
+        Ok(Self {
+            packages,
+            unresolved,
+        })
+    }
+
+    /// Load a previously written `sources.json`.
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read source manifest at {}", path.display()))?;
+        serde_json::from_str(&content)
+            .with_context(|| format!("Invalid source manifest at {}", path.display()))
+    }
+
+    /// Render the human-readable `SOURCES.txt`.
+    pub fn to_text(&self) -> String {
+        let name_width = self
+            .packages
+            .iter()
+            .map(|p| p.name.len())
+            .max()
+            .unwrap_or(0);
+        let version_width = self
+            .packages
+            .iter()
+            .map(|p| p.version.len())
+            .max()
+            .unwrap_or(0);
+
+        let mut out = format!(
+            "# Corresponding source for {} {}\n\
+             # {} source packages. Source RPMs are available on request.\n\
+             #\n\
+             # NAME VERSION SRPM\n",
+            OS_NAME,
+            OS_VERSION,
+            self.packages.len()
+        );
+        for p in &self.packages {
+            out.push_str(&format!(
+                "{:<nw$}  {:<vw$}  {}\n",
+                p.name,
+                p.version,
+                p.srpm,
+                nw = name_width,
+                vw = version_width
+            ));
+        }
+        out
+    }
+
+    /// Write `sources.json` and `SOURCES.txt` into a directory.
+    pub fn write(&self, dir: &Path) -> Result<()> {
+        fs::create_dir_all(dir)?;
+        fs::write(
+            dir.join(SOURCES_JSON),
+            serde_json::to_string_pretty(self)? + "\n",
+        )?;
+        fs::write(dir.join(SOURCES_TXT), self.to_text())?;
+        Ok(())
+    }
+}
+
+/// Split an SRPM filename into (name, version-release).
+///
+/// `bash-5.2.26-6.el10.src.rpm` -> `("bash", "5.2.26-6.el10")`
+pub fn parse_srpm_filename(srpm: &str) -> Result<(String, String)> {
+    let Some(nvr) = srpm.strip_suffix(".src.rpm") else {
+        bail!("Not a source RPM filename: {}", srpm);
+    };
+    let mut parts = nvr.rsplitn(3, '-');
+    let (Some(release), Some(version), Some(name)) = (parts.next(), parts.next(), parts.next())
+    else {
+        bail!("Malformed source RPM filename: {}", srpm);
+    };
+    if name.is_empty() {
+        bail!("Malformed source RPM filename: {}", srpm);
+    }
+    Ok((name.to_string(), format!("{}-{}", version, release)))
+}
+
+/// Parse `NAME<TAB>SOURCERPM` lines from `rpm -q --qf`.
+///
+/// Lines for packages that are not installed ("package foo is not installed")
+/// and packages without a SOURCERPM ("(none)") are skipped.
+fn parse_rpm_query(stdout: &str) -> Vec<(String, String)> {
+    stdout
+        .lines()
+        .filter_map(|line| line.split_once('\t'))
+        .filter(|(_, srpm)| srpm.ends_with(".src.rpm"))
+        .map(|(name, srpm)| (name.trim().to_string(), srpm.trim().to_string()))
+        .collect()
+}
+
+/// Map binary packages to their SRPMs using the rpmdb in the source rootfs.
+pub fn collect_source_manifest(source: &Path, packages: &[String]) -> Result<SourceManifest> {
+    if packages.is_empty() {
+        return Ok(SourceManifest::default());
+    }
+
+    // Some registered packages may not be in the rpmdb; rpm exits non-zero
+    // but still prints the ones it found.
+    let mut cmd = Cmd::new("rpm").arg("--root").arg_path(source).args([
+        "-q",
+        "--qf",
+        "%{NAME}\\t%{SOURCERPM}\\n",
+    ]);
+    for package in packages {
+        cmd = cmd.arg(package);
+    }
+    let result = cmd.allow_fail().run()?;
+
+    let pairs = parse_rpm_query(&result.stdout);
+    let unresolved = packages
+        .iter()
+        .filter(|p| !pairs.iter().any(|(name, _)| name == *p))
+        .cloned()
+        .collect();
+
+    SourceManifest::from_pairs(&pairs, unresolved)
+}
+
+/// Write the source manifest for all packages registered during the build.
+pub fn write_source_manifest(source: &Path, output: &Path, packages: &[String]) -> Result<()> {
+    let manifest = collect_source_manifest(source, packages)?;
+    manifest.write(output)?;
+
+    println!(
+        "  Source manifest: {} SRPMs for {} packages",
+        manifest.packages.len(),
+        packages.len()
+    );
+    for name in &manifest.unresolved {
+        println!("  [WARN] No SOURCERPM for '{}' (not in rpmdb)", name);
+    }
+    Ok(())
+}
+
+/// Copy every SRPM listed in the manifest from a local mirror.
+///
+/// The mirror is searched recursively, so both flat directories and the
+/// usual `Source/Packages/<letter>/` layout work. Returns the SRPMs that
+/// could not be found.
+pub fn collect_srpms(manifest: &SourceManifest, mirror: &Path, dest: &Path) -> Result<Vec<String>> {
+    if !mirror.is_dir() {
+        bail!("SRPM mirror not found at {}", mirror.display());
+    }
+    fs::create_dir_all(dest)?;
+
+    let mut index: BTreeMap<String, PathBuf> = BTreeMap::new();
+    for entry in walkdir::WalkDir::new(mirror)
+        .follow_links(true)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+    {
+        let name = entry.file_name().to_string_lossy().to_string();
+        if name.ends_with(".src.rpm") {
+            index.entry(name).or_insert_with(|| entry.into_path());
+        }
+    }
+
+    let mut missing = Vec::new();
+    for p in &manifest.packages {
+        match index.get(&p.srpm) {
+            Some(src) => {
+                let dst = dest.join(&p.srpm);
+                if !dst.exists() {
+                    fs::copy(src, &dst)
+                        .with_context(|| format!("Failed to copy {}", src.display()))?;
+                }
+            }
+            None => missing.push(p.srpm.clone()),
+        }
+    }
+    Ok(missing)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_parse_srpm_filename() {
+        assert_eq!(
+            parse_srpm_filename("bash-5.2.26-6.el10.src.rpm").unwrap(),
+            ("bash".to_string(), "5.2.26-6.el10".to_string())
+        );
+        assert_eq!(
+            parse_srpm_filename("NetworkManager-libnm-1.52.0-1.el10.src.rpm").unwrap(),
+            (
+                "NetworkManager-libnm".to_string(),
+                "1.52.0-1.el10".to_string()
+            )
+        );
+        assert!(parse_srpm_filename("bash-5.2.26-6.el10.x86_64.rpm").is_err());
+        assert!(parse_srpm_filename("bash.src.rpm").is_err());
+    }
+
+    #[test]
+    fn test_parse_rpm_query_skips_missing() {
+        let out = "bash\tbash-5.2.26-6.el10.src.rpm\n\
+                   package kernel is not installed\n\
+                   gpg-pubkey\t(none)\n\
+                   systemd-udev\tsystemd-257-9.el10.src.rpm\n";
+        let pairs = parse_rpm_query(out);
+        assert_eq!(pairs.len(), 2);
+        assert_eq!(pairs[1].0, "systemd-udev");
+    }
+
+    #[test]
+    fn test_manifest_groups_binaries_by_srpm() {
+        let pairs = vec![
+            ("systemd-udev".into(), "systemd-257-9.el10.src.rpm".into()),
+            ("bash".into(), "bash-5.2.26-6.el10.src.rpm".into()),
+            ("systemd".into(), "systemd-257-9.el10.src.rpm".into()),
+        ];
+        let manifest = SourceManifest::from_pairs(&pairs, vec!["kernel".into()]).unwrap();
+
+        assert_eq!(manifest.packages.len(), 2);
+        assert_eq!(manifest.packages[0].name, "bash");
+        assert_eq!(
+            manifest.packages[1].binary_packages,
+            vec!["systemd".to_string(), "systemd-udev".to_string()]
+        );
+        assert_eq!(manifest.unresolved, vec!["kernel".to_string()]);
+        let text = manifest.to_text();
+        assert!(text
+            .lines()
+            .any(|l| l.starts_with("systemd ") && l.ends_with(" systemd-257-9.el10.src.rpm")));
+    }
+
+    #[test]
+    fn test_collect_srpms_from_mirror() {
+        let temp = TempDir::new().unwrap();
+        let mirror = temp.path().join("mirror/Source/Packages/b");
+        fs::create_dir_all(&mirror).unwrap();
+        fs::write(mirror.join("bash-5.2.26-6.el10.src.rpm"), "srpm").unwrap();
+
+        let pairs = vec![
+            ("bash".into(), "bash-5.2.26-6.el10.src.rpm".into()),
+            ("systemd".into(), "systemd-257-9.el10.src.rpm".into()),
+        ];
+        let manifest = SourceManifest::from_pairs(&pairs, Vec::new()).unwrap();
+        let dest = temp.path().join("srpms");
+        let missing = collect_srpms(&manifest, &temp.path().join("mirror"), &dest).unwrap();
+
+        assert!(dest.join("bash-5.2.26-6.el10.src.rpm").exists());
+        assert_eq!(missing, vec!["systemd-257-9.el10.src.rpm".to_string()]);
+    }
+}
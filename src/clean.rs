@@ -9,6 +9,14 @@ use distro_spec::levitate::{
     ISO_CHECKSUM_SUFFIX, ISO_FILENAME, ROOTFS_NAME,
 };
 
+use crate::artifact::netboot::NETBOOT_DIR;
+use crate::artifact::release::RELEASE_JSON;
+use crate::artifact::{persist, signing, verity};
+use crate::build::checkpoint;
+use crate::build::inputs::ROOTFS_INPUTS_LIST;
+use crate::build::sources::{SOURCES_JSON, SOURCES_TXT};
+use crate::rebuild::input_manifest_path;
+
 /// Clean all build outputs (preserves downloads).
 pub fn clean_outputs(base_dir: &Path) -> Result<()> {
     let output_dir = distro_builder::artifact_store::central_output_dir_for_distro(base_dir);
@@ -98,6 +106,24 @@ pub fn clean_iso(base_dir: &Path) -> Result<()> {
         cleaned = true;
     }
 
+    let release_json = output_dir.join(RELEASE_JSON);
+    if release_json.exists() {
+        println!("Removing release manifest...");
+        fs::remove_file(&release_json)?;
+        cleaned = true;
+    }
+
+    for signature in signing::SIGNATURE_SUFFIXES
+        .iter()
+        .flat_map(|suffix| [&iso, &checksum].map(|f| signing::with_suffix(f, suffix)))
+    {
+        if signature.exists() {
+            println!("Removing {}...", signature.display());
+            fs::remove_file(&signature)?;
+            cleaned = true;
+        }
+    }
+
     if initramfs.exists() {
         println!("Removing initramfs.img...");
         fs::remove_file(&initramfs)?;
@@ -122,6 +148,20 @@ pub fn clean_iso(base_dir: &Path) -> Result<()> {
         cleaned = true;
     }
 
+    let bench = output_dir.join("bench");
+    if bench.exists() {
+        println!("Removing initramfs benchmark variants...");
+        fs::remove_dir_all(&bench)?;
+        cleaned = true;
+    }
+
+    let live_init_target = output_dir.join("live-init-target");
+    if live_init_target.exists() {
+        println!("Removing live init build directory...");
+        fs::remove_dir_all(&live_init_target)?;
+        cleaned = true;
+    }
+
     if efiboot.exists() {
         println!("Removing efiboot.img...");
         fs::remove_file(&efiboot)?;
@@ -134,6 +174,21 @@ pub fn clean_iso(base_dir: &Path) -> Result<()> {
         cleaned = true;
     }
 
+    let persist_image = persist::persist_image_path(&iso);
+    if persist_image.exists() {
+        println!("Removing persistence image...");
+        fs::remove_file(&persist_image)?;
+        cleaned = true;
+    }
+
+    let netboot = output_dir.join(NETBOOT_DIR);
+    if netboot.exists() {
+        println!("Removing netboot bundle...");
+        fs::remove_dir_all(&netboot)?;
+        cleaned = true;
+    }
+
+    let _ = fs::remove_file(input_manifest_path(&initramfs_hash));
     if initramfs_hash.exists() {
         fs::remove_file(&initramfs_hash)?;
         cleaned = true;
@@ -153,8 +208,12 @@ pub fn clean_rootfs(base_dir: &Path) -> Result<()> {
     let output_dir = distro_builder::artifact_store::central_output_dir_for_distro(base_dir);
     let rootfs = output_dir.join(ROOTFS_NAME);
     let rootfs_staging = output_dir.join("rootfs-staging");
+    let rootfs_staging_work = output_dir.join("rootfs-staging.work");
     let rootfs_extracted = output_dir.join("rootfs-extracted");
     let rootfs_hash = output_dir.join(".rootfs-inputs.hash");
+    let rootfs_inputs = output_dir.join(ROOTFS_INPUTS_LIST);
+    let sources_json = output_dir.join(SOURCES_JSON);
+    let sources_txt = output_dir.join(SOURCES_TXT);
 
     let mut cleaned = false;
 
@@ -163,6 +222,8 @@ pub fn clean_rootfs(base_dir: &Path) -> Result<()> {
         fs::remove_file(&rootfs)?;
         cleaned = true;
     }
+    let _ = fs::remove_file(verity::hash_tree_path(&rootfs));
+    let _ = fs::remove_file(verity::root_hash_path(&rootfs));
 
     if rootfs_staging.exists() {
         println!("Removing rootfs staging...");
@@ -170,17 +231,37 @@ pub fn clean_rootfs(base_dir: &Path) -> Result<()> {
         cleaned = true;
     }
 
+    if rootfs_staging_work.exists() {
+        println!("Removing interrupted rootfs build (and its checkpoint)...");
+        fs::remove_dir_all(&rootfs_staging_work)?;
+        cleaned = true;
+    }
+    checkpoint::clear(&output_dir)?;
+
     if rootfs_extracted.exists() {
         println!("Removing extracted rootfs...");
         fs::remove_dir_all(&rootfs_extracted)?;
         cleaned = true;
     }
 
+    let _ = fs::remove_file(input_manifest_path(&rootfs_hash));
     if rootfs_hash.exists() {
         fs::remove_file(&rootfs_hash)?;
         cleaned = true;
     }
 
+    if rootfs_inputs.exists() {
+        fs::remove_file(&rootfs_inputs)?;
+        cleaned = true;
+    }
+
+    if sources_json.exists() || sources_txt.exists() {
+        println!("Removing source manifest...");
+        let _ = fs::remove_file(&sources_json);
+        let _ = fs::remove_file(&sources_txt);
+        cleaned = true;
+    }
+
     if cleaned {
         println!("Rootfs artifacts cleaned.");
     } else {
@@ -0,0 +1,247 @@
+//! Bench command - initramfs size and unpack time per compression.
+//!
+//! Every variant is recompressed from the same built image, so only the
+//! compression differs. Each is booted in QEMU with `initramfs_async=0
+//! initcall_debug`, which makes the kernel report how long `populate_rootfs`
+//! (decompressing and unpacking the initramfs) took; `rdinit=` points nowhere,
+//! so QEMU is stopped right after instead of booting on.
+
+use anyhow::{bail, Context, Result};
+use std::fs;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+use std::process::Stdio;
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+use distro_spec::levitate::{INITRAMFS_INSTALLED_OUTPUT, INITRAMFS_LIVE_OUTPUT, QEMU_MEMORY_GB};
+use recqemu::QemuBuilder;
+
+use crate::artifact::compression::Compression;
+use crate::artifact::{self, compression, cpio};
+use crate::common::OutputLock;
+use crate::recipe;
+
+/// Output subdirectory holding the variants.
+const BENCH_DIR: &str = "bench";
+
+/// Synchronous unpack with timing, and nothing to run afterwards.
+const BENCH_CMDLINE: &str = "console=ttyS0 initcall_debug initramfs_async=0 \
+                             rdinit=/leviso-bench init=/leviso-bench panic=-1";
+
+/// Time allowed for one variant to reach `populate_rootfs`.
+const BOOT_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// What to benchmark.
+pub enum BenchTarget {
+    /// Size and unpack time of the live and install initramfs per compression
+    Initramfs,
+}
+
+/// Execute the bench command.
+pub fn cmd_bench(base_dir: &Path, target: BenchTarget) -> Result<()> {
+    let _lock = OutputLock::acquire(base_dir, "leviso bench")?;
+
+    match target {
+        BenchTarget::Initramfs => bench_initramfs(base_dir),
+    }
+}
+
+struct BenchResult {
+    image: &'static str,
+    compression: Compression,
+    size: u64,
+    /// None if the kernel config cannot unpack this format
+    unpack: Option<Duration>,
+}
+
+fn bench_initramfs(base_dir: &Path) -> Result<()> {
+    recipe::ensure_qemu(base_dir)?;
+    let output_dir = distro_builder::artifact_store::central_output_dir_for_distro(base_dir);
+    let kernel = output_dir.join("staging/boot/vmlinuz");
+    if !kernel.exists() {
+        bail!(
+            "LevitateOS kernel not found at: {}\n\
+             Run 'cargo xtask kernels build leviso' first.",
+            kernel.display()
+        );
+    }
+    let kernel_config = compression::kernel_config_path(base_dir)
+        .map(fs::read_to_string)
+        .transpose()?
+        .unwrap_or_default();
+
+    let bench_dir = output_dir.join(BENCH_DIR);
+    let _ = fs::remove_dir_all(&bench_dir);
+    fs::create_dir_all(&bench_dir)?;
+
+    let images: [(&'static str, &str, fn(&Path) -> Result<()>); 2] = [
+        (
+            "live",
+            INITRAMFS_LIVE_OUTPUT,
+            artifact::build_tiny_initramfs,
+        ),
+        (
+            "install",
+            INITRAMFS_INSTALLED_OUTPUT,
+            artifact::build_install_initramfs,
+        ),
+    ];
+    let mut results = Vec::new();
+    for (image, output, build) in images {
+        let built = output_dir.join(output);
+        if !built.exists() {
+            build(base_dir)?;
+        }
+        let data = fs::read(&built)?;
+        let (early, payload) = cpio::split_archive(&data);
+
+        for compression in Compression::ALL {
+            println!("=== {} initramfs, {} ===", image, compression);
+            let variant = bench_dir.join(format!("{}-initramfs.{}", image, compression));
+            fs::write(&variant, payload)?;
+            compression::recompress(&variant, compression)?;
+            let compressed = fs::read(&variant)?;
+            fs::write(&variant, [early, compressed.as_slice()].concat())?;
+
+            let unpack = if compression::kernel_supports(&kernel_config, compression) {
+                let unpack = measure_unpack(&kernel, &variant)?;
+                println!("  Unpacked in {} ms", unpack.as_millis());
+                Some(unpack)
+            } else {
+                println!(
+                    "  {} not enabled, skipping boot",
+                    compression.kconfig_symbol()
+                );
+                None
+            };
+            results.push(BenchResult {
+                image,
+                compression,
+                size: fs::metadata(&variant)?.len(),
+                unpack,
+            });
+        }
+    }
+
+    println!();
+    println!(
+        "{:<8} {:<6} {:>10} {:>8} {:>10}",
+        "IMAGE", "FORMAT", "SIZE", "VS GZIP", "UNPACK"
+    );
+    for result in &results {
+        let gzip_size = results
+            .iter()
+            .find(|r| r.image == result.image && r.compression == Compression::Gzip)
+            .map_or(result.size, |r| r.size);
+        println!(
+            "{:<8} {:<6} {:>6.1} MiB {:>7.0}% {:>10}",
+            result.image,
+            result.compression.name(),
+            result.size as f64 / (1024.0 * 1024.0),
+            result.size as f64 * 100.0 / gzip_size as f64,
+            result
+                .unpack
+                .map_or("n/a".to_string(), |d| format!("{} ms", d.as_millis()))
+        );
+    }
+    println!("\nVariants kept in {}", bench_dir.display());
+    Ok(())
+}
+
+/// Boot `kernel` with `initrd` and return the time `populate_rootfs` took.
+fn measure_unpack(kernel: &Path, initrd: &Path) -> Result<Duration> {
+    let mut cmd = QemuBuilder::new()
+        .memory(&format!("{}G", QEMU_MEMORY_GB))
+        .smp(2)
+        .nographic()
+        .serial_stdio()
+        .no_reboot()
+        .build();
+    cmd.arg("-kernel")
+        .arg(kernel)
+        .arg("-initrd")
+        .arg(initrd)
+        .args(["-append", BENCH_CMDLINE])
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null());
+
+    let mut child = cmd.spawn().context("Failed to spawn qemu-system-x86_64")?;
+    let stdout = child.stdout.take().context("Failed to capture stdout")?;
+    let (tx, rx) = mpsc::channel();
+    std::thread::spawn(move || {
+        for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+            if tx.send(line).is_err() {
+                break;
+            }
+        }
+    });
+
+    let start = Instant::now();
+    let result = loop {
+        if start.elapsed() > BOOT_TIMEOUT {
+            break Err(anyhow::anyhow!(
+                "TIMEOUT: populate_rootfs not reported within {}s",
+                BOOT_TIMEOUT.as_secs()
+            ));
+        }
+        match rx.recv_timeout(Duration::from_millis(100)) {
+            Ok(line) => {
+                if let Some(unpack) = parse_unpack_time(&line) {
+                    break Ok(unpack);
+                }
+            }
+            Err(mpsc::RecvTimeoutError::Timeout) => continue,
+            Err(mpsc::RecvTimeoutError::Disconnected) => {
+                break Err(anyhow::anyhow!(
+                    "QEMU exited before the kernel reported populate_rootfs"
+                ));
+            }
+        }
+    };
+    let _ = child.kill();
+    let _ = child.wait();
+    result
+}
+
+/// Duration from the `initcall_debug` line for `populate_rootfs`:
+/// `initcall populate_rootfs+0x0/0xf0 returned 0 after 412345 usecs`.
+fn parse_unpack_time(line: &str) -> Option<Duration> {
+    let (_, rest) = line.split_once("initcall populate_rootfs+")?;
+    let (_, after) = rest.split_once(" after ")?;
+    let mut words = after.split_whitespace();
+    let value: u64 = words.next()?.parse().ok()?;
+    match words.next()? {
+        "usecs" => Some(Duration::from_micros(value)),
+        "msecs" => Some(Duration::from_millis(value)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_unpack_time() {
+        assert_eq!(
+            parse_unpack_time(
+                "[    1.802213] initcall populate_rootfs+0x0/0xf0 returned 0 after 412345 usecs"
+            ),
+            Some(Duration::from_micros(412345))
+        );
+        assert_eq!(
+            parse_unpack_time("initcall populate_rootfs+0x0/0x4c returned 0 after 87 msecs"),
+            Some(Duration::from_millis(87))
+        );
+        assert_eq!(
+            parse_unpack_time("calling  populate_rootfs+0x0/0xf0 @ 1"),
+            None
+        );
+        assert_eq!(
+            parse_unpack_time("initcall init_ramfs_fs+0x0/0x10 returned 0 after 3 usecs"),
+            None
+        );
+    }
+}
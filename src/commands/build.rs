@@ -1,6 +1,7 @@
 //! Build command - builds LevitateOS artifacts.
 
 use anyhow::Result;
+use std::fs;
 use std::path::Path;
 use std::time::Instant;
 
@@ -8,13 +9,29 @@ use distro_spec::levitate::{
     INITRAMFS_INSTALLED_OUTPUT, INITRAMFS_LIVE_OUTPUT, ISO_FILENAME, ROOTFS_NAME,
 };
 
+use super::graph::BuildGraph;
 use crate::artifact;
+use crate::build::reproducible;
+use crate::build::sources::{SourceManifest, ROOTFS_SOURCES_KIND, SOURCES_JSON};
+use crate::common::{remote_store, OutputLock};
 use crate::config::Config;
 use crate::rebuild;
 use crate::recipe;
+use distro_builder::artifact_store::ArtifactStore;
 use distro_builder::timing::Timer;
 
+/// Set to "1" to build without the local artifact store (no restores, no
+/// stores), as `verify-reproducible` does.
+pub const NO_ARTIFACT_STORE_ENV: &str = "LEVISO_NO_ARTIFACT_STORE";
+
+fn artifact_store_disabled() -> bool {
+    std::env::var(NO_ARTIFACT_STORE_ENV).as_deref() == Ok("1")
+}
+
 fn open_artifact_store(base_dir: &Path) -> Option<distro_builder::artifact_store::ArtifactStore> {
+    if artifact_store_disabled() {
+        return None;
+    }
     match distro_builder::artifact_store::ArtifactStore::open_for_distro(base_dir) {
         Ok(s) => Some(s),
         Err(e) => {
@@ -33,31 +50,42 @@ fn require_conformance_contract() -> Result<()> {
 pub enum BuildTarget {
     /// Full build (all artifacts, skip kernel if not available)
     Full,
-    /// Rootfs (EROFS) only
-    Rootfs,
+    /// Rootfs (EROFS) only, optionally resuming from the last checkpoint
+    Rootfs { resume: bool },
     /// Initramfs only
     Initramfs,
-    /// ISO only
-    Iso,
+    /// ISO only, optionally with a hybrid image carrying a persistence
+    /// partition of `persist_size` MiB
+    Iso { persist_size: Option<u32> },
     /// qcow2 VM disk image
     Qcow2 { disk_size: u32 },
+    /// PXE/HTTP network boot bundle served from `url`
+    Netboot { url: String },
 }
 
 /// Execute the build command.
 pub fn cmd_build(base_dir: &Path, target: BuildTarget, config: &Config) -> Result<()> {
     require_conformance_contract()?;
+    let _lock = OutputLock::acquire(base_dir, "leviso build")?;
+    if let Some(epoch) = reproducible::source_date_epoch()? {
+        println!("Reproducible build (SOURCE_DATE_EPOCH={})\n", epoch);
+    }
 
     match target {
         BuildTarget::Full => build_full(base_dir, config),
-        BuildTarget::Rootfs => build_rootfs_only(base_dir),
+        BuildTarget::Rootfs { resume } => build_rootfs_only(base_dir, resume),
         BuildTarget::Initramfs => build_initramfs_only(base_dir),
-        BuildTarget::Iso => build_iso_only(base_dir),
+        BuildTarget::Iso { persist_size } => build_iso_only(base_dir, persist_size),
         BuildTarget::Qcow2 { disk_size } => build_qcow2_only(base_dir, disk_size),
+        BuildTarget::Netboot { url } => build_netboot_only(base_dir, &url),
     }
 }
 
 /// Full build: rootfs (EROFS) + tiny initramfs + ISO.
-/// Rebuilds all non-kernel artifacts every run.
+/// Rebuilds all non-kernel artifacts every run, except those whose current
+/// input key is found in the artifact store.
+///
+/// Independent artifacts are built in parallel (see `graph::BuildGraph`).
 fn build_full(base_dir: &Path, _config: &Config) -> Result<()> {
     println!("=== Full LevitateOS Build ===\n");
     let build_start = Instant::now();
@@ -82,57 +110,25 @@ fn build_full(base_dir: &Path, _config: &Config) -> Result<()> {
     println!("\nDownloading EPEL packages...");
     recipe::epel(base_dir)?;
 
-    // Try to restore outputs from the centralized artifact store if the output
-    // files are missing but input hashes are known.
-    if let Some(store) = &store {
-        let rootfs_key = out.join(".rootfs-inputs.hash");
-        let rootfs_out = out.join(ROOTFS_NAME);
-        match distro_builder::artifact_store::try_restore_file_from_key(
-            store,
-            "rootfs_erofs",
-            &rootfs_key,
-            &rootfs_out,
-        ) {
-            Ok(true) => println!("\n[RESTORE] Rootfs restored from artifact store"),
-            Ok(false) => {}
-            Err(e) => eprintln!(
-                "[WARN] Failed to restore rootfs from artifact store: {:#}",
-                e
-            ),
-        }
-
-        let initramfs_key = out.join(".initramfs-inputs.hash");
-        let initramfs_out = out.join(INITRAMFS_LIVE_OUTPUT);
-        match distro_builder::artifact_store::try_restore_file_from_key(
-            store,
+    // Take outputs whose current input key is in the centralized artifact
+    // store (or the shared remote store) from there; their nodes are skipped.
+    let rootfs_restored = report_restore("Rootfs", restore_rootfs(store.as_ref(), base_dir));
+    let initramfs_restored = report_restore(
+        "Initramfs",
+        restore_artifact(
+            store.as_ref(),
             "initramfs",
-            &initramfs_key,
-            &initramfs_out,
-        ) {
-            Ok(true) => println!("\n[RESTORE] Initramfs restored from artifact store"),
-            Ok(false) => {}
-            Err(e) => eprintln!(
-                "[WARN] Failed to restore initramfs from artifact store: {:#}",
-                e
-            ),
-        }
-
-        let install_initramfs_key = out.join(".install-initramfs-inputs.hash");
-        let install_initramfs_out = out.join(INITRAMFS_INSTALLED_OUTPUT);
-        match distro_builder::artifact_store::try_restore_file_from_key(
-            store,
+            &rebuild::initramfs_artifact(base_dir),
+        ),
+    );
+    let install_initramfs_restored = report_restore(
+        "Install initramfs",
+        restore_artifact(
+            store.as_ref(),
             "install_initramfs",
-            &install_initramfs_key,
-            &install_initramfs_out,
-        ) {
-            Ok(true) => println!("\n[RESTORE] Install initramfs restored from artifact store"),
-            Ok(false) => {}
-            Err(e) => eprintln!(
-                "[WARN] Failed to restore install initramfs from artifact store: {:#}",
-                e
-            ),
-        }
-    }
+            &rebuild::install_initramfs_artifact(base_dir),
+        ),
+    );
 
     // 2. Kernel: compilation is centralized in xtask; leviso should only use existing artifacts.
     let needs_compile = rebuild::kernel_needs_compile(base_dir);
@@ -175,79 +171,78 @@ fn build_full(base_dir: &Path, _config: &Config) -> Result<()> {
         println!("\n[SKIP] Kernel already built and installed");
     }
 
-    // 4. Build rootfs (EROFS)
-    println!("\nBuilding EROFS rootfs image...");
-    let t = Timer::start("Rootfs");
-    artifact::build_rootfs(base_dir)?;
-    rebuild::cache_rootfs_hash(base_dir);
-    if let Some(store) = &store {
-        let key = out.join(".rootfs-inputs.hash");
-        let out_file = out.join(ROOTFS_NAME);
-        if let Err(e) = distro_builder::artifact_store::try_store_file_from_key(
-            store,
-            "rootfs_erofs",
-            &key,
-            &out_file,
-            std::collections::BTreeMap::new(),
-        ) {
-            eprintln!("[WARN] Failed to store rootfs in artifact store: {:#}", e);
+    // 4-6. Build rootfs, initramfs, install initramfs, installed UKIs and ISO.
+    // Only the ISO depends on the rootfs, so everything else runs alongside it.
+    let mut graph = BuildGraph::new();
+
+    let dir = base_dir.to_path_buf();
+    graph.add("Rootfs", &[], move |log| {
+        if rootfs_restored {
+            log.line("[SKIP] Restored from artifact store");
+        } else {
+            artifact::build_rootfs(&dir)?;
+            rebuild::cache_rootfs_hash(&dir)?;
         }
-    }
-    t.finish();
+        store_output(&dir, "rootfs_erofs", ".rootfs-inputs.hash", ROOTFS_NAME);
+        store_output(
+            &dir,
+            ROOTFS_SOURCES_KIND,
+            ".rootfs-inputs.hash",
+            SOURCES_JSON,
+        );
+        Ok(())
+    });
 
-    // 5. Build tiny initramfs
-    println!("\nBuilding tiny initramfs...");
-    let t = Timer::start("Initramfs");
-    artifact::build_tiny_initramfs(base_dir)?;
-    rebuild::cache_initramfs_hash(base_dir);
-    if let Some(store) = &store {
-        let key = out.join(".initramfs-inputs.hash");
-        let out_file = out.join(INITRAMFS_LIVE_OUTPUT);
-        if let Err(e) = distro_builder::artifact_store::try_store_file_from_key(
-            store,
-            "initramfs",
-            &key,
-            &out_file,
-            std::collections::BTreeMap::new(),
-        ) {
-            eprintln!(
-                "[WARN] Failed to store initramfs in artifact store: {:#}",
-                e
-            );
+    let dir = base_dir.to_path_buf();
+    graph.add("Initramfs", &[], move |log| {
+        if initramfs_restored {
+            log.line("[SKIP] Restored from artifact store");
+        } else {
+            artifact::build_tiny_initramfs(&dir)?;
+            rebuild::cache_initramfs_hash(&dir);
         }
-    }
-    t.finish();
+        store_output(
+            &dir,
+            "initramfs",
+            ".initramfs-inputs.hash",
+            INITRAMFS_LIVE_OUTPUT,
+        );
+        Ok(())
+    });
 
-    // 5b. Build install initramfs (REQUIRED for installation)
+    // Install initramfs (REQUIRED for installation)
     // This is copied to installed systems during installation
     // The initramfs is generic (no hostonly) so it works on any hardware
-    println!("\nBuilding install initramfs...");
-    let t = Timer::start("Install Initramfs");
-    artifact::build_install_initramfs(base_dir)?;
-    rebuild::cache_install_initramfs_hash(base_dir);
-    if let Some(store) = &store {
-        let key = out.join(".install-initramfs-inputs.hash");
-        let out_file = out.join(INITRAMFS_INSTALLED_OUTPUT);
-        if let Err(e) = distro_builder::artifact_store::try_store_file_from_key(
-            store,
-            "install_initramfs",
-            &key,
-            &out_file,
-            std::collections::BTreeMap::new(),
-        ) {
-            eprintln!(
-                "[WARN] Failed to store install initramfs in artifact store: {:#}",
-                e
-            );
+    let dir = base_dir.to_path_buf();
+    graph.add("Install Initramfs", &[], move |log| {
+        if install_initramfs_restored {
+            log.line("[SKIP] Restored from artifact store");
+        } else {
+            artifact::build_install_initramfs(&dir)?;
+            rebuild::cache_install_initramfs_hash(&dir);
         }
-    }
-    t.finish();
+        store_output(
+            &dir,
+            "install_initramfs",
+            ".install-initramfs-inputs.hash",
+            INITRAMFS_INSTALLED_OUTPUT,
+        );
+        Ok(())
+    });
+
+    let dir = base_dir.to_path_buf();
+    graph.add("Installed UKIs", &["Install Initramfs"], move |_| {
+        artifact::build_iso_installed_ukis(&dir)
+    });
+
+    let dir = base_dir.to_path_buf();
+    graph.add(
+        "ISO",
+        &["Rootfs", "Initramfs", "Installed UKIs"],
+        move |_| artifact::assemble_iso(&dir),
+    );
 
-    // 6. Build ISO
-    println!("\nBuilding ISO...");
-    let t = Timer::start("ISO");
-    artifact::create_iso(base_dir)?;
-    t.finish();
+    graph.run()?;
 
     // 7. ALWAYS verify all artifacts (whether just built or skipped)
     // This catches broken artifacts from previous runs
@@ -272,6 +267,103 @@ fn build_full(base_dir: &Path, _config: &Config) -> Result<()> {
     Ok(())
 }
 
+/// Print the outcome of a restore; true if `what` was restored.
+fn report_restore(what: &str, result: Result<bool>) -> bool {
+    match result {
+        Ok(true) => {
+            println!("\n[RESTORE] {} restored from artifact store", what);
+            true
+        }
+        Ok(false) => false,
+        Err(e) => {
+            eprintln!(
+                "[WARN] Failed to restore {} from artifact store: {:#}",
+                what.to_lowercase(),
+                e
+            );
+            false
+        }
+    }
+}
+
+/// Restore an out-of-date artifact from the artifact store, looked up by its
+/// current input hash, and record that hash as built.
+fn restore_artifact(
+    store: Option<&ArtifactStore>,
+    kind: &str,
+    artifact: &rebuild::Artifact,
+) -> Result<bool> {
+    if !artifact.needs_rebuild() {
+        return Ok(false);
+    }
+    let Some(key) = artifact.current_hash() else {
+        return Ok(false);
+    };
+    if !remote_store::restore_file(store, kind, &key, &artifact.output)? {
+        return Ok(false);
+    }
+    artifact.cache_hash();
+    Ok(true)
+}
+
+/// Restore the rootfs and its source manifest from the artifact store.
+///
+/// `sources.json` is stored next to the rootfs under the same key and
+/// `SOURCES.txt` is regenerated from it. A rootfs whose manifest can't be
+/// restored is removed again, so it is rebuilt with a matching manifest.
+fn restore_rootfs(store: Option<&ArtifactStore>, base_dir: &Path) -> Result<bool> {
+    let artifact = rebuild::rootfs_artifact(base_dir)?;
+    if !artifact.needs_rebuild() {
+        return Ok(false);
+    }
+    let Some(key) = artifact.current_hash() else {
+        return Ok(false);
+    };
+    let out = distro_builder::artifact_store::central_output_dir_for_distro(base_dir);
+    if !remote_store::restore_file(store, "rootfs_erofs", &key, &artifact.output)? {
+        return Ok(false);
+    }
+    let sources = out.join(SOURCES_JSON);
+    let restored =
+        remote_store::restore_file(store, ROOTFS_SOURCES_KIND, &key, &sources).and_then(|found| {
+            if found {
+                SourceManifest::load(&sources)?.write(&out)?;
+            }
+            Ok(found)
+        });
+    if !matches!(restored, Ok(true)) {
+        let _ = fs::remove_file(&artifact.output);
+        return restored.map(|_| {
+            println!("  Stored rootfs has no source manifest - rebuilding");
+            false
+        });
+    }
+    artifact.cache_hash();
+    Ok(true)
+}
+
+/// Store an output in the artifact store (local and, when pushing, remote),
+/// keyed by its input hash.
+///
+/// Opens its own store handle so it can be called from build graph nodes;
+/// without a local store the output is still pushed.
+fn store_output(base_dir: &Path, kind: &str, hash_name: &str, output_name: &str) {
+    let store = if artifact_store_disabled() {
+        None
+    } else {
+        ArtifactStore::open_for_distro(base_dir).ok()
+    };
+    let out = distro_builder::artifact_store::central_output_dir_for_distro(base_dir);
+    if let Err(e) = remote_store::store_file_from_key(
+        store.as_ref(),
+        kind,
+        &out.join(hash_name),
+        &out.join(output_name),
+    ) {
+        eprintln!("[WARN] Failed to store {} in artifact store: {:#}", kind, e);
+    }
+}
+
 /// Verify hardware compatibility against all profiles.
 fn verify_hardware_compat(base_dir: &Path) -> Result<()> {
     println!("\n=== Hardware Compatibility Verification ===");
@@ -319,45 +411,32 @@ fn verify_hardware_compat(base_dir: &Path) -> Result<()> {
 }
 
 /// Build rootfs (EROFS) only.
-fn build_rootfs_only(base_dir: &Path) -> Result<()> {
+///
+/// With `resume`, continues an earlier failed build from its checkpoint.
+fn build_rootfs_only(base_dir: &Path, resume: bool) -> Result<()> {
     let store = open_artifact_store(base_dir);
-    let output_dir = distro_builder::artifact_store::central_output_dir_for_distro(base_dir);
 
-    if let Some(store) = &store {
-        let key = output_dir.join(".rootfs-inputs.hash");
-        let out = output_dir.join(ROOTFS_NAME);
-        match distro_builder::artifact_store::try_restore_file_from_key(
-            store,
-            "rootfs_erofs",
-            &key,
-            &out,
-        ) {
-            Ok(true) => println!("[RESTORE] Rootfs restored from artifact store"),
-            Ok(false) => {}
-            Err(e) => eprintln!(
-                "[WARN] Failed to restore rootfs from artifact store: {:#}",
-                e
-            ),
-        }
+    if resume {
+        artifact::build_rootfs_resume(base_dir)?;
+        rebuild::cache_rootfs_hash(base_dir)?;
+        return Ok(());
     }
 
-    artifact::build_rootfs(base_dir)?;
-    rebuild::cache_rootfs_hash(base_dir);
-    if let Some(store) = &store {
-        let key = output_dir.join(".rootfs-inputs.hash");
-        let out = output_dir.join(ROOTFS_NAME);
-        if let Err(e) = distro_builder::artifact_store::try_store_file_from_key(
-            store,
-            "rootfs_erofs",
-            &key,
-            &out,
-            std::collections::BTreeMap::new(),
-        ) {
-            eprintln!("[WARN] Failed to store rootfs in artifact store: {:#}", e);
-        }
+    if report_restore("Rootfs", restore_rootfs(store.as_ref(), base_dir)) {
+        println!("[SKIP] Rootfs build");
+    } else {
+        artifact::build_rootfs(base_dir)?;
+        rebuild::cache_rootfs_hash(base_dir)?;
     }
-    // Rootfs verification happens inside build_rootfs() via verify_staging()
-    // No separate verification needed here since EROFS is always built fresh
+    store_output(base_dir, "rootfs_erofs", ".rootfs-inputs.hash", ROOTFS_NAME);
+    store_output(
+        base_dir,
+        ROOTFS_SOURCES_KIND,
+        ".rootfs-inputs.hash",
+        SOURCES_JSON,
+    );
+    // Rootfs verification happens inside build_rootfs() via verify_staging();
+    // a restored EROFS was verified when it was built and stored
     Ok(())
 }
 
@@ -366,42 +445,24 @@ fn build_initramfs_only(base_dir: &Path) -> Result<()> {
     let store = open_artifact_store(base_dir);
     let output_dir = distro_builder::artifact_store::central_output_dir_for_distro(base_dir);
 
-    if let Some(store) = &store {
-        let key = output_dir.join(".initramfs-inputs.hash");
-        let out = output_dir.join(INITRAMFS_LIVE_OUTPUT);
-        match distro_builder::artifact_store::try_restore_file_from_key(
-            store,
-            "initramfs",
-            &key,
-            &out,
-        ) {
-            Ok(true) => println!("[RESTORE] Initramfs restored from artifact store"),
-            Ok(false) => {}
-            Err(e) => eprintln!(
-                "[WARN] Failed to restore initramfs from artifact store: {:#}",
-                e
-            ),
-        }
-    }
-
-    artifact::build_tiny_initramfs(base_dir)?;
-    rebuild::cache_initramfs_hash(base_dir);
-    if let Some(store) = &store {
-        let key = output_dir.join(".initramfs-inputs.hash");
-        let out = output_dir.join(INITRAMFS_LIVE_OUTPUT);
-        if let Err(e) = distro_builder::artifact_store::try_store_file_from_key(
-            store,
+    let restored = report_restore(
+        "Initramfs",
+        restore_artifact(
+            store.as_ref(),
             "initramfs",
-            &key,
-            &out,
-            std::collections::BTreeMap::new(),
-        ) {
-            eprintln!(
-                "[WARN] Failed to store initramfs in artifact store: {:#}",
-                e
-            );
-        }
+            &rebuild::initramfs_artifact(base_dir),
+        ),
+    );
+    if !restored {
+        artifact::build_tiny_initramfs(base_dir)?;
+        rebuild::cache_initramfs_hash(base_dir);
     }
+    store_output(
+        base_dir,
+        "initramfs",
+        ".initramfs-inputs.hash",
+        INITRAMFS_LIVE_OUTPUT,
+    );
 
     // Always verify (whether just built or skipped)
     artifact::verify_live_initramfs(&output_dir.join(INITRAMFS_LIVE_OUTPUT))?;
@@ -409,7 +470,7 @@ fn build_initramfs_only(base_dir: &Path) -> Result<()> {
 }
 
 /// Build ISO only.
-fn build_iso_only(base_dir: &Path) -> Result<()> {
+fn build_iso_only(base_dir: &Path, persist_size: Option<u32>) -> Result<()> {
     let output_dir = distro_builder::artifact_store::central_output_dir_for_distro(base_dir);
     println!("Building rootfs...");
     artifact::build_rootfs(base_dir)?;
@@ -417,7 +478,7 @@ fn build_iso_only(base_dir: &Path) -> Result<()> {
     artifact::build_tiny_initramfs(base_dir)?;
     println!("Building install initramfs...");
     artifact::build_install_initramfs(base_dir)?;
-    rebuild::cache_rootfs_hash(base_dir);
+    rebuild::cache_rootfs_hash(base_dir)?;
     rebuild::cache_initramfs_hash(base_dir);
     rebuild::cache_install_initramfs_hash(base_dir);
 
@@ -430,6 +491,10 @@ fn build_iso_only(base_dir: &Path) -> Result<()> {
 
     // Verify final ISO
     artifact::verify_iso(&output_dir.join(ISO_FILENAME))?;
+
+    if let Some(size) = persist_size {
+        artifact::persist::build_persist_image(&output_dir.join(ISO_FILENAME), size)?;
+    }
     Ok(())
 }
 
@@ -464,3 +529,23 @@ fn build_qcow2_only(base_dir: &Path, disk_size: u32) -> Result<()> {
 
     Ok(())
 }
+
+/// Build the network boot bundle only.
+fn build_netboot_only(base_dir: &Path, url: &str) -> Result<()> {
+    let output_dir = distro_builder::artifact_store::central_output_dir_for_distro(base_dir);
+    if !output_dir.join(ROOTFS_NAME).exists() {
+        println!("Building rootfs...");
+        artifact::build_rootfs(base_dir)?;
+        rebuild::cache_rootfs_hash(base_dir)?;
+    }
+    // The key covers the module lists, so a build without the NIC drivers
+    // is rebuilt here
+    if rebuild::initramfs_needs_rebuild(base_dir) {
+        println!("Building tiny initramfs (inputs changed)...");
+        artifact::build_tiny_initramfs(base_dir)?;
+        rebuild::cache_initramfs_hash(base_dir);
+        artifact::verify_live_initramfs(&output_dir.join(INITRAMFS_LIVE_OUTPUT))?;
+    }
+
+    artifact::netboot::build_netboot(base_dir, url)
+}
@@ -4,6 +4,7 @@ use anyhow::Result;
 use std::path::Path;
 
 use crate::clean;
+use crate::common::OutputLock;
 use crate::recipe;
 
 /// Clean target for the clean command.
@@ -26,6 +27,8 @@ pub enum CleanTarget {
 
 /// Execute the clean command.
 pub fn cmd_clean(base_dir: &Path, target: CleanTarget) -> Result<()> {
+    let _lock = OutputLock::acquire(base_dir, "leviso clean")?;
+
     match target {
         CleanTarget::Outputs => {
             clean::clean_outputs(base_dir)?;
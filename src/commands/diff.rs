@@ -0,0 +1,392 @@
+//! Diff command - compare the contents of two initramfs images.
+//!
+//! Each side is a file (e.g. the output of another build) or an artifact
+//! store entry given as `<kind>:<key prefix>`, as listed by `leviso store
+//! list`. Both images are read completely - early microcode cpio, then every
+//! archive of the compressed stream, later entries replacing earlier ones like
+//! when the kernel unpacks them - and compared path by path:
+//!
+//! - size per directory, largest change first
+//! - added, removed and changed files with size, mode and symlink changes
+//! - kernel modules added, removed or changed, by module name
+//! - `diff -u` of init scripts (`/init` and other `#!` files)
+
+use anyhow::Result;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use distro_builder::process::Cmd;
+
+use crate::artifact::cpio::{self, CpioEntry};
+use crate::artifact::modules;
+
+/// Changed-file lines shown per section unless `--all`.
+const LIST_LIMIT: usize = 40;
+
+/// What to diff.
+pub enum DiffTarget {
+    /// Two initramfs images
+    Initramfs { a: String, b: String, all: bool },
+}
+
+/// Execute the diff command.
+pub fn cmd_diff(base_dir: &Path, target: DiffTarget) -> Result<()> {
+    match target {
+        DiffTarget::Initramfs { a, b, all } => diff_initramfs(base_dir, &a, &b, all),
+    }
+}
+
+/// One path that differs between the images.
+#[derive(Debug, PartialEq, Eq)]
+enum Change<'a> {
+    Added(&'a CpioEntry),
+    Removed(&'a CpioEntry),
+    Changed {
+        old: &'a CpioEntry,
+        new: &'a CpioEntry,
+    },
+}
+
+impl Change<'_> {
+    /// Unpacked size difference in bytes.
+    fn delta(&self) -> i64 {
+        match self {
+            Change::Added(e) => e.data.len() as i64,
+            Change::Removed(e) => -(e.data.len() as i64),
+            Change::Changed { old, new } => new.data.len() as i64 - old.data.len() as i64,
+        }
+    }
+}
+
+/// Resolve a diff argument to an image path.
+fn resolve_image(base_dir: &Path, arg: &str) -> Result<PathBuf> {
+    let path = PathBuf::from(arg);
+    if path.exists() {
+        return Ok(path);
+    }
+    match parse_store_ref(arg) {
+        Some((kind, key)) => super::store::find_entry(base_dir, kind, key),
+        None => anyhow::bail!(
+            "{} is neither a file nor a store entry (<kind>:<key prefix>, see 'leviso store list')",
+            arg
+        ),
+    }
+}
+
+/// `initramfs:3fa9c2` -> `("initramfs", "3fa9c2")`
+fn parse_store_ref(arg: &str) -> Option<(&str, &str)> {
+    let (kind, key) = arg.split_once(':')?;
+    let is_key = !key.is_empty() && key.chars().all(|c| c.is_ascii_hexdigit());
+    (!kind.is_empty() && !kind.contains('/') && is_key).then_some((kind, key))
+}
+
+fn diff_initramfs(base_dir: &Path, a: &str, b: &str, all: bool) -> Result<()> {
+    let (path_a, path_b) = (resolve_image(base_dir, a)?, resolve_image(base_dir, b)?);
+    let work_dir = distro_builder::artifact_store::central_output_dir_for_distro(base_dir)
+        .join("initramfs-diff.work");
+    let entries_a = cpio::read_initramfs(&path_a, &work_dir)?;
+    let entries_b = cpio::read_initramfs(&path_b, &work_dir)?;
+
+    println!("=== Initramfs Diff ===\n");
+    for (label, path, entries) in [("a", &path_a, &entries_a), ("b", &path_b, &entries_b)] {
+        println!(
+            "  {}: {} ({} compressed, {} unpacked, {} entries)",
+            label,
+            path.display(),
+            format_size(fs::metadata(path)?.len() as i64),
+            format_size(unpacked_size(entries) as i64),
+            entries.len()
+        );
+    }
+
+    let changes = diff_entries(&entries_a, &entries_b);
+    if changes.is_empty() {
+        println!("\nNo differences.");
+        return Ok(());
+    }
+    let total: i64 = changes.values().map(Change::delta).sum();
+    println!("  Unpacked size change: {}", format_delta(total));
+
+    print_directory_sizes(&changes);
+    print_changes(&changes, all);
+    print_modules(&changes);
+    print_script_diffs(&changes)?;
+    Ok(())
+}
+
+/// Paths that differ in content, mode or presence, sorted by path.
+fn diff_entries<'a>(
+    a: &'a BTreeMap<String, CpioEntry>,
+    b: &'a BTreeMap<String, CpioEntry>,
+) -> BTreeMap<&'a str, Change<'a>> {
+    let mut changes = BTreeMap::new();
+    for (path, old) in a {
+        match b.get(path) {
+            None => {
+                changes.insert(path.as_str(), Change::Removed(old));
+            }
+            Some(new) if new != old => {
+                changes.insert(path.as_str(), Change::Changed { old, new });
+            }
+            Some(_) => {}
+        }
+    }
+    for (path, new) in b {
+        if !a.contains_key(path) {
+            changes.insert(path.as_str(), Change::Added(new));
+        }
+    }
+    changes
+}
+
+fn unpacked_size(entries: &BTreeMap<String, CpioEntry>) -> u64 {
+    entries.values().map(|e| e.data.len() as u64).sum()
+}
+
+/// Directory a path is accounted to: its parent, cut to three components
+/// (`usr/lib/modules`, `usr/lib/firmware`, `usr/bin`).
+fn size_group(path: &str) -> String {
+    let parent = path.rsplit_once('/').map_or(".", |(dir, _)| dir);
+    parent.split('/').take(3).collect::<Vec<_>>().join("/")
+}
+
+fn print_directory_sizes(changes: &BTreeMap<&str, Change>) {
+    let mut groups: BTreeMap<String, i64> = BTreeMap::new();
+    for (path, change) in changes {
+        *groups.entry(size_group(path)).or_default() += change.delta();
+    }
+    let mut groups: Vec<_> = groups.into_iter().filter(|(_, d)| *d != 0).collect();
+    groups.sort_by_key(|(_, delta)| std::cmp::Reverse(delta.abs()));
+
+    println!("\nSize change by directory:");
+    for (dir, delta) in groups.iter().take(LIST_LIMIT) {
+        println!("  {:>12}  /{}", format_delta(*delta), dir);
+    }
+}
+
+fn print_changes(changes: &BTreeMap<&str, Change>, all: bool) {
+    let sections: [(&str, fn(&Change) -> bool); 3] = [
+        ("Added", |c| matches!(c, Change::Added(_))),
+        ("Removed", |c| matches!(c, Change::Removed(_))),
+        ("Changed", |c| matches!(c, Change::Changed { .. })),
+    ];
+    for (title, select) in sections {
+        let mut section: Vec<_> = changes.iter().filter(|(_, c)| select(c)).collect();
+        if section.is_empty() {
+            continue;
+        }
+        section.sort_by_key(|(_, c)| std::cmp::Reverse(c.delta().abs()));
+        println!("\n{} ({}):", title, section.len());
+        let shown = if all { section.len() } else { LIST_LIMIT };
+        for (path, change) in section.iter().take(shown) {
+            println!(
+                "  {:>12}  /{}{}",
+                format_delta(change.delta()),
+                path,
+                describe(change)
+            );
+        }
+        if section.len() > shown {
+            println!("  ... {} more (--all to list them)", section.len() - shown);
+        }
+    }
+}
+
+/// Mode and symlink details of a change.
+fn describe(change: &Change) -> String {
+    let link = |e: &CpioEntry| String::from_utf8_lossy(&e.data).to_string();
+    match change {
+        Change::Added(e) | Change::Removed(e) if e.is_symlink() => format!(" -> {}", link(e)),
+        Change::Added(e) | Change::Removed(e) => format!("  ({:o})", e.mode & 0o7777),
+        Change::Changed { old, new } => {
+            let mut details = Vec::new();
+            if old.mode != new.mode {
+                details.push(format!("mode {:o} -> {:o}", old.mode, new.mode));
+            }
+            if old.data != new.data {
+                if old.is_symlink() && new.is_symlink() {
+                    details.push(format!("link {} -> {}", link(old), link(new)));
+                } else {
+                    details.push("content".to_string());
+                }
+            }
+            format!("  ({})", details.join(", "))
+        }
+    }
+}
+
+fn print_modules(changes: &BTreeMap<&str, Change>) {
+    let is_module = |path: &str| path.contains("lib/modules/") && path.contains(".ko");
+    let mut added = Vec::new();
+    let mut removed = Vec::new();
+    let mut changed = Vec::new();
+    for (path, change) in changes.iter().filter(|(p, _)| is_module(p)) {
+        let name = modules::module_name(path);
+        match change {
+            Change::Added(_) => added.push(name),
+            Change::Removed(_) => removed.push(name),
+            Change::Changed { .. } => changed.push(name),
+        }
+    }
+    // A module that only changed its compression shows up as removed + added
+    let renamed: Vec<String> = added
+        .iter()
+        .filter(|name| removed.contains(name))
+        .cloned()
+        .collect();
+    added.retain(|name| !renamed.contains(name));
+    removed.retain(|name| !renamed.contains(name));
+    changed.extend(renamed);
+    changed.sort();
+
+    if added.is_empty() && removed.is_empty() && changed.is_empty() {
+        return;
+    }
+    println!("\nKernel modules:");
+    for (label, names) in [
+        ("added", &added),
+        ("removed", &removed),
+        ("changed", &changed),
+    ] {
+        if !names.is_empty() {
+            println!("  {} ({}): {}", label, names.len(), names.join(" "));
+        }
+    }
+}
+
+/// `diff -u` of init scripts present in both images.
+fn print_script_diffs(changes: &BTreeMap<&str, Change>) -> Result<()> {
+    let is_script =
+        |path: &str, e: &CpioEntry| e.is_file() && (path == "init" || e.data.starts_with(b"#!"));
+    let work = std::env::temp_dir().join(format!("leviso-diff-{}", std::process::id()));
+    for (path, change) in changes {
+        let Change::Changed { old, new } = change else {
+            continue;
+        };
+        if old.data == new.data {
+            continue;
+        }
+        match (is_script(path, old), is_script(path, new)) {
+            (true, true) if old.data.starts_with(b"#!") && new.data.starts_with(b"#!") => {}
+            (true, true) => {
+                println!(
+                    "\n/{}: binary ({} -> {})",
+                    path,
+                    file_kind(old),
+                    file_kind(new)
+                );
+                continue;
+            }
+            _ => continue,
+        }
+
+        fs::create_dir_all(&work)?;
+        let (file_a, file_b) = (work.join("a"), work.join("b"));
+        fs::write(&file_a, &old.data)?;
+        fs::write(&file_b, &new.data)?;
+        // diff exits 1 when the files differ
+        let diff = Cmd::new("diff")
+            .arg("-u")
+            .args(["--label", &format!("a/{}", path)])
+            .args(["--label", &format!("b/{}", path)])
+            .arg_path(&file_a)
+            .arg_path(&file_b)
+            .allow_fail()
+            .run()?;
+        println!("\n{}", diff.stdout.trim_end());
+    }
+    let _ = fs::remove_dir_all(&work);
+    Ok(())
+}
+
+fn file_kind(entry: &CpioEntry) -> &'static str {
+    if entry.data.starts_with(b"#!") {
+        "script"
+    } else if entry.data.starts_with(b"\x7fELF") {
+        "ELF"
+    } else {
+        "data"
+    }
+}
+
+fn format_size(bytes: i64) -> String {
+    let abs = bytes.unsigned_abs() as f64;
+    if abs >= (1 << 20) as f64 {
+        format!("{:.1} MiB", bytes as f64 / (1 << 20) as f64)
+    } else if abs >= 1024.0 {
+        format!("{:.1} KiB", bytes as f64 / 1024.0)
+    } else {
+        format!("{} B", bytes)
+    }
+}
+
+fn format_delta(bytes: i64) -> String {
+    if bytes > 0 {
+        format!("+{}", format_size(bytes))
+    } else {
+        format_size(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(mode: u32, data: &[u8]) -> CpioEntry {
+        CpioEntry {
+            mode,
+            data: data.to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_diff_entries() {
+        let a = BTreeMap::from([
+            ("init".to_string(), entry(0o100755, b"#!/bin/sh\n")),
+            ("bin/sh".to_string(), entry(0o120777, b"busybox")),
+            ("etc/passwd".to_string(), entry(0o100644, b"root:x:0:0")),
+        ]);
+        let mut b = a.clone();
+        b.remove("bin/sh");
+        b.insert("etc/passwd".to_string(), entry(0o100600, b"root:x:0:0"));
+        b.insert("init".to_string(), entry(0o100755, b"\x7fELF....."));
+        b.insert(
+            "etc/os-release".to_string(),
+            entry(0o100644, b"ID=levitate"),
+        );
+
+        let changes = diff_entries(&a, &b);
+        assert_eq!(
+            changes.keys().copied().collect::<Vec<_>>(),
+            ["bin/sh", "etc/os-release", "etc/passwd", "init"]
+        );
+        assert!(matches!(changes["bin/sh"], Change::Removed(_)));
+        assert!(matches!(changes["etc/os-release"], Change::Added(_)));
+        assert_eq!(changes["etc/passwd"].delta(), 0);
+        assert_eq!(
+            describe(&changes["etc/passwd"]),
+            "  (mode 100644 -> 100600)"
+        );
+        assert_eq!(changes["init"].delta(), -1);
+        assert_eq!(describe(&changes["bin/sh"]), " -> busybox");
+    }
+
+    #[test]
+    fn test_store_ref_and_groups() {
+        assert_eq!(
+            parse_store_ref("install_initramfs:3fa9c2"),
+            Some(("install_initramfs", "3fa9c2"))
+        );
+        assert_eq!(parse_store_ref("out/initramfs.img"), None);
+        assert_eq!(parse_store_ref("./a:b/initramfs"), None);
+        assert_eq!(
+            size_group("usr/lib/modules/6.12.0/kernel/fs/ext4/ext4.ko"),
+            "usr/lib/modules"
+        );
+        assert_eq!(size_group("usr/bin/bash"), "usr/bin");
+        assert_eq!(size_group("init"), ".");
+        assert_eq!(format_delta(3 << 20), "+3.0 MiB");
+        assert_eq!(format_delta(-2048), "-2.0 KiB");
+    }
+}
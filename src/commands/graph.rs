@@ -0,0 +1,300 @@
+//! Build graph - runs artifact builds as a DAG.
+//!
+//! Each node names the nodes it depends on. As soon as all dependencies of a
+//! node have finished, it starts on the tokio blocking pool, so independent
+//! artifacts (rootfs, live initramfs, install initramfs) build in parallel.
+//! The artifact builders themselves stay synchronous.
+//!
+//! Output of parallel nodes interleaves, so each job gets a `NodeLog` that
+//! prefixes its lines with the node name (see `output`), every node start,
+//! finish and failure is announced on its own `[graph]` line, and a per-node
+//! timing summary is printed once the graph completes.
+
+use anyhow::{anyhow, bail, Context, Result};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::time::{Duration, Instant};
+use tokio::task::JoinSet;
+
+use crate::output::NodeLog;
+use distro_builder::timing::Timer;
+
+type Job = Box<dyn FnOnce(&NodeLog) -> Result<()> + Send + 'static>;
+
+struct Node {
+    name: &'static str,
+    deps: Vec<&'static str>,
+    job: Job,
+}
+
+/// A set of build steps with explicit dependencies.
+#[derive(Default)]
+pub struct BuildGraph {
+    nodes: Vec<Node>,
+}
+
+impl BuildGraph {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a node that runs `job` after every node in `deps` has succeeded.
+    ///
+    /// `job` reports through the `NodeLog` it is given.
+    pub fn add<F>(&mut self, name: &'static str, deps: &[&'static str], job: F)
+    where
+        F: FnOnce(&NodeLog) -> Result<()> + Send + 'static,
+    {
+        self.nodes.push(Node {
+            name,
+            deps: deps.to_vec(),
+            job: Box::new(job),
+        });
+    }
+
+    /// Check for duplicate names, unknown dependencies and cycles.
+    ///
+    /// Returns the nodes in a valid sequential order.
+    fn topological_order(&self) -> Result<Vec<&'static str>> {
+        let mut indegree: BTreeMap<&str, usize> = BTreeMap::new();
+        for node in &self.nodes {
+            if indegree.insert(node.name, node.deps.len()).is_some() {
+                bail!("Duplicate build graph node '{}'", node.name);
+            }
+        }
+        for node in &self.nodes {
+            if let Some(dep) = node.deps.iter().find(|d| !indegree.contains_key(*d)) {
+                bail!(
+                    "Build graph node '{}' depends on unknown node '{}'",
+                    node.name,
+                    dep
+                );
+            }
+        }
+
+        let mut ready: VecDeque<&'static str> = self
+            .nodes
+            .iter()
+            .filter(|n| n.deps.is_empty())
+            .map(|n| n.name)
+            .collect();
+        let mut order = Vec::new();
+        while let Some(name) = ready.pop_front() {
+            order.push(name);
+            for node in self.nodes.iter().filter(|n| n.deps.contains(&name)) {
+                let remaining = indegree.get_mut(node.name).expect("validated above");
+                *remaining -= 1;
+                if *remaining == 0 {
+                    ready.push_back(node.name);
+                }
+            }
+        }
+
+        if order.len() != self.nodes.len() {
+            let cyclic: Vec<&str> = self
+                .nodes
+                .iter()
+                .map(|n| n.name)
+                .filter(|n| !order.contains(n))
+                .collect();
+            bail!(
+                "Build graph has a dependency cycle involving: {}",
+                cyclic.join(", ")
+            );
+        }
+        Ok(order)
+    }
+
+    /// Run every node as soon as its dependencies have finished.
+    ///
+    /// After the first failure no new nodes are started; nodes already
+    /// running are allowed to finish, then the first error is returned.
+    pub fn run(self) -> Result<()> {
+        self.topological_order()?;
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .context("Failed to start tokio runtime for the build graph")?;
+        runtime.block_on(self.run_async())
+    }
+
+    async fn run_async(self) -> Result<()> {
+        let graph_start = Instant::now();
+        let mut pending: Vec<Node> = self.nodes;
+        let mut finished: BTreeSet<&'static str> = BTreeSet::new();
+        let mut timings: Vec<(&'static str, Duration)> = Vec::new();
+        let mut running = JoinSet::new();
+        let mut failure: Option<anyhow::Error> = None;
+        let log = NodeLog::new("graph");
+
+        loop {
+            if failure.is_none() {
+                let (ready, waiting): (Vec<Node>, Vec<Node>) = pending
+                    .into_iter()
+                    .partition(|n| n.deps.iter().all(|d| finished.contains(d)));
+                pending = waiting;
+
+                for node in ready {
+                    log.line(format_args!("\n▶ {}", node.name));
+                    running.spawn_blocking(move || {
+                        let timer = Timer::start(node.name);
+                        let start = Instant::now();
+                        let result = (node.job)(&NodeLog::new(node.name));
+                        if result.is_ok() {
+                            timer.finish();
+                        }
+                        (node.name, start.elapsed(), result)
+                    });
+                }
+            }
+
+            let Some(joined) = running.join_next().await else {
+                break;
+            };
+            match joined {
+                Ok((name, elapsed, Ok(()))) => {
+                    log.line(format_args!("✓ {} ({})", name, format_duration(elapsed)));
+                    finished.insert(name);
+                    timings.push((name, elapsed));
+                }
+                Ok((name, _, Err(e))) => {
+                    log.line(format_args!("✗ {} failed", name));
+                    failure.get_or_insert(e.context(format!("Build step '{}' failed", name)));
+                }
+                Err(e) => {
+                    failure.get_or_insert(anyhow!("Build step panicked: {}", e));
+                }
+            }
+        }
+
+        if let Some(e) = failure {
+            if !pending.is_empty() {
+                let skipped: Vec<&str> = pending.iter().map(|n| n.name).collect();
+                log.line(format_args!("skipped: {}", skipped.join(", ")));
+            }
+            return Err(e);
+        }
+
+        print_summary(&timings, graph_start.elapsed());
+        Ok(())
+    }
+}
+
+/// Format a duration the way the build summary does (`12.3s` / `4.5m`).
+fn format_duration(d: Duration) -> String {
+    let secs = d.as_secs_f64();
+    if secs >= 60.0 {
+        format!("{:.1}m", secs / 60.0)
+    } else {
+        format!("{:.1}s", secs)
+    }
+}
+
+/// Print per-node times in completion order, plus the time saved by running
+/// nodes in parallel.
+fn print_summary(timings: &[(&'static str, Duration)], wall: Duration) {
+    let width = timings.iter().map(|(n, _)| n.len()).max().unwrap_or(0);
+    let sequential: Duration = timings.iter().map(|(_, d)| *d).sum();
+
+    println!("\n=== Build Graph ===");
+    for (name, elapsed) in timings {
+        println!(
+            "  {:<width$}  {:>7}",
+            name,
+            format_duration(*elapsed),
+            width = width
+        );
+    }
+    println!(
+        "  Wall clock {} (sequential would be {})",
+        format_duration(wall),
+        format_duration(sequential)
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::thread::sleep;
+
+    #[test]
+    fn test_rejects_unknown_dependency() {
+        let mut graph = BuildGraph::new();
+        graph.add("iso", &["rootfs"], |_| Ok(()));
+        let err = graph.run().unwrap_err();
+        assert!(err.to_string().contains("unknown node 'rootfs'"));
+    }
+
+    #[test]
+    fn test_rejects_cycle() {
+        let mut graph = BuildGraph::new();
+        graph.add("a", &["b"], |_| Ok(()));
+        graph.add("b", &["a"], |_| Ok(()));
+        graph.add("c", &[], |_| Ok(()));
+        let err = graph.run().unwrap_err();
+        assert!(err.to_string().contains("cycle involving: a, b"));
+    }
+
+    #[test]
+    fn test_dependencies_run_first() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let mut graph = BuildGraph::new();
+        for (name, deps) in [
+            ("iso", &["rootfs", "ukis"][..]),
+            ("ukis", &["install"][..]),
+            ("rootfs", &[][..]),
+            ("install", &[][..]),
+        ] {
+            let log = Arc::clone(&log);
+            graph.add(name, deps, move |_| {
+                log.lock().unwrap().push(name);
+                Ok(())
+            });
+        }
+        graph.run().unwrap();
+
+        let log = log.lock().unwrap();
+        let pos = |n: &str| log.iter().position(|x| *x == n).unwrap();
+        assert_eq!(log.len(), 4);
+        assert!(pos("install") < pos("ukis"));
+        assert!(pos("ukis") < pos("iso"));
+        assert!(pos("rootfs") < pos("iso"));
+    }
+
+    #[test]
+    fn test_independent_nodes_run_in_parallel() {
+        let active = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+        let mut graph = BuildGraph::new();
+        for name in ["rootfs", "initramfs", "install"] {
+            let active = Arc::clone(&active);
+            let peak = Arc::clone(&peak);
+            graph.add(name, &[], move |_| {
+                let now = active.fetch_add(1, Ordering::SeqCst) + 1;
+                peak.fetch_max(now, Ordering::SeqCst);
+                sleep(Duration::from_millis(100));
+                active.fetch_sub(1, Ordering::SeqCst);
+                Ok(())
+            });
+        }
+        graph.run().unwrap();
+        assert!(peak.load(Ordering::SeqCst) > 1);
+    }
+
+    #[test]
+    fn test_failure_skips_dependents() {
+        let ran = Arc::new(AtomicUsize::new(0));
+        let mut graph = BuildGraph::new();
+        graph.add("rootfs", &[], |_| bail!("mkfs.erofs failed"));
+        let ran_iso = Arc::clone(&ran);
+        graph.add("iso", &["rootfs"], move |_| {
+            ran_iso.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        });
+
+        let err = graph.run().unwrap_err();
+        assert!(format!("{:#}", err).contains("Build step 'rootfs' failed"));
+        assert_eq!(ran.load(Ordering::SeqCst), 0);
+    }
+}
@@ -1,27 +1,54 @@
 //! CLI command handlers.
 //!
 //! Each submodule handles a specific CLI command:
+//! - `bench` - Initramfs compression size/unpack-time benchmark
 //! - `build` - Build LevitateOS artifacts
 //! - `run` - Run ISO in QEMU (GUI)
 //! - `test` - Test ISO boots (headless, automated)
 //! - `clean` - Clean build artifacts
+//! - `diff` - Compare the contents of two initramfs images
 //! - `show` - Display information
 //! - `download` - Download dependencies
 //! - `extract` - Extract archives
 //! - `preflight` - Run preflight checks
+//! - `remaster` - Repack a released ISO with a new overlay or cmdline
+//! - `reproducible` - Build twice and compare artifacts
+//! - `secureboot` - Secure Boot test key generation
+//! - `sources` - Corresponding-source manifest and SRPM collection
+//! - `store` - Inspect, verify and trim the artifact store
+//! - `verify_iso` - Check an ISO's checksum and detached signatures
+//!
+//! `graph` is shared plumbing: the DAG runner used by `build`.
 
+pub mod bench;
 pub mod build;
 pub mod clean;
+pub mod diff;
 pub mod download;
 pub mod extract;
+mod graph;
 mod preflight;
+mod remaster;
+mod reproducible;
 mod run;
+mod secureboot;
 pub mod show;
+mod sources;
+pub mod store;
+mod verify_iso;
 
+pub use bench::cmd_bench;
 pub use build::cmd_build;
 pub use clean::cmd_clean;
+pub use diff::cmd_diff;
 pub use download::cmd_download;
 pub use extract::cmd_extract;
 pub use preflight::cmd_preflight;
+pub use remaster::cmd_remaster;
+pub use reproducible::cmd_verify_reproducible;
 pub use run::{cmd_run, cmd_test};
+pub use secureboot::cmd_secureboot_keys;
 pub use show::cmd_show;
+pub use sources::cmd_sources;
+pub use store::cmd_store;
+pub use verify_iso::cmd_verify_iso;
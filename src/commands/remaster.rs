@@ -0,0 +1,27 @@
+//! Remaster command - modify a released ISO without rebuilding.
+
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+
+use crate::artifact::remaster::{self, RemasterOptions};
+
+/// Execute the remaster command.
+pub fn cmd_remaster(
+    iso: &Path,
+    output: &Path,
+    overlay: Option<PathBuf>,
+    replace_overlay: bool,
+    add: &[String],
+    cmdline: Option<String>,
+) -> Result<()> {
+    let options = RemasterOptions {
+        overlay,
+        replace_overlay,
+        add: add
+            .iter()
+            .map(|spec| remaster::parse_add(spec))
+            .collect::<Result<_>>()?,
+        cmdline,
+    };
+    remaster::remaster_iso(iso, output, &options)
+}
@@ -0,0 +1,444 @@
+//! Verify-reproducible command - build twice and compare the artifacts.
+//!
+//! Both builds use the same `SOURCE_DATE_EPOCH` (from the environment, or
+//! the last commit time) and neither touches the local or remote artifact
+//! store, so every artifact is really built.
+//!
+//! Each build runs in its own copy of the leviso checkout under
+//! `reproducible/checkout-N` (the rest of the monorepo and `downloads/` are
+//! symlinked in), so the two builds differ in their absolute paths and each
+//! starts from an empty output directory: a build path leaking into an
+//! artifact shows up as a difference, and nothing (e.g. the cargo target dir
+//! of the Rust `/init`) is reused between them. Only the kernel, which is
+//! built by xtask and not compared, is seeded from the main output directory.
+//! Monorepo tools (recipe, the install tools) are built in the monorepo
+//! itself, like in every build.
+//!
+//! The artifacts of each build are copied to `reproducible/build-1` and
+//! `build-2`; any artifact whose hash differs is unpacked and walked down to
+//! the first differing file.
+
+use anyhow::{bail, Context, Result};
+use std::cmp::Ordering;
+use std::env;
+use std::fs;
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+
+use distro_builder::process::{self, Cmd};
+use distro_spec::levitate::{
+    INITRAMFS_INSTALLED_OUTPUT, INITRAMFS_LIVE_OUTPUT, ISO_FILENAME, ROOTFS_NAME,
+};
+
+use super::build::{cmd_build, BuildTarget, NO_ARTIFACT_STORE_ENV};
+use crate::artifact::compression;
+use crate::build::reproducible::{self, SOURCE_DATE_EPOCH_ENV};
+use crate::common::remote_store::REMOTE_STORE_ENV;
+use crate::config::Config;
+
+/// Output subdirectory holding the checkouts and artifacts of both builds.
+const REPRODUCIBLE_DIR: &str = "reproducible";
+
+/// Kernel files (built by xtask) a fresh output directory needs, relative to
+/// the output directory.
+const KERNEL_OUTPUTS: &[&str] = &[
+    "kernel-build/arch/x86/boot/bzImage",
+    "kernel-build/.config",
+    ".kernel-inputs.hash",
+    ".kernel-inputs.manifest",
+    "staging/boot",
+    "staging/usr/lib/modules",
+];
+
+/// How to unpack an artifact to look inside it.
+#[derive(Debug, Clone, Copy)]
+enum Unpack {
+    Iso,
+    Erofs,
+    Cpio,
+}
+
+/// Compared artifacts, in build order.
+const ARTIFACTS: &[(&str, Unpack)] = &[
+    (ROOTFS_NAME, Unpack::Erofs),
+    (INITRAMFS_LIVE_OUTPUT, Unpack::Cpio),
+    (INITRAMFS_INSTALLED_OUTPUT, Unpack::Cpio),
+    (ISO_FILENAME, Unpack::Iso),
+];
+
+/// Execute the verify-reproducible command.
+pub fn cmd_verify_reproducible(base_dir: &Path, config: &Config) -> Result<()> {
+    let epoch = match reproducible::source_date_epoch()? {
+        Some(epoch) => epoch,
+        None => reproducible::git_commit_epoch(base_dir)?,
+    };
+    // Inherited by every tool both builds run
+    env::set_var(SOURCE_DATE_EPOCH_ENV, epoch.to_string());
+    // A local or shared store could hand a build stored bytes (possibly from
+    // a non-reproducible build) instead of building them
+    env::remove_var(REMOTE_STORE_ENV);
+    env::set_var(NO_ARTIFACT_STORE_ENV, "1");
+
+    let out = distro_builder::artifact_store::central_output_dir_for_distro(base_dir);
+    let compare_dir = out.join(REPRODUCIBLE_DIR);
+    let _ = fs::remove_dir_all(&compare_dir);
+
+    let mut build_dirs = Vec::new();
+    let mut build_outputs = vec![out.clone()];
+    for run in 1..=2 {
+        println!(
+            "=== Reproducibility build {}/2 (SOURCE_DATE_EPOCH={}) ===\n",
+            run, epoch
+        );
+        let checkout = stage_checkout(base_dir, &compare_dir.join(format!("checkout-{}", run)))?;
+        let run_out = distro_builder::artifact_store::central_output_dir_for_distro(&checkout);
+        if build_outputs.contains(&run_out) {
+            bail!(
+                "Build {} would share the output directory {} with another build",
+                run,
+                run_out.display()
+            );
+        }
+        seed_kernel(&out, &run_out)?;
+        cmd_build(&checkout, BuildTarget::Full, config)?;
+
+        let dir = compare_dir.join(format!("build-{}", run));
+        fs::create_dir_all(&dir)?;
+        for (name, _) in ARTIFACTS {
+            fs::copy(run_out.join(name), dir.join(name))
+                .with_context(|| format!("Failed to copy {} from build {}", name, run))?;
+        }
+        build_dirs.push(dir);
+        build_outputs.push(run_out);
+    }
+
+    println!("\n=== Comparing Builds ===");
+    let (first, second) = (&build_dirs[0], &build_dirs[1]);
+    let mut differing = Vec::new();
+    for (name, unpack) in ARTIFACTS {
+        let (a, b) = (first.join(name), second.join(name));
+        let (hash_a, hash_b) = (sha256_file(&a)?, sha256_file(&b)?);
+        if hash_a == hash_b {
+            println!("  [OK]   {}  {}", hash_a, name);
+            continue;
+        }
+
+        println!("  [DIFF] {}", name);
+        let unpacked_a = compare_dir.join(format!("{}.build-1", name));
+        let unpacked_b = compare_dir.join(format!("{}.build-2", name));
+        unpack_artifact(*unpack, &a, &unpacked_a)?;
+        unpack_artifact(*unpack, &b, &unpacked_b)?;
+        match first_difference(&unpacked_a, &unpacked_b)? {
+            Some(diff) => println!("         first difference: {}", diff),
+            None => println!("         contents identical, image metadata differs"),
+        }
+        differing.push(*name);
+    }
+
+    if !differing.is_empty() {
+        bail!(
+            "Build is NOT reproducible: {} differ.\n\
+             Both builds are kept in {}",
+            differing.join(", "),
+            compare_dir.display()
+        );
+    }
+
+    for run_out in &build_outputs[1..] {
+        let _ = fs::remove_dir_all(run_out);
+    }
+    let _ = fs::remove_dir_all(&compare_dir);
+    println!("\nBuild is reproducible (SOURCE_DATE_EPOCH={})", epoch);
+    Ok(())
+}
+
+/// Copy the leviso checkout at `base_dir` into `root/<monorepo>/<leviso>`
+/// and return the copy's leviso directory.
+///
+/// Only files git would commit are copied (tracked, or untracked and not
+/// ignored), so uncommitted changes are built too. The other monorepo
+/// entries and `downloads/` are symlinked; the directory holding the output
+/// is left out, so the copy gets an output directory of its own.
+fn stage_checkout(base_dir: &Path, root: &Path) -> Result<PathBuf> {
+    let base_dir = fs::canonicalize(base_dir)?;
+    let monorepo = base_dir
+        .parent()
+        .context("leviso has no parent directory")?;
+    let out = distro_builder::artifact_store::central_output_dir_for_distro(&base_dir);
+    let copy_root = root.join(monorepo.file_name().unwrap_or("monorepo".as_ref()));
+    let checkout = copy_root.join(base_dir.file_name().context("leviso has no name")?);
+    fs::create_dir_all(&checkout)?;
+
+    for entry in fs::read_dir(monorepo)? {
+        let path = entry?.path();
+        if path == base_dir || out.starts_with(&path) {
+            continue;
+        }
+        std::os::unix::fs::symlink(&path, copy_root.join(path.file_name().unwrap()))?;
+    }
+
+    let files = Cmd::new("git")
+        .arg("-C")
+        .arg_path(&base_dir)
+        .args([
+            "ls-files",
+            "-z",
+            "--cached",
+            "--others",
+            "--exclude-standard",
+        ])
+        .error_msg("Failed to list the checkout's files (is this a git checkout?)")
+        .run()?;
+    for rel in files.stdout.split('\0').filter(|f| !f.is_empty()) {
+        let (src, dst) = (base_dir.join(rel), checkout.join(rel));
+        // Deleted but not yet staged
+        let Ok(meta) = fs::symlink_metadata(&src) else {
+            continue;
+        };
+        fs::create_dir_all(dst.parent().unwrap())?;
+        if meta.file_type().is_symlink() {
+            std::os::unix::fs::symlink(fs::read_link(&src)?, &dst)?;
+        } else {
+            fs::copy(&src, &dst).with_context(|| format!("Failed to copy {}", src.display()))?;
+        }
+    }
+
+    let downloads = base_dir.join("downloads");
+    if downloads.exists() {
+        std::os::unix::fs::symlink(&downloads, checkout.join("downloads"))?;
+    }
+    Ok(checkout)
+}
+
+/// Copy the xtask-built kernel from `out` into the fresh output `run_out`.
+fn seed_kernel(out: &Path, run_out: &Path) -> Result<()> {
+    let _ = fs::remove_dir_all(run_out);
+    for rel in KERNEL_OUTPUTS {
+        let src = out.join(rel);
+        if !src.exists() {
+            continue;
+        }
+        let dst = run_out.join(rel);
+        fs::create_dir_all(dst.parent().unwrap())?;
+        Cmd::new("cp")
+            .args(["-a", "--reflink=auto"])
+            .arg_path(&src)
+            .arg_path(&dst)
+            .error_msg(&format!("Failed to copy {}", src.display()))
+            .run()?;
+    }
+    Ok(())
+}
+
+fn sha256_file(path: &Path) -> Result<String> {
+    use sha2::{Digest, Sha256};
+    let mut file =
+        fs::File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut file, &mut hasher)?;
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect())
+}
+
+/// Unpack `artifact` into `dest` with the matching host tool.
+fn unpack_artifact(unpack: Unpack, artifact: &Path, dest: &Path) -> Result<()> {
+    let _ = fs::remove_dir_all(dest);
+    fs::create_dir_all(dest)?;
+    match unpack {
+        Unpack::Iso => {
+            Cmd::new("xorriso")
+                .args(["-osirrox", "on", "-indev"])
+                .arg_path(artifact)
+                .args(["-extract", "/"])
+                .arg_path(dest)
+                .error_msg(&format!("Failed to extract {}", artifact.display()))
+                .run()?;
+        }
+        Unpack::Erofs => {
+            if !process::exists("fsck.erofs") {
+                bail!("fsck.erofs not found. Install erofs-utils package.");
+            }
+            Cmd::new("fsck.erofs")
+                .arg(format!("--extract={}", dest.display()))
+                .arg_path(artifact)
+                .error_msg(&format!("Failed to extract {}", artifact.display()))
+                .run()?;
+        }
+        Unpack::Cpio => {
+            // Device nodes cannot be created without root; the remaining
+            // files are still enough to locate a difference
+            compression::extract_initramfs(artifact, dest)?;
+        }
+    }
+    Ok(())
+}
+
+/// Walk two trees in the same order and describe the first differing entry.
+fn first_difference(a: &Path, b: &Path) -> Result<Option<String>> {
+    let entries_a = sorted_entries(a)?;
+    let entries_b = sorted_entries(b)?;
+    let (mut ia, mut ib) = (entries_a.iter().peekable(), entries_b.iter().peekable());
+
+    loop {
+        match (ia.peek(), ib.peek()) {
+            (None, None) => return Ok(None),
+            (Some(pa), None) => return Ok(Some(format!("{} only in build 1", pa.display()))),
+            (None, Some(pb)) => return Ok(Some(format!("{} only in build 2", pb.display()))),
+            (Some(pa), Some(pb)) => match pa.cmp(pb) {
+                Ordering::Less => return Ok(Some(format!("{} only in build 1", pa.display()))),
+                Ordering::Greater => return Ok(Some(format!("{} only in build 2", pb.display()))),
+                Ordering::Equal => {
+                    if let Some(what) = entry_difference(&a.join(pa), &b.join(pb))? {
+                        return Ok(Some(format!("{}: {}", pa.display(), what)));
+                    }
+                    ia.next();
+                    ib.next();
+                }
+            },
+        }
+    }
+}
+
+/// Relative paths under `root`, depth-first with siblings sorted by name
+/// (the same order as `Path`'s component-wise `Ord`).
+fn sorted_entries(root: &Path) -> Result<Vec<PathBuf>> {
+    let mut entries = Vec::new();
+    for entry in walkdir::WalkDir::new(root).min_depth(1).sort_by_file_name() {
+        let entry = entry?;
+        entries.push(entry.path().strip_prefix(root)?.to_path_buf());
+    }
+    Ok(entries)
+}
+
+fn entry_difference(a: &Path, b: &Path) -> Result<Option<String>> {
+    let (meta_a, meta_b) = (fs::symlink_metadata(a)?, fs::symlink_metadata(b)?);
+    let (type_a, type_b) = (meta_a.file_type(), meta_b.file_type());
+
+    if type_a.is_symlink() || type_b.is_symlink() {
+        if !(type_a.is_symlink() && type_b.is_symlink()) {
+            return Ok(Some("file type differs".to_string()));
+        }
+        let (target_a, target_b) = (fs::read_link(a)?, fs::read_link(b)?);
+        if target_a != target_b {
+            return Ok(Some(format!(
+                "symlink target {} vs {}",
+                target_a.display(),
+                target_b.display()
+            )));
+        }
+        return Ok(None);
+    }
+    if type_a.is_dir() != type_b.is_dir() {
+        return Ok(Some("file type differs".to_string()));
+    }
+
+    let (mode_a, mode_b) = (
+        meta_a.permissions().mode() & 0o7777,
+        meta_b.permissions().mode() & 0o7777,
+    );
+    if mode_a != mode_b {
+        return Ok(Some(format!("mode {:o} vs {:o}", mode_a, mode_b)));
+    }
+    if meta_a.mtime() != meta_b.mtime() {
+        return Ok(Some(format!(
+            "mtime {} vs {}",
+            meta_a.mtime(),
+            meta_b.mtime()
+        )));
+    }
+    if type_a.is_file() {
+        if meta_a.len() != meta_b.len() {
+            return Ok(Some(format!("size {} vs {}", meta_a.len(), meta_b.len())));
+        }
+        if fs::read(a)? != fs::read(b)? {
+            return Ok(Some("contents differ".to_string()));
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn tree(files: &[(&str, &str)]) -> TempDir {
+        let temp = TempDir::new().unwrap();
+        for (path, contents) in files {
+            let path = temp.path().join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, contents).unwrap();
+        }
+        temp
+    }
+
+    fn same_mtimes(a: &Path, b: &Path) {
+        reproducible::clamp_mtimes(a, 1_700_000_000).unwrap();
+        reproducible::clamp_mtimes(b, 1_700_000_000).unwrap();
+    }
+
+    #[test]
+    fn test_first_difference_identical_trees() {
+        let a = tree(&[("usr/bin/ls", "ls"), ("etc/os-release", "ID=levitateos")]);
+        let b = tree(&[("usr/bin/ls", "ls"), ("etc/os-release", "ID=levitateos")]);
+        same_mtimes(a.path(), b.path());
+        assert_eq!(first_difference(a.path(), b.path()).unwrap(), None);
+    }
+
+    #[test]
+    fn test_first_difference_reports_first_entry() {
+        let a = tree(&[("etc/machine-id", "aaaa"), ("usr/bin/ls", "ls")]);
+        let b = tree(&[("etc/machine-id", "bbbb"), ("usr/bin/ls", "ls2")]);
+        same_mtimes(a.path(), b.path());
+        assert_eq!(
+            first_difference(a.path(), b.path()).unwrap().as_deref(),
+            Some("etc/machine-id: contents differ")
+        );
+
+        let c = tree(&[
+            ("etc/machine-id", "aaaa"),
+            ("etc/ssh/ssh_host_rsa_key", "k"),
+        ]);
+        same_mtimes(a.path(), c.path());
+        assert_eq!(
+            first_difference(a.path(), c.path()).unwrap().as_deref(),
+            Some("etc/ssh only in build 2")
+        );
+    }
+
+    #[test]
+    fn test_stage_checkout() {
+        let monorepo = tree(&[
+            ("leviso/src/main.rs", "fn main() {}"),
+            ("leviso/new.rs", "// untracked"),
+            ("leviso/downloads/rootfs/etc/os-release", "ID=rocky"),
+            ("leviso/.gitignore", "downloads/\n"),
+            ("distro-spec/Cargo.toml", "[package]"),
+        ]);
+        let base_dir = monorepo.path().join("leviso");
+        for args in [&["init", "-q"][..], &["add", "src", ".gitignore"][..]] {
+            Cmd::new("git")
+                .arg("-C")
+                .arg_path(&base_dir)
+                .args(args)
+                .run()
+                .unwrap();
+        }
+
+        let root = TempDir::new().unwrap();
+        let checkout = stage_checkout(&base_dir, root.path()).unwrap();
+        assert_ne!(checkout, fs::canonicalize(&base_dir).unwrap());
+        assert_eq!(checkout.file_name().unwrap(), "leviso");
+        assert!(checkout.join("src/main.rs").is_file());
+        assert!(checkout.join("new.rs").is_file());
+        assert!(!checkout.join(".git").exists());
+        assert!(fs::symlink_metadata(checkout.join("downloads"))
+            .unwrap()
+            .file_type()
+            .is_symlink());
+        assert!(checkout.join("../distro-spec/Cargo.toml").is_file());
+    }
+}
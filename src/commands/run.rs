@@ -44,9 +44,11 @@ pub fn cmd_run(base_dir: &Path, no_disk: bool, disk_size: String) -> Result<()>
 }
 
 /// Execute the test command - headless boot verification.
-pub fn cmd_test(base_dir: &Path, timeout: u64) -> Result<()> {
+///
+/// With `secureboot`, boots with Secure Boot enforced (see `qemu::test_iso`).
+pub fn cmd_test(base_dir: &Path, timeout: u64, secureboot: bool) -> Result<()> {
     recipe::ensure_qemu(base_dir)?;
     ensure_iso_built(base_dir)?;
-    qemu::test_iso(base_dir, timeout)?;
+    qemu::test_iso(base_dir, timeout, secureboot)?;
     Ok(())
 }
@@ -0,0 +1,16 @@
+//! Secure Boot key generation command.
+
+use anyhow::Result;
+use std::path::Path;
+
+use crate::artifact::secureboot::{self, KEY_DIR_ENV};
+
+/// Execute the secureboot-keys command - generate a PK/KEK/db test key set.
+pub fn cmd_secureboot_keys(dir: &Path) -> Result<()> {
+    println!("=== Generating Secure Boot Test Keys ===\n");
+    secureboot::generate_keys(dir)?;
+    println!("\nSign builds with these keys:");
+    println!("  export {}={}", KEY_DIR_ENV, dir.display());
+    println!("  leviso build && leviso test --secureboot");
+    Ok(())
+}
@@ -18,8 +18,8 @@ pub enum ShowTarget {
     Config,
     /// Show rootfs (EROFS) contents
     Rootfs,
-    /// Show build status
-    Status,
+    /// Show build status (optionally explaining why artifacts are stale)
+    Status { explain: bool },
 }
 
 /// Execute the show command.
@@ -45,15 +45,18 @@ pub fn cmd_show(base_dir: &Path, target: ShowTarget, config: &Config) -> Result<
                 .error_msg("fsck.erofs failed. Install: sudo dnf install erofs-utils")
                 .run_interactive()?;
         }
-        ShowTarget::Status => {
-            show_build_status(base_dir)?;
+        ShowTarget::Status { explain } => {
+            show_build_status(base_dir, explain)?;
         }
     }
     Ok(())
 }
 
 /// Show what will be rebuilt on next `leviso build`.
-fn show_build_status(base_dir: &Path) -> Result<()> {
+///
+/// With `explain`, each stale artifact also lists the inputs that were added,
+/// removed or changed since its last successful build.
+fn show_build_status(base_dir: &Path, explain: bool) -> Result<()> {
     println!("=== Build Status ===\n");
 
     // Check each artifact
@@ -73,6 +76,9 @@ fn show_build_status(base_dir: &Path) -> Result<()> {
         println!("MISSING → will build");
     } else if kernel_compile {
         println!("STALE → will rebuild");
+        if explain {
+            print_input_changes(&rebuild::kernel_artifact(base_dir));
+        }
     } else {
         println!("OK (up to date)");
     }
@@ -87,12 +93,15 @@ fn show_build_status(base_dir: &Path) -> Result<()> {
     }
 
     // Rootfs (EROFS)
-    let rootfs_rebuild = rebuild::rootfs_needs_rebuild(base_dir);
+    let rootfs_rebuild = rebuild::rootfs_needs_rebuild(base_dir)?;
     print!("Rootfs (EROFS):    ");
     if !rootfs.exists() {
         println!("MISSING → will build");
     } else if rootfs_rebuild {
         println!("STALE → will rebuild");
+        if explain {
+            print_input_changes(&rebuild::rootfs_artifact(base_dir)?);
+        }
     } else {
         println!("OK (up to date)");
     }
@@ -104,6 +113,9 @@ fn show_build_status(base_dir: &Path) -> Result<()> {
         println!("MISSING → will build");
     } else if initramfs_rebuild {
         println!("STALE → will rebuild");
+        if explain {
+            print_input_changes(&rebuild::initramfs_artifact(base_dir));
+        }
     } else {
         println!("OK (up to date)");
     }
@@ -115,6 +127,9 @@ fn show_build_status(base_dir: &Path) -> Result<()> {
         println!("MISSING → will build");
     } else if install_initramfs_rebuild {
         println!("STALE → will rebuild");
+        if explain {
+            print_input_changes(&rebuild::install_initramfs_artifact(base_dir));
+        }
     } else {
         println!("OK (up to date)");
     }
@@ -126,6 +141,11 @@ fn show_build_status(base_dir: &Path) -> Result<()> {
         println!("MISSING → will build");
     } else if iso_rebuild {
         println!("STALE → will rebuild");
+        if explain {
+            for input in rebuild::iso_stale_inputs(base_dir) {
+                println!("    ~ {} (newer than ISO or missing)", input.display());
+            }
+        }
     } else {
         println!("OK (up to date)");
     }
@@ -147,6 +167,28 @@ fn show_build_status(base_dir: &Path) -> Result<()> {
     Ok(())
 }
 
+/// Print which inputs of an artifact changed since its last successful build.
+fn print_input_changes(artifact: &rebuild::Artifact) {
+    let Some(changes) = artifact.explain() else {
+        println!("    (no input manifest from a previous build - cannot explain)");
+        return;
+    };
+    if changes.is_empty() {
+        // Same inputs, but the hash file is missing or the output is older
+        println!("    (inputs unchanged - hash cache missing or output replaced)");
+        return;
+    }
+    for input in &changes.added {
+        println!("    + {}", input);
+    }
+    for input in &changes.removed {
+        println!("    - {}", input);
+    }
+    for input in &changes.changed {
+        println!("    ~ {}", input);
+    }
+}
+
 /// Print dependency status (replaces resolver.print_status()).
 fn print_dependency_status(base_dir: &Path) {
     let monorepo = base_dir.parent().unwrap_or(base_dir);
@@ -0,0 +1,63 @@
+//! Sources command - corresponding-source manifest and SRPM collection.
+
+use anyhow::{bail, Result};
+use std::path::{Path, PathBuf};
+
+use crate::build::sources::{self, SourceManifest, SOURCES_JSON, SOURCES_TXT};
+
+/// Execute the sources command.
+///
+/// Without a mirror, prints the manifest written by the last rootfs build.
+/// With a mirror, also copies every listed SRPM into the output directory.
+pub fn cmd_sources(
+    base_dir: &Path,
+    mirror: Option<PathBuf>,
+    output: Option<PathBuf>,
+) -> Result<()> {
+    let output_dir = distro_builder::artifact_store::central_output_dir_for_distro(base_dir);
+    let manifest_path = output_dir.join(SOURCES_JSON);
+    if !manifest_path.exists() {
+        bail!(
+            "Source manifest not found at {}.\n\
+             Run 'leviso build rootfs' first.",
+            manifest_path.display()
+        );
+    }
+    let manifest = SourceManifest::load(&manifest_path)?;
+
+    println!("=== Corresponding Source ===\n");
+    println!("  Manifest: {}", output_dir.join(SOURCES_TXT).display());
+    println!("  Source packages: {}", manifest.packages.len());
+    if !manifest.unresolved.is_empty() {
+        println!(
+            "  [WARN] Packages without SOURCERPM: {}",
+            manifest.unresolved.join(", ")
+        );
+    }
+
+    let Some(mirror) = mirror else {
+        return Ok(());
+    };
+
+    let dest = output.unwrap_or_else(|| output_dir.join("srpms"));
+    println!("\nCollecting SRPMs from {}...", mirror.display());
+    let missing = sources::collect_srpms(&manifest, &mirror, &dest)?;
+
+    println!(
+        "  Collected {}/{} SRPMs into {}",
+        manifest.packages.len() - missing.len(),
+        manifest.packages.len(),
+        dest.display()
+    );
+    if !missing.is_empty() {
+        for srpm in &missing {
+            println!("    ✗ {}", srpm);
+        }
+        bail!(
+            "{} SRPMs not found in mirror {}",
+            missing.len(),
+            mirror.display()
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,525 @@
+//! Store command - inspect and trim the artifact store.
+//!
+//! The centralized artifact store (`distro_builder::artifact_store`) keeps
+//! every rootfs_erofs (with its rootfs_sources manifest), initramfs and
+//! install_initramfs ever built, keyed by input hash. This command lists,
+//! verifies and evicts entries:
+//!
+//! - `list` - entries with their input keys, sizes and ages
+//! - `verify [--remove]` - re-hash every stored blob against its recorded
+//!   checksum, optionally removing the corrupt ones
+//! - `prune --keep N` - keep the newest N entries per kind
+//! - `gc --max-age-days D --max-size SIZE` - evict by age, then oldest-first
+//!   until the store fits in SIZE
+//!
+//! Entries whose key matches the current `.{artifact}-inputs.hash` are never
+//! evicted, so a gc can't force the next build to start from scratch. A
+//! rootfs_sources manifest is evicted together with the rootfs_erofs stored
+//! under the same key, never on its own.
+
+use anyhow::{bail, Context, Result};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use distro_builder::artifact_store::ArtifactStore;
+
+use crate::build::sources::ROOTFS_SOURCES_KIND;
+
+/// Artifact kinds leviso stores, with the hash file holding their current key.
+const STORE_KINDS: [(&str, &str); 4] = [
+    ("rootfs_erofs", ".rootfs-inputs.hash"),
+    (ROOTFS_SOURCES_KIND, ".rootfs-inputs.hash"),
+    ("initramfs", ".initramfs-inputs.hash"),
+    ("install_initramfs", ".install-initramfs-inputs.hash"),
+];
+
+/// Kind evicted along with the entry of `PAIRED_WITH` kind under the same key.
+const PAIRED_KIND: &str = ROOTFS_SOURCES_KIND;
+const PAIRED_WITH: &str = "rootfs_erofs";
+
+/// Store action for the store command.
+pub enum StoreAction {
+    /// List stored entries
+    List,
+    /// Verify the checksum of every stored entry, removing corrupt ones if `remove`
+    Verify { remove: bool },
+    /// Keep only the newest N entries per kind
+    Prune { keep: usize },
+    /// Evict entries older than `max_age_days`, then oldest-first down to `max_size`
+    Gc {
+        max_age_days: Option<u64>,
+        max_size: Option<String>,
+    },
+}
+
+/// A stored artifact, as leviso sees it.
+#[derive(Debug, Clone)]
+struct Entry {
+    kind: &'static str,
+    key: String,
+    path: PathBuf,
+    size: u64,
+    sha256: String,
+    /// Seconds since the epoch.
+    stored_at: u64,
+    /// Key matches the current input hash of the output directory.
+    current: bool,
+}
+
+/// Execute the store command.
+pub fn cmd_store(base_dir: &Path, action: StoreAction) -> Result<()> {
+    let store =
+        ArtifactStore::open_for_distro(base_dir).context("Failed to open artifact store")?;
+    let entries = load_entries(base_dir, &store)?;
+
+    match action {
+        StoreAction::List => print_entries(&entries),
+        StoreAction::Verify { remove } => {
+            let corrupt = verify_entries(&entries);
+            if !corrupt.is_empty() {
+                if !remove {
+                    bail!(
+                        "{} of {} store entries are corrupt. Remove them with 'leviso store verify --remove'.",
+                        corrupt.len(),
+                        entries.len()
+                    );
+                }
+                evict_entries(&store, &entries, &corrupt)?;
+            }
+        }
+        StoreAction::Prune { keep } => {
+            let evict = select_prune(&entries, keep);
+            evict_entries(&store, &entries, &evict)?;
+        }
+        StoreAction::Gc {
+            max_age_days,
+            max_size,
+        } => {
+            if max_age_days.is_none() && max_size.is_none() {
+                bail!("Nothing to do: pass --max-age-days and/or --max-size");
+            }
+            let max_size = max_size.as_deref().map(parse_size).transpose()?;
+            let evict = select_gc(&entries, now_secs(), max_age_days, max_size);
+            evict_entries(&store, &entries, &evict)?;
+        }
+    }
+    Ok(())
+}
+
+/// Path of the stored `kind` entry whose key starts with `key_prefix` (as
+/// shown by `store list`).
+pub(super) fn find_entry(base_dir: &Path, kind: &str, key_prefix: &str) -> Result<PathBuf> {
+    if !STORE_KINDS.iter().any(|(k, _)| *k == kind) {
+        bail!(
+            "Unknown artifact kind '{}' (expected one of: {})",
+            kind,
+            STORE_KINDS.map(|(k, _)| k).join(", ")
+        );
+    }
+    let store =
+        ArtifactStore::open_for_distro(base_dir).context("Failed to open artifact store")?;
+    let matches: Vec<Entry> = load_entries(base_dir, &store)?
+        .into_iter()
+        .filter(|e| e.kind == kind && e.key.starts_with(key_prefix))
+        .collect();
+    match matches.as_slice() {
+        [entry] => Ok(entry.path.clone()),
+        [] => bail!("No stored {} entry with key {}...", kind, key_prefix),
+        _ => bail!(
+            "Key prefix {} matches {} stored {} entries; use a longer prefix",
+            key_prefix,
+            matches.len(),
+            kind
+        ),
+    }
+}
+
+/// Collect all entries of the kinds leviso stores, newest first per kind.
+fn load_entries(base_dir: &Path, store: &ArtifactStore) -> Result<Vec<Entry>> {
+    let output_dir = distro_builder::artifact_store::central_output_dir_for_distro(base_dir);
+    let mut entries = Vec::new();
+
+    for (kind, hash_file) in STORE_KINDS {
+        let current_key = fs::read_to_string(output_dir.join(hash_file))
+            .map(|k| k.trim().to_string())
+            .unwrap_or_default();
+
+        for stored in store
+            .list(kind)
+            .with_context(|| format!("Failed to list '{}' entries", kind))?
+        {
+            entries.push(Entry {
+                kind,
+                current: stored.key == current_key,
+                key: stored.key,
+                path: stored.path,
+                size: stored.size,
+                sha256: stored.sha256,
+                stored_at: stored
+                    .created
+                    .duration_since(UNIX_EPOCH)
+                    .map(|d| d.as_secs())
+                    .unwrap_or(0),
+            });
+        }
+    }
+
+    entries.sort_by(|a, b| a.kind.cmp(b.kind).then(b.stored_at.cmp(&a.stored_at)));
+    Ok(entries)
+}
+
+fn print_entries(entries: &[Entry]) {
+    println!("=== Artifact Store ===\n");
+    if entries.is_empty() {
+        println!("  (empty)");
+        return;
+    }
+
+    let now = now_secs();
+    println!("  {:<18} {:<14} {:>10} {:>8}", "KIND", "KEY", "SIZE", "AGE");
+    for e in entries {
+        println!(
+            "  {:<18} {:<14} {:>10} {:>8}{}",
+            e.kind,
+            short_key(&e.key),
+            format_size(e.size),
+            format_age(now.saturating_sub(e.stored_at)),
+            if e.current { "  (current)" } else { "" }
+        );
+    }
+    let total: u64 = entries.iter().map(|e| e.size).sum();
+    println!(
+        "\n  {} entries, {} total",
+        entries.len(),
+        format_size(total)
+    );
+}
+
+/// Re-hash every entry; returns the indices of corrupt or unreadable ones.
+fn verify_entries(entries: &[Entry]) -> Vec<usize> {
+    println!("=== Verifying Artifact Store ===\n");
+    let mut corrupt = Vec::new();
+    for (i, e) in entries.iter().enumerate() {
+        let ok = match sha256_file(&e.path) {
+            Ok(actual) => actual == e.sha256,
+            Err(_) => false,
+        };
+        if ok {
+            println!("  ✓ {} {}", e.kind, short_key(&e.key));
+        } else {
+            println!(
+                "  ✗ {} {} - checksum mismatch or unreadable ({})",
+                e.kind,
+                short_key(&e.key),
+                e.path.display()
+            );
+            corrupt.push(i);
+        }
+    }
+
+    if corrupt.is_empty() {
+        println!("\n  All {} entries OK", entries.len());
+    }
+    corrupt
+}
+
+fn evict_entries(store: &ArtifactStore, entries: &[Entry], evict: &[usize]) -> Result<()> {
+    if evict.is_empty() {
+        println!("Nothing to evict.");
+        return Ok(());
+    }
+
+    let evict = with_pairs(entries, evict);
+    let mut freed = 0;
+    for &i in &evict {
+        let e = &entries[i];
+        println!(
+            "  Evicting {} {} ({})",
+            e.kind,
+            short_key(&e.key),
+            format_size(e.size)
+        );
+        store
+            .remove(e.kind, &e.key)
+            .with_context(|| format!("Failed to remove {} {}", e.kind, e.key))?;
+        freed += e.size;
+    }
+    println!(
+        "Evicted {} entries, freed {}.",
+        evict.len(),
+        format_size(freed)
+    );
+    Ok(())
+}
+
+/// Index of the entry evicted together with `entries[i]`, if it is stored.
+fn pair_of(entries: &[Entry], i: usize) -> Option<usize> {
+    let other = match entries[i].kind {
+        PAIRED_KIND => PAIRED_WITH,
+        PAIRED_WITH => PAIRED_KIND,
+        _ => return None,
+    };
+    entries
+        .iter()
+        .position(|e| e.kind == other && e.key == entries[i].key)
+}
+
+/// `evict` plus the pairs of its entries, sorted and deduplicated.
+fn with_pairs(entries: &[Entry], evict: &[usize]) -> Vec<usize> {
+    let mut all: Vec<usize> = evict
+        .iter()
+        .flat_map(|&i| std::iter::once(i).chain(pair_of(entries, i)))
+        .collect();
+    all.sort_unstable();
+    all.dedup();
+    all
+}
+
+/// Entry only ever evicted through its pair, so prune and gc don't pick it.
+fn follows_pair(entries: &[Entry], i: usize) -> bool {
+    entries[i].kind == PAIRED_KIND && pair_of(entries, i).is_some()
+}
+
+/// Entries to evict so that at most `keep` remain per kind (newest kept).
+///
+/// Expects entries sorted newest first per kind; current entries are kept
+/// in addition to the newest `keep`. Paired entries are included.
+fn select_prune(entries: &[Entry], keep: usize) -> Vec<usize> {
+    let mut evict = Vec::new();
+    let mut seen: HashMap<&str, usize> = HashMap::new();
+    for (i, e) in entries.iter().enumerate() {
+        if follows_pair(entries, i) {
+            continue;
+        }
+        let count = seen.entry(e.kind).or_default();
+        *count += 1;
+        if *count > keep && !e.current {
+            evict.push(i);
+        }
+    }
+    with_pairs(entries, &evict)
+}
+
+/// Entries to evict: everything older than `max_age_days`, then the oldest
+/// remaining entries until the total size is at most `max_size`. Paired
+/// entries are included and count towards the freed size.
+fn select_gc(
+    entries: &[Entry],
+    now: u64,
+    max_age_days: Option<u64>,
+    max_size: Option<u64>,
+) -> Vec<usize> {
+    let mut evict: Vec<usize> = Vec::new();
+
+    if let Some(days) = max_age_days {
+        let max_age = days * 24 * 60 * 60;
+        evict.extend(
+            entries
+                .iter()
+                .enumerate()
+                .filter(|(i, e)| {
+                    !e.current
+                        && !follows_pair(entries, *i)
+                        && now.saturating_sub(e.stored_at) > max_age
+                })
+                .map(|(i, _)| i),
+        );
+        evict = with_pairs(entries, &evict);
+    }
+
+    if let Some(max_size) = max_size {
+        let mut total: u64 = entries
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| !evict.contains(i))
+            .map(|(_, e)| e.size)
+            .sum();
+
+        let mut oldest_first: Vec<usize> = (0..entries.len())
+            .filter(|i| !evict.contains(i) && !entries[*i].current && !follows_pair(entries, *i))
+            .collect();
+        oldest_first.sort_by_key(|i| entries[*i].stored_at);
+
+        for i in oldest_first {
+            if total <= max_size {
+                break;
+            }
+            for j in std::iter::once(i).chain(pair_of(entries, i)) {
+                total -= entries[j].size;
+                evict.push(j);
+            }
+        }
+    }
+
+    with_pairs(entries, &evict)
+}
+
+/// Parse a size like `500M`, `20G` or `1073741824`.
+fn parse_size(s: &str) -> Result<u64> {
+    let s = s.trim();
+    let (number, unit) = match s.find(|c: char| !c.is_ascii_digit()) {
+        Some(pos) => s.split_at(pos),
+        None => (s, ""),
+    };
+    let multiplier: u64 = match unit.to_ascii_uppercase().trim_end_matches('B') {
+        "" => 1,
+        "K" => 1 << 10,
+        "M" => 1 << 20,
+        "G" => 1 << 30,
+        "T" => 1 << 40,
+        _ => bail!("Invalid size '{}' (use e.g. 500M, 20G)", s),
+    };
+    let number: u64 = number
+        .parse()
+        .with_context(|| format!("Invalid size '{}' (use e.g. 500M, 20G)", s))?;
+    Ok(number * multiplier)
+}
+
+fn format_size(bytes: u64) -> String {
+    let mb = bytes as f64 / (1 << 20) as f64;
+    if mb >= 1024.0 {
+        format!("{:.1} GB", mb / 1024.0)
+    } else {
+        format!("{:.1} MB", mb)
+    }
+}
+
+fn format_age(secs: u64) -> String {
+    match secs {
+        s if s < 3600 => format!("{}m", s / 60),
+        s if s < 86400 => format!("{}h", s / 3600),
+        s => format!("{}d", s / 86400),
+    }
+}
+
+fn short_key(key: &str) -> &str {
+    &key[..key.len().min(12)]
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+fn sha256_file(path: &Path) -> Result<String> {
+    let mut file = fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0u8; 1 << 20];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: u64 = 86400;
+
+    fn entry(kind: &'static str, key: &str, size_mb: u64, age_days: u64) -> Entry {
+        Entry {
+            kind,
+            key: key.to_string(),
+            path: PathBuf::new(),
+            size: size_mb << 20,
+            sha256: String::new(),
+            stored_at: 100 * DAY - age_days * DAY,
+            current: false,
+        }
+    }
+
+    #[test]
+    fn test_prune_keeps_newest_per_kind() {
+        let mut entries = vec![
+            entry("initramfs", "i1", 5, 1),
+            entry("initramfs", "i2", 5, 2),
+            entry("rootfs_erofs", "r1", 400, 1),
+            entry("rootfs_erofs", "r2", 400, 3),
+            entry("rootfs_erofs", "r3", 400, 9),
+        ];
+        entries[4].current = true;
+
+        // r3 is current and survives even though it's the oldest
+        assert_eq!(select_prune(&entries, 1), vec![1, 3]);
+        assert!(select_prune(&entries, 5).is_empty());
+    }
+
+    #[test]
+    fn test_gc_by_age_then_size() {
+        let mut entries = vec![
+            entry("rootfs_erofs", "r1", 400, 1),
+            entry("rootfs_erofs", "r2", 400, 10),
+            entry("rootfs_erofs", "r3", 400, 40),
+            entry("initramfs", "i1", 5, 50),
+        ];
+        entries[3].current = true;
+        let now = 100 * DAY;
+
+        assert_eq!(select_gc(&entries, now, Some(30), None), vec![2]);
+        // After dropping r3 by age, 805 MB remain; r2 goes to fit in 500 MB
+        assert_eq!(
+            select_gc(&entries, now, Some(30), Some(500 << 20)),
+            vec![1, 2]
+        );
+    }
+
+    #[test]
+    fn test_sources_are_evicted_with_their_erofs() {
+        let entries = vec![
+            entry("rootfs_erofs", "r1", 400, 1),
+            entry("rootfs_erofs", "r2", 400, 40),
+            entry(ROOTFS_SOURCES_KIND, "r1", 1, 1),
+            entry(ROOTFS_SOURCES_KIND, "r2", 1, 40),
+            entry(ROOTFS_SOURCES_KIND, "orphan", 1, 2),
+        ];
+        let now = 100 * DAY;
+
+        // Sources never count against `keep` on their own; the orphan does
+        assert_eq!(select_prune(&entries, 1), vec![1, 3]);
+        assert_eq!(select_gc(&entries, now, Some(30), None), vec![1, 3]);
+        // Evicting r2 with its manifest frees 401 MB, which is enough
+        assert_eq!(select_gc(&entries, now, None, Some(402 << 20)), vec![1, 3]);
+        // Corrupt sources take their erofs along
+        assert_eq!(with_pairs(&entries, &[2]), vec![0, 2]);
+    }
+
+    #[test]
+    fn test_verify_finds_corrupt_entries() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let good = temp.path().join("good");
+        fs::write(&good, "rootfs").unwrap();
+
+        let mut entries = vec![
+            entry("rootfs_erofs", "r1", 0, 1),
+            entry("rootfs_erofs", "r2", 0, 2),
+            entry("initramfs", "i1", 0, 1),
+        ];
+        entries[0].path = good.clone();
+        entries[0].sha256 = sha256_file(&good).unwrap();
+        entries[1].path = good;
+        entries[1].sha256 = "0".repeat(64);
+        entries[2].path = temp.path().join("missing");
+
+        assert_eq!(verify_entries(&entries), vec![1, 2]);
+    }
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("1024").unwrap(), 1024);
+        assert_eq!(parse_size("500M").unwrap(), 500 << 20);
+        assert_eq!(parse_size("20GB").unwrap(), 20 << 30);
+        assert!(parse_size("20X").is_err());
+        assert!(parse_size("G").is_err());
+    }
+}
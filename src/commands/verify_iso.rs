@@ -0,0 +1,14 @@
+//! Verify-iso command - check a downloaded ISO offline.
+
+use anyhow::Result;
+use std::path::Path;
+
+use crate::artifact::signing;
+
+/// Execute the verify-iso command - check the checksum and detached signatures.
+pub fn cmd_verify_iso(iso: &Path, key: Option<&Path>, gpg_fingerprint: Option<&str>) -> Result<()> {
+    println!("=== Verifying {} ===\n", iso.display());
+    signing::verify_iso_signatures(iso, key, gpg_fingerprint)?;
+    println!("\nISO checksum and signatures are valid.");
+    Ok(())
+}
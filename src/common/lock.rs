@@ -0,0 +1,289 @@
+//! Advisory lock on the central output directory.
+//!
+//! `build`, `clean` and `build_qcow2` hold the lock for their whole duration,
+//! so a `leviso clean` can no longer delete `rootfs-staging.work` while
+//! mkfs.erofs is reading it.
+//!
+//! The lock is an `flock(2)` on a persistent `<output_dir>.lock` file next to
+//! the output directory (so `clean` can remove the directory itself). The
+//! kernel releases it when the holder exits, however it exits, so there is no
+//! stale-lock takeover to race on. The file records the holder's PID for the
+//! error message and is emptied on release: finding a previous holder's
+//! record when taking the lock means that build crashed, and the `.work`/`.tmp`
+//! leftovers are removed (except a checkpointed `rootfs-staging.work`, see
+//! `build::checkpoint`).
+
+use anyhow::{bail, Context, Result};
+use std::fs::{self, File, OpenOptions};
+use std::io::{ErrorKind, Read, Seek, Write};
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Whether this process already holds the lock (nested commands reuse it).
+static HELD: AtomicBool = AtomicBool::new(false);
+
+/// Held lock on the output directory, released on drop.
+#[derive(Debug)]
+pub struct OutputLock {
+    /// The flocked lock file. `None` for nested acquisitions, which must not
+    /// release the outer lock.
+    file: Option<File>,
+}
+
+/// Contents of a lock file.
+#[derive(Debug, PartialEq, Eq)]
+struct LockInfo {
+    pid: u32,
+    command: String,
+    started: u64,
+}
+
+impl LockInfo {
+    fn parse(content: &str) -> Option<Self> {
+        let mut pid = None;
+        let mut command = String::new();
+        let mut started = 0;
+        for line in content.lines() {
+            match line.split_once('=') {
+                Some(("pid", v)) => pid = v.trim().parse().ok(),
+                Some(("command", v)) => command = v.trim().to_string(),
+                Some(("started", v)) => started = v.trim().parse().unwrap_or(0),
+                _ => {}
+            }
+        }
+        Some(Self {
+            pid: pid?,
+            command,
+            started,
+        })
+    }
+
+    fn render(&self) -> String {
+        format!(
+            "pid={}\ncommand={}\nstarted={}\n",
+            self.pid, self.command, self.started
+        )
+    }
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Take an exclusive `flock` on `file` without blocking. `Ok(false)` if
+/// another process holds it.
+fn try_flock(file: &File) -> std::io::Result<bool> {
+    // SAFETY: flock on a valid open file descriptor
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } == 0 {
+        return Ok(true);
+    }
+    let err = std::io::Error::last_os_error();
+    if err.kind() == ErrorKind::WouldBlock {
+        Ok(false)
+    } else {
+        Err(err)
+    }
+}
+
+/// Lock file path for an output directory (`.../out/leviso` -> `.../out/leviso.lock`).
+pub fn lock_path(output_dir: &Path) -> PathBuf {
+    let mut name = output_dir
+        .file_name()
+        .map(|n| n.to_os_string())
+        .unwrap_or_default();
+    name.push(".lock");
+    output_dir.with_file_name(name)
+}
+
+impl OutputLock {
+    /// Lock the central output directory for `command` (e.g. "leviso build").
+    pub fn acquire(base_dir: &Path, command: &str) -> Result<Self> {
+        let output_dir = distro_builder::artifact_store::central_output_dir_for_distro(base_dir);
+        Self::acquire_at(&output_dir, command)
+    }
+
+    /// Lock a specific output directory.
+    pub fn acquire_at(output_dir: &Path, command: &str) -> Result<Self> {
+        if HELD.load(Ordering::SeqCst) {
+            return Ok(Self { file: None });
+        }
+        let path = lock_path(output_dir);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)
+            .with_context(|| format!("Failed to open lock {}", path.display()))?;
+        let locked =
+            try_flock(&file).with_context(|| format!("Failed to lock {}", path.display()))?;
+
+        let mut content = String::new();
+        let _ = file.read_to_string(&mut content);
+        let previous = LockInfo::parse(&content);
+
+        if !locked {
+            let holder = previous
+                .map(|h| {
+                    format!(
+                        "PID {} ('{}', started {}s ago)",
+                        h.pid,
+                        h.command,
+                        now_secs().saturating_sub(h.started)
+                    )
+                })
+                .unwrap_or_else(|| "another process".to_string());
+            bail!(
+                "Output directory {} is locked by {}.\n\
+                 Wait for it to finish.",
+                output_dir.display(),
+                holder
+            );
+        }
+
+        // We hold the lock; a leftover record means its previous holder crashed
+        if let Some(crashed) = previous {
+            println!(
+                "[WARN] Previous lock holder PID {} ('{}') did not finish - cleaning up interrupted build",
+                crashed.pid, crashed.command
+            );
+            remove_leftovers(output_dir)?;
+        }
+
+        let info = LockInfo {
+            pid: std::process::id(),
+            command: command.to_string(),
+            started: now_secs(),
+        };
+        file.set_len(0)
+            .and_then(|_| file.rewind())
+            .and_then(|_| file.write_all(info.render().as_bytes()))
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        HELD.store(true, Ordering::SeqCst);
+        Ok(Self { file: Some(file) })
+    }
+}
+
+impl Drop for OutputLock {
+    fn drop(&mut self) {
+        if let Some(file) = self.file.take() {
+            // Empty the record so the next holder doesn't see a crash; closing
+            // the file releases the flock
+            let _ = file.set_len(0);
+            HELD.store(false, Ordering::SeqCst);
+        }
+    }
+}
+
+/// Remove half-written `.work`/`.tmp` outputs left behind by a crashed build.
+///
+/// Finished outputs are only ever produced by renaming these, so removing
+/// them never loses a completed artifact.
+fn remove_leftovers(output_dir: &Path) -> Result<()> {
+    let Ok(entries) = fs::read_dir(output_dir) else {
+        return Ok(());
+    };
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().to_string();
+        if !(name.ends_with(".work") || name.ends_with(".tmp")) {
+            continue;
+        }
+        let path = entry.path();
+        // A checkpointed work directory is kept for `build rootfs --resume`
+        if output_dir.join(format!("{}.checkpoint", name)).exists() {
+            println!("  Keeping {} (checkpoint for --resume)", path.display());
+            continue;
+        }
+        println!("  Removing {}", path.display());
+        if path.is_dir() {
+            fs::remove_dir_all(&path)?;
+        } else {
+            fs::remove_file(&path)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serial_test::serial;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_lock_path_is_sibling() {
+        assert_eq!(
+            lock_path(Path::new("/repo/.artifacts/out/leviso")),
+            PathBuf::from("/repo/.artifacts/out/leviso.lock")
+        );
+    }
+
+    #[test]
+    #[serial]
+    fn test_acquire_release_and_nesting() {
+        let temp = TempDir::new().unwrap();
+        let out = temp.path().join("leviso");
+        let lock_file = lock_path(&out);
+
+        let outer = OutputLock::acquire_at(&out, "leviso build").unwrap();
+        let content = fs::read_to_string(&lock_file).unwrap();
+        assert_eq!(LockInfo::parse(&content).unwrap().pid, std::process::id());
+
+        // Nested acquisition (build -> qcow2) must not release the outer lock
+        drop(OutputLock::acquire_at(&out, "build_qcow2").unwrap());
+        let other = File::open(&lock_file).unwrap();
+        assert!(!try_flock(&other).unwrap());
+
+        drop(outer);
+        assert!(try_flock(&other).unwrap());
+        assert_eq!(fs::read_to_string(&lock_file).unwrap(), "");
+    }
+
+    #[test]
+    #[serial]
+    fn test_live_holder_is_reported() {
+        let temp = TempDir::new().unwrap();
+        let out = temp.path().join("leviso");
+        fs::create_dir_all(temp.path()).unwrap();
+        // A separate open file description conflicts even within this process
+        let holder = File::create(lock_path(&out)).unwrap();
+        assert!(try_flock(&holder).unwrap());
+        fs::write(lock_path(&out), "pid=1\ncommand=leviso clean\nstarted=0\n").unwrap();
+
+        let err = OutputLock::acquire_at(&out, "leviso build").unwrap_err();
+        let msg = err.to_string();
+        assert!(msg.contains("locked by PID 1"));
+        assert!(msg.contains("leviso clean"));
+        drop(holder);
+        drop(OutputLock::acquire_at(&out, "leviso build").unwrap());
+    }
+
+    #[test]
+    #[serial]
+    fn test_crashed_holder_is_cleaned_up() {
+        let temp = TempDir::new().unwrap();
+        let out = temp.path().join("leviso");
+        fs::create_dir_all(out.join("rootfs-staging.work")).unwrap();
+        fs::write(out.join("filesystem.erofs"), "done").unwrap();
+        // Record left behind without a flock: the holder died mid-build
+        fs::write(
+            lock_path(&out),
+            format!("pid={}\ncommand=leviso build\n", u32::MAX),
+        )
+        .unwrap();
+
+        let lock = OutputLock::acquire_at(&out, "leviso build").unwrap();
+        assert!(!out.join("rootfs-staging.work").exists());
+        assert!(out.join("filesystem.erofs").exists());
+        drop(lock);
+    }
+}
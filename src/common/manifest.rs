@@ -4,11 +4,16 @@ use anyhow::{Context, Result};
 use std::fs;
 use std::path::PathBuf;
 
+use crate::build::inputs::record_input;
+
 /// Read a file from a manifest-relative directory.
 ///
 /// This reads files bundled with the binary at compile time via `env!("CARGO_MANIFEST_DIR")`.
 /// Used for reading configuration templates, overlay files, etc. that are stored alongside source.
 ///
+/// Every file read is recorded as a build input (see `build::inputs`), so editing
+/// it triggers a rootfs rebuild without listing it in `rebuild.rs`.
+///
 /// # Arguments
 /// * `subdir` - Subdirectory relative to `src/component/custom/` (e.g. "etc/files", "live/overlay", "packages/files")
 /// * `path` - Relative path within that subdirectory
@@ -25,6 +30,7 @@ pub fn read_manifest_file(subdir: &str, path: &str) -> Result<String> {
         .join("src/component/custom")
         .join(subdir)
         .join(path);
+    record_input(&file_path);
     fs::read_to_string(&file_path)
         .with_context(|| format!("Failed to read {} from {}", path, file_path.display()))
 }
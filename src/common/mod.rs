@@ -1,11 +1,14 @@
 //! Shared utilities across leviso modules.
 
 pub mod files;
+pub mod lock;
 pub mod manifest;
 pub mod paths;
+pub mod remote_store;
 pub mod temp;
 
 pub use files::{write_file_mode, write_file_with_dirs};
+pub use lock::OutputLock;
 pub use manifest::read_manifest_file;
 pub use paths::{ensure_dir_exists, ensure_parent_exists, find_and_copy_dir, find_dir};
 pub use temp::{cleanup_work_dir, prepare_work_dir};
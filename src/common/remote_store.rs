@@ -0,0 +1,783 @@
+//! Shared artifact store over HTTP.
+//!
+//! The local artifact store (`distro_builder::artifact_store`) only helps on
+//! the machine that built an artifact. A remote store lets developers restore
+//! the rootfs EROFS and both initramfs images that CI already built:
+//!
+//! ```text
+//! LEVISO_REMOTE_STORE=http://buildbox:8750       # restore from the remote
+//! LEVISO_REMOTE_STORE_PUSH=1                     # also upload (CI)
+//! LEVISO_REMOTE_STORE_TOKEN=<shared secret>       # required for uploads
+//! ```
+//!
+//! The protocol is content-addressed by input hash, one blob per key:
+//!
+//! - `GET /<kind>/<key>` - 200 with the blob and an `X-Sha256` header, or 404
+//! - `HEAD /<kind>/<key>` - same, without the body
+//! - `PUT /<kind>/<key>` - upload with an `X-Sha256` header and an
+//!   `Authorization: Bearer <token>` header; the server rejects the blob
+//!   (400) if the body doesn't match, a missing or wrong token (403), and a
+//!   key that is already stored (409)
+//!
+//! Keys are write-once, so an upload can never replace a blob that other
+//! developers already restore. A server started without a push token is
+//! read-only.
+//!
+//! Clients verify the checksum of every download before using it.
+//!
+//! Only this minimal subset of HTTP/1.1 is spoken: one request per
+//! connection, bodies sized by `Content-Length` (chunked uploads are refused
+//! with 411), and timeouts on every socket. It is not meant to face a
+//! network. `leviso-store-server` (`src/bin/leviso-store-server.rs`), the
+//! reference server also used by the tests, listens on localhost only by
+//! default; other machines reach it through an SSH tunnel, which provides
+//! the encryption and host authentication plain HTTP lacks:
+//!
+//! ```text
+//! ssh -N -L 8750:localhost:8750 buildbox &
+//! LEVISO_REMOTE_STORE=http://localhost:8750
+//! ```
+//!
+//! `restore_file()` / `store_file_from_key()` wrap the local store: the local
+//! store is tried first, and a remote hit is copied into it when the caller
+//! stores the restored output. Keys are the artifacts' current input hashes
+//! (`rebuild::Artifact::current_hash`), which don't depend on the checkout,
+//! so a fresh clone can restore the initramfs images CI built. The rootfs key
+//! includes the inputs recorded by its last local build, so the rootfs is
+//! restored only once the checkout has built it. Pushing works with or
+//! without a local store.
+
+use anyhow::{bail, Context, Result};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use distro_builder::artifact_store::ArtifactStore;
+
+/// Environment variable holding the remote store URL.
+pub const REMOTE_STORE_ENV: &str = "LEVISO_REMOTE_STORE";
+/// Set to "1" to upload freshly built artifacts to the remote store.
+pub const REMOTE_STORE_PUSH_ENV: &str = "LEVISO_REMOTE_STORE_PUSH";
+/// Shared token sent with uploads; must match the server's push token.
+pub const REMOTE_STORE_TOKEN_ENV: &str = "LEVISO_REMOTE_STORE_TOKEN";
+
+const CHECKSUM_HEADER: &str = "x-sha256";
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const IO_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Restore `out` for input hash `key` from the local store, falling back to
+/// the remote store.
+///
+/// Returns `Ok(true)` if `out` was replaced. Nothing is written on a miss.
+pub fn restore_file(
+    store: Option<&ArtifactStore>,
+    kind: &str,
+    key: &str,
+    out: &Path,
+) -> Result<bool> {
+    if let Some(store) = store {
+        if restore_local(store, kind, key, out)? {
+            return Ok(true);
+        }
+    }
+    let Some(remote) = RemoteStore::from_env()? else {
+        return Ok(false);
+    };
+    if !remote.get(kind, key, out)? {
+        return Ok(false);
+    }
+    println!(
+        "  Downloaded {} {} from {}",
+        kind,
+        short_key(key),
+        remote.url
+    );
+    Ok(true)
+}
+
+/// Restore from the local store, which looks keys up through a key file.
+///
+/// Restores into `<out>.restore.tmp` first, so an existing (stale) `out` is
+/// only replaced on a hit.
+fn restore_local(store: &ArtifactStore, kind: &str, key: &str, out: &Path) -> Result<bool> {
+    let key_file = with_suffix(out, ".key.tmp");
+    let restored = with_suffix(out, ".restore.tmp");
+    let _ = fs::remove_file(&restored);
+    fs::write(&key_file, format!("{}\n", key))
+        .with_context(|| format!("Failed to write {}", key_file.display()))?;
+    let result = distro_builder::artifact_store::try_restore_file_from_key(
+        store, kind, &key_file, &restored,
+    )
+    .and_then(|found| {
+        if found {
+            fs::rename(&restored, out)?;
+        }
+        Ok(found)
+    });
+    let _ = fs::remove_file(&key_file);
+    let _ = fs::remove_file(&restored);
+    result
+}
+
+/// Store `out` in the local store, and upload it if pushing is enabled.
+pub fn store_file_from_key(
+    store: Option<&ArtifactStore>,
+    kind: &str,
+    key_path: &Path,
+    out: &Path,
+) -> Result<()> {
+    if let Some(store) = store {
+        distro_builder::artifact_store::try_store_file_from_key(
+            store,
+            kind,
+            key_path,
+            out,
+            BTreeMap::new(),
+        )?;
+    }
+    if std::env::var(REMOTE_STORE_PUSH_ENV).as_deref() != Ok("1") || !out.exists() {
+        return Ok(());
+    }
+    let (Some(remote), Some(key)) = (RemoteStore::from_env()?, read_key(key_path)) else {
+        return Ok(());
+    };
+    if remote.contains(kind, &key)? {
+        return Ok(());
+    }
+    if remote.put(kind, &key, out)? {
+        println!("  Uploaded {} {} to {}", kind, short_key(&key), remote.url);
+    }
+    Ok(())
+}
+
+fn read_key(key_path: &Path) -> Option<String> {
+    let key = fs::read_to_string(key_path).ok()?.trim().to_string();
+    (!key.is_empty()).then_some(key)
+}
+
+fn short_key(key: &str) -> &str {
+    &key[..key.len().min(12)]
+}
+
+/// HTTP client for a remote store (`LEVISO_REMOTE_STORE`).
+#[derive(Debug, Clone)]
+pub struct RemoteStore {
+    url: String,
+    host: String,
+    port: u16,
+    prefix: String,
+    token: Option<String>,
+}
+
+/// Longest request/status or header line accepted.
+const MAX_LINE: u64 = 8 << 10;
+/// Most header lines accepted.
+const MAX_HEADERS: usize = 64;
+
+/// Status, headers and body reader of an HTTP response.
+struct Response {
+    status: u16,
+    headers: BTreeMap<String, String>,
+    body: BufReader<TcpStream>,
+}
+
+impl Response {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name).map(String::as_str)
+    }
+
+    fn content_length(&self) -> Result<u64> {
+        self.header("content-length")
+            .context("Response has no Content-Length")?
+            .parse()
+            .context("Invalid Content-Length")
+    }
+}
+
+impl RemoteStore {
+    /// Remote store from `LEVISO_REMOTE_STORE`, if set, with the push token
+    /// from `LEVISO_REMOTE_STORE_TOKEN`.
+    pub fn from_env() -> Result<Option<Self>> {
+        let url = match std::env::var(REMOTE_STORE_ENV) {
+            Ok(url) if !url.trim().is_empty() => url,
+            _ => return Ok(None),
+        };
+        let mut remote = Self::new(url.trim())?;
+        remote.token = std::env::var(REMOTE_STORE_TOKEN_ENV)
+            .ok()
+            .map(|t| t.trim().to_string())
+            .filter(|t| !t.is_empty());
+        Ok(Some(remote))
+    }
+
+    /// Use `token` to authorize uploads.
+    pub fn with_token(mut self, token: &str) -> Self {
+        self.token = Some(token.to_string());
+        self
+    }
+
+    /// Parse a store URL like `http://buildbox:8750` or `http://host/leviso`.
+    pub fn new(url: &str) -> Result<Self> {
+        let Some(rest) = url.strip_prefix("http://") else {
+            bail!(
+                "Unsupported remote store URL '{}' - only http:// is supported",
+                url
+            );
+        };
+        let (authority, prefix) = match rest.find('/') {
+            Some(pos) => rest.split_at(pos),
+            None => (rest, ""),
+        };
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) => (
+                host,
+                port.parse()
+                    .with_context(|| format!("Invalid port in remote store URL '{}'", url))?,
+            ),
+            None => (authority, 80),
+        };
+        if host.is_empty() {
+            bail!("Missing host in remote store URL '{}'", url);
+        }
+        Ok(Self {
+            url: url.trim_end_matches('/').to_string(),
+            host: host.to_string(),
+            port,
+            prefix: prefix.trim_end_matches('/').to_string(),
+            token: None,
+        })
+    }
+
+    /// Whether the remote store has a blob for `kind`/`key`.
+    pub fn contains(&self, kind: &str, key: &str) -> Result<bool> {
+        let response = self.request("HEAD", kind, key, &[], None)?;
+        match response.status {
+            200 => Ok(true),
+            404 => Ok(false),
+            status => bail!("Remote store HEAD {}/{} failed: HTTP {}", kind, key, status),
+        }
+    }
+
+    /// Download `kind`/`key` to `out`, verifying its checksum.
+    ///
+    /// Returns `Ok(false)` if the remote store has no such blob. The download
+    /// goes to `<out>.tmp` and is only renamed into place once verified.
+    pub fn get(&self, kind: &str, key: &str, out: &Path) -> Result<bool> {
+        let mut response = self.request("GET", kind, key, &[], None)?;
+        match response.status {
+            200 => {}
+            404 => return Ok(false),
+            status => bail!("Remote store GET {}/{} failed: HTTP {}", kind, key, status),
+        }
+        let expected = response
+            .header(CHECKSUM_HEADER)
+            .context("Remote store response has no X-Sha256 header")?
+            .to_string();
+        let length = response.content_length()?;
+
+        let tmp = tmp_path(out);
+        if let Some(parent) = out.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let result = (|| -> Result<()> {
+            let mut file = BufWriter::new(File::create(&tmp)?);
+            let (actual, received) =
+                copy_hashed(&mut (&mut response.body).take(length), &mut file)?;
+            file.flush()?;
+            if received != length {
+                bail!(
+                    "Remote store download of {}/{} truncated ({} of {} bytes)",
+                    kind,
+                    key,
+                    received,
+                    length
+                );
+            }
+            if actual != expected {
+                bail!(
+                    "Checksum mismatch for remote {}/{}: expected {}, got {}",
+                    kind,
+                    key,
+                    expected,
+                    actual
+                );
+            }
+            fs::rename(&tmp, out)?;
+            Ok(())
+        })();
+        if result.is_err() {
+            let _ = fs::remove_file(&tmp);
+        }
+        result.map(|()| true)
+    }
+
+    /// Upload `file` as `kind`/`key`.
+    ///
+    /// Returns `Ok(false)` if the key was already stored; keys are write-once,
+    /// so the stored blob is kept.
+    pub fn put(&self, kind: &str, key: &str, file: &Path) -> Result<bool> {
+        let Some(token) = &self.token else {
+            bail!(
+                "Uploading to the remote store needs a push token - set {}",
+                REMOTE_STORE_TOKEN_ENV
+            );
+        };
+        let sha256 = sha256_file(file)?;
+        let length = fs::metadata(file)?.len();
+        let mut body =
+            File::open(file).with_context(|| format!("Failed to open {}", file.display()))?;
+        let response = self.request(
+            "PUT",
+            kind,
+            key,
+            &[
+                ("X-Sha256", sha256),
+                ("Authorization", format!("Bearer {}", token)),
+            ],
+            Some((&mut body, length)),
+        )?;
+        match response.status {
+            200 | 201 => Ok(true),
+            409 => Ok(false),
+            403 => bail!(
+                "Remote store rejected the upload of {}/{}: wrong or missing push token ({})",
+                kind,
+                key,
+                REMOTE_STORE_TOKEN_ENV
+            ),
+            status => bail!("Remote store PUT {}/{} failed: HTTP {}", kind, key, status),
+        }
+    }
+
+    fn request(
+        &self,
+        method: &str,
+        kind: &str,
+        key: &str,
+        headers: &[(&str, String)],
+        body: Option<(&mut dyn Read, u64)>,
+    ) -> Result<Response> {
+        let addr = (self.host.as_str(), self.port)
+            .to_socket_addrs()
+            .with_context(|| format!("Failed to resolve remote store {}", self.url))?
+            .next()
+            .with_context(|| format!("No address for remote store {}", self.url))?;
+        let stream = TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT)
+            .with_context(|| format!("Failed to connect to remote store {}", self.url))?;
+        stream.set_read_timeout(Some(IO_TIMEOUT))?;
+        stream.set_write_timeout(Some(IO_TIMEOUT))?;
+
+        let mut writer = BufWriter::new(stream.try_clone()?);
+        write!(
+            writer,
+            "{} {}/{}/{} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n",
+            method, self.prefix, kind, key, self.host
+        )?;
+        for (name, value) in headers {
+            write!(writer, "{}: {}\r\n", name, value)?;
+        }
+        match body {
+            Some((body, length)) => {
+                write!(writer, "Content-Length: {}\r\n\r\n", length)?;
+                std::io::copy(&mut body.take(length), &mut writer)?;
+            }
+            None => write!(writer, "\r\n")?,
+        }
+        writer.flush()?;
+
+        let mut reader = BufReader::new(stream);
+        let (status, headers) = read_head(&mut reader)?;
+        let status = status
+            .split_whitespace()
+            .nth(1)
+            .and_then(|s| s.parse().ok())
+            .with_context(|| format!("Invalid HTTP status line from {}", self.url))?;
+        Ok(Response {
+            status,
+            headers,
+            body: reader,
+        })
+    }
+}
+
+/// Read an HTTP request/status line and headers (names lowercased).
+fn read_head(reader: &mut impl BufRead) -> Result<(String, BTreeMap<String, String>)> {
+    let first = read_line(reader)?;
+    let mut headers = BTreeMap::new();
+    loop {
+        if headers.len() >= MAX_HEADERS {
+            bail!("Too many HTTP headers");
+        }
+        let line = read_line(reader)?;
+        if line.is_empty() {
+            bail!("Connection closed in HTTP headers");
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            headers.insert(name.trim().to_ascii_lowercase(), value.trim().to_string());
+        }
+    }
+    Ok((first.trim_end().to_string(), headers))
+}
+
+/// Read one CRLF-terminated line of at most `MAX_LINE` bytes.
+fn read_line(reader: &mut impl BufRead) -> Result<String> {
+    let mut line = String::new();
+    (&mut *reader).take(MAX_LINE).read_line(&mut line)?;
+    if line.len() as u64 == MAX_LINE && !line.ends_with('\n') {
+        bail!("HTTP line too long");
+    }
+    Ok(line)
+}
+
+/// Copy `reader` to `writer`, returning the SHA-256 and byte count.
+fn copy_hashed(reader: &mut impl Read, writer: &mut impl Write) -> Result<(String, u64)> {
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0u8; 1 << 20];
+    let mut total = 0;
+    loop {
+        let n = reader.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+        writer.write_all(&buf[..n])?;
+        total += n as u64;
+    }
+    Ok((format!("{:x}", hasher.finalize()), total))
+}
+
+fn sha256_file(path: &Path) -> Result<String> {
+    let mut file =
+        File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    Ok(copy_hashed(&mut file, &mut std::io::sink())?.0)
+}
+
+fn tmp_path(path: &Path) -> PathBuf {
+    with_suffix(path, ".tmp")
+}
+
+/// Temp file for one upload, unique per request so concurrent PUTs of the
+/// same blob don't write into each other.
+fn upload_tmp_path(blob: &Path) -> PathBuf {
+    static UPLOADS: AtomicU64 = AtomicU64::new(0);
+    let n = UPLOADS.fetch_add(1, Ordering::Relaxed);
+    with_suffix(blob, &format!(".{}.{}.tmp", std::process::id(), n))
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path
+        .file_name()
+        .map(|n| n.to_os_string())
+        .unwrap_or_default();
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+/// Reference remote store server (`leviso-store-server`): stores blobs as
+/// `<root>/<kind>/<key>` with the checksum in `<key>.sha256`.
+pub struct Server {
+    root: PathBuf,
+    listener: TcpListener,
+    push_token: Option<String>,
+}
+
+impl Server {
+    /// Bind to `addr` (e.g. `127.0.0.1:8750`, or port 0 for tests).
+    pub fn bind(root: &Path, addr: &str) -> Result<Self> {
+        fs::create_dir_all(root).with_context(|| format!("Failed to create {}", root.display()))?;
+        let listener =
+            TcpListener::bind(addr).with_context(|| format!("Failed to listen on {}", addr))?;
+        Ok(Self {
+            root: root.to_path_buf(),
+            listener,
+            push_token: None,
+        })
+    }
+
+    /// Accept uploads that carry `token`. Without one the server is read-only.
+    pub fn with_push_token(mut self, token: &str) -> Self {
+        self.push_token = Some(token.to_string());
+        self
+    }
+
+    pub fn local_addr(&self) -> Result<std::net::SocketAddr> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    /// Serve requests forever, one thread per connection.
+    pub fn run(self) -> Result<()> {
+        for stream in self.listener.incoming() {
+            let Ok(stream) = stream else { continue };
+            let root = self.root.clone();
+            let push_token = self.push_token.clone();
+            std::thread::spawn(move || {
+                if let Err(e) = handle_connection(&root, push_token.as_deref(), stream) {
+                    eprintln!("[WARN] Request failed: {:#}", e);
+                }
+            });
+        }
+        Ok(())
+    }
+}
+
+fn handle_connection(root: &Path, push_token: Option<&str>, stream: TcpStream) -> Result<()> {
+    stream.set_read_timeout(Some(IO_TIMEOUT))?;
+    stream.set_write_timeout(Some(IO_TIMEOUT))?;
+    let mut writer = BufWriter::new(stream.try_clone()?);
+    let mut reader = BufReader::new(stream);
+    let (request_line, headers) = read_head(&mut reader)?;
+
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or_default();
+    let path = parts.next().unwrap_or_default();
+    let Some(blob) = blob_path(root, path) else {
+        return respond(&mut writer, 400, "Bad Request", &[], None);
+    };
+    let checksum_file = with_suffix(&blob, ".sha256");
+
+    match method {
+        "GET" | "HEAD" => {
+            let (Ok(file), Ok(sha256)) = (File::open(&blob), fs::read_to_string(&checksum_file))
+            else {
+                return respond(&mut writer, 404, "Not Found", &[], None);
+            };
+            let length = file.metadata()?.len();
+            let body = (method == "GET").then_some(file);
+            respond(
+                &mut writer,
+                200,
+                "OK",
+                &[
+                    ("X-Sha256", sha256.trim()),
+                    ("Content-Length", &length.to_string()),
+                ],
+                body,
+            )
+        }
+        "PUT" => {
+            if !authorized(push_token, headers.get("authorization")) {
+                return respond(&mut writer, 403, "Forbidden", &[], None);
+            }
+            if blob.exists() {
+                return respond(&mut writer, 409, "Conflict", &[], None);
+            }
+            if headers.contains_key("transfer-encoding") {
+                return respond(&mut writer, 411, "Length Required", &[], None);
+            }
+            let (Some(expected), Some(length)) = (
+                headers.get(CHECKSUM_HEADER),
+                headers
+                    .get("content-length")
+                    .and_then(|l| l.parse::<u64>().ok()),
+            ) else {
+                return respond(&mut writer, 400, "Bad Request", &[], None);
+            };
+            fs::create_dir_all(blob.parent().unwrap_or(root))?;
+            let tmp = upload_tmp_path(&blob);
+            let mut file = BufWriter::new(File::create(&tmp)?);
+            let (actual, received) = copy_hashed(&mut (&mut reader).take(length), &mut file)?;
+            file.flush()?;
+            drop(file);
+            if received != length || &actual != expected {
+                let _ = fs::remove_file(&tmp);
+                return respond(&mut writer, 400, "Checksum Mismatch", &[], None);
+            }
+            // Link instead of rename so a concurrent upload of the same key
+            // can't replace the blob; the checksum file appears (atomically)
+            // only after the blob, so a GET never pairs old and new.
+            let linked = fs::hard_link(&tmp, &blob);
+            let _ = fs::remove_file(&tmp);
+            match linked {
+                Ok(()) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                    return respond(&mut writer, 409, "Conflict", &[], None);
+                }
+                Err(e) => return Err(e.into()),
+            }
+            let checksum_tmp = upload_tmp_path(&checksum_file);
+            fs::write(&checksum_tmp, format!("{}\n", actual))?;
+            fs::rename(&checksum_tmp, &checksum_file)?;
+            respond(&mut writer, 201, "Created", &[], None)
+        }
+        _ => respond(&mut writer, 405, "Method Not Allowed", &[], None),
+    }
+}
+
+/// Whether an `Authorization` header carries the server's push token.
+fn authorized(push_token: Option<&str>, header: Option<&String>) -> bool {
+    let (Some(expected), Some(given)) =
+        (push_token, header.and_then(|h| h.strip_prefix("Bearer ")))
+    else {
+        return false;
+    };
+    // Compare in constant time so the token can't be guessed byte by byte
+    expected.len() == given.len()
+        && expected
+            .bytes()
+            .zip(given.bytes())
+            .fold(0u8, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+/// Map `/<kind>/<key>` to a blob path, rejecting anything else.
+fn blob_path(root: &Path, path: &str) -> Option<PathBuf> {
+    let valid = |s: &str| {
+        !s.is_empty()
+            && !s.starts_with('.')
+            && s.chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+    };
+    let (kind, key) = path.strip_prefix('/')?.split_once('/')?;
+    (valid(kind) && valid(key) && !key.ends_with(".sha256") && !key.ends_with(".tmp"))
+        .then(|| root.join(kind).join(key))
+}
+
+fn respond(
+    writer: &mut impl Write,
+    status: u16,
+    reason: &str,
+    headers: &[(&str, &str)],
+    body: Option<File>,
+) -> Result<()> {
+    write!(
+        writer,
+        "HTTP/1.1 {} {}\r\nConnection: close\r\n",
+        status, reason
+    )?;
+    for (name, value) in headers {
+        write!(writer, "{}: {}\r\n", name, value)?;
+    }
+    if !headers.iter().any(|(name, _)| *name == "Content-Length") {
+        write!(writer, "Content-Length: 0\r\n")?;
+    }
+    write!(writer, "\r\n")?;
+    if let Some(mut body) = body {
+        std::io::copy(&mut body, writer)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    const TOKEN: &str = "test-token";
+
+    fn start_server(root: &Path) -> RemoteStore {
+        let server = Server::bind(root, "127.0.0.1:0")
+            .unwrap()
+            .with_push_token(TOKEN);
+        let url = format!("http://{}", server.local_addr().unwrap());
+        std::thread::spawn(move || server.run());
+        RemoteStore::new(&url).unwrap().with_token(TOKEN)
+    }
+
+    #[test]
+    fn test_put_then_get_roundtrip() {
+        let temp = TempDir::new().unwrap();
+        let remote = start_server(&temp.path().join("server"));
+        let blob = temp.path().join("filesystem.erofs");
+        fs::write(&blob, vec![7u8; 3 << 20]).unwrap();
+
+        assert!(!remote.contains("rootfs_erofs", "abc123").unwrap());
+        assert!(remote.put("rootfs_erofs", "abc123", &blob).unwrap());
+        assert!(remote.contains("rootfs_erofs", "abc123").unwrap());
+
+        let out = temp.path().join("restored/filesystem.erofs");
+        assert!(remote.get("rootfs_erofs", "abc123", &out).unwrap());
+        assert_eq!(fs::read(&out).unwrap(), fs::read(&blob).unwrap());
+        assert!(!remote.get("rootfs_erofs", "missing", &out).unwrap());
+    }
+
+    #[test]
+    fn test_corrupt_blob_is_rejected_on_download() {
+        let temp = TempDir::new().unwrap();
+        let root = temp.path().join("server");
+        let remote = start_server(&root);
+        let blob = temp.path().join("initramfs-live.cpio.gz");
+        fs::write(&blob, b"initramfs").unwrap();
+        remote.put("initramfs", "k1", &blob).unwrap();
+
+        // Bit rot on the server
+        fs::write(root.join("initramfs/k1"), b"initramfz").unwrap();
+
+        let out = temp.path().join("out.cpio.gz");
+        let err = remote.get("initramfs", "k1", &out).unwrap_err();
+        assert!(err.to_string().contains("Checksum mismatch"));
+        assert!(!out.exists());
+        assert!(!tmp_path(&out).exists());
+    }
+
+    #[test]
+    fn test_keys_are_write_once_and_need_the_token() {
+        let temp = TempDir::new().unwrap();
+        let remote = start_server(&temp.path().join("server"));
+        let (blob, other) = (temp.path().join("a"), temp.path().join("b"));
+        fs::write(&blob, b"built by CI").unwrap();
+        fs::write(&other, b"replacement").unwrap();
+
+        let anonymous = RemoteStore::new(&remote.url).unwrap();
+        assert!(anonymous.put("initramfs", "k1", &blob).is_err());
+        let wrong = RemoteStore::new(&remote.url).unwrap().with_token("guess");
+        assert!(wrong.put("initramfs", "k1", &blob).is_err());
+        assert!(!remote.contains("initramfs", "k1").unwrap());
+
+        assert!(remote.put("initramfs", "k1", &blob).unwrap());
+        assert!(!remote.put("initramfs", "k1", &other).unwrap());
+        let out = temp.path().join("out");
+        assert!(remote.get("initramfs", "k1", &out).unwrap());
+        assert_eq!(fs::read(&out).unwrap(), b"built by CI");
+    }
+
+    #[test]
+    fn test_concurrent_puts_of_one_blob() {
+        let temp = TempDir::new().unwrap();
+        let root = temp.path().join("server");
+        let remote = start_server(&root);
+        let blob = temp.path().join("filesystem.erofs");
+        fs::write(&blob, vec![3u8; 4 << 20]).unwrap();
+
+        let uploads: Vec<_> = (0..4)
+            .map(|_| {
+                let (remote, blob) = (remote.clone(), blob.clone());
+                std::thread::spawn(move || remote.put("rootfs_erofs", "k1", &blob))
+            })
+            .collect();
+        for upload in uploads {
+            upload.join().unwrap().unwrap();
+        }
+
+        let out = temp.path().join("out.erofs");
+        assert!(remote.get("rootfs_erofs", "k1", &out).unwrap());
+        assert_eq!(fs::read(&out).unwrap(), fs::read(&blob).unwrap());
+        assert_eq!(fs::read_dir(root.join("rootfs_erofs")).unwrap().count(), 2);
+    }
+
+    #[test]
+    fn test_url_and_path_validation() {
+        let remote = RemoteStore::new("http://buildbox:8750/leviso/").unwrap();
+        assert_eq!((remote.host.as_str(), remote.port), ("buildbox", 8750));
+        assert_eq!(remote.prefix, "/leviso");
+        assert!(RemoteStore::new("https://buildbox").is_err());
+
+        let root = Path::new("/srv/store");
+        assert_eq!(
+            blob_path(root, "/initramfs/abc"),
+            Some(root.join("initramfs/abc"))
+        );
+        assert!(blob_path(root, "/initramfs/../etc").is_none());
+        assert!(blob_path(root, "/initramfs/abc.sha256").is_none());
+        assert!(blob_path(root, "/initramfs").is_none());
+    }
+}
@@ -7,14 +7,21 @@ use anyhow::Result;
 
 use super::definitions::*;
 use super::executor;
+use crate::build::checkpoint::Checkpoints;
 use crate::build::context::BuildContext;
+use crate::build::{inputs, licenses, sources};
 use distro_builder::timing::Timer;
 use distro_builder::LicenseTracker;
 use distro_builder::PackageManager;
 
-/// Build the complete system into the staging directory.
+/// A build phase: name (for timers and checkpoints) and the function that runs it.
+type BuildPhase = (
+    &'static str,
+    fn(&BuildContext, &LicenseTracker) -> Result<()>,
+);
+
+/// Phases in execution order.
 ///
-/// Components and Services are installed in phase order:
 /// 1. Filesystem - directories must exist before files
 /// 2. Binaries - shells and tools before services
 /// 3. Systemd - unit files before enabling
@@ -24,88 +31,132 @@ use distro_builder::PackageManager;
 /// 7. Packages - recipe, dracut
 /// 8. Firmware - hardware support
 /// 9. Final - welcome message, installer tools
-/// 10. Licenses - copy license files for all redistributed packages
-pub fn build_system(ctx: &BuildContext) -> Result<()> {
+/// 10. Licenses - copy license files, enforce the license policy, write SOURCES.txt
+const PHASES: [BuildPhase; 10] = [
+    ("Filesystem", phase_filesystem),
+    ("Binaries", phase_binaries),
+    ("Systemd", phase_systemd),
+    ("D-Bus", phase_dbus),
+    ("Services", phase_services),
+    ("Config", phase_config),
+    ("Packages", phase_packages),
+    ("Firmware", phase_firmware),
+    ("Final", phase_final),
+    ("Licenses", phase_licenses),
+];
+
+/// Build the complete system into the staging directory.
+///
+/// Components and Services are installed in phase order (see `PHASES`).
+///
+/// With `checkpoints`, a checkpoint is written after every phase, and when
+/// resuming, phases up to the checkpoint are skipped.
+pub fn build_system(ctx: &BuildContext, checkpoints: Option<&Checkpoints>) -> Result<()> {
     println!("Building complete system for rootfs (EROFS)...");
 
     // Track licenses for all binaries we copy
     let tracker = LicenseTracker::new(ctx.source.clone(), PackageManager::Rpm);
 
-    // Phase 1: Filesystem
-    let t = Timer::start("Filesystem");
-    executor::execute(ctx, &FILESYSTEM, &tracker)?;
-    t.finish();
-
-    // Phase 2: Binaries
-    let t = Timer::start("Binaries");
-    executor::execute(ctx, &SHELL, &tracker)?;
-    executor::execute(ctx, &COREUTILS, &tracker)?;
-    executor::execute(ctx, &SBIN_BINARIES, &tracker)?;
-    executor::execute(ctx, &SYSTEMD_BINS, &tracker)?;
-    t.finish();
-
-    // Phase 3: Systemd
-    let t = Timer::start("Systemd");
-    executor::execute(ctx, &SYSTEMD_UNITS, &tracker)?;
-    executor::execute(ctx, &GETTY, &tracker)?;
-    executor::execute(ctx, &EFIVARS, &tracker)?; // EFI variable filesystem for efibootmgr
-    executor::execute(ctx, &UDEV, &tracker)?;
-    executor::execute(ctx, &TMPFILES, &tracker)?;
-    executor::execute(ctx, &LIVE_SYSTEMD, &tracker)?;
-    t.finish();
-
-    // Phase 4: D-Bus (using Service abstraction)
-    let t = Timer::start("D-Bus");
-    executor::execute(ctx, &DBUS_SVC, &tracker)?;
-    t.finish();
-
-    // Phase 5: Services (using Service abstraction where applicable)
-    let t = Timer::start("Services");
-    executor::execute(ctx, &NETWORK, &tracker)?; // Has custom ops, keeping as Component
-    executor::execute(ctx, &CHRONY_SVC, &tracker)?;
-    executor::execute(ctx, &OPENSSH_SVC, &tracker)?;
-    executor::execute(ctx, &PAM, &tracker)?;
-    executor::execute(ctx, &MODULES, &tracker)?;
-    // Desktop services
-    executor::execute(ctx, &BLUETOOTH_SVC, &tracker)?;
-    executor::execute(ctx, &PIPEWIRE_SVC, &tracker)?;
-    executor::execute(ctx, &POLKIT_SVC, &tracker)?;
-    executor::execute(ctx, &UDISKS_SVC, &tracker)?;
-    executor::execute(ctx, &UPOWER_SVC, &tracker)?;
-    t.finish();
-
-    // Phase 6: Config
-    let t = Timer::start("Config");
-    executor::execute(ctx, &ETC_CONFIG, &tracker)?;
-    t.finish();
-
-    // Phase 7: Packages
-    // NOTE: DRACUT removed - initramfs built using custom rootless builder
-    let t = Timer::start("Packages");
-    executor::execute(ctx, &RECIPE, &tracker)?;
-    executor::execute(ctx, &BOOTLOADER, &tracker)?;
-    t.finish();
-
-    // Phase 8: Firmware
-    let t = Timer::start("Firmware");
-    executor::execute(ctx, &FIRMWARE, &tracker)?;
-    t.finish();
-
-    // Phase 9: Final
-    let t = Timer::start("Final");
-    executor::execute(ctx, &FINAL, &tracker)?;
-    t.finish();
-
-    // Phase 10: Licenses - copy license files for all redistributed packages
-    let t = Timer::start("Licenses");
-    let license_count = tracker.copy_licenses(&ctx.source, &ctx.staging)?;
-    println!("  Copied licenses for {} packages", license_count);
-    t.finish();
+    let mut skip = 0;
+    if let Some(point) = checkpoints.and_then(|c| c.resume_point()) {
+        println!(
+            "  [RESUME] Continuing after phase {} ({})",
+            point.completed_phase, point.phase_name
+        );
+        for package in &point.packages {
+            tracker.register_package(package);
+        }
+        for input in &point.inputs {
+            inputs::record_input(input);
+        }
+        skip = point.completed_phase;
+    }
+
+    for (index, (name, run)) in PHASES.iter().enumerate().skip(skip) {
+        let t = Timer::start(name);
+        run(ctx, &tracker)?;
+        t.finish();
+
+        if let Some(checkpoints) = checkpoints {
+            checkpoints.save(
+                index + 1,
+                name,
+                tracker.registered_packages(),
+                inputs::recorded_inputs(),
+            )?;
+        }
+    }
 
     println!("System build complete.");
     Ok(())
 }
 
+fn phase_filesystem(ctx: &BuildContext, tracker: &LicenseTracker) -> Result<()> {
+    executor::execute(ctx, &FILESYSTEM, tracker)
+}
+
+fn phase_binaries(ctx: &BuildContext, tracker: &LicenseTracker) -> Result<()> {
+    executor::execute(ctx, &SHELL, tracker)?;
+    executor::execute(ctx, &COREUTILS, tracker)?;
+    executor::execute(ctx, &SBIN_BINARIES, tracker)?;
+    executor::execute(ctx, &SYSTEMD_BINS, tracker)
+}
+
+fn phase_systemd(ctx: &BuildContext, tracker: &LicenseTracker) -> Result<()> {
+    executor::execute(ctx, &SYSTEMD_UNITS, tracker)?;
+    executor::execute(ctx, &GETTY, tracker)?;
+    executor::execute(ctx, &EFIVARS, tracker)?; // EFI variable filesystem for efibootmgr
+    executor::execute(ctx, &UDEV, tracker)?;
+    executor::execute(ctx, &TMPFILES, tracker)?;
+    executor::execute(ctx, &LIVE_SYSTEMD, tracker)
+}
+
+/// D-Bus (using Service abstraction)
+fn phase_dbus(ctx: &BuildContext, tracker: &LicenseTracker) -> Result<()> {
+    executor::execute(ctx, &DBUS_SVC, tracker)
+}
+
+/// Services (using Service abstraction where applicable)
+fn phase_services(ctx: &BuildContext, tracker: &LicenseTracker) -> Result<()> {
+    executor::execute(ctx, &NETWORK, tracker)?; // Has custom ops, keeping as Component
+    executor::execute(ctx, &CHRONY_SVC, tracker)?;
+    executor::execute(ctx, &OPENSSH_SVC, tracker)?;
+    executor::execute(ctx, &PAM, tracker)?;
+    executor::execute(ctx, &MODULES, tracker)?;
+    // Desktop services
+    executor::execute(ctx, &BLUETOOTH_SVC, tracker)?;
+    executor::execute(ctx, &PIPEWIRE_SVC, tracker)?;
+    executor::execute(ctx, &POLKIT_SVC, tracker)?;
+    executor::execute(ctx, &UDISKS_SVC, tracker)?;
+    executor::execute(ctx, &UPOWER_SVC, tracker)
+}
+
+fn phase_config(ctx: &BuildContext, tracker: &LicenseTracker) -> Result<()> {
+    executor::execute(ctx, &ETC_CONFIG, tracker)
+}
+
+/// NOTE: DRACUT removed - initramfs built using custom rootless builder
+fn phase_packages(ctx: &BuildContext, tracker: &LicenseTracker) -> Result<()> {
+    executor::execute(ctx, &RECIPE, tracker)?;
+    executor::execute(ctx, &BOOTLOADER, tracker)
+}
+
+fn phase_firmware(ctx: &BuildContext, tracker: &LicenseTracker) -> Result<()> {
+    executor::execute(ctx, &FIRMWARE, tracker)
+}
+
+fn phase_final(ctx: &BuildContext, tracker: &LicenseTracker) -> Result<()> {
+    executor::execute(ctx, &FINAL, tracker)
+}
+
+/// Copy license files for all redistributed packages
+fn phase_licenses(ctx: &BuildContext, tracker: &LicenseTracker) -> Result<()> {
+    let license_count = tracker.copy_licenses(&ctx.source, &ctx.staging)?;
+    println!("  Copied licenses for {} packages", license_count);
+    licenses::enforce_license_policy(ctx, tracker)?;
+    sources::write_source_manifest(&ctx.source, &ctx.output, &tracker.registered_packages())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
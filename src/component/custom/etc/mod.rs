@@ -8,6 +8,7 @@ use std::process::Command;
 use leviso_elf::copy_dir_recursive;
 
 use crate::build::context::BuildContext;
+use crate::build::reproducible;
 use crate::common::read_manifest_file;
 
 /// Create all /etc configuration files.
@@ -56,7 +57,15 @@ fn create_tmpfiles_configs(ctx: &BuildContext) -> Result<()> {
 ///
 /// This was previously documented in KNOWLEDGE_install-test-debugging.md as
 /// a manual workaround (R4). Now codified in the build system.
+///
+/// Skipped in reproducible builds (random keys would change the image) unless
+/// `LEVISO_SSH_HOST_KEYS=1`; sshd-keygen@.service generates them on first boot.
 pub fn create_ssh_host_keys(ctx: &BuildContext) -> Result<()> {
+    if !reproducible::ssh_host_keys_enabled()? {
+        println!("Skipping SSH host keys (generated on first boot)");
+        return Ok(());
+    }
+
     println!("Generating SSH host keys...");
 
     let ssh_dir = ctx.staging.join("etc/ssh");
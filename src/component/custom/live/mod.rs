@@ -9,6 +9,7 @@ use distro_spec::levitate::LIVE_ISSUE_MESSAGE;
 use distro_spec::shared::LEVITATE_CARGO_TOOLS;
 
 use crate::build::context::BuildContext;
+use crate::build::inputs::record_source_tree;
 use crate::common::read_manifest_file;
 
 /// Read test instrumentation file - used by both live ISO and qcow2
@@ -122,6 +123,7 @@ pub fn install_tools(ctx: &BuildContext) -> Result<()> {
     for tool in LEVITATE_CARGO_TOOLS {
         build_args.push("-p");
         build_args.push(tool);
+        record_source_tree(&monorepo_dir.join("tools").join(tool));
     }
 
     // ALWAYS rebuild tools to ensure latest version
@@ -18,10 +18,12 @@ use anyhow::Result;
 
 use super::CustomOp;
 use crate::build::context::BuildContext;
+use crate::build::inputs::{record_input, record_source_tree};
 use distro_builder::LicenseTracker;
 
 // Re-export public API
 pub use live::{create_live_overlay_at, read_test_instrumentation};
+pub use packages::find_systemd_boot_rpm;
 
 /// Execute a custom operation.
 ///
@@ -113,6 +115,11 @@ fn install_docs_tui(ctx: &BuildContext) -> Result<()> {
     let docs_tui_dir = monorepo_dir.join("docs/tui");
     let docs_tui_binary = docs_tui_dir.join("levitate-docs");
 
+    record_source_tree(&docs_tui_dir.join("src"));
+    if docs_tui_dir.join("package.json").exists() {
+        record_input(&docs_tui_dir.join("package.json"));
+    }
+
     // ALWAYS rebuild docs-TUI to ensure latest version
     println!("  Rebuilding levitate-docs...");
     let status = Command::new("bun")
@@ -208,6 +215,7 @@ fn install_stage_tests(ctx: &BuildContext) -> Result<()> {
         let path = entry.path();
 
         if path.is_file() && path.extension().is_some_and(|ext| ext == "sh") {
+            record_input(&path);
             let filename = path
                 .file_name()
                 .ok_or_else(|| anyhow::anyhow!("Invalid filename"))?;
@@ -236,6 +244,7 @@ fn install_stage_tests(ctx: &BuildContext) -> Result<()> {
             let path = entry.path();
 
             if path.is_file() {
+                record_input(&path);
                 let filename = path
                     .file_name()
                     .ok_or_else(|| anyhow::anyhow!("Invalid filename"))?;
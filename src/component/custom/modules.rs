@@ -5,6 +5,7 @@ use std::fs;
 use std::path::Path;
 
 use crate::build::context::BuildContext;
+use crate::build::inputs::record_input;
 use distro_builder::process::Cmd;
 
 /// Module metadata files needed by modprobe.
@@ -48,6 +49,8 @@ pub fn copy_modules(ctx: &BuildContext) -> Result<()> {
             "  Using CUSTOM kernel modules from {}",
             custom_modules_base.display()
         );
+        // The modules are built by xtask from this config
+        record_input(&ctx.base_dir.join("kconfig"));
         (custom_modules_base, true)
     } else {
         println!(
@@ -9,28 +9,31 @@ use std::fs;
 use leviso_elf::{copy_dir_recursive, make_executable};
 
 use crate::build::context::BuildContext;
+use crate::build::inputs::{record_input, record_source_tree};
 use crate::common::read_manifest_file;
 use distro_builder::process::shell_in;
 
-/// Extract and copy systemd-boot EFI files from RPM.
-pub fn copy_systemd_boot_efi(ctx: &BuildContext) -> Result<()> {
-    let efi_dst = ctx.staging.join("usr/lib/systemd/boot/efi");
+/// Directory of the Rocky ISO holding the systemd-boot-unsigned RPM.
+const SYSTEMD_BOOT_RPM_DIR: &str = "downloads/iso-contents/AppStream/Packages/s";
 
-    let rpm_dir = ctx
-        .base_dir
-        .join("downloads/iso-contents/AppStream/Packages/s");
-    let rpm_pattern = "systemd-boot-unsigned";
-
-    let rpm_path = std::fs::read_dir(&rpm_dir)?
+/// The systemd-boot-unsigned RPM from the extracted Rocky ISO, if present.
+pub fn find_systemd_boot_rpm(base_dir: &std::path::Path) -> Result<Option<std::path::PathBuf>> {
+    Ok(std::fs::read_dir(base_dir.join(SYSTEMD_BOOT_RPM_DIR))?
         .filter_map(|e| e.ok())
         .map(|e| e.path())
         .find(|p| {
             p.file_name()
-                .map(|n| n.to_string_lossy().contains(rpm_pattern))
+                .map(|n| n.to_string_lossy().contains("systemd-boot-unsigned"))
                 .unwrap_or(false)
-        });
+        }))
+}
 
-    let Some(rpm_path) = rpm_path else {
+/// Extract and copy systemd-boot EFI files from RPM.
+pub fn copy_systemd_boot_efi(ctx: &BuildContext) -> Result<()> {
+    let efi_dst = ctx.staging.join("usr/lib/systemd/boot/efi");
+
+    let rpm_dir = ctx.base_dir.join(SYSTEMD_BOOT_RPM_DIR);
+    let Some(rpm_path) = find_systemd_boot_rpm(&ctx.base_dir)? else {
         bail!(
             "systemd-boot-unsigned RPM not found in {}.\n\
              The EFI files from this package are REQUIRED for bootctl install.",
@@ -38,6 +41,8 @@ pub fn copy_systemd_boot_efi(ctx: &BuildContext) -> Result<()> {
         );
     };
 
+    record_input(&rpm_path);
+
     let temp_dir = ctx.output.join(".systemd-boot-extract");
     if temp_dir.exists() {
         fs::remove_dir_all(&temp_dir)?;
@@ -99,6 +104,7 @@ pub fn copy_recipe(ctx: &BuildContext) -> Result<()> {
         let path = std::path::PathBuf::from(&env_path);
         if path.exists() {
             println!("  Using recipe from RECIPE_BINARY env var (skipping rebuild)");
+            record_input(&path);
             path
         } else {
             bail!(
@@ -111,6 +117,7 @@ pub fn copy_recipe(ctx: &BuildContext) -> Result<()> {
         let manifest_dir = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
         let monorepo_dir = manifest_dir.parent().unwrap();
         let recipe_path = monorepo_dir.join("target/release/recipe");
+        record_source_tree(&monorepo_dir.join("tools/recipe"));
 
         // ALWAYS rebuild recipe to ensure latest version
         println!("  Rebuilding recipe...");
@@ -19,6 +19,7 @@ mod common;
 mod component;
 mod config;
 mod extract;
+mod output;
 mod preflight;
 mod qemu;
 mod rebuild;
@@ -64,6 +65,9 @@ enum Commands {
         /// Timeout in seconds (default: 120)
         #[arg(short, long, default_value = "120")]
         timeout: u64,
+        /// Boot with Secure Boot enforced (keys from SECUREBOOT_KEY_DIR)
+        #[arg(long)]
+        secureboot: bool,
     },
 
     /// Clean build artifacts (default: preserves downloads)
@@ -96,22 +100,107 @@ enum Commands {
         #[arg(long)]
         strict: bool,
     },
+
+    /// Show the corresponding-source manifest (SRPMs for GPL compliance)
+    Sources {
+        /// Local SRPM mirror to collect source packages from
+        #[arg(long)]
+        mirror: Option<PathBuf>,
+        /// Directory to collect SRPMs into (default: .artifacts/out/leviso/srpms)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+
+    /// Repack a released ISO with a new live overlay, extra files or cmdline
+    Remaster {
+        /// ISO to start from
+        #[arg(long)]
+        iso: PathBuf,
+        /// Directory merged over the live overlay
+        #[arg(long)]
+        overlay: Option<PathBuf>,
+        /// Replace the live overlay instead of merging into it
+        #[arg(long, requires = "overlay")]
+        replace_overlay: bool,
+        /// Extra file on the ISO (repeatable)
+        #[arg(long, value_name = "SRC:DEST")]
+        add: Vec<String>,
+        /// Arguments appended to every live UKI cmdline
+        #[arg(long)]
+        cmdline: Option<String>,
+        /// Output ISO path
+        #[arg(short, long)]
+        output: PathBuf,
+    },
+
+    /// Build twice with the same SOURCE_DATE_EPOCH and compare the artifacts
+    VerifyReproducible,
+
+    /// Check an ISO's checksum and detached signatures (offline)
+    VerifyIso {
+        /// Path to the ISO
+        path: PathBuf,
+        /// minisign public key
+        #[arg(long)]
+        key: Option<PathBuf>,
+        /// Fingerprint of the OpenPGP release key (or ISO_SIGNING_GPG_FINGERPRINT)
+        #[arg(long)]
+        gpg_fingerprint: Option<String>,
+    },
+
+    /// Generate Secure Boot test keys (PK, KEK, db)
+    SecurebootKeys {
+        /// Directory to write the keys to
+        dir: PathBuf,
+    },
+
+    /// Inspect and trim the artifact store
+    Store {
+        #[command(subcommand)]
+        action: StoreAction,
+    },
+
+    /// Benchmark build variants
+    Bench {
+        #[command(subcommand)]
+        what: BenchTarget,
+    },
+
+    /// Compare build outputs
+    Diff {
+        #[command(subcommand)]
+        what: DiffTarget,
+    },
 }
 
 #[derive(Subcommand)]
 enum BuildTarget {
     /// Build rootfs image (EROFS, complete live system)
-    Rootfs,
+    Rootfs {
+        /// Continue a failed build after its last completed phase
+        #[arg(long)]
+        resume: bool,
+    },
     /// Build tiny initramfs (mounts rootfs, ~5MB)
     Initramfs,
     /// Build only the ISO image
-    Iso,
+    Iso {
+        /// Also write <iso>-persist.img with an ext4 persistence partition of N MiB
+        #[arg(long, value_name = "N")]
+        persist_size: Option<u32>,
+    },
     /// Build VM disk image (qcow2)
     Qcow2 {
         /// Disk size in GB (default: 256, sparse allocation)
         #[arg(long, default_value = "256")]
         disk_size: u32,
     },
+    /// Build PXE/HTTP network boot bundle (kernel, initramfs, EROFS, iPXE script)
+    Netboot {
+        /// Base URL the bundle is served from (default: host as seen by QEMU user-net)
+        #[arg(long, default_value = artifact::netboot::DEFAULT_URL)]
+        url: String,
+    },
 }
 
 #[derive(Subcommand)]
@@ -121,7 +210,11 @@ enum ShowTarget {
     /// Show rootfs contents (EROFS)
     Rootfs,
     /// Show build status (what needs rebuilding)
-    Status,
+    Status {
+        /// List the inputs that changed since the last successful build
+        #[arg(long)]
+        explain: bool,
+    },
 }
 
 #[derive(Subcommand)]
@@ -140,6 +233,53 @@ enum CleanTarget {
     All,
 }
 
+#[derive(Subcommand)]
+enum StoreAction {
+    /// List stored artifacts with their input keys, sizes and ages
+    List,
+    /// Verify the checksum of every stored artifact
+    Verify {
+        /// Remove corrupt or unreadable entries instead of failing
+        #[arg(long)]
+        remove: bool,
+    },
+    /// Keep only the newest N entries per artifact kind
+    Prune {
+        /// Number of entries to keep per kind
+        #[arg(long)]
+        keep: usize,
+    },
+    /// Evict old entries by age and/or total size
+    Gc {
+        /// Evict entries older than this many days
+        #[arg(long)]
+        max_age_days: Option<u64>,
+        /// Evict oldest entries until the store fits (e.g. 20G)
+        #[arg(long)]
+        max_size: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum BenchTarget {
+    /// Size and QEMU unpack time of each initramfs compression (gzip, zstd, xz, lz4)
+    Initramfs,
+}
+
+#[derive(Subcommand)]
+enum DiffTarget {
+    /// Compare two initramfs images (paths or <kind>:<key prefix> from 'store list')
+    Initramfs {
+        /// Old image
+        a: String,
+        /// New image
+        b: String,
+        /// List every changed file instead of the largest ones
+        #[arg(long)]
+        all: bool,
+    },
+}
+
 #[derive(Subcommand)]
 enum DownloadTarget {
     /// Download Rocky Linux ISO
@@ -180,12 +320,17 @@ fn main() -> Result<()> {
         Commands::Build { target } => {
             let build_target = match target {
                 None => commands::build::BuildTarget::Full,
-                Some(BuildTarget::Rootfs) => commands::build::BuildTarget::Rootfs,
+                Some(BuildTarget::Rootfs { resume }) => {
+                    commands::build::BuildTarget::Rootfs { resume }
+                }
                 Some(BuildTarget::Initramfs) => commands::build::BuildTarget::Initramfs,
-                Some(BuildTarget::Iso) => commands::build::BuildTarget::Iso,
+                Some(BuildTarget::Iso { persist_size }) => {
+                    commands::build::BuildTarget::Iso { persist_size }
+                }
                 Some(BuildTarget::Qcow2 { disk_size }) => {
                     commands::build::BuildTarget::Qcow2 { disk_size }
                 }
+                Some(BuildTarget::Netboot { url }) => commands::build::BuildTarget::Netboot { url },
             };
             commands::cmd_build(&base_dir, build_target, &config)?;
         }
@@ -194,8 +339,11 @@ fn main() -> Result<()> {
             commands::cmd_run(&base_dir, no_disk, disk_size)?;
         }
 
-        Commands::Test { timeout } => {
-            commands::cmd_test(&base_dir, timeout)?;
+        Commands::Test {
+            timeout,
+            secureboot,
+        } => {
+            commands::cmd_test(&base_dir, timeout, secureboot)?;
         }
 
         Commands::Clean { what } => {
@@ -215,7 +363,7 @@ fn main() -> Result<()> {
             let show_target = match what {
                 ShowTarget::Config => commands::show::ShowTarget::Config,
                 ShowTarget::Rootfs => commands::show::ShowTarget::Rootfs,
-                ShowTarget::Status => commands::show::ShowTarget::Status,
+                ShowTarget::Status { explain } => commands::show::ShowTarget::Status { explain },
             };
             commands::cmd_show(&base_dir, show_target, &config)?;
         }
@@ -242,6 +390,69 @@ fn main() -> Result<()> {
         Commands::Preflight { strict } => {
             commands::cmd_preflight(&base_dir, strict)?;
         }
+
+        Commands::Sources { mirror, output } => {
+            commands::cmd_sources(&base_dir, mirror, output)?;
+        }
+
+        Commands::Remaster {
+            iso,
+            overlay,
+            replace_overlay,
+            add,
+            cmdline,
+            output,
+        } => {
+            commands::cmd_remaster(&iso, &output, overlay, replace_overlay, &add, cmdline)?;
+        }
+
+        Commands::VerifyReproducible => {
+            commands::cmd_verify_reproducible(&base_dir, &config)?;
+        }
+
+        Commands::VerifyIso {
+            path,
+            key,
+            gpg_fingerprint,
+        } => {
+            commands::cmd_verify_iso(&path, key.as_deref(), gpg_fingerprint.as_deref())?;
+        }
+
+        Commands::SecurebootKeys { dir } => {
+            commands::cmd_secureboot_keys(&dir)?;
+        }
+
+        Commands::Store { action } => {
+            let store_action = match action {
+                StoreAction::List => commands::store::StoreAction::List,
+                StoreAction::Verify { remove } => commands::store::StoreAction::Verify { remove },
+                StoreAction::Prune { keep } => commands::store::StoreAction::Prune { keep },
+                StoreAction::Gc {
+                    max_age_days,
+                    max_size,
+                } => commands::store::StoreAction::Gc {
+                    max_age_days,
+                    max_size,
+                },
+            };
+            commands::cmd_store(&base_dir, store_action)?;
+        }
+
+        Commands::Bench { what } => {
+            let bench_target = match what {
+                BenchTarget::Initramfs => commands::bench::BenchTarget::Initramfs,
+            };
+            commands::cmd_bench(&base_dir, bench_target)?;
+        }
+
+        Commands::Diff { what } => {
+            let diff_target = match what {
+                DiffTarget::Initramfs { a, b, all } => {
+                    commands::diff::DiffTarget::Initramfs { a, b, all }
+                }
+            };
+            commands::cmd_diff(&base_dir, diff_target)?;
+        }
     }
 
     Ok(())
@@ -0,0 +1,61 @@
+//! Console output prefixed with the build step that printed it.
+//!
+//! Build graph nodes (see `commands::graph`) run in parallel on their own
+//! threads. Each node job gets a `NodeLog` and reports through it; every line
+//! is written as `[<node>] <line>`, whole messages at a time, so lines of
+//! parallel nodes don't tear.
+//!
+//! Only what goes through a `NodeLog` is prefixed. Builders called by a node
+//! keep using plain `println!`, and output of child processes that inherit
+//! stdout (cargo, bun) is not prefixed either.
+
+use std::fmt;
+use std::io::Write;
+
+/// Prefixed output for one build graph node.
+#[derive(Debug, Clone, Copy)]
+pub struct NodeLog {
+    node: &'static str,
+}
+
+impl NodeLog {
+    pub fn new(node: &'static str) -> Self {
+        Self { node }
+    }
+
+    /// Print `msg` to stdout, `[node] ` in front of every line.
+    pub fn line(&self, msg: impl fmt::Display) {
+        let text = prefixed(self.node, &msg.to_string());
+        let _ = std::io::stdout().lock().write_all(text.as_bytes());
+    }
+
+    /// Print `msg` to stderr, `[node] ` in front of every line.
+    pub fn warn(&self, msg: impl fmt::Display) {
+        let text = prefixed(self.node, &msg.to_string());
+        let _ = std::io::stderr().lock().write_all(text.as_bytes());
+    }
+}
+
+/// `text` with `[node] ` in front of every non-empty line.
+fn prefixed(node: &str, text: &str) -> String {
+    text.split('\n')
+        .map(|line| match line {
+            "" => "\n".to_string(),
+            line => format!("[{}] {}\n", node, line),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prefixed() {
+        assert_eq!(
+            prefixed("Rootfs", "\n=== Building EROFS ===\n  done"),
+            "\n[Rootfs] === Building EROFS ===\n[Rootfs]   done\n"
+        );
+        assert_eq!(prefixed("ISO", ""), "\n");
+    }
+}
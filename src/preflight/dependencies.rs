@@ -5,6 +5,7 @@ use std::path::Path;
 use anyhow::Result;
 use distro_spec::shared::LEVITATE_CARGO_TOOLS;
 
+use crate::build::libdeps::find_sbin_binary;
 use crate::recipe;
 
 use super::types::CheckResult;
@@ -62,6 +63,21 @@ pub fn check_dependencies(base_dir: &Path) -> Result<Vec<CheckResult>> {
         ));
     }
 
+    // veritysetup from the Rocky rootfs opens the dm-verity root in the live
+    // initramfs (see `artifact::verity::append_verity_tools`)
+    if rootfs_path.exists() {
+        match find_sbin_binary(&rootfs_path, "veritysetup") {
+            Some(path) => results.push(CheckResult::pass_with(
+                "veritysetup (rootfs)",
+                &path.display().to_string(),
+            )),
+            None => results.push(CheckResult::fail(
+                "veritysetup (rootfs)",
+                "Not in downloads/rootfs - the Rocky rootfs needs the cryptsetup package",
+            )),
+        }
+    }
+
     // Installation tools (recstrap, recfstab, recchroot)
     // Check if installed in staging (placed there by recipes)
     let output_dir = distro_builder::artifact_store::central_output_dir_for_distro(base_dir);
@@ -4,6 +4,8 @@ use std::path::Path;
 
 use distro_builder::process::Cmd;
 
+use crate::artifact::live_init;
+
 use super::types::CheckResult;
 use super::validators::{validate_init_script, validate_kconfig};
 
@@ -105,6 +107,25 @@ pub fn check_build_environment(base_dir: &Path) -> Vec<CheckResult> {
         ));
     }
 
+    // The Rust /init must link statically (see `artifact::live_init`)
+    match live_init::rust_init_enabled() {
+        Ok(false) => results.push(CheckResult::pass_with(
+            "static /init toolchain",
+            "not needed (LEVISO_LIVE_INIT=shell)",
+        )),
+        Ok(true) => match live_init::static_toolchain() {
+            Some(toolchain) => {
+                results.push(CheckResult::pass_with("static /init toolchain", toolchain))
+            }
+            None => results.push(CheckResult::fail(
+                "static /init toolchain",
+                "No musl target or static glibc - run 'rustup target add \
+                 x86_64-unknown-linux-musl' or 'sudo dnf install glibc-static'",
+            )),
+        },
+        Err(e) => results.push(CheckResult::fail("static /init toolchain", &e.to_string())),
+    }
+
     // Check disk space (warn if < 20GB free)
     // Use df command to avoid nix crate dependency
     if let Ok(result) = Cmd::new("df")
@@ -29,6 +29,16 @@ pub fn check_host_tools() -> Vec<CheckResult> {
             "systemd-ukify",
             "Required for UKI (Unified Kernel Image) creation",
         ),
+        (
+            "rpm",
+            "rpm",
+            "Required to query the Rocky rpmdb (licenses, sources, rebuild detection)",
+        ),
+        (
+            "veritysetup",
+            "cryptsetup",
+            "Required to build the dm-verity hash tree of the live EROFS",
+        ),
     ];
 
     for (tool, package, purpose) in required_tools {
@@ -48,6 +58,8 @@ pub fn check_host_tools() -> Vec<CheckResult> {
             "qemu-utils",
             "Required for virtual disk creation",
         ),
+        ("zstd", "zstd", "Required for zstd initramfs compression"),
+        ("lz4", "lz4", "Required for lz4 initramfs compression"),
     ];
 
     for (tool, package, purpose) in optional_tools {
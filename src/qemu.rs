@@ -4,12 +4,14 @@
 //! Uses the shared `recqemu` crate for QEMU command building.
 
 use anyhow::{bail, Context, Result};
+use std::ffi::OsString;
 use std::io::{BufRead, BufReader};
 use std::path::Path;
-use std::process::Stdio;
+use std::process::{Command, Stdio};
 use std::sync::mpsc;
 use std::time::{Duration, Instant};
 
+use crate::artifact::secureboot::{self, SecureBootKeys, SECUREBOOT_OUTPUT_DIR};
 use distro_spec::levitate::{
     ISO_FILENAME, QEMU_DISK_FILENAME, QEMU_DISK_GB, QEMU_MEMORY_GB, QEMU_SERIAL_LOG,
 };
@@ -24,6 +26,12 @@ const SUCCESS_PATTERNS: &[&str] = &[
     "systemd[1]: Started Getty",  // Getty started
 ];
 
+/// Kernel message confirming Secure Boot was enforced (`--secureboot` tests).
+const SECURE_BOOT_ENABLED: &str = "Secure boot enabled";
+
+/// Machine type for Secure Boot: the secure pflash needs SMM.
+const SECURE_BOOT_MACHINE: &str = "q35,smm=on";
+
 /// Failure patterns - if we see any of these, boot failed.
 const FAILURE_PATTERNS: &[&str] = &[
     "Kernel panic",
@@ -107,9 +115,13 @@ pub fn run_iso(base_dir: &Path, disk_size: Option<String>) -> Result<()> {
 /// Uses AHCI for CD-ROM (like real SATA hardware) to verify that the
 /// real hardware drivers (ahci, libata, sr_mod) work correctly.
 ///
+/// With `secureboot`, boots secure-boot OVMF with the keys from
+/// `SECUREBOOT_KEY_DIR` enrolled, and additionally requires the kernel to
+/// report Secure Boot enabled.
+///
 /// Returns Ok(()) if boot succeeds (login prompt reached).
 /// Returns Err if boot fails or times out.
-pub fn test_iso(base_dir: &Path, timeout_secs: u64) -> Result<()> {
+pub fn test_iso(base_dir: &Path, timeout_secs: u64, secureboot: bool) -> Result<()> {
     let output_dir = distro_builder::artifact_store::central_output_dir_for_distro(base_dir);
     let iso_path = output_dir.join(ISO_FILENAME);
 
@@ -123,23 +135,31 @@ pub fn test_iso(base_dir: &Path, timeout_secs: u64) -> Result<()> {
     println!("=== LevitateOS Boot Test ===\n");
     println!("ISO: {}", iso_path.display());
     println!("Timeout: {}s", timeout_secs);
-    println!();
-
-    // Find OVMF
-    let ovmf_path = find_ovmf().context("OVMF not found - UEFI boot required")?;
 
     // Build headless QEMU command
     // Note: We use build() + manual stdio setup because test needs piped stdout
     // but different serial config than build_piped() provides
-    let mut cmd = QemuBuilder::new()
+    let mut builder = QemuBuilder::new()
         .memory(&format!("{}G", QEMU_MEMORY_GB))
         .smp(2)
         .cdrom(&iso_path)
-        .uefi(&ovmf_path)
         .nographic()
         .serial_stdio()
-        .no_reboot()
-        .build();
+        .no_reboot();
+    let secure_boot_args = if secureboot {
+        secure_boot_firmware(&output_dir)?
+    } else {
+        let ovmf_path = find_ovmf().context("OVMF not found - UEFI boot required")?;
+        builder = builder.uefi(&ovmf_path);
+        Vec::new()
+    };
+    println!();
+
+    let mut cmd = builder.build();
+    if secureboot {
+        cmd = with_machine(&cmd, SECURE_BOOT_MACHINE);
+    }
+    cmd.args(&secure_boot_args);
 
     cmd.stdin(Stdio::null())
         .stdout(Stdio::piped())
@@ -172,6 +192,7 @@ pub fn test_iso(base_dir: &Path, timeout_secs: u64) -> Result<()> {
     let mut saw_uefi = false;
     let mut saw_kernel = false;
     let mut saw_init = false;
+    let mut saw_secure_boot = false;
 
     println!("Watching boot output...\n");
 
@@ -229,6 +250,9 @@ pub fn test_iso(base_dir: &Path, timeout_secs: u64) -> Result<()> {
                 if line.contains("systemd") || line.contains("init") {
                     saw_init = true;
                 }
+                if line.contains(SECURE_BOOT_ENABLED) {
+                    saw_secure_boot = true;
+                }
 
                 // Check failure patterns first (fail fast)
                 for pattern in FAILURE_PATTERNS {
@@ -255,12 +279,23 @@ pub fn test_iso(base_dir: &Path, timeout_secs: u64) -> Result<()> {
                         let _ = child.kill();
                         let _ = child.wait();
 
+                        if secureboot && !saw_secure_boot {
+                            bail!(
+                                "Booted, but the kernel never reported '{}'.\n\
+                                 The firmware did not enforce Secure Boot - check the enrolled vars.",
+                                SECURE_BOOT_ENABLED
+                            );
+                        }
+
                         println!();
                         println!("═══════════════════════════════════════════════════════════");
                         println!("BOOT SUCCESS: Matched '{}'", pattern);
                         println!("═══════════════════════════════════════════════════════════");
                         println!();
                         println!("Boot completed in {:.1}s", elapsed);
+                        if secureboot {
+                            println!("Secure Boot was enforced.");
+                        }
                         println!("LevitateOS is ready for login.");
 
                         return Ok(());
@@ -283,3 +318,59 @@ pub fn test_iso(base_dir: &Path, timeout_secs: u64) -> Result<()> {
         }
     }
 }
+
+/// QEMU arguments for secure-boot OVMF with the configured keys enrolled.
+///
+/// Secure Boot needs the SMM-protected pflash setup instead of `-bios`, and a
+/// writable copy of the vars with PK/KEK/db enrolled. The machine type is set
+/// separately (see `with_machine`).
+fn secure_boot_firmware(output_dir: &Path) -> Result<Vec<String>> {
+    let keys = SecureBootKeys::from_env()?.context(
+        "--secureboot needs signing keys: set SECUREBOOT_KEY_DIR and rebuild the ISO.\n\
+         Generate test keys with: leviso secureboot-keys <dir>",
+    )?;
+    let (code, template) = secureboot::find_ovmf_secboot()?;
+    let vars = output_dir
+        .join(SECUREBOOT_OUTPUT_DIR)
+        .join("OVMF_VARS.enrolled.fd");
+    secureboot::enroll_ovmf_vars(&keys, &template, &vars)?;
+
+    println!("Boot: UEFI Secure Boot ({})", code.display());
+    println!("Keys: {}", keys.cert("db").display());
+
+    Ok(vec![
+        "-global".to_string(),
+        "driver=cfi.pflash01,property=secure,value=on".to_string(),
+        "-drive".to_string(),
+        format!(
+            "if=pflash,format=raw,unit=0,readonly=on,file={}",
+            code.display()
+        ),
+        "-drive".to_string(),
+        format!("if=pflash,format=raw,unit=1,file={}", vars.display()),
+    ])
+}
+
+/// `cmd` with its `-machine` (or `-M`) value replaced by `machine`, or the
+/// option added if the builder didn't set one, so the command line carries a
+/// single machine definition.
+fn with_machine(cmd: &Command, machine: &str) -> Command {
+    let mut args: Vec<OsString> = cmd.get_args().map(OsString::from).collect();
+    match args.iter().position(|a| a == "-machine" || a == "-M") {
+        Some(i) if i + 1 < args.len() => args[i + 1] = machine.into(),
+        _ => args.extend(["-machine".into(), machine.into()]),
+    }
+
+    let mut new = Command::new(cmd.get_program());
+    new.args(args);
+    for (key, value) in cmd.get_envs() {
+        match value {
+            Some(value) => new.env(key, value),
+            None => new.env_remove(key),
+        };
+    }
+    if let Some(dir) = cmd.get_current_dir() {
+        new.current_dir(dir);
+    }
+    new
+}
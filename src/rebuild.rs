@@ -3,7 +3,22 @@
 //! Uses hash-based caching to skip rebuilding artifacts that haven't changed.
 //! Each artifact defines its input files once, eliminating duplication between
 //! needs_rebuild and cache_hash functions.
+//!
+//! The rootfs does not hand-list its data files: the files read during the
+//! last `build_system()` are recorded (see `build::inputs`) and hashed instead.
+//!
+//! Input paths are hashed relative to the leviso directory (`../` for the
+//! rest of the monorepo), so the same inputs give the same key in every
+//! checkout and a key can be looked up in a shared store (see
+//! `common::remote_store`).
+//!
+//! Next to each `.{artifact}-inputs.hash` a per-file manifest
+//! (`.{artifact}-inputs.manifest`) is stored, so `leviso show status --explain`
+//! can say exactly which inputs changed since the last successful build.
 
+use anyhow::Result;
+use std::collections::BTreeMap;
+use std::fs;
 use std::path::{Path, PathBuf};
 
 use distro_spec::levitate::{
@@ -11,27 +26,154 @@ use distro_spec::levitate::{
 };
 use distro_spec::shared::QCOW2_IMAGE_FILENAME;
 
+use crate::artifact::compression;
+use crate::artifact::microcode::MicrocodeFiles;
+use crate::build::inputs;
+use crate::build::libdeps::find_sbin_binary;
+use crate::build::reproducible;
 use distro_builder::cache;
+use sha2::{Digest, Sha256};
 
 /// An artifact that can be incrementally rebuilt.
 pub struct Artifact {
+    /// Directory input paths are hashed relative to (the leviso directory)
+    pub root: PathBuf,
     /// Path to the output file
     pub output: PathBuf,
     /// Path to the hash cache file
     pub hash_file: PathBuf,
     /// Input files that affect this artifact
     pub inputs: Vec<PathBuf>,
+    /// Content hashes of inputs that aren't plain files, labelled for
+    /// `--explain` (e.g. the source rootfs package manifest). `None` means
+    /// "unknown", which forces a rebuild.
+    pub fingerprints: Vec<(&'static str, Option<String>)>,
+}
+
+/// Per-input content hashes, keyed by input path (or fingerprint label).
+pub type InputManifest = BTreeMap<String, String>;
+
+/// Differences between the stored and the current input manifest.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct InputChanges {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub changed: Vec<String>,
+}
+
+impl InputChanges {
+    /// Compare the manifest of the last successful build with the current one.
+    pub fn between(previous: &InputManifest, current: &InputManifest) -> Self {
+        let mut changes = Self::default();
+        for (input, hash) in current {
+            match previous.get(input) {
+                None => changes.added.push(input.clone()),
+                Some(old) if old != hash => changes.changed.push(input.clone()),
+                Some(_) => {}
+            }
+        }
+        for input in previous.keys() {
+            if !current.contains_key(input) {
+                changes.removed.push(input.clone());
+            }
+        }
+        changes
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// Manifest file stored next to a `.{artifact}-inputs.hash` file.
+pub fn input_manifest_path(hash_file: &Path) -> PathBuf {
+    hash_file.with_extension("manifest")
+}
+
+/// `path` relative to `root`, or to its parent as `../...`; other paths are
+/// kept as they are.
+fn relative_label(root: &Path, path: &Path) -> String {
+    if let Ok(rel) = path.strip_prefix(root) {
+        return rel.display().to_string();
+    }
+    match root.parent().map(|parent| path.strip_prefix(parent)) {
+        Some(Ok(rel)) => Path::new("..").join(rel).display().to_string(),
+        _ => path.display().to_string(),
+    }
 }
 
 impl Artifact {
+    /// Content hash of every input, keyed by its path relative to `root`
+    /// (`None` if unreadable). An input reached by two paths is listed once.
+    fn file_hashes(&self) -> BTreeMap<String, Option<String>> {
+        self.inputs
+            .iter()
+            .map(|input| {
+                let hash = fs::read(input)
+                    .ok()
+                    .map(|content| format!("{:x}", Sha256::digest(&content)));
+                (relative_label(&self.root, input), hash)
+            })
+            .collect()
+    }
+
+    /// Hash of all input files (with their relative paths) and fingerprints,
+    /// or `None` if any is missing.
+    pub fn current_hash(&self) -> Option<String> {
+        let mut hasher = Sha256::new();
+        for (label, hash) in self.file_hashes() {
+            hasher.update(label.as_bytes());
+            hasher.update(b"\0");
+            hasher.update(hash?.as_bytes());
+            hasher.update(b"\n");
+        }
+        for (_, fingerprint) in &self.fingerprints {
+            hasher.update(b"\n");
+            hasher.update(fingerprint.as_deref()?.as_bytes());
+        }
+        Some(format!("{:x}", hasher.finalize()))
+    }
+
+    /// Per-file hashes of the inputs as they are now.
+    ///
+    /// Missing inputs and unknown fingerprints are left out, so they show up
+    /// as "removed" when compared with the stored manifest.
+    pub fn input_manifest(&self) -> InputManifest {
+        let mut manifest: InputManifest = self
+            .file_hashes()
+            .into_iter()
+            .filter_map(|(label, hash)| Some((label, hash?)))
+            .collect();
+        for (label, fingerprint) in &self.fingerprints {
+            if let Some(hash) = fingerprint {
+                manifest.insert(label.to_string(), hash.clone());
+            }
+        }
+        manifest
+    }
+
+    /// Manifest stored by the last successful build, if any.
+    pub fn stored_manifest(&self) -> Option<InputManifest> {
+        let content = fs::read_to_string(input_manifest_path(&self.hash_file)).ok()?;
+        serde_json::from_str(&content).ok()
+    }
+
+    /// Which inputs changed since the last successful build.
+    ///
+    /// Returns `None` if no manifest was stored (never built, or built before
+    /// manifests existed).
+    pub fn explain(&self) -> Option<InputChanges> {
+        let previous = self.stored_manifest()?;
+        Some(InputChanges::between(&previous, &self.input_manifest()))
+    }
+
     /// Check if this artifact needs to be rebuilt.
     pub fn needs_rebuild(&self) -> bool {
         if !self.output.exists() {
             return true;
         }
 
-        let input_refs: Vec<&Path> = self.inputs.iter().map(|p| p.as_path()).collect();
-        let current_hash = match cache::hash_files(&input_refs) {
+        let current_hash = match self.current_hash() {
             Some(h) => h,
             None => return true,
         };
@@ -41,9 +183,11 @@ impl Artifact {
 
     /// Cache the input hash after a successful build.
     pub fn cache_hash(&self) {
-        let input_refs: Vec<&Path> = self.inputs.iter().map(|p| p.as_path()).collect();
-        if let Some(hash) = cache::hash_files(&input_refs) {
+        if let Some(hash) = self.current_hash() {
             let _ = cache::write_cached_hash(&self.hash_file, &hash);
+            if let Ok(json) = serde_json::to_string_pretty(&self.input_manifest()) {
+                let _ = fs::write(input_manifest_path(&self.hash_file), json + "\n");
+            }
         }
     }
 }
@@ -62,79 +206,144 @@ pub fn kernel_artifact(base_dir: &Path) -> Artifact {
     }
 
     Artifact {
+        root: base_dir.to_path_buf(),
         output: output_dir.join("kernel-build/arch/x86/boot/bzImage"),
         hash_file: output_dir.join(".kernel-inputs.hash"),
         inputs,
+        fingerprints: Vec::new(),
     }
 }
 
 /// Rootfs (EROFS) artifact.
-pub fn rootfs_artifact(base_dir: &Path) -> Artifact {
+///
+/// Inputs are discovered rather than hand-listed:
+/// - build logic: every `.rs` file under `src/component` and `src/build`
+/// - data files: whatever the last build recorded in `.rootfs-inputs.list`
+///   (bundled files, tool and docs sources, RPMs, kernel config)
+/// - distro-spec component definitions
+/// - the Rocky source rootfs, via a hash of its rpmdb package manifest
+///
+/// A checkout that never built the rootfs has no recorded list, so its key
+/// matches no stored rootfs and the first build there runs locally.
+///
+/// Fails if the rpmdb of an extracted source rootfs cannot be queried.
+pub fn rootfs_artifact(base_dir: &Path) -> Result<Artifact> {
     let output_dir = distro_builder::artifact_store::central_output_dir_for_distro(base_dir);
     let distro_spec_base = base_dir.join("../distro-spec/src/shared");
 
-    Artifact {
+    let mut files = inputs::source_files(&base_dir.join("src/component"), "rs");
+    files.extend(inputs::source_files(&base_dir.join("src/build"), "rs"));
+    files.extend(inputs::read_input_list(
+        &output_dir.join(inputs::ROOTFS_INPUTS_LIST),
+    ));
+    files.push(distro_spec_base.join("services.rs"));
+    files.extend(inputs::source_files(
+        &distro_spec_base.join("components"),
+        "rs",
+    ));
+    files.sort();
+    files.dedup();
+
+    Ok(Artifact {
+        root: base_dir.to_path_buf(),
         output: output_dir.join(ROOTFS_NAME),
         hash_file: output_dir.join(".rootfs-inputs.hash"),
-        inputs: vec![
-            // Rocky rootfs marker
-            base_dir.join("downloads/rootfs/usr/bin/bash"),
-            // Component source files
-            base_dir.join("src/component/definitions.rs"),
-            base_dir.join("src/component/mod.rs"),
-            base_dir.join("src/component/custom/mod.rs"),
-            base_dir.join("src/component/custom/etc/mod.rs"),
-            base_dir.join("src/component/custom/pam.rs"),
-            base_dir.join("src/component/custom/live/mod.rs"),
-            base_dir.join("src/component/custom/packages/mod.rs"),
-            base_dir.join("src/component/custom/firmware.rs"),
-            base_dir.join("src/component/custom/modules.rs"),
-            // Component data files (read via read_manifest_file)
-            base_dir.join("src/component/custom/etc/files/adjtime"),
-            base_dir.join("src/component/custom/etc/files/bashrc"),
-            base_dir.join("src/component/custom/etc/files/fstab"),
-            base_dir.join("src/component/custom/etc/files/group"),
-            base_dir.join("src/component/custom/etc/files/gshadow"),
-            base_dir.join("src/component/custom/etc/files/hosts"),
-            base_dir.join("src/component/custom/etc/files/locale.conf"),
-            base_dir.join("src/component/custom/etc/files/login.defs"),
-            base_dir.join("src/component/custom/etc/files/motd"),
-            base_dir.join("src/component/custom/etc/files/nsswitch.conf"),
-            base_dir.join("src/component/custom/etc/files/passwd"),
-            base_dir.join("src/component/custom/etc/files/profile"),
-            base_dir.join("src/component/custom/etc/files/shadow"),
-            base_dir.join("src/component/custom/etc/files/shells"),
-            base_dir.join("src/component/custom/etc/files/sudo.conf"),
-            base_dir.join("src/component/custom/etc/files/sudoers"),
-            base_dir.join("src/component/custom/etc/files/vconsole.conf"),
-            base_dir.join("src/component/custom/packages/files/recipe.conf"),
-            base_dir.join("src/component/custom/packages/files/recipe.sh"),
-            // Build logic
-            base_dir.join("src/build/libdeps.rs"),
-            base_dir.join("src/build/filesystem.rs"),
-            base_dir.join("src/build/users.rs"),
-            // distro-spec definitions
-            distro_spec_base.join("services.rs"),
-            distro_spec_base.join("components/mod.rs"),
-            distro_spec_base.join("components/bins.rs"),
-            distro_spec_base.join("components/etc.rs"),
-            distro_spec_base.join("components/filesystem.rs"),
-            distro_spec_base.join("components/systemd.rs"),
-            distro_spec_base.join("components/units.rs"),
-            distro_spec_base.join("components/users.rs"),
+        inputs: files,
+        fingerprints: vec![
+            (
+                "rpmdb:downloads/rootfs",
+                inputs::rpm_manifest_hash(&base_dir.join("downloads/rootfs"))?,
+            ),
+            // mtimes, EROFS UUID and verity salt, and whether host keys are
+            // baked in (see `build::reproducible`)
+            (
+                "env:SOURCE_DATE_EPOCH",
+                Some(env_fingerprint(reproducible::SOURCE_DATE_EPOCH_ENV)),
+            ),
+            (
+                "env:LEVISO_SSH_HOST_KEYS",
+                Some(env_fingerprint(reproducible::SSH_HOST_KEYS_ENV)),
+            ),
         ],
-    }
+    })
+}
+
+/// Value of a build setting from the environment; unset is a valid setting
+/// (the default), not an unknown one.
+fn env_fingerprint(name: &str) -> String {
+    std::env::var(name).unwrap_or_default()
 }
 
+/// Files under `src/artifact` that decide what the live initramfs holds on
+/// top of recinit's base image: module lists, appended segments, the `/init`
+/// config, compression and microcode.
+const LIVE_INITRAMFS_SOURCES: &[&str] = &[
+    "initramfs.rs",
+    "modules.rs",
+    "verity.rs",
+    "netboot.rs",
+    "persist.rs",
+    "live_init.rs",
+    "compression.rs",
+    "cpio.rs",
+    "microcode.rs",
+];
+
 /// Live initramfs artifact (tiny busybox-based).
 pub fn initramfs_artifact(base_dir: &Path) -> Artifact {
     let output_dir = distro_builder::artifact_store::central_output_dir_for_distro(base_dir);
+    let init_dir = base_dir.join(crate::artifact::live_init::LIVE_INIT_CRATE);
+
+    let mut files = vec![
+        base_dir.join("profile/init_tiny.template"),
+        base_dir.join("downloads/busybox-static"),
+        // static Rust /init
+        init_dir.join("Cargo.toml"),
+        init_dir.join("Cargo.lock"),
+    ];
+    files.extend(inputs::source_files(&init_dir.join("src"), "rs"));
+    // veritysetup appended for the dm-verity root; its absence is reported
+    // by preflight and fails the build, it must not make the key unknown
+    files.extend(find_sbin_binary(
+        &base_dir.join("downloads/rootfs"),
+        "veritysetup",
+    ));
+    // early microcode prepended to the image
+    files.extend(MicrocodeFiles::find(&base_dir.join("downloads/rootfs")).all());
+    // the kernel module tree the load lists are resolved from
+    if let Some(modules) = crate::artifact::initramfs::live_modules_path(base_dir) {
+        files.push(modules.join("modules.dep"));
+        files.push(modules.join("modules.alias"));
+        // optional, like in append_live_modules
+        let builtin = modules.join("modules.builtin");
+        if builtin.exists() {
+            files.push(builtin);
+        }
+    }
+    files.extend(
+        LIVE_INITRAMFS_SOURCES
+            .iter()
+            .map(|f| base_dir.join("src/artifact").join(f)),
+    );
     Artifact {
+        root: base_dir.to_path_buf(),
         output: output_dir.join(INITRAMFS_LIVE_OUTPUT),
         hash_file: output_dir.join(".initramfs-inputs.hash"),
-        inputs: vec![
-            base_dir.join("profile/init_tiny.template"),
-            base_dir.join("downloads/busybox-static"),
+        inputs: files,
+        // build settings that change the image
+        fingerprints: vec![
+            (
+                "env:LEVISO_LIVE_INIT",
+                Some(env_fingerprint(crate::artifact::live_init::LIVE_INIT_ENV)),
+            ),
+            (
+                "env:LEVISO_LIVE_INITRAMFS_COMPRESSION",
+                Some(env_fingerprint(compression::LIVE_COMPRESSION_ENV)),
+            ),
+            (
+                "env:SOURCE_DATE_EPOCH",
+                Some(env_fingerprint(reproducible::SOURCE_DATE_EPOCH_ENV)),
+            ),
         ],
     }
 }
@@ -145,28 +354,43 @@ pub fn install_initramfs_artifact(base_dir: &Path) -> Artifact {
     let recinit_base = base_dir.join("../tools/recinit/src");
     let distro_spec_base = base_dir.join("../distro-spec/src/shared");
 
+    let mut files = vec![
+        // recinit source files
+        recinit_base.join("systemd.rs"),
+        recinit_base.join("install.rs"),
+        recinit_base.join("lib.rs"),
+        recinit_base.join("elf.rs"),
+        recinit_base.join("cpio.rs"),
+        recinit_base.join("modules.rs"),
+        // distro-spec components (ESSENTIAL_UNITS, BIN_UTILS, etc.)
+        distro_spec_base.join("components/mod.rs"),
+        distro_spec_base.join("components/bins.rs"),
+        distro_spec_base.join("components/etc.rs"),
+        distro_spec_base.join("components/filesystem.rs"),
+        distro_spec_base.join("components/systemd.rs"),
+        distro_spec_base.join("components/units.rs"),
+        distro_spec_base.join("components/users.rs"),
+        distro_spec_base.join("udev.rs"),
+        // rootfs marker (source of binaries)
+        base_dir.join("downloads/rootfs/usr/bin/bash"),
+    ];
+    // early microcode prepended to the image
+    files.extend(MicrocodeFiles::find(&base_dir.join("downloads/rootfs")).all());
+
     Artifact {
+        root: base_dir.to_path_buf(),
         output: output_dir.join(INITRAMFS_INSTALLED_OUTPUT),
         hash_file: output_dir.join(".install-initramfs-inputs.hash"),
-        inputs: vec![
-            // recinit source files
-            recinit_base.join("systemd.rs"),
-            recinit_base.join("install.rs"),
-            recinit_base.join("lib.rs"),
-            recinit_base.join("elf.rs"),
-            recinit_base.join("cpio.rs"),
-            recinit_base.join("modules.rs"),
-            // distro-spec components (ESSENTIAL_UNITS, BIN_UTILS, etc.)
-            distro_spec_base.join("components/mod.rs"),
-            distro_spec_base.join("components/bins.rs"),
-            distro_spec_base.join("components/etc.rs"),
-            distro_spec_base.join("components/filesystem.rs"),
-            distro_spec_base.join("components/systemd.rs"),
-            distro_spec_base.join("components/units.rs"),
-            distro_spec_base.join("components/users.rs"),
-            distro_spec_base.join("udev.rs"),
-            // rootfs marker (source of binaries)
-            base_dir.join("downloads/rootfs/usr/bin/bash"),
+        inputs: files,
+        fingerprints: vec![
+            (
+                "env:LEVISO_INSTALL_INITRAMFS_COMPRESSION",
+                Some(env_fingerprint(compression::INSTALL_COMPRESSION_ENV)),
+            ),
+            (
+                "env:SOURCE_DATE_EPOCH",
+                Some(env_fingerprint(reproducible::SOURCE_DATE_EPOCH_ENV)),
+            ),
         ],
     }
 }
@@ -176,6 +400,7 @@ pub fn install_initramfs_artifact(base_dir: &Path) -> Artifact {
 pub fn qcow2_artifact(base_dir: &Path) -> Artifact {
     let output_dir = distro_builder::artifact_store::central_output_dir_for_distro(base_dir);
     Artifact {
+        root: base_dir.to_path_buf(),
         output: output_dir.join(QCOW2_IMAGE_FILENAME),
         hash_file: output_dir.join(".qcow2-inputs.hash"),
         inputs: vec![
@@ -186,6 +411,7 @@ pub fn qcow2_artifact(base_dir: &Path) -> Artifact {
             // qcow2-specific config
             base_dir.join("src/artifact/qcow2.rs"),
         ],
+        fingerprints: Vec::new(),
     }
 }
 
@@ -211,8 +437,8 @@ pub fn kernel_needs_install(base_dir: &Path) -> bool {
     cache::is_newer(&bzimage, &vmlinuz)
 }
 
-pub fn rootfs_needs_rebuild(base_dir: &Path) -> bool {
-    rootfs_artifact(base_dir).needs_rebuild()
+pub fn rootfs_needs_rebuild(base_dir: &Path) -> Result<bool> {
+    Ok(rootfs_artifact(base_dir)?.needs_rebuild())
 }
 
 pub fn initramfs_needs_rebuild(base_dir: &Path) -> bool {
@@ -223,46 +449,61 @@ pub fn install_initramfs_needs_rebuild(base_dir: &Path) -> bool {
     install_initramfs_artifact(base_dir).needs_rebuild()
 }
 
-pub fn iso_needs_rebuild(base_dir: &Path) -> bool {
+/// Inputs of the ISO as `(required, optional)` paths.
+///
+/// Unlike the other artifacts, the ISO is checked by mtime.
+fn iso_inputs(base_dir: &Path) -> (Vec<PathBuf>, Vec<PathBuf>) {
     let output_dir = distro_builder::artifact_store::central_output_dir_for_distro(base_dir);
-    let iso = output_dir.join(ISO_FILENAME);
-    let rootfs = output_dir.join(ROOTFS_NAME);
-    let initramfs = output_dir.join(INITRAMFS_LIVE_OUTPUT);
-    let vmlinuz = output_dir.join("staging/boot/vmlinuz");
+    let required = vec![
+        output_dir.join(ROOTFS_NAME),
+        output_dir.join(INITRAMFS_LIVE_OUTPUT),
+        output_dir.join("staging/boot/vmlinuz"),
+    ];
 
     // Live overlay files affect ISO content
     let live_overlay = base_dir.join("profile/live-overlay");
-    let live_shadow = live_overlay.join("etc/shadow");
-    let live_autologin =
-        live_overlay.join("etc/systemd/system/getty@tty1.service.d/autologin.conf");
-    let live_serial =
-        live_overlay.join("etc/systemd/system/serial-getty@.service.d/zz-autologin.conf");
-    let live_docs = live_overlay.join("etc/profile.d/live-docs.sh");
-    let live_test = live_overlay.join("etc/profile.d/00-levitate-test.sh");
-
-    if !iso.exists() {
-        return true;
+    let mut optional = vec![
+        live_overlay.join("etc/shadow"),
+        live_overlay.join("etc/systemd/system/getty@tty1.service.d/autologin.conf"),
+        live_overlay.join("etc/systemd/system/serial-getty@.service.d/zz-autologin.conf"),
+        live_overlay.join("etc/profile.d/live-docs.sh"),
+        live_overlay.join("etc/profile.d/00-levitate-test.sh"),
+    ];
+
+    // A new db key means every UKI in the ISO must be re-signed
+    if let Ok(Some(keys)) = crate::artifact::secureboot::SecureBootKeys::from_env() {
+        optional.push(keys.cert("db"));
     }
 
-    !rootfs.exists()
-        || !initramfs.exists()
-        || !vmlinuz.exists()
-        || cache::is_newer(&rootfs, &iso)
-        || cache::is_newer(&initramfs, &iso)
-        || cache::is_newer(&vmlinuz, &iso)
-        || cache::is_newer(&live_shadow, &iso)
-        || cache::is_newer(&live_autologin, &iso)
-        || cache::is_newer(&live_serial, &iso)
-        || cache::is_newer(&live_docs, &iso)
-        || cache::is_newer(&live_test, &iso)
+    (required, optional)
+}
+
+/// ISO inputs that are missing (if required) or newer than the ISO.
+pub fn iso_stale_inputs(base_dir: &Path) -> Vec<PathBuf> {
+    let output_dir = distro_builder::artifact_store::central_output_dir_for_distro(base_dir);
+    let iso = output_dir.join(ISO_FILENAME);
+    let (required, optional) = iso_inputs(base_dir);
+
+    let missing = required.iter().filter(|p| !p.exists());
+    let newer = required
+        .iter()
+        .chain(&optional)
+        .filter(|p| cache::is_newer(p, &iso));
+    missing.chain(newer).cloned().collect()
+}
+
+pub fn iso_needs_rebuild(base_dir: &Path) -> bool {
+    let output_dir = distro_builder::artifact_store::central_output_dir_for_distro(base_dir);
+    !output_dir.join(ISO_FILENAME).exists() || !iso_stale_inputs(base_dir).is_empty()
 }
 
 pub fn cache_kernel_hash(base_dir: &Path) {
     kernel_artifact(base_dir).cache_hash();
 }
 
-pub fn cache_rootfs_hash(base_dir: &Path) {
-    rootfs_artifact(base_dir).cache_hash();
+pub fn cache_rootfs_hash(base_dir: &Path) -> Result<()> {
+    rootfs_artifact(base_dir)?.cache_hash();
+    Ok(())
 }
 
 pub fn cache_initramfs_hash(base_dir: &Path) {
@@ -282,3 +523,60 @@ pub fn qcow2_needs_rebuild(base_dir: &Path) -> bool {
 pub fn cache_qcow2_hash(base_dir: &Path) {
     qcow2_artifact(base_dir).cache_hash()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_input_manifest_path() {
+        assert_eq!(
+            input_manifest_path(Path::new("/out/.rootfs-inputs.hash")),
+            PathBuf::from("/out/.rootfs-inputs.manifest")
+        );
+    }
+
+    #[test]
+    fn test_input_changes_between() {
+        let previous: InputManifest = [("a", "1"), ("b", "2"), ("c", "3")]
+            .into_iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        let current: InputManifest = [("a", "1"), ("b", "9"), ("d", "4")]
+            .into_iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+
+        let changes = InputChanges::between(&previous, &current);
+        assert_eq!(changes.added, vec!["d".to_string()]);
+        assert_eq!(changes.removed, vec!["c".to_string()]);
+        assert_eq!(changes.changed, vec!["b".to_string()]);
+        assert!(InputChanges::between(&current, &current).is_empty());
+    }
+
+    #[test]
+    fn test_explain_after_cache_hash() {
+        let temp = TempDir::new().unwrap();
+        let input = temp.path().join("init_tiny.template");
+        let output = temp.path().join("initramfs.cpio.gz");
+        fs::write(&input, "v1").unwrap();
+        fs::write(&output, "out").unwrap();
+
+        let artifact = Artifact {
+            root: temp.path().to_path_buf(),
+            output,
+            hash_file: temp.path().join(".initramfs-inputs.hash"),
+            inputs: vec![input.clone()],
+            fingerprints: vec![("rpmdb:test", Some("abc".to_string()))],
+        };
+        assert!(artifact.explain().is_none());
+
+        artifact.cache_hash();
+        assert!(artifact.explain().unwrap().is_empty());
+
+        fs::write(&input, "v2").unwrap();
+        let changes = artifact.explain().unwrap();
+        assert_eq!(changes.changed, vec!["init_tiny.template".to_string()]);
+    }
+}